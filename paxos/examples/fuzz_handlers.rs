@@ -0,0 +1,50 @@
+//! Feed random sequences of messages to a fresh proposer and a fresh acceptor, as
+//! the `message_handlers` fuzz target does, without `cargo-fuzz`:
+//!
+//! ```sh
+//! cargo run --release --example fuzz_handlers --features arbitrary -- --cases 10000
+//! ```
+//!
+//! Inputs are drawn from a seeded RNG, so a case that panics can be run again on
+//! its own with `--first-case <case> --cases 1`.
+
+use anyhow::Result;
+use arbitrary::{Arbitrary, Unstructured};
+use clap::Parser;
+use paxos::fuzzing::{handle_messages, HandlerInput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Number of inputs to feed.
+    #[arg(long, default_value_t = 1000)]
+    cases: u64,
+
+    /// First case run, the following ones being run in order. Each case draws its
+    /// input from an RNG seeded with its number.
+    #[arg(long, default_value_t = 0)]
+    first_case: u64,
+
+    /// Most random bytes an input is drawn from.
+    #[arg(long, default_value_t = 4096)]
+    max_len: usize,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut messages = 0;
+    for case in args.first_case..args.first_case + args.cases {
+        let mut rng = StdRng::seed_from_u64(case);
+        let mut data = vec![0; rng.gen_range(0..=args.max_len)];
+        rng.fill(&mut data[..]);
+        let Ok(input) = HandlerInput::arbitrary(&mut Unstructured::new(&data)) else {
+            continue;
+        };
+        messages += input.messages.len();
+        handle_messages(input)?;
+    }
+
+    println!("{} cases run, {messages} messages handled", args.cases);
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Sweep seeds of the deterministic simulation of two proposers competing for
+//! three acceptors, checking that every run is safe:
+//!
+//! ```sh
+//! cargo run --example simulate --features simulation -- --seeds 1000
+//! ```
+//!
+//! A seed breaking safety is reported, and can be replayed with `--replay <seed>`,
+//! which prints the messages of its run in the order they were delivered.
+//!
+//! With `--faults`, every seed also draws its own scenario: 3 to 7 acceptors, 1 to
+//! 3 proposers with a few values each, and messages lost, delayed or acceptors
+//! crashing at random steps. The faults of an unsafe scenario are then shrunk to
+//! the fewest that still break safety, before it is reported.
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use paxos::simulation::{simulate, Fault, FaultKind, Scenario, SimulationReport};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Number of seeds to simulate.
+    #[arg(long, default_value_t = 1000)]
+    seeds: u64,
+
+    /// First seed simulated, the following ones being simulated in order.
+    #[arg(long, default_value_t = 0)]
+    first_seed: u64,
+
+    /// Simulate this seed only, printing its trace.
+    #[arg(long)]
+    replay: Option<u64>,
+
+    /// Draw a scenario with faults from every seed, rather than simulating two
+    /// proposers on a reliable network.
+    #[arg(long)]
+    faults: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if let Some(seed) = args.replay {
+        let (scenario, report) = shrink(seed, scenario(seed, args.faults))?;
+        println!("faults: {:?}", scenario.faults);
+        for line in &report.trace {
+            println!("{line}");
+        }
+        println!("decisions: {:?}", report.decisions);
+        return report.check_safety();
+    }
+
+    let mut unsafe_seeds = Vec::new();
+    let mut restless = 0;
+    for seed in args.first_seed..args.first_seed + args.seeds {
+        let report = simulate(seed, scenario(seed, args.faults))?;
+        if !report.quiescent {
+            restless += 1;
+        }
+        if let Err(e) = report.check_safety() {
+            let reproduced =
+                simulate(seed, scenario(seed, args.faults))?.trace == report.trace;
+            let (shrunk, _) = shrink(seed, scenario(seed, args.faults))?;
+            println!(
+                "seed {seed} is unsafe: {e} (reproduced: {reproduced}, faults: {:?})",
+                shrunk.faults
+            );
+            unsafe_seeds.push(seed);
+        }
+    }
+
+    println!(
+        "{} seeds simulated, {} unsafe, {restless} still busy after the last step",
+        args.seeds,
+        unsafe_seeds.len()
+    );
+    if !unsafe_seeds.is_empty() {
+        bail!("unsafe seeds: {unsafe_seeds:?}, replay them with --replay <seed>");
+    }
+    Ok(())
+}
+
+/// Scenario simulated with `seed`: the default one, or one drawn from the seed if
+/// `with_faults`.
+fn scenario(seed: u64, with_faults: bool) -> Scenario {
+    if !with_faults {
+        return Scenario::default();
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let acceptors = rng.gen_range(3..=7);
+    let proposers = rng.gen_range(1..=3);
+    let values = (0..proposers)
+        .map(|proposer| {
+            (0..rng.gen_range(1..=3))
+                .map(|i| (proposer + 1) * 1000 + i)
+                .collect()
+        })
+        .collect();
+    let faults = (0..rng.gen_range(0..=8))
+        .map(|_| Fault {
+            step: rng.gen_range(0..200),
+            kind: match rng.gen_range(0..3) {
+                0 => FaultKind::Drop,
+                1 => FaultKind::Delay {
+                    steps: rng.gen_range(1..=50),
+                },
+                _ => FaultKind::Crash {
+                    acceptor: rng.gen_range(0..acceptors as u64),
+                },
+            },
+        })
+        .collect();
+    Scenario {
+        acceptors,
+        values,
+        faults,
+    }
+}
+
+/// Remove the faults of `scenario` one at a time, as long as the run stays
+/// unsafe, to find a smaller set of faults breaking safety. A safe scenario is
+/// left as is.
+fn shrink(seed: u64, mut scenario: Scenario) -> Result<(Scenario, SimulationReport)> {
+    let mut report = simulate(seed, scenario.clone())?;
+    if report.check_safety().is_ok() {
+        return Ok((scenario, report));
+    }
+
+    let mut i = 0;
+    while i < scenario.faults.len() {
+        let mut candidate = scenario.clone();
+        candidate.faults.remove(i);
+        let candidate_report = simulate(seed, candidate.clone())?;
+        if candidate_report.check_safety().is_err() {
+            scenario = candidate;
+            report = candidate_report;
+        } else {
+            i += 1;
+        }
+    }
+    Ok((scenario, report))
+}
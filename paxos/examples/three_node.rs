@@ -0,0 +1,132 @@
+//! Three acceptors and a proposer reaching consensus over TCP, on localhost.
+//!
+//! Every node runs as a task of this process, but they only talk to each other
+//! through their sockets, as separate `paxos-node` processes would. A value is
+//! submitted to the proposer over the network, like `paxos-node propose` does, and
+//! the decision it learns is printed:
+//!
+//! ```sh
+//! cargo run --example three_node -- 42
+//! ```
+//!
+//! Exits with an error if the value decided is not the one submitted.
+
+use std::{
+    net::{SocketAddr, TcpListener},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use paxos::{
+    actors::learner::Decision,
+    config::{ClusterConfig, PeerConfig, Role},
+    node::Node,
+    transport::{
+        tcp::{self, TcpTransport},
+        wire::Codec,
+    },
+};
+use tokio::time::timeout;
+use tracing_subscriber::EnvFilter;
+
+const ACCEPTORS: u64 = 3;
+const PROPOSER_ID: u64 = ACCEPTORS;
+/// Id the value is submitted under, outside of the ids of the cluster.
+const CLIENT_ID: u64 = 100;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new("warn")),
+        )
+        .init();
+
+    let value = match std::env::args().nth(1) {
+        Some(value) => value.parse().context("the value must be a number")?,
+        None => 42,
+    };
+
+    let decision = decide(value).await?;
+    println!(
+        "decided {} in slot {} at ballot {}, by acceptors {:?}",
+        decision.proposal.value,
+        decision.slot,
+        decision.proposal.ballot,
+        decision.quorum
+    );
+    if decision.proposal.value != value {
+        bail!("decided {} instead of {value}", decision.proposal.value);
+    }
+    Ok(())
+}
+
+/// Start the cluster, submit `value` to its proposer over the network, and return
+/// the decision it learns once every node is shut down.
+async fn decide(value: u64) -> Result<Decision> {
+    let peers = (0..=PROPOSER_ID)
+        .map(|node_id| {
+            let role = if node_id == PROPOSER_ID {
+                Role::Proposer
+            } else {
+                Role::Acceptor
+            };
+            Ok(PeerConfig {
+                node_id,
+                roles: vec![role],
+                address: Some(free_address()?),
+                priority: 0,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let proposer_address = peers[PROPOSER_ID as usize].address.expect("address set");
+    let cluster = ClusterConfig {
+        peers,
+        ..ClusterConfig::default()
+    };
+
+    let mut nodes = Vec::new();
+    for peer in &cluster.peers {
+        let transport =
+            TcpTransport::listen(peer.node_id, cluster.clone(), Codec::Bincode, None)
+                .await?;
+        nodes.push(Node::new(
+            peer.node_id,
+            cluster.clone(),
+            Box::new(transport),
+        )?);
+    }
+    let proposer = &nodes[PROPOSER_ID as usize];
+
+    let mut decisions = proposer.decisions();
+    tcp::submit(proposer_address, Codec::Bincode, None, CLIENT_ID, value).await?;
+    println!("submitted {value} to node {PROPOSER_ID} at {proposer_address}");
+    let decision = timeout(Duration::from_secs(10), decisions.recv())
+        .await
+        .context("no decision in time")??;
+
+    for node in nodes {
+        node.shutdown().await?;
+    }
+    Ok(decision)
+}
+
+/// Address on localhost that nothing listens on, for a node to listen on.
+fn free_address() -> Result<SocketAddr> {
+    Ok(TcpListener::bind(("127.0.0.1", 0))?.local_addr()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn submitted_value_is_decided() {
+        let decision = decide(42).await.unwrap();
+
+        assert_eq!(decision.proposal.value, 42);
+        assert_eq!(decision.slot, 0);
+        assert_eq!(decision.quorum.len(), 2);
+    }
+}
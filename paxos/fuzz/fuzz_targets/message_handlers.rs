@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use paxos::fuzzing::{handle_messages, HandlerInput};
+
+// Whatever messages the proposer and the acceptors are sent, in whatever order,
+// their handlers must neither panic nor count more votes than there are acceptors.
+fuzz_target!(|input: HandlerInput| {
+    handle_messages(input).expect("could not set up the proposer");
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use paxos::transport::wire::WireMessage;
+
+// Decoding arbitrary bytes must only ever fail with an error. Whatever decodes
+// successfully must also survive a round trip.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = WireMessage::decode(data) {
+        let encoded = message.encode().expect("decoded message must encode");
+        WireMessage::decode(&encoded).expect("encoded message must decode");
+    }
+});
@@ -1,111 +1,787 @@
-use anyhow::Result;
-use tracing::debug;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
 
-use crate::domain::{
-    id::BrandedUuid,
-    message::{AcceptPhaseBody, Message, PreparePhaseBody},
-    node::Node,
+use crate::{
+    clock::{Clock, TokioClock},
+    config::ClusterConfig,
+    domain::{
+        acceptor::{AcceptorLogic, AcceptorState},
+        ballot::BallotNumber,
+        consensus::record_decision,
+        id::{BrandedUuid, ProposalId},
+        message::{AcceptPhaseBody, LeaseBody, Message, PreVoteBody, PreparePhaseBody},
+        proposal::Proposal,
+        quorum::Phase,
+    },
+    rate_limit::RateLimiter,
+    repository::AcceptorRepository,
+    telemetry,
+    transport::{AcceptorTransport, Envelope},
 };
 
-impl Node {
+/// Node that promises and accepts the proposals broadcast by the proposers.
+///
+/// The decisions are made by its `AcceptorLogic`, an `AcceptorState` unless another
+/// one is given to `with_logic`: the actor only feeds it the requests received from
+/// the transport, and sends its replies back.
+pub struct Acceptor<L = AcceptorState> {
+    /// Identifier of the node.
+    // TODO: this should probably be an uuid, that will be stored in non-volatile
+    // memory to keep track of nodes, especially those thay may die and then restart.
+    pub id: u64,
+    /// Static view of the cluster this node belongs to.
+    pub cluster: ClusterConfig,
+    /// Interface to receive messages **from** the proposer and reply to it.
+    pub transport: Box<dyn AcceptorTransport>,
+    /// Promises and accepted proposal of this node.
+    pub state: L,
+    /// Where the state is saved before every reply, so that a restarted acceptor
+    /// does not break the promises of the previous one.
+    pub checkpoint: Option<Arc<Mutex<L>>>,
+    /// Where the state is saved before every reply as well, so that it also
+    /// survives restarts of the process. A reply is not sent if it can not be saved.
+    pub repository: Option<Arc<dyn AcceptorRepository<L>>>,
+    /// Whether the proposals accepted by this node are also gossiped to the other
+    /// acceptors. When every acceptor does, each of them learns the decisions on its
+    /// own, without the proposer relaying them.
+    pub gossip: bool,
+    /// Acceptors known to have accepted each proposal, by slot and ballot, as
+    /// gossiped. Forgotten once the slot is decided.
+    pub accepts: HashMap<(u64, BallotNumber), HashSet<u64>>,
+    /// Proposals decided for each slot, as counted from the gossip.
+    pub decided: BTreeMap<u64, Proposal>,
+    /// Lease granted to a proposer, as its id and when the lease expires. Until
+    /// then, the prepare and lease requests of the other proposers are ignored.
+    /// It is not checkpointed: a restarted acceptor forgets it.
+    pub lease: Option<(u64, Instant)>,
+    /// Clock the leases expire on, and the prepare rate limit is measured in.
+    pub clock: Arc<dyn Clock>,
+    /// Prepare requests answered for every proposer, taken from
+    /// `ClusterConfig::prepare_rate_limit`. Those beyond it are dropped.
+    pub prepare_limiter: Option<RateLimiter>,
+}
+
+impl Acceptor {
+    pub fn new(
+        id: u64,
+        cluster: ClusterConfig,
+        transport: Box<dyn AcceptorTransport>,
+    ) -> Self {
+        Self::with_logic(id, cluster, transport, AcceptorState::new(id))
+    }
+
+    /// Start with a promise and an accepted proposal, as if the node had already
+    /// taken part in earlier rounds, e.g. to model one that crashed after
+    /// accepting. `promised` is the ballot and id of the proposal last promised,
+    /// `accepted` the slot and proposal last accepted.
+    pub fn with_state(
+        mut self,
+        promised: Option<(BallotNumber, ProposalId)>,
+        accepted: Option<(u64, Proposal)>,
+    ) -> Self {
+        self.state.promised = promised;
+        self.state.accepted = accepted.into_iter().collect();
+        self
+    }
+}
+
+impl<L: AcceptorLogic + Clone> Acceptor<L> {
+    /// Acceptor answering requests with `logic` rather than an `AcceptorState`.
+    pub fn with_logic(
+        id: u64,
+        cluster: ClusterConfig,
+        transport: Box<dyn AcceptorTransport>,
+        logic: L,
+    ) -> Self {
+        let prepare_limiter = cluster.prepare_rate_limit.map(RateLimiter::new);
+        Self {
+            id,
+            cluster,
+            transport,
+            state: logic,
+            checkpoint: None,
+            repository: None,
+            gossip: false,
+            accepts: HashMap::new(),
+            decided: BTreeMap::new(),
+            lease: None,
+            clock: Arc::new(TokioClock),
+            prepare_limiter,
+        }
+    }
+
+    /// Value decided for `slot`, if a quorum of the acceptors was seen accepting
+    /// it. Only known when the acceptors gossip their accepts.
+    pub fn decided_value(&self, slot: u64) -> Option<u64> {
+        self.decided.get(&slot).map(|proposal| proposal.value)
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn run(&mut self) -> Result<()> {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Answer requests until `shutdown` resolves. A request being answered is not
+    /// interrupted.
+    #[tracing::instrument(skip_all, fields(node_id = self.id))]
+    pub async fn run_until(
+        &mut self,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()> {
+        self.cluster.bootstrap(self.id).await?;
+        tokio::pin!(shutdown);
+
+        // It has to be a infinite loop because otherwise, acceptors are dropped after
+        // receiving the first message and the channel closes.
+        loop {
+            let envelope = tokio::select! {
+                _ = &mut shutdown => {
+                    debug!("acceptor stopped");
+                    return Ok(());
+                },
+                envelope = self.transport.recv() => envelope,
+            };
+            // Every proposer is gone: there is nothing left to answer.
+            let Some(Envelope {
+                from,
+                message: received_message,
+            }) = envelope
+            else {
+                debug!("transport closed, acceptor stopped");
+                return Ok(());
+            };
+
+            // A proposer may stop while its requests are being answered, so the
+            // reply not making it is not fatal.
+            let replied = match received_message {
+                Message::PrepareRequest { body } if self.is_throttled(from) => {
+                    debug!(
+                        from,
+                        slot = body.slot,
+                        "dropping throttled prepare request"
+                    );
+                    Ok(())
+                }
+                Message::PrepareRequest { body } if self.is_leased_to_other(from) => {
+                    debug!(
+                        from,
+                        slot = body.slot,
+                        "ignoring prepare request during the lease of another proposer"
+                    );
+                    Ok(())
+                }
+                Message::PrepareRequest { body } => {
+                    self.reply_prepare_request(from, body).await
+                }
+                Message::AcceptRequest { body } => {
+                    self.reply_accept_request(from, body).await
+                }
+                Message::PreVoteRequest { body } => {
+                    self.reply_pre_vote_request(from, body).await
+                }
+                Message::LeaseRequest { body } => {
+                    self.reply_lease_request(from, body).await
+                }
+                // Like gossip, an abort is only trusted from the proposer it is about.
+                Message::AbortRound { body } if body.issuer_id == from => {
+                    debug!(from, ballot = %body.ballot, "round aborted");
+                    self.state.on_abort(body);
+                    Ok(())
+                }
+                Message::AcceptGossip { body } => {
+                    self.learn_gossip(from, body);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = replied {
+                warn!("could not reply: {e}");
+            }
+        }
+    }
+
     #[tracing::instrument(skip_all, fields(
         node_id = self.id,
+        epoch = received_proposal.epoch,
         proposal_id = received_proposal.proposal_id.formatted()
     ))]
+    /// Answer the prepare request received from the node `from`. Like every reply,
+    /// it goes to the node the request came from, whatever its body claims.
     pub async fn reply_prepare_request(
         &mut self,
+        from: u64,
         received_proposal: PreparePhaseBody,
     ) -> Result<()> {
         debug!("received proposal");
-
-        // Get latest value that is set to be accepted in this node.
-        if let Some(proposal_in_buffer) = self.buffer {
-            let up_to_date_proposal =
-                if proposal_in_buffer > received_proposal.proposal_id {
-                    debug!("proposal in node {} buffer is more updated", self.id);
-                    proposal_in_buffer
-                } else {
-                    debug!("proposal received in node {} is more updated", self.id);
-                    received_proposal.proposal_id
-                };
-            self.buffer = Some(up_to_date_proposal);
-
-            self.proposer_sender
-                .send(Message::PrepareResponse {
-                    body: PreparePhaseBody {
-                        issuer_id: self.id,
-                        proposal_id: up_to_date_proposal,
-                    },
-                })
-                .await
-                .map_err(anyhow::Error::from)
-
-        // This node has not set any value to be accepted, so according to the
-        // algorithm, we set the first value received to be accepted.
-        } else {
-            self.buffer = Some(received_proposal.proposal_id);
-
-            self.proposer_sender
-                .send(Message::PrepareResponse {
-                    body: PreparePhaseBody {
-                        issuer_id: self.id,
-                        proposal_id: received_proposal.proposal_id,
-                    },
-                })
-                .await
-                .map_err(anyhow::Error::from)
-        }
+        let reply = self.state.on_prepare(received_proposal);
+        self.reply(from, reply).await
     }
 
     /// If the value is accepted:
-    ///  - reply to the proposer with an ACK message
-    ///  - send the accepted value to the learner
-    /// If the value is not accepted, simply ignore the message received and do nothing.
+    ///  - reply to `from`, the proposer, with an ACK message
+    ///  - gossip the ACK to the other acceptors, if `gossip` is on
+    /// If the value is not accepted, reply with the reason of the rejection.
     #[tracing::instrument(skip_all, fields(
         node_id = self.id,
+        epoch = received_proposal.epoch,
         proposal_id = received_proposal.proposal_id.formatted()
     ))]
     pub async fn reply_accept_request(
         &mut self,
+        from: u64,
         received_proposal: AcceptPhaseBody,
     ) -> Result<()> {
-        let accept_response = AcceptPhaseBody {
-            issuer_id: self.id,
-            ..received_proposal
+        debug!("received accept request");
+        let reply = self.state.on_accept(received_proposal);
+        let accepted = match &reply {
+            Some(Message::AcceptResponse { body }) if self.gossip => Some(body.clone()),
+            _ => None,
         };
-        if let Some(proposal_in_buffer) = self.buffer {
-            debug!("received accept request");
-            // Do not accept the value if the one in buffer is more updated.
-            if proposal_in_buffer > received_proposal.proposal_id {
-                Ok(())
-            } else {
-                // The value received is more up-to-date than the one we have stored in
-                // the buffer. **Accept** the proposal (answer the proposer and
-                // send the accepted value to learners)
-                // Clear the buffer after accepting the value.
-                self.buffer = None;
-
-                self.proposer_sender
-                    .send(Message::AcceptResponse {
-                        body: accept_response,
-                    })
-                    .await
-                    .map_err(anyhow::Error::from)?;
-
-                debug!("node is ready for the next decree");
-                Ok(())
-            }
+        self.reply(from, reply).await?;
+        if let Some(accepted) = accepted {
+            self.gossip_accept(accepted).await;
+        }
+
+        debug!("node is ready for the next decree");
+        Ok(())
+    }
 
-        // This node has not set any value to be accepted, so according to the
-        // algorithm, we accept it. There is no need to clear the buffer because it
-        // is already empty.
+    #[tracing::instrument(skip_all, fields(
+        node_id = self.id,
+        epoch = pre_vote.epoch,
+        slot = pre_vote.slot
+    ))]
+    pub async fn reply_pre_vote_request(
+        &mut self,
+        from: u64,
+        pre_vote: PreVoteBody,
+    ) -> Result<()> {
+        debug!(ballot = %pre_vote.ballot, "received pre-vote request");
+        let reply = self.state.on_pre_vote(pre_vote);
+        self.reply(from, reply).await
+    }
+
+    /// Grant the lease if no other proposer holds one and the logic promises its
+    /// ballot. A lease being renewed by its holder is extended.
+    #[tracing::instrument(skip_all, fields(
+        node_id = self.id,
+        epoch = request.epoch,
+        proposal_id = request.proposal_id.formatted()
+    ))]
+    pub async fn reply_lease_request(
+        &mut self,
+        from: u64,
+        request: LeaseBody,
+    ) -> Result<()> {
+        debug!(ballot = %request.ballot, "received lease request");
+        let duration = request.duration;
+        let reply = if self.is_leased_to_other(from) {
+            Some(Message::LeaseResponse {
+                body: LeaseBody {
+                    issuer_id: self.id,
+                    granted: false,
+                    ..request
+                },
+            })
         } else {
-            self.proposer_sender
-                .send(Message::AcceptResponse {
-                    body: accept_response,
+            self.state.on_lease(request)
+        };
+        if let Some(Message::LeaseResponse { body }) = &reply {
+            if body.granted {
+                self.lease = Some((from, self.clock.now() + duration));
+                debug!(holder = from, ?duration, "lease granted");
+            }
+        }
+        self.reply(from, reply).await
+    }
+
+    /// Whether a prepare request of `node_id` exceeds the prepare rate limit, if
+    /// any. Throttling is logged once for every run of requests dropped.
+    fn is_throttled(&mut self, node_id: u64) -> bool {
+        let now = self.clock.now();
+        let Some(limiter) = &mut self.prepare_limiter else {
+            return false;
+        };
+        if limiter.try_acquire(node_id, now) {
+            return false;
+        }
+
+        telemetry::count(telemetry::PREPARES_THROTTLED, self.id, 1);
+        if limiter.throttled(node_id) == 1 {
+            let limit = limiter.limit();
+            warn!(
+                from = node_id,
+                per_second = limit.per_second,
+                burst = limit.burst,
+                "throttling the prepare requests of a proposer"
+            );
+        }
+        true
+    }
+
+    /// Whether a proposer other than `node_id` holds a lease that has not expired.
+    fn is_leased_to_other(&self, node_id: u64) -> bool {
+        self.lease.is_some_and(|(holder, expires_at)| {
+            holder != node_id && self.clock.now() < expires_at
+        })
+    }
+
+    /// Count the accept of this node, and tell the other acceptors about it. Those
+    /// that can not be reached just learn the decision later, or from others.
+    async fn gossip_accept(&mut self, accepted: AcceptPhaseBody) {
+        self.count_accept(self.id, &accepted);
+        let peers: Vec<u64> = self
+            .cluster
+            .acceptors()
+            .map(|peer| peer.node_id)
+            .filter(|&node_id| node_id != self.id)
+            .collect();
+        for peer in peers {
+            let gossip = Message::AcceptGossip {
+                body: accepted.clone(),
+            };
+            if let Err(e) = self.transport.send(peer, gossip).await {
+                debug!(peer, "could not gossip accept: {e}");
+            }
+        }
+    }
+
+    /// Count the accept gossiped by the acceptor `from`.
+    fn learn_gossip(&mut self, from: u64, accepted: AcceptPhaseBody) {
+        // Like a proposer, only trust the issuer id if it matches the sender, so
+        // that an acceptor can not vote on behalf of another one.
+        if accepted.issuer_id != from {
+            warn!(
+                from,
+                issuer_id = accepted.issuer_id,
+                "dropping gossip with spoofed issuer id"
+            );
+            return;
+        }
+        if accepted.epoch < self.state.epoch() {
+            debug!(from, epoch = accepted.epoch, "ignoring stale gossip");
+            return;
+        }
+        self.count_accept(from, &accepted);
+    }
+
+    /// Record that `acceptor` accepted the proposal, and whether this makes it
+    /// decided. Quorums are counted among the acceptors of the static cluster.
+    fn count_accept(&mut self, acceptor: u64, accepted: &AcceptPhaseBody) {
+        let slot = accepted.slot;
+        if self.decided.contains_key(&slot) {
+            return;
+        }
+
+        let voters = self.accepts.entry((slot, accepted.ballot)).or_default();
+        voters.insert(acceptor);
+        if !self
+            .cluster
+            .quorum_tracker(Phase::Accept)
+            .is_reached(voters)
+        {
+            return;
+        }
+
+        let proposal =
+            Proposal::new(accepted.value, accepted.proposal_id, accepted.ballot);
+        match record_decision(&mut self.decided, slot, proposal) {
+            Ok(_) => {
+                info!(
+                    slot,
+                    value = self.cluster.log_values.then_some(accepted.value),
+                    proposal_id = accepted.proposal_id.formatted(),
+                    "decision learned from gossip"
+                );
+                self.state.on_decided(slot, proposal);
+                self.checkpoint();
+            }
+            Err(e) => {
+                telemetry::count(telemetry::CONSENSUS_VIOLATIONS, self.id, 1);
+                error!(proposal_id = accepted.proposal_id.formatted(), "{e}");
+            }
+        }
+        self.accepts
+            .retain(|(voted_slot, _), _| *voted_slot != slot);
+    }
+
+    /// Save the state in `checkpoint`, if any.
+    fn checkpoint(&self) {
+        if let Some(checkpoint) = &self.checkpoint {
+            *checkpoint
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = self.state.clone();
+        }
+    }
+
+    /// Send the reply of the state machine, if any, to `to`, the node the request
+    /// came from.
+    async fn reply(&self, to: u64, reply: Option<Message>) -> Result<()> {
+        self.checkpoint();
+        if let Some(repository) = &self.repository {
+            repository
+                .save(&self.state)
+                .context("could not save the state of the acceptor")?;
+        }
+
+        let Some(reply) = reply else {
+            debug!(current_epoch = self.state.epoch(), "ignoring stale epoch");
+            return Ok(());
+        };
+
+        match &reply {
+            Message::PrepareResponse { .. } => {
+                telemetry::count(telemetry::PROMISES_SENT, self.id, 1)
+            }
+            Message::AcceptResponse { .. } => {
+                telemetry::count(telemetry::PROPOSALS_ACCEPTED, self.id, 1)
+            }
+            Message::PrepareReject { body } => {
+                telemetry::count(telemetry::REQUESTS_REJECTED, self.id, 1);
+                info!(reason = ?body.reason, "rejecting prepare request")
+            }
+            Message::AcceptReject { body } => {
+                telemetry::count(telemetry::REQUESTS_REJECTED, self.id, 1);
+                info!(reason = ?body.reason, "rejecting accept request")
+            }
+            _ => (),
+        }
+
+        self.transport.send(to, reply).await
+    }
+}
+
+impl<L> Drop for Acceptor<L> {
+    fn drop(&mut self) {
+        println!("Acceptor dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        clock::ManualClock, domain::message::RejectReason, rate_limit::RateLimit,
+        repository::AcceptorRepositoryImpl,
+    };
+
+    /// Node the requests of the tests come from.
+    const PROPOSER: u64 = 3;
+
+    /// Transport fed by hand, recording what is sent through it.
+    struct MockTransport {
+        inbox: mpsc::UnboundedReceiver<Envelope>,
+        sent: mpsc::UnboundedSender<(u64, Message)>,
+    }
+
+    #[async_trait::async_trait]
+    impl AcceptorTransport for MockTransport {
+        async fn send(&self, to: u64, message: Message) -> Result<()> {
+            Ok(self.sent.send((to, message))?)
+        }
+
+        async fn recv(&mut self) -> Option<Envelope> {
+            self.inbox.recv().await
+        }
+    }
+
+    /// Acceptor 0 of a cluster of three, running on a `MockTransport`, starting
+    /// from the state saved in `repository` if any. Returns where to deliver its
+    /// requests, and what it sends.
+    fn spawn_acceptor(
+        repository: Option<Arc<AcceptorRepositoryImpl>>,
+    ) -> (
+        mpsc::UnboundedSender<Envelope>,
+        mpsc::UnboundedReceiver<(u64, Message)>,
+    ) {
+        let (requests, inbox) = mpsc::unbounded_channel();
+        let (sent, replies) = mpsc::unbounded_channel();
+        let mut acceptor = Acceptor::new(
+            0,
+            ClusterConfig::in_process(3),
+            Box::new(MockTransport { inbox, sent }),
+        );
+        if let Some(repository) = repository {
+            if let Some(state) = repository.load().unwrap() {
+                acceptor.state = state;
+            }
+            acceptor.repository = Some(repository);
+        }
+        tokio::spawn(async move { acceptor.run().await });
+        (requests, replies)
+    }
+
+    fn prepare(round: u64, issuer_id: u64) -> Envelope {
+        Envelope {
+            from: PROPOSER,
+            message: Message::PrepareRequest {
+                body: PreparePhaseBody {
+                    issuer_id,
+                    epoch: 0,
+                    slot: 0,
+                    proposal_id: ProposalId(Uuid::from_u128(u128::from(round))),
+                    ballot: BallotNumber::new(round, PROPOSER),
+                    accepted: None,
+                    nonce: 0,
+                    sent_at: None,
+                },
+            },
+        }
+    }
+
+    fn accept(round: u64, value: u64) -> Envelope {
+        Envelope {
+            from: PROPOSER,
+            message: Message::AcceptRequest {
+                body: AcceptPhaseBody {
+                    issuer_id: PROPOSER,
+                    epoch: 0,
+                    slot: 0,
+                    proposal_id: ProposalId(Uuid::from_u128(u128::from(round))),
+                    ballot: BallotNumber::new(round, PROPOSER),
+                    value,
+                    nonce: 0,
+                    sent_at: None,
+                },
+            },
+        }
+    }
+
+    async fn next_reply(
+        replies: &mut mpsc::UnboundedReceiver<(u64, Message)>,
+    ) -> (u64, Message) {
+        tokio::time::timeout(Duration::from_secs(1), replies.recv())
+            .await
+            .expect("no reply in time")
+            .expect("acceptor stopped")
+    }
+
+    #[tokio::test]
+    async fn replies_go_to_the_node_the_request_came_from() {
+        let (requests, mut replies) = spawn_acceptor(None);
+        // The body claims to come from another node than the envelope.
+        let claimed = 1;
+        let ballot = BallotNumber::new(1, PROPOSER);
+        let proposal_id = ProposalId(Uuid::from_u128(1));
+
+        requests.send(prepare(1, claimed)).unwrap();
+        let (to, promise) = next_reply(&mut replies).await;
+        assert_eq!(to, PROPOSER);
+        assert!(
+            matches!(promise, Message::PrepareResponse { .. }),
+            "{promise}"
+        );
+
+        let accept = AcceptPhaseBody {
+            issuer_id: claimed,
+            epoch: 0,
+            slot: 0,
+            proposal_id,
+            ballot,
+            value: 7,
+            nonce: 0,
+            sent_at: None,
+        };
+        requests
+            .send(Envelope {
+                from: PROPOSER,
+                message: Message::AcceptRequest { body: accept },
+            })
+            .unwrap();
+        let (to, accepted) = next_reply(&mut replies).await;
+        assert_eq!(to, PROPOSER);
+        assert!(
+            matches!(&accepted, Message::AcceptResponse { body } if body.value == 7),
+            "{accepted}"
+        );
+    }
+
+    #[tokio::test]
+    async fn promise_survives_a_restart_of_the_process() {
+        let path = std::env::temp_dir()
+            .join(format!("paxos-acceptor-{}.sqlite", Uuid::now_v7().simple()));
+        let repository = Arc::new(AcceptorRepositoryImpl::open(&path).unwrap());
+        let (requests, mut replies) = spawn_acceptor(Some(repository));
+        requests.send(prepare(5, PROPOSER)).unwrap();
+        let (_, promise) = next_reply(&mut replies).await;
+        assert!(
+            matches!(promise, Message::PrepareResponse { .. }),
+            "{promise}"
+        );
+        drop(requests);
+
+        // Nothing is kept in memory: the new acceptor only has the database.
+        let repository = Arc::new(AcceptorRepositoryImpl::open(&path).unwrap());
+        let (requests, mut replies) = spawn_acceptor(Some(repository));
+        requests.send(prepare(3, PROPOSER)).unwrap();
+        let (_, reply) = next_reply(&mut replies).await;
+        assert!(matches!(reply, Message::PrepareReject { .. }), "{reply}");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn lower_ballot_is_rejected_once_the_slot_is_decided() {
+        let (requests, mut replies) = spawn_acceptor(None);
+        // Acceptors 1 and 2, a quorum, gossip that they accepted a proposal.
+        for acceptor in [1, 2] {
+            requests
+                .send(Envelope {
+                    from: acceptor,
+                    message: Message::AcceptGossip {
+                        body: AcceptPhaseBody {
+                            issuer_id: acceptor,
+                            epoch: 0,
+                            slot: 0,
+                            proposal_id: ProposalId(Uuid::from_u128(5)),
+                            ballot: BallotNumber::new(5, 4),
+                            value: 50,
+                            nonce: 0,
+                            sent_at: None,
+                        },
+                    },
                 })
-                .await
-                .map_err(anyhow::Error::from)
+                .unwrap();
+        }
+
+        requests.send(prepare(3, PROPOSER)).unwrap();
+        let (to, reply) = next_reply(&mut replies).await;
+        assert_eq!(to, PROPOSER);
+        assert!(
+            matches!(
+                &reply,
+                Message::PrepareReject { body } if matches!(
+                    body.reason,
+                    RejectReason::AlreadyDecided { value: 50, .. }
+                )
+            ),
+            "{reply}"
+        );
+    }
+
+    #[tokio::test]
+    async fn accepted_value_is_reported_to_the_next_prepare() {
+        let (requests, mut replies) = spawn_acceptor(None);
+
+        requests.send(prepare(1, PROPOSER)).unwrap();
+        let (_, promise) = next_reply(&mut replies).await;
+        assert!(
+            matches!(&promise, Message::PrepareResponse { body } if body.accepted.is_none()),
+            "{promise}"
+        );
+        requests.send(accept(1, 7)).unwrap();
+        let (_, accepted) = next_reply(&mut replies).await;
+        assert!(
+            matches!(&accepted, Message::AcceptResponse { body } if body.value == 7),
+            "{accepted}"
+        );
+
+        requests.send(prepare(2, PROPOSER)).unwrap();
+        let (_, promise) = next_reply(&mut replies).await;
+        assert!(
+            matches!(
+                &promise,
+                Message::PrepareResponse { body } if body.accepted.as_ref().is_some_and(
+                    |proposal| proposal.value == 7
+                        && proposal.ballot == BallotNumber::new(1, PROPOSER)
+                )
+            ),
+            "{promise}"
+        );
+        // The accept of the older ballot comes too late.
+        requests.send(accept(1, 8)).unwrap();
+        let (_, reply) = next_reply(&mut replies).await;
+        assert!(matches!(reply, Message::AcceptReject { .. }), "{reply}");
+    }
+
+    #[tokio::test]
+    async fn seeded_acceptor_reports_its_prior_accepted_value() {
+        let (requests, inbox) = mpsc::unbounded_channel();
+        let (sent, mut replies) = mpsc::unbounded_channel();
+        let ballot = BallotNumber::new(2, 9);
+        let proposal_id = ProposalId(Uuid::from_u128(2));
+        let mut acceptor = Acceptor::new(
+            0,
+            ClusterConfig::in_process(3),
+            Box::new(MockTransport { inbox, sent }),
+        )
+        .with_state(
+            Some((ballot, proposal_id)),
+            Some((0, Proposal::new(42, proposal_id, ballot))),
+        );
+        tokio::spawn(async move { acceptor.run().await });
+
+        // Below the seeded promise.
+        requests.send(prepare(1, PROPOSER)).unwrap();
+        let (_, reply) = next_reply(&mut replies).await;
+        assert!(matches!(reply, Message::PrepareReject { .. }), "{reply}");
+
+        requests.send(prepare(3, PROPOSER)).unwrap();
+        let (_, promise) = next_reply(&mut replies).await;
+        assert!(
+            matches!(
+                &promise,
+                Message::PrepareResponse { body } if body.accepted.as_ref().is_some_and(
+                    |proposal| proposal.value == 42 && proposal.ballot == ballot
+                )
+            ),
+            "{promise}"
+        );
+    }
+
+    #[tokio::test]
+    async fn prepares_past_the_rate_limit_are_dropped_while_accepts_proceed() {
+        let (requests, inbox) = mpsc::unbounded_channel();
+        let (sent, mut replies) = mpsc::unbounded_channel();
+        let mut cluster = ClusterConfig::in_process(3);
+        cluster.prepare_rate_limit = Some(RateLimit {
+            per_second: 1,
+            burst: 3,
+        });
+        let clock = Arc::new(ManualClock::new());
+        let mut acceptor =
+            Acceptor::new(0, cluster, Box::new(MockTransport { inbox, sent }));
+        acceptor.clock = clock.clone();
+        tokio::spawn(async move { acceptor.run().await });
+
+        for round in 1..=10 {
+            requests.send(prepare(round, PROPOSER)).unwrap();
         }
+        for round in 11..=15 {
+            requests.send(accept(round, round)).unwrap();
+        }
+        let mut promised = Vec::new();
+        let mut accepted = Vec::new();
+        for _ in 0..8 {
+            match next_reply(&mut replies).await.1 {
+                Message::PrepareResponse { body } => promised.push(body.ballot.round),
+                Message::AcceptResponse { body } => accepted.push(body.value),
+                reply => panic!("unexpected reply {reply}"),
+            }
+        }
+        assert_eq!(promised, [1, 2, 3]);
+        assert_eq!(accepted, [11, 12, 13, 14, 15]);
+        assert!(replies.try_recv().is_err(), "throttled prepare answered");
+
+        // A second later, the bucket of the proposer has a token again.
+        clock.advance(Duration::from_secs(1));
+        requests.send(prepare(20, PROPOSER)).unwrap();
+        let (_, promise) = next_reply(&mut replies).await;
+        assert!(
+            matches!(promise, Message::PrepareResponse { .. }),
+            "{promise}"
+        );
     }
 }
@@ -0,0 +1,132 @@
+use tokio::sync::{broadcast, watch};
+use tracing::debug;
+
+use super::learner::Decision;
+use crate::domain::{command::Command, id::ProposalId};
+
+/// Read-only replica of the decided state. It follows the decisions of a learner
+/// and answers reads from its own cache, without ever taking part in a quorum, so
+/// that reads do not load the proposer.
+///
+/// The cache only holds the value of the highest slot decided so far. It may lag
+/// behind the learner, but never goes back to an older slot.
+#[derive(Clone)]
+pub struct Follower {
+    latest: watch::Receiver<Option<(u64, ProposalId, u64)>>,
+}
+
+impl Follower {
+    /// Follow `decisions`, from the next one on. Must be called from within a tokio
+    /// runtime: the cache is kept up to date by a task, which stops along with the
+    /// learner.
+    pub fn spawn(mut decisions: broadcast::Receiver<Decision>) -> Self {
+        let (sender, latest) = watch::channel(None);
+        tokio::spawn(async move {
+            loop {
+                let decision = match decisions.recv().await {
+                    Ok(decision) => decision,
+                    // Only the latest decision matters, and newer ones are coming.
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        debug!(missed, "follower lagged behind the decisions");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                // Commands of the cluster are not part of the state the follower
+                // serves.
+                if !matches!(
+                    Command::decode(decision.proposal.value),
+                    Command::Value(_)
+                ) {
+                    continue;
+                }
+
+                sender.send_if_modified(|latest| {
+                    if latest.is_some_and(|(slot, _, _)| slot >= decision.slot) {
+                        return false;
+                    }
+                    *latest = Some((
+                        decision.slot,
+                        decision.proposal.id,
+                        decision.proposal.value,
+                    ));
+                    true
+                });
+            }
+        });
+
+        Self { latest }
+    }
+
+    /// Id and value of the proposal decided for the highest slot observed so far.
+    pub fn get(&self) -> Option<(ProposalId, u64)> {
+        self.latest.borrow().map(|(_, id, value)| (id, value))
+    }
+
+    /// Slot of the value returned by `get`.
+    pub fn slot(&self) -> Option<u64> {
+        self.latest.borrow().map(|(slot, _, _)| slot)
+    }
+
+    /// Resolve once a value for a slot at least as high as `slot` was observed.
+    /// Returns `None` if the learner stopped before that.
+    pub async fn wait_for_slot(&mut self, slot: u64) -> Option<(ProposalId, u64)> {
+        self.latest
+            .wait_for(|latest| latest.is_some_and(|(decided, _, _)| decided >= slot))
+            .await
+            .ok()?
+            .map(|(_, id, value)| (id, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        domain::{ballot::BallotNumber, command::Command, proposal::Proposal},
+        testkit::spawn_cluster,
+    };
+
+    fn decision(slot: u64, value: u64) -> Decision {
+        Decision {
+            slot,
+            proposal: Proposal::new(
+                value,
+                ProposalId(Uuid::from_u128(u128::from(slot))),
+                BallotNumber::new(1, 3),
+            ),
+            quorum: vec![0, 1],
+        }
+    }
+
+    #[tokio::test]
+    async fn follower_serves_the_value_decided_by_the_cluster() {
+        let handle = spawn_cluster(3);
+        let mut follower = Follower::spawn(handle.learner.subscribe());
+        assert_eq!(follower.get(), None);
+
+        handle.propose(7).await.unwrap();
+
+        let decided = handle.learner.decision(0).unwrap();
+        assert_eq!(follower.wait_for_slot(0).await, Some((decided.id, 7)));
+        assert_eq!(follower.get(), Some((decided.id, 7)));
+    }
+
+    #[tokio::test]
+    async fn follower_keeps_the_value_of_the_highest_slot() {
+        let (decisions, receiver) = broadcast::channel(8);
+        let mut follower = Follower::spawn(receiver);
+
+        decisions.send(decision(2, 20)).unwrap();
+        decisions.send(decision(1, 10)).unwrap();
+        decisions.send(decision(3, Command::Noop.encode())).unwrap();
+        // Once the learner stops, every decision sent was observed.
+        drop(decisions);
+        assert_eq!(follower.wait_for_slot(u64::MAX).await, None);
+
+        assert_eq!(follower.slot(), Some(2));
+        assert_eq!(follower.get().map(|(_, value)| value), Some(20));
+    }
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{broadcast, Notify};
+
+use crate::{
+    audit::{AuditRecord, AuditWriter, DecisionLog},
+    domain::{
+        ballot::BallotNumber,
+        command::Command,
+        consensus::{record_decision, ConsensusViolation},
+        proposal::Proposal,
+    },
+};
+
+/// How many decisions a slow subscriber can lag behind before missing some.
+const DECISIONS_CAPACITY: usize = 1024;
+
+/// How many of the latest decisions the decision log keeps.
+pub const DECISION_LOG_CAPACITY: usize = 1024;
+
+/// Value chosen for a slot.
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub slot: u64,
+    pub proposal: Proposal,
+    /// Acceptors whose votes formed the quorum, sorted by id.
+    pub quorum: Vec<u64>,
+}
+
+/// Entry of the decision log, for an audit trail of what the cluster decided.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct DecisionRecord {
+    pub slot: u64,
+    pub ballot: BallotNumber,
+    pub value: u64,
+    /// Acceptors whose votes formed the quorum, sorted by id.
+    pub acceptors: Vec<u64>,
+}
+
+/// Record of the values chosen for each slot. The proposer plays the role of the
+/// learner, so it is the one recording decisions; the handle can be cloned and
+/// shared with anyone interested in them.
+#[derive(Clone)]
+pub struct Learner {
+    state: Arc<Mutex<LearnerState>>,
+    /// Stream of decisions, in the order they are recorded.
+    decisions: broadcast::Sender<Decision>,
+}
+
+#[derive(Default)]
+struct LearnerState {
+    decided: BTreeMap<u64, Proposal>,
+    /// Tasks waiting for the decision of a slot that is not decided yet.
+    waiters: HashMap<u64, Arc<Notify>>,
+    /// The latest decisions, in the order they were recorded.
+    log: VecDeque<DecisionRecord>,
+    /// File every decision is also written to, if any, by a thread of its own.
+    audit: Option<AuditWriter>,
+}
+
+impl Default for Learner {
+    fn default() -> Self {
+        let (decisions, _) = broadcast::channel(DECISIONS_CAPACITY);
+        Self {
+            state: Arc::default(),
+            decisions,
+        }
+    }
+}
+
+impl Learner {
+    /// Record the decision for `slot`, reached by the votes of the acceptors in
+    /// `quorum`, waking up whoever is waiting for it. Returns whether the slot was
+    /// newly decided: a slot is only decided once, and a decision for a slot
+    /// already decided with another value is an error, leaving the slot as is.
+    pub fn record(
+        &self,
+        slot: u64,
+        proposal: Proposal,
+        mut quorum: Vec<u64>,
+    ) -> Result<bool, ConsensusViolation> {
+        let mut state = self.state.lock().expect("learner lock poisoned");
+        if !record_decision(&mut state.decided, slot, proposal)? {
+            return Ok(false);
+        }
+
+        if let Some(waiters) = state.waiters.remove(&slot) {
+            waiters.notify_waiters();
+        }
+        quorum.sort_unstable();
+        if state.log.len() == DECISION_LOG_CAPACITY {
+            state.log.pop_front();
+        }
+        state.log.push_back(DecisionRecord {
+            slot,
+            ballot: proposal.ballot,
+            value: proposal.value,
+            acceptors: quorum.clone(),
+        });
+        if let Some(audit) = &state.audit {
+            audit.append(AuditRecord {
+                slot,
+                proposal_id: proposal.id,
+                value: proposal.value,
+                decided_at: chrono::Utc::now(),
+            });
+        }
+        // Nobody may be listening, which is fine.
+        let _ = self.decisions.send(Decision {
+            slot,
+            proposal,
+            quorum,
+        });
+        Ok(true)
+    }
+
+    /// Write every decision recorded from now on to `log` as well, in place of the
+    /// previous audit log if any. Decisions are written by a thread of their own:
+    /// recording one does not wait for the disk.
+    pub fn set_audit_log(&self, log: DecisionLog) -> anyhow::Result<()> {
+        let writer = AuditWriter::spawn(log)?;
+        self.state.lock().expect("learner lock poisoned").audit = Some(writer);
+        Ok(())
+    }
+
+    /// Receive every decision recorded from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Decision> {
+        self.decisions.subscribe()
+    }
+
+    /// The latest `DECISION_LOG_CAPACITY` decisions, oldest first, in the order
+    /// they were recorded. Unlike `subscribe`, this can not lag behind: older
+    /// records are dropped instead.
+    pub fn decision_log(&self) -> Vec<DecisionRecord> {
+        let state = self.state.lock().expect("learner lock poisoned");
+        state.log.iter().cloned().collect()
+    }
+
+    pub fn decision(&self, slot: u64) -> Option<Proposal> {
+        self.state
+            .lock()
+            .expect("learner lock poisoned")
+            .decided
+            .get(&slot)
+            .copied()
+    }
+
+    /// Highest slot decided so far. Slots below it may not all be decided, see
+    /// `gaps`.
+    pub fn highest_slot(&self) -> Option<u64> {
+        let state = self.state.lock().expect("learner lock poisoned");
+        state.decided.keys().next_back().copied()
+    }
+
+    /// Proposals decided for the slots of `slots`, in increasing order of slot. Up
+    /// to `limit` of them, the lowest slots first.
+    pub fn decided_range(
+        &self,
+        slots: RangeInclusive<u64>,
+        limit: usize,
+    ) -> Vec<(u64, Proposal)> {
+        let state = self.state.lock().expect("learner lock poisoned");
+        state
+            .decided
+            .range(slots)
+            .take(limit)
+            .map(|(&slot, &proposal)| (slot, proposal))
+            .collect()
+    }
+
+    /// Value of a client decided for the highest slot, the commands of the cluster
+    /// aside. `None` if no value of a client was decided yet.
+    pub fn latest_value(&self) -> Option<u64> {
+        let state = self.state.lock().expect("learner lock poisoned");
+        state.decided.values().rev().find_map(|proposal| {
+            match Command::decode(proposal.value) {
+                Command::Value(value) => Some(value),
+                _ => None,
+            }
+        })
+    }
+
+    /// Slots below the highest decided one that are not decided yet, in increasing
+    /// order.
+    pub fn gaps(&self) -> Vec<u64> {
+        let state = self.state.lock().expect("learner lock poisoned");
+        let Some(&highest) = state.decided.keys().next_back() else {
+            return Vec::new();
+        };
+        (0..highest)
+            .filter(|slot| !state.decided.contains_key(slot))
+            .collect()
+    }
+
+    /// Resolve once `slot` is decided, immediately if it already is.
+    pub async fn wait_for_decision(&self, slot: u64) -> Proposal {
+        loop {
+            let notify = {
+                let mut state = self.state.lock().expect("learner lock poisoned");
+                if let Some(proposal) = state.decided.get(&slot) {
+                    return *proposal;
+                }
+                state.waiters.entry(slot).or_default().clone()
+            };
+
+            // Register as a waiter before checking again, so that a decision
+            // recorded in between is not missed.
+            let notified = notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(proposal) = self.decision(slot) {
+                return proposal;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::domain::id::ProposalId;
+
+    fn proposal(value: u64) -> Proposal {
+        Proposal::new(
+            value,
+            ProposalId(Uuid::from_u128(u128::from(value))),
+            BallotNumber::new(1, 0),
+        )
+    }
+
+    #[tokio::test]
+    async fn waiter_of_a_decided_slot_resolves_right_away() {
+        let learner = Learner::default();
+        learner.record(3, proposal(30), vec![0, 1]).unwrap();
+
+        let decided = tokio::time::timeout(
+            Duration::from_millis(100),
+            learner.wait_for_decision(3),
+        )
+        .await
+        .expect("waiter not resolved");
+
+        assert_eq!(decided, proposal(30));
+    }
+
+    #[tokio::test]
+    async fn waiter_only_resolves_for_its_own_slot() {
+        let learner = Learner::default();
+        let waiter = tokio::spawn({
+            let learner = learner.clone();
+            async move { learner.wait_for_decision(2).await }
+        });
+
+        learner.record(1, proposal(10), vec![0, 1]).unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        learner.record(2, proposal(20), vec![0, 1]).unwrap();
+        assert_eq!(waiter.await.unwrap(), proposal(20));
+    }
+
+    #[test]
+    fn decisions_are_logged_in_the_order_they_are_recorded() {
+        let learner = Learner::default();
+        for (slot, value) in [(0, 10), (2, 30), (1, 20)] {
+            learner.record(slot, proposal(value), vec![2, 0]).unwrap();
+        }
+        // Already decided: not logged again.
+        learner.record(0, proposal(10), vec![1, 2]).unwrap();
+
+        let log = learner.decision_log();
+
+        let logged: Vec<_> = log
+            .iter()
+            .map(|record| (record.slot, record.value))
+            .collect();
+        assert_eq!(logged, [(0, 10), (2, 30), (1, 20)]);
+        assert!(log.iter().all(|record| record.acceptors == [0, 2]
+            && record.ballot == BallotNumber::new(1, 0)));
+    }
+
+    #[test]
+    fn decision_log_drops_the_oldest_records_past_its_capacity() {
+        let learner = Learner::default();
+        for slot in 0..=DECISION_LOG_CAPACITY as u64 {
+            learner.record(slot, proposal(slot), vec![0, 1]).unwrap();
+        }
+
+        let log = learner.decision_log();
+
+        assert_eq!(log.len(), DECISION_LOG_CAPACITY);
+        assert_eq!(log[0].slot, 1);
+        assert_eq!(log[log.len() - 1].slot, DECISION_LOG_CAPACITY as u64);
+    }
+
+    #[tokio::test]
+    async fn decisions_are_audited_in_order_off_the_recording_task() {
+        let path = std::env::temp_dir()
+            .join(format!("paxos-learner-{}.log", Uuid::now_v7().simple()));
+        let learner = Learner::default();
+        learner
+            .set_audit_log(DecisionLog::open(&path).unwrap())
+            .unwrap();
+        for slot in [0, 2, 1] {
+            learner.record(slot, proposal(slot), vec![0, 1]).unwrap();
+        }
+
+        // Written by the thread of the log, in the background.
+        let audited = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let audited: Vec<u64> = DecisionLog::iter(&path)
+                    .unwrap()
+                    .map(|record| record.slot)
+                    .collect();
+                if audited.len() == 3 {
+                    break audited;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("decisions not audited");
+
+        assert_eq!(audited, [0, 2, 1]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn decided_slot_is_only_confirmed_never_overwritten() {
+        let learner = Learner::default();
+        let mut decisions = learner.subscribe();
+        assert_eq!(learner.record(4, proposal(10), vec![0, 1]), Ok(true));
+
+        // Learned again from another quorum, possibly under another ballot.
+        let again = Proposal::new(10, proposal(10).id, BallotNumber::new(2, 1));
+        assert_eq!(learner.record(4, again, vec![1, 2]), Ok(false));
+        assert_eq!(
+            learner.record(4, proposal(11), vec![1, 2]),
+            Err(ConsensusViolation {
+                slot: 4,
+                existing: 10,
+                attempted: 11,
+            })
+        );
+
+        assert_eq!(learner.decision(4), Some(proposal(10)));
+        assert_eq!(decisions.try_recv().unwrap().proposal, proposal(10));
+        assert!(decisions.try_recv().is_err(), "slot decided more than once");
+    }
+}
@@ -1,2 +1,6 @@
 pub mod acceptor;
+pub mod follower;
+pub mod learner;
+pub mod proposal_history;
 pub mod proposer;
+pub mod vote_log;
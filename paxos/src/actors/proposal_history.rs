@@ -0,0 +1,157 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::domain::id::ProposalId;
+
+/// How many proposals the history of a proposer remembers by default.
+pub const PROPOSAL_HISTORY_CAPACITY: usize = 1024;
+
+/// Values of the proposals issued by a proposer, keyed by their id, evicting the
+/// least recently used once full.
+///
+/// Every insert and every `get` marks the proposal as used, so the one of the
+/// round in flight, looked up each time its accept requests are sent, stays while
+/// the proposals of rounds long finished make room.
+pub struct ProposalHistory {
+    capacity: usize,
+    /// Value of each proposal, and the tick it was last used at.
+    entries: HashMap<ProposalId, (u64, u64)>,
+    /// Proposals by the tick they were last used at, least recent first.
+    recency: BTreeMap<u64, ProposalId>,
+    tick: u64,
+}
+
+impl Default for ProposalHistory {
+    fn default() -> Self {
+        Self::with_capacity(PROPOSAL_HISTORY_CAPACITY)
+    }
+}
+
+impl ProposalHistory {
+    /// History remembering up to `capacity` proposals, at least one.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "proposal history must hold at least one proposal"
+        );
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Remember `value` as the value of the proposal `id`, replacing the previous
+    /// one if any. Returns the proposal evicted to make room for it, if any.
+    pub fn insert(&mut self, id: ProposalId, value: u64) -> Option<(ProposalId, u64)> {
+        let tick = self.next_tick();
+        if let Some((_, used_at)) = self.entries.insert(id, (value, tick)) {
+            self.recency.remove(&used_at);
+        }
+        self.recency.insert(tick, id);
+
+        if self.entries.len() <= self.capacity {
+            return None;
+        }
+        let (_, evicted) = self.recency.pop_first()?;
+        let (value, _) = self.entries.remove(&evicted)?;
+        Some((evicted, value))
+    }
+
+    /// Value of the proposal `id`, marking it as used.
+    pub fn get(&mut self, id: &ProposalId) -> Option<u64> {
+        let tick = self.next_tick();
+        let (value, used_at) = self.entries.get_mut(id)?;
+        self.recency.remove(used_at);
+        *used_at = tick;
+        self.recency.insert(tick, *id);
+        Some(*value)
+    }
+
+    /// Value of the proposal `id`, without marking it as used.
+    pub fn peek(&self, id: &ProposalId) -> Option<u64> {
+        self.entries.get(id).map(|(value, _)| *value)
+    }
+
+    pub fn contains(&self, id: &ProposalId) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+/// Lists the proposals from the least to the most recently used.
+impl fmt::Debug for ProposalHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.recency.values().map(|id| (id, self.entries[id].0)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn id(n: u128) -> ProposalId {
+        ProposalId(Uuid::from_u128(n))
+    }
+
+    #[test]
+    fn only_proposals_left_unused_are_evicted() {
+        let mut history = ProposalHistory::with_capacity(3);
+        // Proposal 1 is the one of the round in flight, looked up between every
+        // proposal issued; the others are stale as soon as they are issued.
+        assert_eq!(history.insert(id(1), 10), None);
+        for n in 2..=3 {
+            assert_eq!(history.insert(id(n), n as u64 * 10), None);
+            assert_eq!(history.get(&id(1)), Some(10));
+        }
+
+        let mut evicted = Vec::new();
+        for n in 4..=6 {
+            evicted.extend(history.insert(id(n), n as u64 * 10));
+            assert_eq!(history.get(&id(1)), Some(10));
+        }
+
+        assert_eq!(evicted, [(id(2), 20), (id(3), 30), (id(4), 40)]);
+        assert_eq!(history.len(), 3);
+        assert!(history.contains(&id(1)));
+        assert!(history.contains(&id(5)) && history.contains(&id(6)));
+    }
+
+    #[test]
+    fn peeking_at_a_proposal_does_not_keep_it() {
+        let mut history = ProposalHistory::with_capacity(2);
+        history.insert(id(1), 10);
+        history.insert(id(2), 20);
+
+        assert_eq!(history.peek(&id(1)), Some(10));
+        assert_eq!(history.insert(id(3), 30), Some((id(1), 10)));
+
+        // Inserting a proposal again uses it, without growing the history.
+        assert_eq!(history.insert(id(2), 21), None);
+        assert_eq!(history.insert(id(4), 40), Some((id(3), 30)));
+        assert_eq!(history.peek(&id(2)), Some(21));
+    }
+}
@@ -1,213 +1,4276 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use anyhow::Result;
-use tokio::sync::{broadcast, mpsc};
-use tracing::{debug, error, info};
-use uuid::Uuid;
+use anyhow::{bail, Context, Result};
+use rand::{rngs::StdRng, SeedableRng};
+use tokio::{
+    sync::{broadcast, mpsc, watch},
+    time::Instant,
+};
+use tracing::{debug, error, field, info, info_span, warn, Instrument, Span};
 
-use crate::domain::{
-    id::{BrandedUuid, ProposalId},
-    message::{AcceptPhaseBody, Message, PreparePhaseBody},
-    proposal::Proposal,
+use super::{
+    learner::Learner,
+    proposal_history::ProposalHistory,
+    vote_log::{VoteKind, VoteLog, VoteRecord},
+};
+use crate::{
+    clock::{self, Clock, TokioClock},
+    config::ClusterConfig,
+    domain::{
+        ballot::BallotNumber,
+        command::Command,
+        consensus::{
+            ClientOutcome, ClusterHealth, ConsensusResult, FailureReason,
+            ProposeOutcome, ProposerStats, ProposerStatus, RoundStatus,
+        },
+        event::PaxosEvent,
+        id::{BrandedUuid, ProposalId, ProposalIdGen, TimeBasedIdGen},
+        message::{
+            AbortBody, AcceptPhaseBody, LeaseBody, Message, PreVoteBody,
+            PreparePhaseBody, RejectBody, RejectReason,
+        },
+        proposal::Proposal,
+        quorum::{Phase, QuorumTracker},
+    },
+    repository::{LatestProposal, ValueRepository},
+    retry::RetryPolicy,
+    telemetry,
+    transport::{Envelope, Transport},
 };
 
 /// Node that broadcast proposals to all the acceptors. All the information stored in
 /// this struct is ephemeral, being erased once the round completes.
+///
+/// Rounds are run one at a time: values received meanwhile queue up in
+/// `client_receiver` until the round in flight is decided, fails or times out, so
+/// that a proposer never has more than one round pending at the acceptors.
 pub struct Proposer {
     pub id: u64,
+    /// Static view of the cluster, used to know how many acceptors form a quorum.
+    pub cluster: ClusterConfig,
+    /// Incremented every time the cluster is reconfigured. Outgoing messages are
+    /// stamped with it, and responses from another epoch are ignored.
+    pub epoch: u64,
     /// Interface to receive values from the client, that are assigned an unique id  to
     /// be broadcast to all the nodes as a proposal.
     pub client_receiver: mpsc::Receiver<u64>,
-    /// Interface to broadcast messages to the acceptors.
-    pub acceptor_sender: broadcast::Sender<Message>,
-    /// Interface to receive messages **from** the acceptors.
-    pub acceptor_receiver: mpsc::Receiver<Message>,
+    /// Submits commands of the cluster, e.g. config changes, to `command_receiver`.
+    /// Clients can not submit commands as values: those are refused.
+    pub commands: mpsc::Sender<Command>,
+    /// Commands of the cluster to propose, queued apart from the values of clients.
+    pub command_receiver: mpsc::Receiver<Command>,
+    /// Interface to broadcast messages to the acceptors and receive their responses.
+    pub transport: Box<dyn Transport>,
+    /// Slot of the round in flight (or of the last round, if none is in flight).
+    pub slot: u64,
+    /// Slot that the next value received from the client will be proposed for.
+    pub next_slot: u64,
     /// Buffer that stores temporarily the id and value of the latest proposal set to
     /// be accepted by any acceptor.
     pub latest_proposal: Option<Proposal>,
+    /// Ballot of the last proposal issued by this proposer. Each new proposal gets
+    /// a strictly higher ballot.
+    pub ballot: BallotNumber,
+    /// Priority stamped on the ballots of this proposer, taken from its peer config.
+    pub priority: u64,
+    /// Generates the id of every proposal issued by this proposer.
+    pub id_generator: Box<dyn ProposalIdGen>,
     /// History of proposals sent by this proposer, and their respective values.
-    pub proposal_history: HashMap<ProposalId, u64>,
+    /// Only the most recently used ones are kept, which include the proposal of
+    /// the round in flight.
+    pub proposal_history: ProposalHistory,
     /// Nodes that replied to the prepare request.
     pub prepared_nodes: HashSet<u64>,
+    /// Proposal with the highest ballot the acceptors reported as already accepted
+    /// for the slot in flight. If there is one, its value is proposed instead of
+    /// the value of the client.
+    pub highest_accepted: Option<Proposal>,
     /// Nodes that replied to the accept request.
     pub accepted_value_nodes: HashSet<u64>,
+    /// Nodes that rejected the proposal in flight.
+    pub rejected_nodes: HashSet<u64>,
+    /// Nodes among `rejected_nodes` that rejected it for a ballot whose proposer
+    /// gave up on its round.
+    pub abandoned_rejections: HashSet<u64>,
+    /// Nodes that rejected the proposal in flight because they accepted another
+    /// proposal for the same slot, grouped by the ballot of that proposal. Once a
+    /// quorum accepted the same ballot, its value is chosen.
+    pub accepted_elsewhere: HashMap<BallotNumber, HashSet<u64>>,
+    /// Durable storage for the proposal being driven, so that a round interrupted
+    /// by a crash can be resumed with the same value.
+    pub repository: Arc<dyn ValueRepository + Send + Sync>,
+    /// Slot of the proposal persisted in `repository`, until it is marked decided
+    /// once the slot is.
+    pub persisted_slot: Option<u64>,
+    /// Values chosen so far. The proposer also plays the role of the learner.
+    pub learner: Learner,
+    /// Outcome of every value submitted by the client, once it is known.
+    pub outcomes: broadcast::Sender<ClientOutcome>,
+    /// Steps of the protocol taken by the proposer, for external observers.
+    pub events: broadcast::Sender<PaxosEvent>,
+    /// Decision of the highest slot decided with a value of a client, for readers
+    /// that only care about the latest one. Commands of the cluster are left out.
+    pub latest_decided: watch::Sender<Option<ConsensusResult>>,
+    /// Deadlines of the values submitted by the client, if they have any. A value
+    /// is given up on once its deadline passes.
+    pub deadlines: Deadlines,
+    /// Time the timeouts, backoffs and deadlines of the proposer are measured in.
+    pub clock: Arc<dyn Clock>,
+    /// Whether each round starts with a pre-vote. A proposer that would not win
+    /// then gives up on the round before raising its ballot, instead of disrupting
+    /// the proposer currently making progress.
+    pub pre_vote_enabled: bool,
+    /// Whether the proposer tells the acceptors every time it gives up on a round,
+    /// see `abort_round`. The proposers it outbid then retry right away rather than
+    /// after their backoff.
+    pub abort_abandoned_rounds: bool,
+    /// Whether the proposer stops running as soon as it decided a value of the
+    /// client, for one-shot consensus. See `run_single_shot`.
+    pub single_shot: bool,
+    /// How many rounds are run for a value before giving up on it, when they keep
+    /// failing, and how long to wait between them. Taken from the cluster config.
+    pub retry: RetryPolicy,
+    /// Source of the jitter of the retries, seeded with the id of the proposer so
+    /// that runs are reproducible while proposers do not retry in lockstep.
+    pub retry_rng: StdRng,
+    /// How long the prepare phase of a round may wait for a quorum of promises.
+    /// Past that, the round counts as rejected and is retried with a higher ballot.
+    /// Half of the `round_ms` of the timeouts of the cluster by default.
+    pub prepare_timeout: Duration,
+    /// How long the accept phase of a round may wait for a quorum of acceptances,
+    /// e.g. when acceptors crashed after promising. Past that, the round is
+    /// restarted from the prepare phase with a higher ballot. Like
+    /// `prepare_timeout`, half of the `round_ms` of the cluster by default.
+    pub accept_timeout: Duration,
+    /// Age past which the responses to the requests of the proposer are dropped
+    /// rather than counted, so that messages resurfacing after a long pause do not
+    /// count towards a quorum. Unlimited if `None`.
+    pub max_message_age: Option<Duration>,
+    /// Called once for every slot decided, right after the decision is recorded. It
+    /// runs on the task of the proposer, which is blocked until it returns: long
+    /// running work (writing to a remote store, ...) should be handed over to
+    /// another task.
+    pub on_decided: Option<DecidedHook>,
+    /// Rewrites the values of clients before they are proposed, e.g. to normalize
+    /// them. Unlike a validation, it can not refuse a value: the rewritten value is
+    /// the one stored in the proposal history, sent to the acceptors and decided.
+    /// Commands of the cluster are proposed as they are. See `PreProposeHook`.
+    pub pre_propose: Option<PreProposeHook>,
+    /// Proposer last seen leading the cluster, learned from its ballots outbidding
+    /// ours and from the slots it decided. Client values are redirected to it, as
+    /// long as it was seen within `LEADER_HINT_TTL`.
+    pub leader_hint: Option<u64>,
+    /// When the leader hint was last confirmed.
+    pub leader_seen_at: Instant,
+    /// Whether decided config changes go through joint consensus: until the change
+    /// is committed, quorums must be reached among both the old and the new
+    /// acceptors. Otherwise, the new acceptors take over right away.
+    pub joint_consensus: bool,
+    /// Config that was replaced by the last config change, while the change is not
+    /// committed yet.
+    pub joint: Option<ClusterConfig>,
+    /// Membership of the cluster, when it can change at runtime. Every new config
+    /// is applied through `reconfigure`, and a round in flight is restarted under
+    /// it.
+    pub membership: Option<watch::Receiver<ClusterConfig>>,
+    /// Acceptors a failure detector suspects to be down, if one is plugged in.
+    /// Rounds are not started while too few acceptors are left for a quorum, and
+    /// the round in flight is given up on as soon as the suspected acceptors and
+    /// the ones that rejected it leave too few.
+    pub suspected: Option<watch::Receiver<HashSet<u64>>>,
+    /// Nonce of the round in flight, drawn anew for every round. Responses carrying
+    /// another one are replays of an earlier round, and are ignored.
+    pub round_nonce: u64,
+    /// When the phases of the value in flight started, for its latency histograms.
+    /// Dropped once the value is decided or given up on.
+    pub timings: Option<RoundTimings>,
+    /// Whether the values proposed appear in the logs, taken from
+    /// `ClusterConfig::log_values`. When not, the logs only show the ids of the
+    /// proposals and how many values are involved.
+    pub log_values: bool,
+    /// Span of the value in flight, covering every round run for it. See
+    /// `run_round_until`.
+    pub round_span: Option<Span>,
+    /// Status of the proposer, published after every step of the protocol so that
+    /// it can be read from outside, e.g. by the admin endpoint of the node.
+    pub status: watch::Sender<ProposerStatus>,
+    /// Votes of the acceptors counted towards the quorums of the rounds.
+    pub votes: VoteLog,
+    /// Counters of the rounds run so far, see `stats`.
+    pub stats: ProposerStats,
+    /// Where a snapshot of `stats` is published after every round, see
+    /// `metrics_subscribe`.
+    pub metrics: broadcast::Sender<ProposerStats>,
+    /// When the lease granted by a quorum of the acceptors expires, if one was
+    /// acquired with `acquire_lease`. See `has_lease`.
+    pub lease_expires_at: Option<Instant>,
+    /// Highest slot the acceptors granting the lease knew a proposal of. No slot
+    /// above it was chosen before the lease, and only this proposer can get one
+    /// chosen while it holds: see `read`.
+    pub lease_highest_slot: Option<u64>,
+}
+
+/// How many outcomes a slow subscriber can lag behind before missing some.
+pub const OUTCOMES_CAPACITY: usize = 1024;
+
+/// How many events a slow subscriber can lag behind before missing some.
+pub const EVENTS_CAPACITY: usize = 1024;
+
+/// How many stats snapshots a slow subscriber can lag behind before missing some.
+pub const METRICS_CAPACITY: usize = 64;
+
+/// How many commands of the cluster can be queued before submitting more waits.
+pub const COMMANDS_CAPACITY: usize = 16;
+
+/// How long the prepare phase of a round may last, by default.
+pub const DEFAULT_PREPARE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the accept phase of a round may last, by default.
+pub const DEFAULT_ACCEPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a pre-vote waits for the acceptors to answer.
+const PRE_VOTE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long a proposer keeps deferring to the leader it last saw. Past that, the
+/// leader is presumed gone and the proposer runs rounds of its own again.
+const LEADER_HINT_TTL: Duration = Duration::from_secs(5);
+
+/// Deadlines of the values submitted to a proposer, by value.
+pub type Deadlines = Arc<Mutex<HashMap<u64, Instant>>>;
+
+/// Callback notified of every decision learned by a proposer.
+pub type DecidedHook = Box<dyn Fn(&ConsensusResult) + Send + Sync>;
+
+/// Rewrites the value of a client before the proposer runs rounds for it.
+///
+/// It is called once per value, before the first attempt of its round, and not for
+/// the commands of the cluster. Its result is proposed in every attempt, while the
+/// outcome is still published under the value the client submitted. It may return
+/// any value a client could submit: a value reserved for the commands of the
+/// cluster (see `Command::is_reserved`) fails as `FailureReason::Reserved`, without
+/// any round being run.
+pub type PreProposeHook = Box<dyn Fn(u64) -> u64 + Send + Sync>;
+
+/// Value as it appears in the logs of a proposer: redacted unless the proposer logs
+/// values.
+#[derive(Clone, Copy)]
+struct Logged(Option<u64>);
+
+impl Logged {
+    fn new(value: u64, log_values: bool) -> Self {
+        Self(log_values.then_some(value))
+    }
+}
+
+impl fmt::Display for Logged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "{value}"),
+            None => f.write_str("<redacted>"),
+        }
+    }
+}
+
+impl fmt::Debug for Logged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// `result` as it appears in the logs of a proposer, its value redacted unless the
+/// proposer logs values.
+fn logged_result(result: &ConsensusResult, log_values: bool) -> String {
+    match result {
+        ConsensusResult::Decided {
+            slot,
+            proposal,
+            by_other,
+        } if !log_values => format!(
+            "Decided {{ slot: {slot}, proposal: {}, by_other: {by_other} }}",
+            proposal.id.formatted()
+        ),
+        result => format!("{result:?}"),
+    }
+}
+
+/// Timestamps of the rounds run for a value, on the clock of the proposer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundTimings {
+    /// When the proposer took the value.
+    pub started_at: Instant,
+    /// When the prepare requests of the last round were broadcast.
+    pub prepare_sent_at: Option<Instant>,
+    /// When the accept requests of the last round were first broadcast.
+    pub accept_sent_at: Option<Instant>,
+    /// Requests broadcast again for the value, see `ROUND_RETRANSMISSIONS`.
+    pub retransmissions: u64,
+}
+
+impl RoundTimings {
+    pub fn new(started_at: Instant) -> Self {
+        Self {
+            started_at,
+            prepare_sent_at: None,
+            accept_sent_at: None,
+            retransmissions: 0,
+        }
+    }
+
+    fn prepare_sent(&mut self, now: Instant) {
+        if self.prepare_sent_at.is_some() {
+            self.retransmissions += 1;
+        }
+        self.prepare_sent_at = Some(now);
+        self.accept_sent_at = None;
+    }
+
+    /// Returns how long the prepare phase took, the first time the accept requests
+    /// of the round are sent.
+    fn accept_sent(&mut self, now: Instant) -> Option<Duration> {
+        if self.accept_sent_at.is_some() {
+            self.retransmissions += 1;
+            return None;
+        }
+        self.accept_sent_at = Some(now);
+        self.prepare_sent_at
+            .map(|sent_at| now.saturating_duration_since(sent_at))
+    }
+
+    /// Record the histograms of the value, decided at `now`.
+    fn record_decided(&self, node_id: u64, now: Instant) {
+        if let Some(accept_sent_at) = self.accept_sent_at {
+            telemetry::histogram(
+                telemetry::ACCEPT_LATENCY,
+                node_id,
+                now.saturating_duration_since(accept_sent_at).as_secs_f64(),
+            );
+        }
+        telemetry::histogram(
+            telemetry::ROUND_LATENCY,
+            node_id,
+            now.saturating_duration_since(self.started_at).as_secs_f64(),
+        );
+        telemetry::histogram(
+            telemetry::ROUND_RETRANSMISSIONS,
+            node_id,
+            self.retransmissions as f64,
+        );
+    }
 }
 
 impl Proposer {
     pub fn new(
-        acceptor_sender: broadcast::Sender<Message>,
-        acceptor_receiver: mpsc::Receiver<Message>,
+        id: u64,
+        cluster: ClusterConfig,
+        transport: Box<dyn Transport>,
         client_receiver: mpsc::Receiver<u64>,
+        repository: Arc<dyn ValueRepository + Send + Sync>,
     ) -> Self {
-        let id = 1; // TODO: change when there's more than one proposer
-        let proposal_history = HashMap::new();
+        let proposal_history = ProposalHistory::default();
         let prepared_nodes = HashSet::new();
         let accepted_value_nodes = HashSet::new();
+        let priority = cluster.peer(id).map_or(0, |peer| peer.priority);
 
-        Self {
+        let retry = cluster.retry;
+        let phase_timeout = cluster.timeouts.phase();
+        let log_values = cluster.log_values;
+        let (commands, command_receiver) = mpsc::channel(COMMANDS_CAPACITY);
+        let proposer = Self {
             id,
-            acceptor_sender,
-            acceptor_receiver,
+            cluster,
+            epoch: 0,
+            transport,
             client_receiver,
+            commands,
+            command_receiver,
+            slot: 0,
+            next_slot: 0,
             latest_proposal: None,
+            ballot: BallotNumber::new(0, id).with_priority(priority),
+            priority,
+            id_generator: Box::new(TimeBasedIdGen),
             proposal_history,
             accepted_value_nodes,
             prepared_nodes,
-        }
+            highest_accepted: None,
+            rejected_nodes: HashSet::new(),
+            abandoned_rejections: HashSet::new(),
+            accepted_elsewhere: HashMap::new(),
+            repository,
+            persisted_slot: None,
+            learner: Learner::default(),
+            outcomes: broadcast::channel(OUTCOMES_CAPACITY).0,
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            latest_decided: watch::Sender::new(None),
+            deadlines: Deadlines::default(),
+            clock: Arc::new(TokioClock),
+            pre_vote_enabled: false,
+            abort_abandoned_rounds: false,
+            single_shot: false,
+            retry,
+            retry_rng: StdRng::seed_from_u64(id),
+            prepare_timeout: phase_timeout,
+            accept_timeout: phase_timeout,
+            max_message_age: None,
+            on_decided: None,
+            pre_propose: None,
+            leader_hint: None,
+            leader_seen_at: Instant::now(),
+            joint_consensus: false,
+            joint: None,
+            membership: None,
+            suspected: None,
+            round_nonce: 0,
+            timings: None,
+            log_values,
+            round_span: None,
+            status: watch::Sender::new(ProposerStatus::default()),
+            votes: VoteLog::default(),
+            stats: ProposerStats::default(),
+            metrics: broadcast::channel(METRICS_CAPACITY).0,
+            lease_expires_at: None,
+            lease_highest_slot: None,
+        };
+        proposer.publish_status();
+        proposer
     }
 
-    #[tracing::instrument(skip(self))]
-    pub async fn run(&mut self) -> Result<()> {
-        // Listen to both channels simultaneously.
-        loop {
-            tokio::select! {
-                Some(client_value) = self.client_receiver.recv() => {
-                    self.send_prepare_request(client_value)?;
-                },
-                Some(received_message) = self.acceptor_receiver.recv() => {
-                    match received_message {
-                        Message::PrepareResponse { body } => {
-                            self.handle_prepare_response(body)?;
+    fn logged(&self, value: u64) -> Logged {
+        Logged::new(value, self.log_values)
+    }
+
+    /// Resolve once the value for `slot` is chosen, immediately if it already is.
+    pub async fn wait_for_decision(&self, slot: u64) -> Proposal {
+        self.learner.wait_for_decision(slot).await
+    }
+
+    /// Switch to a new cluster configuration, starting a new epoch. The votes
+    /// gathered so far were counted against the old membership, so they are
+    /// discarded: responses still in flight are stamped with the old epoch and
+    /// will be ignored.
+    #[tracing::instrument(skip_all, fields(node_id = self.id, epoch = self.epoch))]
+    pub fn reconfigure(&mut self, cluster: ClusterConfig) -> Result<()> {
+        self.set_cluster(cluster)?;
+        self.epoch += 1;
+        self.clear_votes();
+        info!(new_epoch = self.epoch, "cluster reconfigured");
+
+        Ok(())
+    }
+
+    fn set_cluster(&mut self, cluster: ClusterConfig) -> Result<()> {
+        cluster.validate(self.id)?;
+
+        self.priority = cluster.peer(self.id).map_or(0, |peer| peer.priority);
+        self.cluster = cluster;
+        Ok(())
+    }
+
+    fn emit(&self, event: PaxosEvent) {
+        // Nobody may be listening, which is fine.
+        let _ = self.events.send(event);
+        self.publish_status();
+    }
+
+    fn record_vote(
+        &self,
+        kind: VoteKind,
+        ballot: BallotNumber,
+        proposal_id: ProposalId,
+        acceptor: u64,
+    ) {
+        self.votes.append(VoteRecord {
+            kind,
+            slot: self.slot,
+            ballot,
+            proposal_id,
+            acceptor,
+            received_at: chrono::Utc::now(),
+        });
+    }
+
+    fn publish_status(&self) {
+        let status = ProposerStatus {
+            leader: self.leader(),
+            round: self.round_status(),
+            suspected: self.suspected.as_ref().map(|suspected| {
+                let mut suspected: Vec<u64> =
+                    suspected.borrow().iter().copied().collect();
+                suspected.sort_unstable();
+                suspected
+            }),
+        };
+        self.status.send_if_modified(|published| {
+            let modified = *published != status;
+            *published = status;
+            modified
+        });
+    }
+
+    /// Record the value chosen for `slot` by the acceptors in `quorum`, notifying
+    /// the `on_decided` hook. Returns whether the slot was not decided yet. A slot
+    /// already decided with another value is left as is, and the violation logged.
+    ///
+    /// No-ops are not notified. Decided config changes are applied instead of being
+    /// notified: the rounds of
+    /// the next slots count their votes against the new membership, along with the
+    /// old one until the change is committed if `joint_consensus` is enabled. The
+    /// epoch is left as is, so that proposers that learn of the change later can
+    /// still talk to the acceptors, and learn of it from them.
+    fn decide(&mut self, slot: u64, proposal: Proposal, quorum: Vec<u64>) -> bool {
+        match self.learner.record(slot, proposal, quorum) {
+            Ok(true) => (),
+            Ok(false) => return false,
+            Err(e) => {
+                telemetry::count(telemetry::CONSENSUS_VIOLATIONS, self.id, 1);
+                error!(proposal_id = proposal.id.formatted(), "{e}");
+                return false;
+            }
+        }
+        self.emit(PaxosEvent::Chosen {
+            slot,
+            ballot: proposal.ballot,
+            value: proposal.value,
+        });
+        self.observe_leader(proposal.ballot.node_id);
+
+        match Command::decode(proposal.value) {
+            Command::Value(_) => (),
+            Command::Noop => return true,
+            Command::ConfigChange(membership) => {
+                let previous = self.cluster.clone();
+                let cluster = self.cluster.with_acceptors(membership.acceptors());
+                match self.set_cluster(cluster) {
+                    Ok(()) => {
+                        if self.joint_consensus {
+                            self.joint = Some(previous);
                         }
-                        Message::AcceptResponse { body } => {
-                            self.handle_accept_response(body);
-                            // If the quorum is reached, we have achieved consensus on a value.
-                            // However, we can´t simply break the loop here because the function will return and then channels will be dropped.
-                        },
-                        _ => (),
+                        info!(
+                            slot,
+                            acceptors = ?membership.acceptors(),
+                            joint = self.joint.is_some(),
+                            "membership changed for the next slots"
+                        );
                     }
-                },
+                    Err(e) => {
+                        error!(slot, "could not apply decided config change: {e:#}")
+                    }
+                }
+                return true;
+            }
+            Command::ConfigCommit => {
+                if self.joint.take().is_some() {
+                    info!(slot, "config change committed");
+                }
+                return true;
             }
         }
+
+        let decided = ConsensusResult::Decided {
+            slot,
+            proposal,
+            by_other: proposal.ballot.node_id != self.id,
+        };
+        // Filling a gap below the latest slot does not make it any less recent.
+        self.latest_decided.send_if_modified(|latest| match latest {
+            Some(ConsensusResult::Decided { slot: latest, .. }) if *latest > slot => {
+                false
+            }
+            _ => {
+                *latest = Some(decided);
+                true
+            }
+        });
+        if let Some(on_decided) = &self.on_decided {
+            on_decided(&decided);
+        }
+        true
     }
 
-    /// The beginning of the protocol. The proposer broadcasts a proposal to all the
-    /// acceptors, using a value it received from the client.
-    /// In this step, we also store how many nodes are active. This information is then
-    /// later used for computations that rely on quorum.
+    /// Counters of the rounds run so far.
+    pub fn stats(&self) -> ProposerStats {
+        self.stats
+    }
+
+    /// What the proposer knows of the cluster right now: how many acceptors are
+    /// live according to its failure detector, whether they are enough for a
+    /// quorum, its round and the highest slot it learned.
+    pub fn health(&self) -> ClusterHealth {
+        let suspected = self.suspected();
+        let known_acceptors = self.cluster.acceptor_count();
+        let live_acceptors = self
+            .cluster
+            .acceptors()
+            .filter(|peer| !suspected.contains(&peer.node_id))
+            .count();
+        ClusterHealth {
+            known_acceptors,
+            live_acceptors,
+            current_round_proposal: self.latest_proposal,
+            last_decided: self.learner.highest_slot(),
+            quorum_available: self.quorum_unavailable().is_none(),
+        }
+    }
+
+    /// Snapshots of `stats` published at the end of every round from now on. A
+    /// subscriber that lags behind by more than `METRICS_CAPACITY` snapshots misses
+    /// the oldest ones: the proposer never waits for it.
+    pub fn metrics_subscribe(&self) -> broadcast::Receiver<ProposerStats> {
+        self.metrics.subscribe()
+    }
+
+    /// Count the round that just ended with `result`, and publish the new stats.
+    fn record_round(&mut self, result: &Result<ConsensusResult>) {
+        self.stats.rounds += 1;
+        match result {
+            Ok(ConsensusResult::Decided { .. }) => self.stats.decided += 1,
+            _ => self.stats.failed += 1,
+        }
+        self.stats.slot = self.slot;
+        self.stats.ballot = self.ballot;
+        // No subscriber is not an error.
+        let _ = self.metrics.send(self.stats);
+    }
+
+    /// Number of rounds started and not decided yet: at most one, see `Proposer`.
+    pub fn in_flight_count(&self) -> usize {
+        let pending = self.latest_proposal.is_some()
+            && self.learner.decision(self.slot).is_none();
+        usize::from(pending)
+    }
+
+    /// Progress of the round in flight, or of the last round if none is.
+    pub fn round_status(&self) -> RoundStatus {
+        RoundStatus {
+            slot: self.slot,
+            ballot: self.ballot,
+            prepared: self.prepared_nodes.len(),
+            prepare_quorum: self.cluster.prepare_quorum(),
+            accepted: self.accepted_value_nodes.len(),
+            accept_quorum: self.cluster.accept_quorum(),
+            in_flight: self.in_flight_count() > 0,
+        }
+    }
+
+    /// The proposer currently leading the cluster, if one was seen recently. It may
+    /// be this proposer.
+    pub fn leader(&self) -> Option<u64> {
+        self.leader_hint.filter(|_| {
+            self.clock
+                .now()
+                .saturating_duration_since(self.leader_seen_at)
+                < LEADER_HINT_TTL
+        })
+    }
+
+    fn observe_leader(&mut self, node_id: u64) {
+        if self.leader_hint != Some(node_id) {
+            debug!(leader = node_id, "leader changed");
+            self.emit(PaxosEvent::LeaderChanged { leader: node_id });
+        }
+        self.leader_hint = Some(node_id);
+        self.leader_seen_at = self.clock.now();
+    }
+
+    /// Forget the responses received for the round in flight.
+    fn clear_votes(&mut self) {
+        self.prepared_nodes.clear();
+        self.highest_accepted = None;
+        self.accepted_value_nodes.clear();
+        self.rejected_nodes.clear();
+        self.abandoned_rejections.clear();
+        self.accepted_elsewhere.clear();
+    }
+
+    /// Quorums the votes of `phase` must reach: among the acceptors of the cluster
+    /// and, while a config change is not committed, among the previous ones too.
+    pub fn quorum(&self, phase: Phase) -> QuorumTracker {
+        let quorum = self.cluster.quorum_tracker(phase);
+        match &self.joint {
+            Some(previous) => quorum.joint(
+                previous.acceptors().map(|peer| peer.node_id),
+                previous.phase_quorum(phase),
+            ),
+            None => quorum,
+        }
+    }
+
+    /// Whether enough acceptors rejected the round in flight that it can not reach
+    /// a quorum anymore. Once enough promises were gathered, the round is in its
+    /// accept phase, whose quorums may have a different size.
+    ///
+    /// Acceptors suspected to be down count as rejecting it, unless they already
+    /// voted in the current phase.
+    fn is_round_lost(&self) -> bool {
+        let (phase, voters) =
+            if self.quorum(Phase::Prepare).is_reached(&self.prepared_nodes) {
+                (Phase::Accept, &self.accepted_value_nodes)
+            } else {
+                (Phase::Prepare, &self.prepared_nodes)
+            };
+        let mut refusing = self.suspected();
+        refusing.retain(|node_id| !voters.contains(node_id));
+        refusing.extend(&self.rejected_nodes);
+        self.quorum(phase).is_lost(&refusing)
+    }
+
+    /// Acceptors currently suspected to be down by the failure detector.
+    fn suspected(&self) -> HashSet<u64> {
+        self.suspected
+            .as_ref()
+            .map(|suspected| suspected.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    /// Why no round can succeed right now, if too few acceptors are left alive for
+    /// the quorums of either phase.
+    fn quorum_unavailable(&self) -> Option<FailureReason> {
+        let suspected = self.suspected();
+        let phase = [Phase::Prepare, Phase::Accept]
+            .into_iter()
+            .find(|&phase| self.quorum(phase).is_lost(&suspected))?;
+        let live = self
+            .cluster
+            .acceptors()
+            .filter(|peer| !suspected.contains(&peer.node_id))
+            .count();
+        Some(FailureReason::QuorumUnavailable {
+            live,
+            required: self.cluster.phase_quorum(phase),
+        })
+    }
+
+    /// Whether a response was sent in a different epoch than the current one.
+    fn is_stale_epoch(&self, epoch: u64) -> bool {
+        if epoch != self.epoch {
+            debug!(epoch, current_epoch = self.epoch, "ignoring stale epoch");
+            return true;
+        }
+        false
+    }
+
+    /// Whether a response belongs to another round than the one in flight.
+    fn is_other_slot(&self, slot: u64) -> bool {
+        if slot != self.slot {
+            debug!(
+                slot,
+                current_slot = self.slot,
+                "ignoring response for another slot"
+            );
+            return true;
+        }
+        false
+    }
+
+    /// Whether a response carries the nonce of another round than the one in
+    /// flight: it was replayed, by a faulty link or by an attacker.
+    fn is_replayed(&self, nonce: u64) -> bool {
+        if nonce != self.round_nonce {
+            debug!(
+                nonce,
+                current = self.round_nonce,
+                "ignoring replayed response"
+            );
+            return true;
+        }
+        false
+    }
+
+    /// Whether a response answers an earlier round for the same slot, abandoned
+    /// for one with a higher ballot: its votes must not be mixed with the new ones.
+    fn is_other_round(&self, ballot: BallotNumber) -> bool {
+        let current = self.latest_proposal.map(|proposal| proposal.ballot);
+        if current != Some(ballot) {
+            debug!(%ballot, ?current, "ignoring response for an abandoned round");
+            return true;
+        }
+        false
+    }
+
+    /// Whether a response is about another proposal than the one in flight, e.g.
+    /// one of another proposer or of an earlier attempt for the same slot: it must
+    /// not count toward the quorum of ours, nor against it.
+    fn is_other_proposal(&self, proposal_id: ProposalId) -> bool {
+        let current = self.latest_proposal.map(|proposal| proposal.id);
+        if current != Some(proposal_id) {
+            debug!(
+                proposal_id = proposal_id.formatted(),
+                current = current.map(|id| id.formatted()),
+                "ignoring response for another proposal"
+            );
+            return true;
+        }
+        false
+    }
+
+    /// Whether a response answers a request sent more than `max_message_age` ago,
+    /// e.g. one held up by a long pause. Responses without a timestamp can not be
+    /// told apart, and are kept.
+    fn is_too_old(&self, sent_at: Option<Instant>) -> bool {
+        let (Some(max_age), Some(sent_at)) = (self.max_message_age, sent_at) else {
+            return false;
+        };
+        let age = self.clock.now().saturating_duration_since(sent_at);
+        if age > max_age {
+            debug!(?age, "ignoring response to an outdated request");
+            return true;
+        }
+        false
+    }
+
+    /// Reload the proposal that was being driven before a restart, if any, and
+    /// re-broadcast its accept request. Acceptors treat a repeated accept request
+    /// for the same proposal idempotently, so this cannot introduce a conflicting
+    /// value: the round is finished with the value that was already proposed.
+    ///
+    /// A proposal whose slot is known to be decided is not resumed: there is
+    /// nothing left to finish, and the next proposals only have to outbid it.
+    #[tracing::instrument(skip_all, fields(node_id = self.id, epoch = self.epoch))]
+    pub async fn resume(&mut self) -> Result<()> {
+        let Some(LatestProposal {
+            slot,
+            proposal,
+            decided,
+        }) = self.repository.get_latest_value().await?
+        else {
+            debug!("no round to resume");
+            return Ok(());
+        };
+        // New proposals must be issued with a ballot higher than the resumed one.
+        self.ballot = self.ballot.max(proposal.ballot);
+        self.next_slot = self.next_slot.max(slot + 1);
+        if decided || self.learner.decision(slot).is_some() {
+            debug!(slot, "round to resume already decided");
+            return self.repository.mark_decided(slot).await;
+        }
+
+        info!(
+            slot,
+            proposal_id = proposal.id.formatted(),
+            value = %self.logged(proposal.value),
+            "resuming interrupted round"
+        );
+        self.proposal_history.insert(proposal.id, proposal.value);
+        self.latest_proposal = Some(proposal);
+        self.slot = slot;
+
+        self.send_accept_request().await
+    }
+
+    /// Run the proposer for good, or until it decided a value in `single_shot`
+    /// mode.
     #[tracing::instrument(skip(self))]
-    pub fn send_prepare_request(&mut self, value: u64) -> Result<()> {
-        let proposal_id = ProposalId(Uuid::now_v7());
-        let new_proposal = Proposal::new(value, proposal_id);
-        self.proposal_history.entry(proposal_id).or_insert(value);
-        debug!("current proposal history {:?}", &self.proposal_history);
+    pub async fn run(&mut self) -> Result<()> {
+        self.run_until(std::future::pending(), Duration::ZERO)
+            .await
+            .map(|_| ())
+    }
 
-        self.latest_proposal = Some(new_proposal);
+    /// Run the proposer until it decided the first value submitted by the client
+    /// that could be decided, and return its decision. Values the proposer gave up
+    /// on or redirected to the leader do not count: it waits for the next one.
+    ///
+    /// Values still queued once the decision is reached are left undecided. The
+    /// proposer keeps its transport until it is dropped, so the acceptors still
+    /// answering the last round are not cut off by its return.
+    pub async fn run_single_shot(&mut self) -> Result<ConsensusResult> {
+        self.single_shot = true;
+        let (decided, _) = self.serve(std::future::pending(), Duration::ZERO).await?;
+        decided.context("proposer stopped before deciding a value")
+    }
+
+    /// Run the proposer until `shutdown` resolves, or until it decided a value in
+    /// `single_shot` mode, then spend up to `grace` trying to decide the value of
+    /// the round in flight, if any, and the values still queued by the client.
+    /// Returns the values that could not be decided, so that they can be submitted
+    /// elsewhere.
+    pub async fn run_until(
+        &mut self,
+        shutdown: impl Future<Output = ()>,
+        grace: Duration,
+    ) -> Result<Vec<u64>> {
+        let (_, undecided) = self.serve(shutdown, grace).await?;
+        Ok(undecided)
+    }
 
-        let acceptor_sender_clone = self.acceptor_sender.clone();
+    /// Body of `run_until`, also returning the decision that ended a single shot.
+    async fn serve(
+        &mut self,
+        shutdown: impl Future<Output = ()>,
+        grace: Duration,
+    ) -> Result<(Option<ConsensusResult>, Vec<u64>)> {
+        self.cluster.bootstrap(self.id).await?;
+        self.resume().await?;
+        tokio::pin!(shutdown);
+        let clock = self.clock.clone();
 
-        let active_acceptors = acceptor_sender_clone
-            .send(Message::PrepareRequest {
-                body: PreparePhaseBody {
-                    issuer_id: self.id,
-                    proposal_id,
+        // Listen to every channel simultaneously. Values from the client and
+        // commands of the cluster are proposed one at a time; in between rounds, late
+        // responses are still drained from the transport.
+        let mut undecided = Vec::new();
+        let mut decided = None;
+        let deadline = loop {
+            let command = tokio::select! {
+                _ = &mut shutdown => break clock.now() + grace,
+                Some(client_value) = self.client_receiver.recv() => {
+                    telemetry::count(telemetry::VALUES_QUEUED, self.id, 1);
+                    telemetry::gauge(
+                        telemetry::QUEUE_DEPTH,
+                        self.id,
+                        self.client_receiver.len() as f64,
+                    );
+                    Command::Value(client_value)
                 },
-            })
-            .expect("could not broadcast proposals");
+                Some(command) = self.command_receiver.recv() => command,
+                Some(envelope) = self.transport.recv() => {
+                    self.handle_message(envelope).await?;
+                    continue;
+                },
+                cluster = membership_change(&mut self.membership) => {
+                    self.reconfigure(cluster)?;
+                    continue;
+                },
+            };
 
-        debug!("proposing for {} acceptors", active_acceptors);
-        Ok(())
+            let client_value = match command {
+                Command::Value(value) => Some(value),
+                _ => None,
+            };
+            let log_values = self.log_values;
+            let single_shot = self.single_shot && client_value.is_some();
+            let logged = Logged::new(command.encode(), log_values);
+            let round = self.propose_command(command);
+            tokio::pin!(round);
+            let (outcome, deadline) = tokio::select! {
+                outcome = &mut round => (Some(outcome), None),
+                _ = &mut shutdown => {
+                    let deadline = clock.now() + grace;
+                    (clock::timeout_at(&*clock, deadline, &mut round).await, Some(deadline))
+                },
+            };
+
+            match outcome {
+                Some(Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                    reason,
+                    ..
+                }))) => warn!(client_value = %logged, ?reason, "value not decided"),
+                Some(Ok(ProposeOutcome::Decided(result))) => {
+                    debug!(
+                        result = logged_result(&result, log_values),
+                        "round finished"
+                    );
+                    if single_shot {
+                        decided = Some(result);
+                    }
+                }
+                Some(Ok(ProposeOutcome::Redirect(leader))) => {
+                    warn!(leader, client_value = %logged, "value not proposed, not the leader")
+                }
+                Some(Err(e)) => warn!("round failed: {e}"),
+                None => {
+                    warn!(client_value = %logged, "round interrupted by shutdown");
+                    undecided.extend(client_value);
+                }
+            }
+            if let Some(deadline) = deadline {
+                break deadline;
+            }
+            if decided.is_some() {
+                info!("value decided, single shot done");
+                break clock.now() + grace;
+            }
+        };
+
+        let grace = deadline.saturating_duration_since(clock.now());
+        undecided.extend(self.drain(grace).await);
+        Ok((decided, undecided))
     }
 
-    #[tracing::instrument(skip_all, fields(
+    /// Stop accepting values from the client, and propose the ones already queued
+    /// until `grace` elapses. Returns the values that were not decided: those that
+    /// were not proposed in time or were meant for another leader, and those that
+    /// lost their slot to the value of another round.
+    #[tracing::instrument(skip(self), fields(node_id = self.id, epoch = self.epoch))]
+    pub async fn drain(&mut self, grace: Duration) -> Vec<u64> {
+        self.client_receiver.close();
+        let mut queued = Vec::new();
+        while let Ok(value) = self.client_receiver.try_recv() {
+            queued.push(value);
+        }
+
+        let clock = self.clock.clone();
+        let deadline = clock.now() + grace;
+        let mut queued = queued.into_iter();
+        let mut undecided = Vec::new();
+        for value in queued.by_ref() {
+            if clock.now() >= deadline {
+                undecided.push(value);
+                break;
+            }
+            match clock::timeout_at(&*clock, deadline, self.propose(value)).await {
+                Some(Ok(ProposeOutcome::Decided(ConsensusResult::Decided {
+                    by_other,
+                    ..
+                }))) => {
+                    if by_other {
+                        undecided.push(value);
+                    }
+                }
+                Some(Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                    ..
+                })))
+                | Some(Ok(ProposeOutcome::Redirect(_))) => undecided.push(value),
+                Some(Err(e)) => {
+                    warn!("round failed: {e}");
+                    undecided.push(value);
+                }
+                None => {
+                    undecided.push(value);
+                    break;
+                }
+            }
+        }
+        undecided.extend(queued);
+
+        if !undecided.is_empty() && self.log_values {
+            warn!(?undecided, "values left undecided on shutdown");
+        } else if !undecided.is_empty() {
+            warn!(
+                undecided = undecided.len(),
+                "values left undecided on shutdown"
+            );
+        }
+        undecided
+    }
+
+    /// Propose `value` of a client for the next slot, unless another proposer is
+    /// known to be leading: the client is then redirected to it instead, so that
+    /// the two do not compete for the slot. The outcome is also published to
+    /// `outcomes`.
+    ///
+    /// Values reserved for the commands of the cluster fail as `Reserved`, without
+    /// any round: commands are proposed with `propose_command`.
+    pub async fn propose(&mut self, value: u64) -> Result<ProposeOutcome> {
+        self.propose_command(Command::Value(value)).await
+    }
+
+    /// Propose `command` for the next slot, like `propose` does for the values of
+    /// clients. A config change decided through joint consensus is committed right
+    /// away, in the next slot, before its outcome is published.
+    #[tracing::instrument(skip(self, command), fields(
         node_id = self.id,
-        proposal_id = received_proposal.proposal_id.formatted()
+        epoch = self.epoch,
+        value = %self.logged(command.encode()),
     ))]
-    pub fn handle_prepare_response(
+    pub async fn propose_command(
         &mut self,
-        received_proposal: PreparePhaseBody,
-    ) -> Result<()> {
-        let received_proposal_id = received_proposal.proposal_id;
-        let node_id = received_proposal.issuer_id;
-        debug!(
-            "received prepare response from node {}",
-            received_proposal.issuer_id
-        );
+        command: Command,
+    ) -> Result<ProposeOutcome> {
+        let value = command.encode();
+        let reserved =
+            matches!(command, Command::Value(value) if Command::is_reserved(value));
+        let leader = self.leader().filter(|&leader| leader != self.id);
+        let outcome = match (reserved, leader) {
+            (true, _) => {
+                warn!("value reserved for the commands of the cluster, not proposed");
+                Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                    reason: FailureReason::Reserved,
+                    last_ballot: self.ballot,
+                }))
+            }
+            (false, Some(leader)) => {
+                info!(leader, "redirecting client to the leader");
+                Ok(ProposeOutcome::Redirect(leader))
+            }
+            (false, None) => {
+                let deadline = self
+                    .deadlines
+                    .lock()
+                    .expect("deadlines lock poisoned")
+                    .remove(&value);
+                self.run_round_until(value, deadline)
+                    .await
+                    .map(ProposeOutcome::Decided)
+            }
+        };
 
-        if let Some(latest_proposal) = self.latest_proposal {
-            // If there's a node that received a more up-to-date proposal, we use it
-            // to update the proposed value for the next iterations.
-            if received_proposal_id > latest_proposal.id {
-                let proposal_value = self
-                    .proposal_history
-                    .get(&received_proposal_id)
-                    .ok_or(anyhow::anyhow!(
-                    "could not find proposal {} in history",
-                    received_proposal_id.to_string()
-                ))?;
-                self.latest_proposal = Some(Proposal {
-                    id: received_proposal_id,
-                    value: *proposal_value,
-                })
+        if let Ok(ProposeOutcome::Decided(ConsensusResult::Decided {
+            proposal, ..
+        })) = &outcome
+        {
+            if proposal.value == value
+                && self.joint.is_some()
+                && matches!(Command::decode(value), Command::ConfigChange(_))
+            {
+                match self.run_round(Command::ConfigCommit.encode()).await {
+                    Ok(ConsensusResult::Decided { slot, .. }) => {
+                        debug!(slot, "config change commit decided")
+                    }
+                    Ok(result) => warn!(
+                        result = logged_result(&result, self.log_values),
+                        "config change not committed"
+                    ),
+                    Err(e) => warn!("config change not committed: {e:#}"),
+                }
             }
         }
 
-        self.prepared_nodes.insert(node_id);
+        // Nobody may be waiting for the outcome, which is fine.
+        let _ = self.outcomes.send(ClientOutcome {
+            value,
+            outcome: outcome.as_ref().copied().map_err(|e| format!("{e:#}")),
+        });
+        outcome
+    }
 
-        if self.prepared_nodes.iter().count()
-            > self.acceptor_sender.receiver_count() / 2
-        {
-            self.send_accept_request()?;
+    /// Propose `value` for `slot` rather than the next one, e.g. to fill a hole in
+    /// the log with a no-op before later slots are committed. If `slot` is already
+    /// decided, its value is reported without running a round.
+    ///
+    /// The next slot of the proposer only moves forward: it is left untouched when
+    /// filling a slot below it, and moves past `slot` otherwise.
+    #[tracing::instrument(skip(self, value), fields(
+        node_id = self.id,
+        epoch = self.epoch,
+        value = %self.logged(value),
+    ))]
+    pub async fn propose_at(
+        &mut self,
+        slot: u64,
+        value: u64,
+    ) -> Result<ConsensusResult> {
+        if let Some(proposal) = self.learner.decision(slot) {
+            debug!(slot, "slot already decided");
+            return Ok(ConsensusResult::Decided {
+                slot,
+                proposal,
+                by_other: proposal.ballot.node_id != self.id,
+            });
         }
 
-        Ok(())
+        let next_slot = self.next_slot;
+        self.next_slot = slot;
+        let result = self.run_round(value).await;
+        self.next_slot = next_slot.max(self.slot + 1);
+        result
     }
 
-    /// The
-    #[tracing::instrument(skip(self))]
-    pub fn send_accept_request(&mut self) -> Result<()> {
-        let latest_proposal_id = self.latest_proposal.unwrap().id;
-        let proposal_value =
-            self.proposal_history
-                .get(&latest_proposal_id)
-                .ok_or(anyhow::anyhow!(
-                    "could not find proposal {} in history",
-                    latest_proposal_id.to_string()
-                ))?;
+    /// Propose `new` for the next slot only if the latest value decided, as told by
+    /// `Learner::latest_value`, is `expected`. Otherwise, the value fails as a
+    /// `Conflict` with the actual one, without any round being run for it.
+    ///
+    /// The check is made against the decisions known to the proposer, which may
+    /// lag behind the cluster. The prepare phase of the round then tells whether
+    /// another value was accepted for the slot in the meantime: that value is
+    /// decided instead of `new`, and the check is made again against it. A proposer
+    /// that missed several slots may thus get a conflict with a stale value.
+    #[tracing::instrument(skip(self, expected, new), fields(
+        node_id = self.id,
+        epoch = self.epoch,
+        expected = ?expected.map(|value| self.logged(value)),
+        new = %self.logged(new),
+    ))]
+    pub async fn propose_if(
+        &mut self,
+        expected: Option<u64>,
+        new: u64,
+    ) -> Result<ConsensusResult> {
+        if Command::is_reserved(new) {
+            bail!("only values of clients can be proposed on a condition");
+        }
 
-        let active_acceptors = self
-            .acceptor_sender
-            .send(Message::AcceptRequest {
-                body: AcceptPhaseBody {
-                    issuer_id: self.id,
-                    proposal_id: latest_proposal_id,
-                    value: *proposal_value,
-                },
-            })
-            .inspect_err(|e| error!("error: {e}"))
-            .expect("could not broadcast accept messages");
+        loop {
+            let actual = self.learner.latest_value();
+            if actual != expected {
+                info!(
+                    actual = ?actual.map(|value| self.logged(value)),
+                    "latest value is not the expected one"
+                );
+                return Ok(ConsensusResult::Failed {
+                    reason: FailureReason::Conflict { actual },
+                    last_ballot: self.ballot,
+                });
+            }
 
-        debug!("accept sent for {} acceptors", active_acceptors);
+            match self.run_round(new).await? {
+                ConsensusResult::Decided { proposal, slot, .. }
+                    if proposal.value != new =>
+                {
+                    debug!(
+                        slot,
+                        value = %self.logged(proposal.value),
+                        "slot taken by another value"
+                    );
+                }
+                result => return Ok(result),
+            }
+        }
+    }
 
-        Ok(())
+    /// Fill the holes of the decided log below its highest slot, e.g. after taking
+    /// over from another leader that did not get to decide every slot it started.
+    /// Returns the slots that were filled.
+    ///
+    /// Each hole goes through a full round for a no-op. If a value was accepted for
+    /// the slot, the prepare phase adopts it instead, so that a value that may have
+    /// been chosen is kept.
+    #[tracing::instrument(skip(self), fields(node_id = self.id, epoch = self.epoch))]
+    pub async fn recover_log(&mut self) -> Result<Vec<u64>> {
+        let gaps = self.learner.gaps();
+        for &slot in &gaps {
+            match self.propose_at(slot, Command::Noop.encode()).await? {
+                ConsensusResult::Decided { proposal, .. } => {
+                    info!(slot, value = %self.logged(proposal.value), "recovered slot")
+                }
+                ConsensusResult::Failed { last_ballot, .. } => {
+                    bail!("could not recover slot {slot}, last ballot {last_ballot}")
+                }
+            }
+        }
+        Ok(gaps)
     }
 
-    #[tracing::instrument(skip_all)]
-    pub fn handle_accept_response(&mut self, received_message: AcceptPhaseBody) {
-        let AcceptPhaseBody {
-            issuer_id,
-            proposal_id,
-            value,
-        } = received_message;
+    /// Propose `value` for the next slot, and drive the round until the slot is
+    /// decided. If another proposer got its own value chosen for the slot in the
+    /// meantime, that value is reported instead.
+    ///
+    /// A round rejected by a majority of the acceptors, or whose phases did not
+    /// gather a quorum within `prepare_timeout` and `accept_timeout`, is retried for
+    /// the same slot with a higher ballot, after the backoff of `retry`. Its value
+    /// is kept, unless the new prepare phase finds another one to adopt. Once
+    /// `retry.max_attempts` rounds failed, the value is reported as `Failed`, for the
+    /// reason of the last one.
+    ///
+    /// When the failure detector leaves too few acceptors alive for a quorum, the
+    /// value fails right away as `QuorumUnavailable`, without being retried. The
+    /// same goes for `NoAcceptors`, when the transport reaches none of them.
+    ///
+    /// If the membership changes during the round, the votes gathered so far no
+    /// longer add up to a quorum of the new cluster: the round starts over with a
+    /// prepare request for the same slot.
+    pub async fn run_round(&mut self, value: u64) -> Result<ConsensusResult> {
+        self.run_round_until(value, None).await
+    }
 
-        debug!(
-            value,
-            issuer_id,
-            proposal_id = proposal_id.formatted(),
-            "received accepted value",
+    /// Like `run_round`, but the value is given up on as `Expired` once `deadline`
+    /// passes: no round is started for it, the round in flight is abandoned, and
+    /// no retry is run past it.
+    ///
+    /// Everything done for the value, from the pre-vote to the handling of the
+    /// last response, happens within a single `round` span. It is tagged with a
+    /// random `round_trace_id` and the id of the latest proposal made for the
+    /// value, and records once it closes whether the value was `chosen` or
+    /// `abandoned`, along with the reason.
+    async fn run_round_until(
+        &mut self,
+        value: u64,
+        deadline: Option<Instant>,
+    ) -> Result<ConsensusResult> {
+        let span = info_span!(
+            "round",
+            node_id = self.id,
+            epoch = self.epoch,
+            round_trace_id = format!("{:016x}", rand::random::<u64>()),
+            proposal_id = field::Empty,
+            value = %self.logged(value),
+            outcome = field::Empty,
+            reason = field::Empty,
         );
-        self.accepted_value_nodes.insert(issuer_id);
+        self.round_span = Some(span.clone());
+        let result = self
+            .run_attempts(value, deadline)
+            .instrument(span.clone())
+            .await;
+        self.round_span = None;
+        self.publish_status();
+        self.record_round(&result);
 
-        if self.accepted_value_nodes.iter().count()
-            > self.acceptor_sender.receiver_count() / 2
-        {
-            // At this point, we reached consensus. However, there will still be some
-            // remaining accept responses to be received by the proposer.
-            info!(
-                "quorum reached by {}, value {} accepted",
-                self.accepted_value_nodes.iter().count(),
-                value
-            );
+        match &result {
+            Ok(ConsensusResult::Decided { .. }) => {
+                span.record("outcome", "chosen");
+            }
+            Ok(ConsensusResult::Failed { reason, .. }) => {
+                span.record("outcome", "abandoned");
+                span.record("reason", field::debug(reason));
+            }
+            Err(e) => {
+                span.record("outcome", "abandoned");
+                span.record("reason", field::display(format!("{e:#}")));
+            }
         }
+        result
+    }
+
+    async fn run_attempts(
+        &mut self,
+        value: u64,
+        deadline: Option<Instant>,
+    ) -> Result<ConsensusResult> {
+        let clock = self.clock.clone();
+        let expired = |deadline: Option<Instant>| {
+            deadline.is_some_and(|deadline| clock.now() >= deadline)
+        };
+        if expired(deadline) {
+            info!("value expired before its round");
+            return Ok(ConsensusResult::Failed {
+                reason: FailureReason::Expired,
+                last_ballot: self.ballot,
+            });
+        }
+
+        // Rewritten once, so that every attempt proposes the same value.
+        let value = match (&self.pre_propose, Command::decode(value)) {
+            (Some(pre_propose), Command::Value(submitted)) => {
+                let value = pre_propose(submitted);
+                if Command::is_reserved(value) {
+                    warn!(
+                        submitted = %self.logged(submitted),
+                        "value rewritten into a command of the cluster, not proposed"
+                    );
+                    return Ok(ConsensusResult::Failed {
+                        reason: FailureReason::Reserved,
+                        last_ballot: self.ballot,
+                    });
+                }
+                value
+            }
+            _ => value,
+        };
+
+        if let Command::ConfigChange(membership) = Command::decode(value) {
+            if self.joint.is_some() {
+                bail!("the previous config change is not committed yet");
+            }
+            self.cluster
+                .with_acceptors(membership.acceptors())
+                .validate(self.id)
+                .context("invalid config change")?;
+        }
+        if let Some(reason) = self.quorum_unavailable() {
+            warn!(?reason, "not starting a round without a quorum alive");
+            return Ok(ConsensusResult::Failed {
+                reason,
+                last_ballot: self.ballot,
+            });
+        }
+        if self.pre_vote_enabled && !self.pre_vote().await {
+            info!("pre-vote lost, not disrupting the current proposer");
+            return Ok(ConsensusResult::Failed {
+                reason: FailureReason::PreVoteLost,
+                last_ballot: self.ballot,
+            });
+        }
+
+        self.timings = Some(RoundTimings::new(clock.now()));
+        let mut last_ballot = self.ballot;
+        let mut reason = FailureReason::Exhausted;
+        let mut outbid_by_abandoned = false;
+        for attempt in 1.. {
+            let Some(backoff) = self.retry.next_delay(attempt, &mut self.retry_rng)
+            else {
+                break;
+            };
+            if attempt > 1 {
+                // A retry that would start past the deadline is not worth waiting for.
+                if deadline.is_some_and(|deadline| clock.now() + backoff >= deadline) {
+                    reason = FailureReason::Expired;
+                    break;
+                }
+                if outbid_by_abandoned {
+                    info!(
+                        slot = self.slot,
+                        attempt, "retrying round outbid by abandoned rounds right away"
+                    );
+                } else {
+                    info!(slot = self.slot, attempt, ?backoff, "retrying failed round");
+                    clock.sleep(backoff).await;
+                }
+                self.next_slot = self.slot;
+            }
+
+            if self.send_prepare_request(value).await? == 0 {
+                reason = FailureReason::NoAcceptors;
+                break;
+            }
+            last_ballot = self.ballot;
+            if let Some(result) = self.drive_round(value, deadline).await? {
+                telemetry::count(telemetry::ROUNDS_DECIDED, self.id, 1);
+                if let Some(timings) = self.timings.take() {
+                    timings.record_decided(self.id, clock.now());
+                }
+                return Ok(result);
+            }
+            telemetry::count(telemetry::ROUNDS_ABANDONED, self.id, 1);
+            reason = if expired(deadline) {
+                FailureReason::Expired
+            } else if let Some(unavailable) = self.quorum_unavailable() {
+                unavailable
+            } else if self.is_round_lost() {
+                FailureReason::Exhausted
+            } else if self.quorum(Phase::Prepare).is_reached(&self.prepared_nodes) {
+                FailureReason::AcceptTimeout
+            } else {
+                FailureReason::PrepareTimeout
+            };
+            self.emit(PaxosEvent::RoundAbandoned {
+                slot: self.slot,
+                ballot: self.ballot,
+                reason,
+            });
+            if self.abort_abandoned_rounds {
+                if let Err(e) = self.abort_round().await {
+                    debug!("could not abort round: {e:#}");
+                }
+            }
+            // Nobody is competing for the slot with the ballots that outbid the
+            // round: backing off would only slow the next one down.
+            outbid_by_abandoned = !self.rejected_nodes.is_empty()
+                && self.rejected_nodes == self.abandoned_rejections;
+            // Retrying would not help.
+            if matches!(
+                reason,
+                FailureReason::Expired | FailureReason::QuorumUnavailable { .. }
+            ) {
+                break;
+            }
+        }
+
+        warn!(
+            slot = self.slot,
+            %last_ballot,
+            ?reason,
+            "giving up on the value"
+        );
+        self.latest_proposal = None;
+        self.timings = None;
+        Ok(ConsensusResult::Failed {
+            reason,
+            last_ballot,
+        })
+    }
+
+    /// Wait for the outcome of the round in flight. Returns `None` if a majority of
+    /// the acceptors rejected it, if one of its phases timed out, or once `deadline`
+    /// passes.
+    async fn drive_round(
+        &mut self,
+        value: u64,
+        deadline: Option<Instant>,
+    ) -> Result<Option<ConsensusResult>> {
+        let slot = self.slot;
+        let clock = self.clock.clone();
+        let mut prepare_deadline = clock.now() + self.prepare_timeout;
+        // Set once the accept requests are sent.
+        let mut accept_deadline = None;
+        loop {
+            if let Some(proposal) = self.learner.decision(slot) {
+                return Ok(Some(ConsensusResult::Decided {
+                    slot,
+                    proposal,
+                    by_other: proposal.ballot.node_id != self.id,
+                }));
+            }
+            if self.is_round_lost() {
+                info!(slot, "proposal rejected by a majority of acceptors");
+                return Ok(None);
+            }
+
+            let prepared = self.quorum(Phase::Prepare).is_reached(&self.prepared_nodes);
+            if prepared && accept_deadline.is_none() {
+                accept_deadline = Some(clock.now() + self.accept_timeout);
+            }
+            let envelope = tokio::select! {
+                envelope = self.transport.recv() => envelope,
+                _ = clock.sleep_until(prepare_deadline), if !prepared => {
+                    info!(
+                        slot,
+                        promised = ?sorted_ids(&self.prepared_nodes),
+                        "no prepare quorum before the deadline"
+                    );
+                    return Ok(None);
+                },
+                _ = clock.sleep_until(accept_deadline.unwrap_or(prepare_deadline)), if prepared => {
+                    info!(
+                        slot,
+                        accepted = ?sorted_ids(&self.accepted_value_nodes),
+                        "no accept quorum before the deadline"
+                    );
+                    return Ok(None);
+                },
+                _ = clock.sleep_until(deadline.unwrap_or(prepare_deadline)), if deadline.is_some() => {
+                    info!(slot, "value expired during its round");
+                    return Ok(None);
+                },
+                cluster = membership_change(&mut self.membership) => {
+                    self.reconfigure(cluster)?;
+                    info!(slot, "restarting the round under the new membership");
+                    telemetry::count(telemetry::ROUNDS_ABANDONED, self.id, 1);
+                    self.next_slot = slot;
+                    self.send_prepare_request(value).await?;
+                    prepare_deadline = clock.now() + self.prepare_timeout;
+                    accept_deadline = None;
+                    continue;
+                },
+                // The round may have become impossible to win.
+                _ = suspicion_change(&mut self.suspected) => continue,
+            };
+            let Some(envelope) = envelope else {
+                bail!("transport closed during the round for slot {slot}");
+            };
+            self.handle_message(envelope).await?;
+        }
+    }
+
+    /// Ask the acceptors whether a prepare request for the next slot would be
+    /// promised by a quorum. Nothing is promised, and the ballot of the proposer is
+    /// left untouched. Gives up after `PRE_VOTE_TIMEOUT`.
+    #[tracing::instrument(skip(self), fields(node_id = self.id, epoch = self.epoch))]
+    pub async fn pre_vote(&mut self) -> bool {
+        let pre_vote = PreVoteBody {
+            issuer_id: self.id,
+            epoch: self.epoch,
+            slot: self.next_slot,
+            ballot: self.ballot.next(self.id, self.priority),
+            granted: false,
+        };
+        if let Err(e) = self
+            .transport
+            .broadcast(Message::PreVoteRequest {
+                body: pre_vote.clone(),
+            })
+            .await
+        {
+            warn!("could not send pre-vote: {e}");
+            return false;
+        }
+
+        let clock = self.clock.clone();
+        let deadline = clock.now() + PRE_VOTE_TIMEOUT;
+        let mut granted = HashSet::new();
+        let mut denied = HashSet::new();
+        loop {
+            if self.quorum(Phase::Prepare).is_reached(&granted) {
+                debug!(granted = granted.len(), "pre-vote won");
+                return true;
+            }
+            if self.quorum(Phase::Prepare).is_lost(&denied) {
+                info!(denied = denied.len(), "pre-vote lost");
+                return false;
+            }
+
+            let envelope =
+                match clock::timeout_at(&*clock, deadline, self.transport.recv()).await
+                {
+                    Some(Some(envelope)) => envelope,
+                    Some(None) => return false,
+                    None => {
+                        info!(granted = granted.len(), "pre-vote timed out");
+                        return false;
+                    }
+                };
+
+            match envelope.message {
+                Message::PreVoteResponse { body }
+                    if body.issuer_id == envelope.from
+                        && body.epoch == pre_vote.epoch
+                        && body.slot == pre_vote.slot
+                        && body.ballot == pre_vote.ballot =>
+                {
+                    if body.granted {
+                        granted.insert(body.issuer_id);
+                    } else {
+                        denied.insert(body.issuer_id);
+                    }
+                }
+                _ => {
+                    if let Err(e) = self.handle_message(envelope).await {
+                        warn!("could not handle message during pre-vote: {e}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run a prepare phase for the next slot with a fresh ballot, without proposing
+    /// any value, to establish this proposer as the leader, e.g. when the cluster
+    /// starts cold. Returns whether a quorum of the acceptors promised the ballot
+    /// within `deadline`.
+    ///
+    /// Every round still runs a prepare phase of its own, from the ballot won here.
+    /// The other proposers learn of the new leader once their own requests get
+    /// rejected for that ballot, and then redirect their clients to it. Two
+    /// proposers contending at the same time may both win, one after the other:
+    /// only the last one remains the leader.
+    #[tracing::instrument(skip(self), fields(node_id = self.id, epoch = self.epoch))]
+    pub async fn elect_leader(&mut self, deadline: Duration) -> Result<bool> {
+        let clock = self.clock.clone();
+        let deadline = clock.now() + deadline;
+        let proposal_id = self.id_generator.next(self.id);
+        self.ballot = self.ballot.next(self.id, self.priority);
+        let prepare = PreparePhaseBody {
+            issuer_id: self.id,
+            epoch: self.epoch,
+            slot: self.next_slot,
+            proposal_id,
+            ballot: self.ballot,
+            accepted: None,
+            nonce: rand::random(),
+            sent_at: Some(clock.now()),
+        };
+        self.transport
+            .broadcast(Message::PrepareRequest {
+                body: prepare.clone(),
+            })
+            .await
+            .context("could not broadcast prepare request")?;
+
+        let mut promised = HashSet::new();
+        let mut rejected = HashSet::new();
+        loop {
+            if self.quorum(Phase::Prepare).is_reached(&promised) {
+                info!(ballot = %prepare.ballot, "elected leader");
+                self.observe_leader(self.id);
+                return Ok(true);
+            }
+            if self.quorum(Phase::Prepare).is_lost(&rejected) {
+                info!(ballot = %prepare.ballot, "election lost");
+                return Ok(false);
+            }
+
+            let envelope =
+                match clock::timeout_at(&*clock, deadline, self.transport.recv()).await
+                {
+                    Some(Some(envelope)) => envelope,
+                    Some(None) => bail!("transport closed during the election"),
+                    None => {
+                        info!(promised = ?sorted_ids(&promised), "election timed out");
+                        return Ok(false);
+                    }
+                };
+
+            match envelope.message {
+                Message::PrepareResponse { body }
+                    if body.issuer_id == envelope.from
+                        && body.epoch == prepare.epoch
+                        && body.slot == prepare.slot
+                        && body.ballot == prepare.ballot
+                        && body.nonce == prepare.nonce =>
+                {
+                    promised.insert(body.issuer_id);
+                }
+                Message::PrepareReject { body }
+                    if body.issuer_id == envelope.from
+                        && body.epoch == prepare.epoch
+                        && body.proposal_id == proposal_id =>
+                {
+                    rejected.insert(body.issuer_id);
+                    let competing_ballot = body.reason.ballot();
+                    if competing_ballot > self.ballot {
+                        self.ballot =
+                            BallotNumber::new(competing_ballot.round, self.id)
+                                .with_priority(self.priority);
+                    }
+                    if competing_ballot.node_id != self.id {
+                        self.observe_leader(competing_ballot.node_id);
+                    }
+                }
+                _ => {
+                    if let Err(e) = self.handle_message(envelope).await {
+                        warn!("could not handle message during the election: {e}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tell the acceptors that the ballot of the latest proposal will not be used
+    /// anymore. They still hold to it, but let the proposers it outbids know that
+    /// they can retry right away. Returns how many acceptors the abort was sent to.
+    #[tracing::instrument(skip(self), fields(node_id = self.id, epoch = self.epoch))]
+    pub async fn abort_round(&mut self) -> Result<usize> {
+        let Some(proposal) = self.latest_proposal else {
+            return Ok(0);
+        };
+        debug!(slot = self.slot, ballot = %proposal.ballot, "aborting round");
+        self.transport
+            .broadcast(Message::AbortRound {
+                body: AbortBody {
+                    issuer_id: self.id,
+                    epoch: self.epoch,
+                    slot: self.slot,
+                    ballot: proposal.ballot,
+                },
+            })
+            .await
+            .context("could not broadcast abort")
+    }
+
+    /// Ask the acceptors for a lease of `duration` under a fresh ballot: those
+    /// granting it promise the ballot, and ignore the other proposers until it
+    /// expires. Returns whether a quorum granted it within `duration`.
+    ///
+    /// The lease is counted from when the request was sent, before any acceptor
+    /// started counting it, so that it expires here first. This only holds as long
+    /// as the clocks of the nodes run at the same pace.
+    #[tracing::instrument(skip(self), fields(node_id = self.id, epoch = self.epoch))]
+    pub async fn acquire_lease(&mut self, duration: Duration) -> Result<bool> {
+        let clock = self.clock.clone();
+        let sent_at = clock.now();
+        let deadline = sent_at + duration;
+        self.ballot = self.ballot.next(self.id, self.priority);
+        let lease = LeaseBody {
+            issuer_id: self.id,
+            epoch: self.epoch,
+            proposal_id: self.id_generator.next(self.id),
+            ballot: self.ballot,
+            duration,
+            granted: false,
+            highest_slot: None,
+        };
+        self.transport
+            .broadcast(Message::LeaseRequest {
+                body: lease.clone(),
+            })
+            .await
+            .context("could not broadcast lease request")?;
+
+        let mut granted = HashSet::new();
+        let mut denied = HashSet::new();
+        let mut highest_slot = None;
+        loop {
+            if self.quorum(Phase::Prepare).is_reached(&granted) {
+                debug!(ballot = %lease.ballot, ?duration, ?highest_slot, "lease acquired");
+                self.lease_expires_at = Some(deadline);
+                self.lease_highest_slot = highest_slot;
+                self.observe_leader(self.id);
+                return Ok(true);
+            }
+            if self.quorum(Phase::Prepare).is_lost(&denied) {
+                info!(denied = ?sorted_ids(&denied), "lease denied");
+                return Ok(false);
+            }
+
+            let envelope = match clock::timeout_at(
+                &*clock,
+                deadline,
+                self.transport.recv(),
+            )
+            .await
+            {
+                Some(Some(envelope)) => envelope,
+                Some(None) => bail!("transport closed while acquiring a lease"),
+                None => {
+                    info!(granted = ?sorted_ids(&granted), "lease request timed out");
+                    return Ok(false);
+                }
+            };
+
+            match envelope.message {
+                Message::LeaseResponse { body }
+                    if body.issuer_id == envelope.from
+                        && body.epoch == lease.epoch
+                        && body.proposal_id == lease.proposal_id =>
+                {
+                    if body.granted {
+                        granted.insert(body.issuer_id);
+                        highest_slot = highest_slot.max(body.highest_slot);
+                    } else {
+                        denied.insert(body.issuer_id);
+                    }
+                }
+                _ => {
+                    if let Err(e) = self.handle_message(envelope).await {
+                        warn!("could not handle message while acquiring a lease: {e}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether this proposer holds a lease that has not expired: no other proposer
+    /// can then win a prepare phase, so no slot can be chosen without it.
+    pub fn has_lease(&self) -> bool {
+        self.lease_expires_at
+            .is_some_and(|expires_at| self.clock.now() < expires_at)
+    }
+
+    /// Value decided for `slot`, `None` if nothing or only a no-op was chosen for
+    /// it. Without a lease, a prepare phase first confirms that this proposer is
+    /// still the leader, and the read fails if it is not.
+    ///
+    /// A slot this proposer learned is read locally. So is a slot above every one
+    /// the acceptors granting the lease knew a proposal of, and above the ones
+    /// decided since: nothing was chosen for it. Any other slot may have been
+    /// chosen without this proposer hearing of it, by another proposer before the
+    /// lease or by a quorum whose answers were lost: a round is run for it, which
+    /// adopts the value chosen if any, and fills the slot with a no-op otherwise.
+    #[tracing::instrument(skip(self), fields(node_id = self.id))]
+    pub async fn read(&mut self, slot: u64) -> Result<Option<u64>> {
+        if !self.has_lease() {
+            debug!("no lease, confirming leadership before reading");
+            if !self.elect_leader(self.prepare_timeout).await? {
+                bail!("could not confirm leadership to read slot {slot}");
+            }
+        } else if self.learner.decision(slot).is_none()
+            && self
+                .lease_highest_slot
+                .max(self.learner.highest_slot())
+                .map_or(true, |highest| slot > highest)
+        {
+            return Ok(None);
+        }
+
+        let proposal = match self.learner.decision(slot) {
+            Some(proposal) => proposal,
+            None => {
+                debug!(slot, "slot not learned, running a round for it");
+                match self.propose_at(slot, Command::Noop.encode()).await? {
+                    ConsensusResult::Decided { proposal, .. } => proposal,
+                    ConsensusResult::Failed { last_ballot, .. } => {
+                        bail!("could not learn slot {slot}, last ballot {last_ballot}")
+                    }
+                }
+            }
+        };
+        Ok(match Command::decode(proposal.value) {
+            Command::Noop => None,
+            _ => Some(proposal.value),
+        })
+    }
+
+    /// Handle `message` as if it had been received from the transport, sent by its
+    /// issuer. Meant for tests, which can then drive a round one response at a time
+    /// without any transport: what the proposer sends is still broadcast through
+    /// its own.
+    pub async fn inject_message(&mut self, message: Message) -> Result<()> {
+        self.handle_message(Envelope {
+            from: message.issuer_id(),
+            message,
+        })
+        .await
+    }
+
+    /// Dispatch a message received from the transport to its handler.
+    pub async fn handle_message(&mut self, envelope: Envelope) -> Result<()> {
+        let Envelope {
+            from,
+            message: received_message,
+        } = envelope;
+
+        // Only trust the issuer id of a message if it matches the node the transport
+        // received it from, otherwise an acceptor could vote on behalf of another one.
+        if received_message.issuer_id() != from {
+            warn!(
+                from,
+                issuer_id = received_message.issuer_id(),
+                "dropping message with spoofed issuer id"
+            );
+            return Ok(());
+        }
+        // A node that was removed from the cluster, or that joined it before this
+        // proposer heard of it, must not count towards a quorum.
+        if !self.quorum(Phase::Accept).contains(from) {
+            debug!(from, "ignoring message from a node that is not an acceptor");
+            return Ok(());
+        }
+
+        telemetry::count_response(self.id, from);
+        match received_message {
+            Message::PrepareResponse { body } => {
+                telemetry::count(telemetry::PROMISES_RECEIVED, self.id, 1);
+                self.handle_prepare_response(body).await?;
+            }
+            Message::AcceptResponse { body } => {
+                telemetry::count(telemetry::ACCEPT_RESPONSES_RECEIVED, self.id, 1);
+                self.handle_accept_response(body);
+            }
+            Message::PrepareReject { body } | Message::AcceptReject { body } => {
+                telemetry::count(telemetry::NACKS_RECEIVED, self.id, 1);
+                self.handle_reject(body);
+            }
+            _ => (),
+        }
+        self.publish_status();
+        self.mark_persisted_if_decided().await
+    }
+
+    /// Mark the proposal persisted for a crash as decided once its slot is, so
+    /// that it is not resumed, and the slot learned again, after every restart.
+    async fn mark_persisted_if_decided(&mut self) -> Result<()> {
+        let Some(slot) = self.persisted_slot else {
+            return Ok(());
+        };
+        if self.learner.decision(slot).is_some() {
+            self.repository.mark_decided(slot).await?;
+            self.persisted_slot = None;
+        }
+        Ok(())
+    }
+
+    /// The beginning of the protocol. The proposer broadcasts a proposal to all the
+    /// acceptors, using a value it received from the client.
+    /// In this step, we also store how many nodes are active. This information is then
+    /// later used for computations that rely on quorum.
+    ///
+    /// Returns how many acceptors the requests were sent to: none when every one of
+    /// them disconnected.
+    #[tracing::instrument(skip(self, value), fields(
+        node_id = self.id,
+        epoch = self.epoch,
+        value = %self.logged(value),
+    ))]
+    pub async fn send_prepare_request(&mut self, value: u64) -> Result<usize> {
+        // A slot that is already decided can not be won anymore: its acceptors would
+        // only reject the proposal, or have it adopt the decided value. Move on to
+        // the first slot left, with a ballot they would not reject either.
+        while let Some(decided) = self.learner.decision(self.next_slot) {
+            debug!(slot = self.next_slot, ballot = %decided.ballot, "skipping decided slot");
+            if decided.ballot > self.ballot {
+                self.ballot = BallotNumber::new(decided.ballot.round, self.id)
+                    .with_priority(self.priority);
+            }
+            self.next_slot += 1;
+        }
+
+        // Start a new round: the votes of the previous one do not count anymore.
+        self.slot = self.next_slot;
+        self.next_slot += 1;
+        self.clear_votes();
+
+        let proposal_id = self.id_generator.next(self.id);
+        if let Some(round_span) = &self.round_span {
+            round_span.record("proposal_id", proposal_id.formatted());
+        }
+        self.ballot = self.ballot.next(self.id, self.priority);
+        let new_proposal = Proposal::new(value, proposal_id, self.ballot);
+        if let Some((evicted, _)) = self.proposal_history.insert(proposal_id, value) {
+            debug!(
+                proposal_id = evicted.formatted(),
+                "proposal evicted from history"
+            );
+        }
+        if self.log_values {
+            debug!("current proposal history {:?}", &self.proposal_history);
+        } else {
+            debug!(
+                proposals = self.proposal_history.len(),
+                "current proposal history"
+            );
+        }
+
+        self.latest_proposal = Some(new_proposal);
+        self.round_nonce = rand::random();
+
+        let active_acceptors = self
+            .transport
+            .broadcast(Message::PrepareRequest {
+                body: PreparePhaseBody {
+                    issuer_id: self.id,
+                    epoch: self.epoch,
+                    slot: self.slot,
+                    proposal_id,
+                    ballot: self.ballot,
+                    accepted: None,
+                    nonce: self.round_nonce,
+                    sent_at: Some(self.clock.now()),
+                },
+            })
+            .await
+            .unwrap_or_else(|e| {
+                warn!("could not broadcast prepare requests: {e:#}");
+                0
+            });
+        if active_acceptors == 0 {
+            warn!(slot = self.slot, "no acceptor to send prepare requests to");
+            return Ok(0);
+        }
+        telemetry::count(telemetry::ROUNDS_STARTED, self.id, 1);
+        self.emit(PaxosEvent::RoundStarted {
+            slot: self.slot,
+            ballot: self.ballot,
+        });
+        if let Some(timings) = &mut self.timings {
+            timings.prepare_sent(self.clock.now());
+        }
+        telemetry::count(telemetry::PREPARES_SENT, self.id, active_acceptors as u64);
+
+        debug!(
+            slot = self.slot,
+            "proposing for {} acceptors", active_acceptors
+        );
+        Ok(active_acceptors)
+    }
+
+    #[tracing::instrument(skip_all, fields(
+        node_id = self.id,
+        epoch = self.epoch,
+        proposal_id = received_proposal.proposal_id.formatted()
+    ))]
+    pub async fn handle_prepare_response(
+        &mut self,
+        received_proposal: PreparePhaseBody,
+    ) -> Result<()> {
+        if self.is_stale_epoch(received_proposal.epoch)
+            || self.is_other_slot(received_proposal.slot)
+            || self.is_other_round(received_proposal.ballot)
+            || self.is_replayed(received_proposal.nonce)
+            || self.is_too_old(received_proposal.sent_at)
+        {
+            return Ok(());
+        }
+
+        let node_id = received_proposal.issuer_id;
+        debug!(
+            "received prepare response from node {}",
+            received_proposal.issuer_id
+        );
+
+        // A value may already have been chosen for this slot, in which case it is
+        // among the ones the acceptors accepted: propose the value with the highest
+        // ballot instead of our own, to keep the decision unchanged. Once a quorum
+        // promised, the value was already sent to be accepted under this ballot: a
+        // ballot must never be accepted with two values, so promises completing the
+        // quorum late do not change it anymore.
+        let was_prepared = self.quorum(Phase::Prepare).is_reached(&self.prepared_nodes);
+        if let (false, Some(accepted), Some(latest_proposal)) = (
+            was_prepared,
+            received_proposal.accepted,
+            self.latest_proposal.as_mut(),
+        ) {
+            let is_highest = self
+                .highest_accepted
+                .map_or(true, |highest| accepted.ballot > highest.ballot);
+            if is_highest {
+                debug!(
+                    ballot = %accepted.ballot,
+                    value = %Logged::new(accepted.value, self.log_values),
+                    "adopting value already accepted for the slot"
+                );
+                self.highest_accepted = Some(accepted);
+                latest_proposal.value = accepted.value;
+                self.proposal_history
+                    .insert(latest_proposal.id, accepted.value);
+            }
+        }
+
+        if self.prepared_nodes.insert(node_id) {
+            self.record_vote(
+                VoteKind::Promise,
+                received_proposal.ballot,
+                received_proposal.proposal_id,
+                node_id,
+            );
+        }
+        self.emit(PaxosEvent::PromiseReceived {
+            slot: self.slot,
+            ballot: received_proposal.ballot,
+            acceptor: node_id,
+        });
+
+        if self.quorum(Phase::Prepare).is_reached(&self.prepared_nodes) {
+            if !was_prepared {
+                self.emit(PaxosEvent::QuorumPrepared {
+                    slot: self.slot,
+                    ballot: received_proposal.ballot,
+                    promised: sorted_ids(&self.prepared_nodes),
+                });
+            }
+            self.send_accept_request().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Ask the acceptors to accept the latest proposal. The proposal is persisted
+    /// before being broadcast, so that it can be resumed after a crash. Does nothing
+    /// if there is no proposal, e.g. when a quorum of promises completes after the
+    /// round was reset.
+    #[tracing::instrument(skip_all, fields(node_id = self.id, epoch = self.epoch))]
+    pub async fn send_accept_request(&mut self) -> Result<()> {
+        let Some(latest_proposal) = self.latest_proposal else {
+            warn!(slot = self.slot, "no proposal to send accept requests for");
+            return Ok(());
+        };
+        self.repository
+            .write_latest_value(self.slot, latest_proposal)
+            .await?;
+        self.persisted_slot = Some(self.slot);
+        let latest_proposal_id = latest_proposal.id;
+        let proposal_value =
+            self.proposal_history
+                .get(&latest_proposal_id)
+                .ok_or(anyhow::anyhow!(
+                    "could not find proposal {} in history",
+                    latest_proposal_id.to_string()
+                ))?;
+
+        let active_acceptors = self
+            .transport
+            .broadcast(Message::AcceptRequest {
+                body: AcceptPhaseBody {
+                    issuer_id: self.id,
+                    epoch: self.epoch,
+                    slot: self.slot,
+                    proposal_id: latest_proposal_id,
+                    ballot: latest_proposal.ballot,
+                    value: proposal_value,
+                    nonce: self.round_nonce,
+                    sent_at: Some(self.clock.now()),
+                },
+            })
+            .await
+            .context("could not broadcast accept requests")?;
+        telemetry::count(telemetry::ACCEPTS_SENT, self.id, active_acceptors as u64);
+        self.emit(PaxosEvent::AcceptSent {
+            slot: self.slot,
+            ballot: latest_proposal.ballot,
+            value: proposal_value,
+        });
+        let now = self.clock.now();
+        if let Some(prepare_latency) = self
+            .timings
+            .as_mut()
+            .and_then(|timings| timings.accept_sent(now))
+        {
+            telemetry::histogram(
+                telemetry::PREPARE_LATENCY,
+                self.id,
+                prepare_latency.as_secs_f64(),
+            );
+        }
+
+        debug!("accept sent for {} acceptors", active_acceptors);
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(node_id = self.id, epoch = self.epoch))]
+    pub fn handle_accept_response(&mut self, received_message: AcceptPhaseBody) {
+        let AcceptPhaseBody {
+            issuer_id,
+            epoch,
+            slot,
+            proposal_id,
+            ballot,
+            value,
+            nonce,
+            sent_at,
+        } = received_message;
+        if self.is_stale_epoch(epoch)
+            || self.is_other_slot(slot)
+            || self.is_other_round(ballot)
+            || self.is_other_proposal(proposal_id)
+            || self.is_replayed(nonce)
+            || self.is_too_old(sent_at)
+        {
+            return;
+        }
+
+        debug!(
+            value = %self.logged(value),
+            issuer_id,
+            proposal_id = proposal_id.formatted(),
+            "received accepted value",
+        );
+        if self.accepted_value_nodes.insert(issuer_id) {
+            self.record_vote(VoteKind::Accept, ballot, proposal_id, issuer_id);
+        }
+
+        if self
+            .quorum(Phase::Accept)
+            .is_reached(&self.accepted_value_nodes)
+        {
+            // At this point, we reached consensus. However, there will still be some
+            // remaining accept responses to be received by the proposer, which must
+            // not decide the slot again.
+            let decided = Proposal::new(value, proposal_id, ballot);
+            let quorum = sorted_ids(&self.accepted_value_nodes);
+            if self.decide(slot, decided, quorum.clone()) {
+                info!(
+                    slot,
+                    ?quorum,
+                    "quorum reached, value {} accepted",
+                    self.logged(value)
+                );
+            }
+        }
+    }
+
+    /// An acceptor refused the proposal in flight. Log why, and make sure the next
+    /// proposal outbids the ballot that caused the rejection.
+    #[tracing::instrument(skip_all, fields(
+        node_id = self.id,
+        epoch = self.epoch,
+        proposal_id = received_reject.proposal_id.formatted()
+    ))]
+    pub fn handle_reject(&mut self, received_reject: RejectBody) {
+        if self.is_stale_epoch(received_reject.epoch)
+            || self.is_other_slot(received_reject.slot)
+            || self.is_other_proposal(received_reject.proposal_id)
+        {
+            return;
+        }
+
+        let competing_ballot = received_reject.reason.ballot();
+        warn!(
+            issuer_id = received_reject.issuer_id,
+            reason = ?received_reject.reason,
+            "proposal rejected"
+        );
+        if competing_ballot > self.ballot {
+            self.ballot = BallotNumber::new(competing_ballot.round, self.id)
+                .with_priority(self.priority);
+        }
+        // The proposer that outbid us is the one making progress.
+        if competing_ballot.node_id != self.id {
+            self.observe_leader(competing_ballot.node_id);
+        }
+        self.rejected_nodes.insert(received_reject.issuer_id);
+        if received_reject.abandoned {
+            self.abandoned_rejections.insert(received_reject.issuer_id);
+        }
+
+        match received_reject.reason {
+            // The acceptor accepted the proposal of another proposer for this slot.
+            // If a quorum did, that proposal is chosen.
+            RejectReason::AlreadyAcceptedHigher {
+                accepted,
+                ballot,
+                slot,
+                value,
+            } if slot == self.slot => {
+                let tracker = self.quorum(Phase::Accept);
+                let voters = self.accepted_elsewhere.entry(ballot).or_default();
+                voters.insert(received_reject.issuer_id);
+                if !tracker.is_reached(voters) {
+                    return;
+                }
+                let quorum = sorted_ids(voters);
+                if self.decide(
+                    slot,
+                    Proposal::new(value, accepted, ballot),
+                    quorum.clone(),
+                ) {
+                    info!(
+                        slot,
+                        ballot = %ballot,
+                        ?quorum,
+                        "slot decided by another proposer, value {} accepted",
+                        self.logged(value)
+                    );
+                }
+            }
+            // The acceptor learned the decision of the slot: the round ends with it,
+            // without retrying for a slot that can not be won. Of its quorum, only
+            // the acceptor reporting it is known.
+            RejectReason::AlreadyDecided {
+                decided,
+                ballot,
+                slot,
+                value,
+            } if slot == self.slot => {
+                let reporter = received_reject.issuer_id;
+                if self.decide(
+                    slot,
+                    Proposal::new(value, decided, ballot),
+                    vec![reporter],
+                ) {
+                    info!(
+                        slot,
+                        ballot = %ballot,
+                        reporter,
+                        "slot already decided, value {} learned",
+                        self.logged(value)
+                    );
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Ids of `nodes`, in increasing order.
+fn sorted_ids(nodes: &HashSet<u64>) -> Vec<u64> {
+    let mut ids: Vec<u64> = nodes.iter().copied().collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Resolve with the next config published on `membership`. Never resolves if the
+/// membership is static, or can not change anymore.
+async fn membership_change(
+    membership: &mut Option<watch::Receiver<ClusterConfig>>,
+) -> ClusterConfig {
+    if let Some(receiver) = membership {
+        if receiver.changed().await.is_ok() {
+            return receiver.borrow_and_update().clone();
+        }
+        *membership = None;
+    }
+    std::future::pending().await
+}
+
+/// Resolve once the acceptors suspected by the failure detector change. Never
+/// resolves without a failure detector, or once it stopped.
+async fn suspicion_change(suspected: &mut Option<watch::Receiver<HashSet<u64>>>) {
+    if let Some(receiver) = suspected {
+        if receiver.changed().await.is_ok() {
+            return;
+        }
+    }
+    std::future::pending().await
+}
+
+impl Drop for Proposer {
+    fn drop(&mut self) {
+        println!("Proposer dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actors::acceptor::Acceptor,
+        clock::ManualClock,
+        domain::{
+            acceptor::{AcceptorLogic, AcceptorState},
+            command::CONFIG_CHANGE_FLAG,
+            id::SequentialIdGen,
+        },
+        repository::ValueRepositoryImpl,
+        testkit::{BroadcastLog, RecordingBroadcast},
+        transport::{
+            channel::{self, AcceptorChannel},
+            AcceptorTransport,
+        },
+    };
+
+    /// Acceptors of the cluster of `proposer`, with the ids `0..ACCEPTORS`.
+    const ACCEPTORS: u64 = 3;
+
+    /// Proposer of a cluster of `ACCEPTORS` acceptors that nothing answers, with
+    /// what it broadcasts recorded. The acceptor channels have to be kept for the
+    /// broadcasts to have receivers.
+    fn proposer() -> (Proposer, BroadcastLog, Vec<AcceptorChannel>) {
+        proposer_with(Arc::new(
+            ValueRepositoryImpl::in_memory().expect("in-memory repository"),
+        ))
+    }
+
+    /// Like `proposer`, keeping its state in `repository`.
+    fn proposer_with(
+        repository: Arc<ValueRepositoryImpl>,
+    ) -> (Proposer, BroadcastLog, Vec<AcceptorChannel>) {
+        let cluster = ClusterConfig::in_process(ACCEPTORS as usize);
+        let (transport, acceptors) = channel::wire(&cluster, false);
+        let transport = RecordingBroadcast::new(Box::new(transport));
+        let log = transport.log();
+        let (_client, client_receiver) = mpsc::channel(1);
+        let proposer = Proposer::new(
+            ACCEPTORS,
+            cluster,
+            Box::new(transport),
+            client_receiver,
+            repository,
+        );
+        (proposer, log, acceptors)
+    }
+
+    /// Body of the last prepare request broadcast.
+    fn last_prepare(log: &BroadcastLog) -> PreparePhaseBody {
+        log.recorded()
+            .into_iter()
+            .rev()
+            .find_map(|broadcast| match broadcast.message {
+                Message::PrepareRequest { body } => Some(body),
+                _ => None,
+            })
+            .expect("no prepare request broadcast")
+    }
+
+    /// Bodies of every accept request broadcast, in order.
+    fn accept_requests(log: &BroadcastLog) -> Vec<AcceptPhaseBody> {
+        log.recorded()
+            .into_iter()
+            .filter_map(|broadcast| match broadcast.message {
+                Message::AcceptRequest { body } => Some(body),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Promise of `acceptor` for `request`, reporting `accepted`.
+    fn promise(
+        request: &PreparePhaseBody,
+        acceptor: u64,
+        accepted: Option<Proposal>,
+    ) -> Message {
+        Message::PrepareResponse {
+            body: PreparePhaseBody {
+                issuer_id: acceptor,
+                accepted,
+                ..request.clone()
+            },
+        }
+    }
+
+    /// Proposal accepted in an earlier round of another proposer.
+    fn accepted(round: u64, value: u64) -> Proposal {
+        Proposal::new(
+            value,
+            ProposalId(uuid::Uuid::from_u128(u128::from(round))),
+            BallotNumber::new(round, 99),
+        )
+    }
+
+    /// Answer the requests the acceptors receive, as fresh acceptors would.
+    fn answer(acceptors: Vec<AcceptorChannel>) {
+        answer_from(acceptors, AcceptorState::new);
+    }
+
+    /// Answer the requests the acceptors receive, each of them starting from the
+    /// state `state` gives for its id.
+    fn answer_from(acceptors: Vec<AcceptorChannel>, state: fn(u64) -> AcceptorState) {
+        for mut channel in acceptors {
+            tokio::spawn(async move {
+                let mut state = state(channel.id);
+                while let Some(envelope) = channel.recv().await {
+                    if let Some(reply) = state.handle(envelope.message) {
+                        let _ = channel.send(envelope.from, reply).await;
+                    }
+                }
+            });
+        }
+    }
+
+    /// Answer the requests the acceptors receive from the state in `states` of
+    /// each, shared with the acceptors of other proposers.
+    fn answer_shared(
+        acceptors: Vec<AcceptorChannel>,
+        states: &[Arc<std::sync::Mutex<AcceptorState>>],
+    ) {
+        for mut channel in acceptors {
+            let state = states[channel.id as usize].clone();
+            tokio::spawn(async move {
+                while let Some(envelope) = channel.recv().await {
+                    let reply = state.lock().unwrap().handle(envelope.message);
+                    if let Some(reply) = reply {
+                        let _ = channel.send(envelope.from, reply).await;
+                    }
+                }
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn reserved_value_is_not_proposed() {
+        let (mut proposer, log, _acceptors) = proposer();
+        let outcome = proposer.propose(CONFIG_CHANGE_FLAG | 1).await.unwrap();
+
+        assert!(matches!(
+            outcome,
+            ProposeOutcome::Decided(ConsensusResult::Failed {
+                reason: FailureReason::Reserved,
+                ..
+            })
+        ));
+        assert!(log.recorded().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pre_propose_rewrites_the_value_decided() {
+        let (mut proposer, log, acceptors) = proposer();
+        answer(acceptors);
+        proposer.pre_propose = Some(Box::new(|value| (value + 5) / 10 * 10));
+
+        let outcome = proposer.propose(17).await.unwrap();
+
+        assert!(matches!(
+            outcome,
+            ProposeOutcome::Decided(ConsensusResult::Decided { proposal, .. })
+                if proposal.value == 20
+        ));
+        let accepts = accept_requests(&log);
+        assert!(accepts.iter().all(|accept| accept.value == 20));
+        assert_eq!(
+            proposer.proposal_history.peek(&accepts[0].proposal_id),
+            Some(20)
+        );
+    }
+
+    #[tokio::test]
+    async fn pre_propose_can_not_rewrite_a_value_into_a_command() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.pre_propose = Some(Box::new(|value| value | CONFIG_CHANGE_FLAG));
+
+        let outcome = proposer.propose(17).await.unwrap();
+
+        assert!(matches!(
+            outcome,
+            ProposeOutcome::Decided(ConsensusResult::Failed {
+                reason: FailureReason::Reserved,
+                ..
+            })
+        ));
+        assert!(log.recorded().is_empty());
+    }
+
+    #[tokio::test]
+    async fn adopts_the_accepted_value_with_the_highest_ballot() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.ballot = BallotNumber::new(10, ACCEPTORS);
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+
+        proposer
+            .inject_message(promise(&prepare, 0, Some(accepted(7, 70))))
+            .await
+            .unwrap();
+        proposer
+            .inject_message(promise(&prepare, 1, Some(accepted(3, 30))))
+            .await
+            .unwrap();
+
+        let accepts = accept_requests(&log);
+        assert_eq!(accepts.len(), 1);
+        assert_eq!(accepts[0].value, 70);
+        assert_eq!(accepts[0].ballot, prepare.ballot);
+    }
+
+    #[tokio::test]
+    async fn late_promise_does_not_change_the_value_sent_to_be_accepted() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.ballot = BallotNumber::new(10, ACCEPTORS);
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+
+        proposer
+            .inject_message(promise(&prepare, 0, None))
+            .await
+            .unwrap();
+        proposer
+            .inject_message(promise(&prepare, 1, None))
+            .await
+            .unwrap();
+        // The quorum already promised: the value of the ballot is set.
+        proposer
+            .inject_message(promise(&prepare, 2, Some(accepted(7, 70))))
+            .await
+            .unwrap();
+
+        let accepts = accept_requests(&log);
+        assert!(!accepts.is_empty());
+        assert!(
+            accepts.iter().all(|accept| accept.value == 1),
+            "ballot {} sent with several values: {accepts:?}",
+            prepare.ballot
+        );
+    }
+
+    #[tokio::test]
+    async fn resumed_proposer_decides_the_value_it_sent_before_crashing() {
+        let repository =
+            Arc::new(ValueRepositoryImpl::in_memory().expect("in-memory repository"));
+        let (mut crashed, log, _acceptors) = proposer_with(repository.clone());
+        crashed.ballot = BallotNumber::new(10, ACCEPTORS);
+        crashed.send_prepare_request(17).await.unwrap();
+        let prepare = last_prepare(&log);
+        for acceptor in 0..2 {
+            crashed
+                .inject_message(promise(&prepare, acceptor, None))
+                .await
+                .unwrap();
+        }
+        let sent = accept_requests(&log).pop().expect("no accept request sent");
+        // Crash before any acceptor answers the accept request.
+        drop(crashed);
+
+        let (mut resumed, log, acceptors) = proposer_with(repository);
+        answer(acceptors);
+        resumed.resume().await.unwrap();
+        let resent = accept_requests(&log)
+            .pop()
+            .expect("accept request not resent");
+        assert_eq!(
+            (resent.slot, resent.proposal_id, resent.value),
+            (sent.slot, sent.proposal_id, 17)
+        );
+        while resumed.learner.decision(sent.slot).is_none() {
+            let envelope = resumed.transport.recv().await.unwrap();
+            resumed.handle_message(envelope).await.unwrap();
+        }
+
+        assert_eq!(resumed.learner.decision(sent.slot).unwrap().value, 17);
+        assert!(resumed.ballot >= prepare.ballot);
+    }
+
+    #[tokio::test]
+    async fn proposal_of_a_decided_slot_is_not_resumed_again() {
+        let repository =
+            Arc::new(ValueRepositoryImpl::in_memory().expect("in-memory repository"));
+        let (mut proposer, _log, acceptors) = proposer_with(repository.clone());
+        answer(acceptors);
+        let decided = proposer.run_round(17).await.unwrap();
+        let ConsensusResult::Decided { proposal, .. } = decided else {
+            panic!("value not decided: {decided:?}");
+        };
+        let latest = repository.get_latest_value().await.unwrap().unwrap();
+        assert_eq!(
+            (latest.slot, latest.proposal, latest.decided),
+            (0, proposal, true)
+        );
+        drop(proposer);
+
+        let (mut restarted, log, _acceptors) = proposer_with(repository.clone());
+        restarted.resume().await.unwrap();
+
+        assert!(log.recorded().is_empty(), "decided round resumed");
+        // The next proposals still outbid the one of the decided slot.
+        assert!(restarted.ballot >= proposal.ballot);
+        assert_eq!(restarted.next_slot, 1);
+    }
+
+    #[tokio::test]
+    async fn round_learned_as_decided_since_the_crash_is_not_resumed() {
+        let repository =
+            Arc::new(ValueRepositoryImpl::in_memory().expect("in-memory repository"));
+        let (mut crashed, log, _acceptors) = proposer_with(repository.clone());
+        crashed.send_prepare_request(18).await.unwrap();
+        let prepare = last_prepare(&log);
+        for acceptor in 0..2 {
+            crashed
+                .inject_message(promise(&prepare, acceptor, None))
+                .await
+                .unwrap();
+        }
+        let sent = accept_requests(&log).pop().expect("no accept request sent");
+        drop(crashed);
+        let (mut restarted, log, _acceptors) = proposer_with(repository.clone());
+        restarted
+            .learner
+            .record(
+                sent.slot,
+                Proposal::new(sent.value, sent.proposal_id, sent.ballot),
+                vec![0, 1],
+            )
+            .unwrap();
+
+        restarted.resume().await.unwrap();
+
+        assert!(accept_requests(&log).is_empty(), "decided round resumed");
+        assert!(
+            repository
+                .get_latest_value()
+                .await
+                .unwrap()
+                .unwrap()
+                .decided
+        );
+        assert!(restarted.ballot >= sent.ballot);
+    }
+
+    #[tokio::test]
+    async fn responses_stamped_with_the_epoch_before_a_reconfiguration_are_ignored() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer
+            .reconfigure(ClusterConfig::in_process(ACCEPTORS as usize))
+            .unwrap();
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+        assert_eq!(prepare.epoch, 1);
+
+        let stale = |acceptor| match promise(&prepare, acceptor, None) {
+            Message::PrepareResponse { body } => Message::PrepareResponse {
+                body: PreparePhaseBody { epoch: 0, ..body },
+            },
+            _ => unreachable!(),
+        };
+        for acceptor in 0..ACCEPTORS {
+            proposer.inject_message(stale(acceptor)).await.unwrap();
+        }
+        assert!(accept_requests(&log).is_empty());
+
+        for acceptor in 0..2 {
+            proposer
+                .inject_message(promise(&prepare, acceptor, None))
+                .await
+                .unwrap();
+        }
+        let accepts = accept_requests(&log);
+        assert_eq!(accepts.len(), 1);
+        assert_eq!(accepts[0].epoch, 1);
+    }
+
+    #[tokio::test]
+    async fn waiter_of_a_slot_gets_its_decision_once_a_quorum_accepted() {
+        let (mut proposer, _log, acceptors) = proposer();
+        answer(acceptors);
+        let slot = proposer.next_slot;
+        let learner = proposer.learner.clone();
+        let waiter = tokio::spawn(async move { learner.wait_for_decision(slot).await });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        proposer.propose(5).await.unwrap();
+
+        let decided = waiter.await.unwrap();
+        assert_eq!(decided.value, 5);
+        assert_eq!(Some(decided), proposer.learner.decision(slot));
+    }
+
+    #[tokio::test]
+    async fn spoofed_promise_does_not_count_toward_the_quorum() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.ballot = BallotNumber::new(10, ACCEPTORS);
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+
+        // Acceptor 2 answers on behalf of acceptor 0, and for itself.
+        proposer
+            .handle_message(Envelope {
+                from: 2,
+                message: promise(&prepare, 0, None),
+            })
+            .await
+            .unwrap();
+        proposer
+            .inject_message(promise(&prepare, 1, None))
+            .await
+            .unwrap();
+        assert!(accept_requests(&log).is_empty());
+        assert_eq!(proposer.prepared_nodes, HashSet::from([1]));
+
+        proposer
+            .inject_message(promise(&prepare, 0, None))
+            .await
+            .unwrap();
+        assert_eq!(accept_requests(&log).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reports_the_value_another_proposer_got_decided_for_the_slot() {
+        let (mut proposer, _log, acceptors) = proposer();
+        // Every acceptor accepted the value of proposer 99 for the first slot.
+        answer_from(acceptors, |id| {
+            let mut state = AcceptorState::new(id);
+            state.accepted.insert(0, accepted(5, 50));
+            state
+        });
+
+        let outcome = proposer.propose(10).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Decided {
+                    slot: 0,
+                    proposal,
+                    by_other: true,
+                }) if proposal.value == 50 && proposal.ballot == BallotNumber::new(5, 99)
+            ),
+            "{outcome:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn value_of_an_already_decided_slot_is_learned_in_a_single_exchange() {
+        let (mut proposer, log, acceptors) = proposer();
+        answer_from(acceptors, |id| {
+            let mut state = AcceptorState::new(id);
+            state.decided.insert(0, accepted(5, 50));
+            state
+        });
+
+        let result = proposer.run_round(10).await.unwrap();
+
+        assert!(
+            matches!(
+                result,
+                ConsensusResult::Decided {
+                    slot: 0,
+                    proposal: Proposal { value: 50, .. },
+                    by_other: true,
+                }
+            ),
+            "{result:?}"
+        );
+        let prepares = log
+            .recorded()
+            .into_iter()
+            .filter(|broadcast| {
+                matches!(broadcast.message, Message::PrepareRequest { .. })
+            })
+            .count();
+        assert_eq!(prepares, 1);
+        assert!(accept_requests(&log).is_empty());
+    }
+
+    #[tokio::test]
+    async fn decided_hook_is_called_once_per_decided_value() {
+        let (mut proposer, _log, acceptors) = proposer();
+        answer(acceptors);
+        let decided = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = decided.clone();
+        proposer.on_decided = Some(Box::new(move |result| {
+            if let ConsensusResult::Decided { proposal, .. } = result {
+                sink.lock().unwrap().push(proposal.value);
+            }
+        }));
+
+        proposer.propose(5).await.unwrap();
+        proposer.propose(6).await.unwrap();
+        // Learning of the first decision again, e.g. from a late acceptance.
+        let first = proposer.learner.decision(0).unwrap();
+        assert!(!proposer.decide(0, first, vec![0, 1, 2]));
+
+        assert_eq!(*decided.lock().unwrap(), vec![5, 6]);
+    }
+
+    #[tokio::test]
+    async fn latest_decided_holds_the_value_of_the_last_decision() {
+        let (mut proposer, _log, acceptors) = proposer();
+        answer(acceptors);
+        let latest = proposer.latest_decided.subscribe();
+        assert_eq!(*latest.borrow(), None);
+
+        for value in [1, 2] {
+            proposer.propose(value).await.unwrap();
+            assert!(
+                matches!(
+                    *latest.borrow(),
+                    Some(ConsensusResult::Decided { proposal, .. }) if proposal.value == value
+                ),
+                "{:?}",
+                *latest.borrow()
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drain_reports_the_queued_values_it_could_not_decide() {
+        let (mut proposer, _log, _acceptors) = proposer();
+        let (client, client_receiver) = mpsc::channel(3);
+        proposer.client_receiver = client_receiver;
+        for value in [1, 2, 3] {
+            client.send(value).await.unwrap();
+        }
+
+        let undecided = proposer.drain(Duration::from_secs(1)).await;
+
+        assert_eq!(undecided, vec![1, 2, 3]);
+        assert!(client.send(4).await.is_err(), "client still accepted");
+    }
+
+    #[tokio::test]
+    async fn drain_decides_the_queued_values_in_time() {
+        let (mut proposer, _log, acceptors) = proposer();
+        answer(acceptors);
+        let (client, client_receiver) = mpsc::channel(3);
+        proposer.client_receiver = client_receiver;
+        for value in [1, 2, 3] {
+            client.send(value).await.unwrap();
+        }
+
+        let undecided = proposer.drain(Duration::from_secs(5)).await;
+
+        assert!(undecided.is_empty(), "{undecided:?}");
+        assert_eq!(proposer.learner.latest_value(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn queued_values_start_their_round_once_the_previous_one_is_decided() {
+        let (mut proposer, _log, acceptors) = proposer();
+        answer(acceptors);
+        let (client, client_receiver) = mpsc::channel(3);
+        proposer.client_receiver = client_receiver;
+        let mut events = proposer.events.subscribe();
+        for value in [1, 2, 3] {
+            client.send(value).await.unwrap();
+        }
+        assert_eq!(proposer.in_flight_count(), 0);
+
+        let undecided = proposer.drain(Duration::from_secs(5)).await;
+
+        assert!(undecided.is_empty(), "{undecided:?}");
+        assert_eq!(proposer.in_flight_count(), 0);
+        let mut in_flight = None;
+        let mut decided = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            match event {
+                PaxosEvent::AcceptSent { slot, .. } => {
+                    assert!(
+                        in_flight.is_none_or(|open| open == slot),
+                        "slot {slot} started while {in_flight:?} was in flight"
+                    );
+                    in_flight = Some(slot);
+                }
+                PaxosEvent::Chosen { slot, value, .. } => {
+                    assert_eq!(in_flight.take(), Some(slot));
+                    decided.push(value);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(decided, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn rejoining_proposer_losing_its_pre_vote_does_not_disrupt_the_leader() {
+        let (mut proposer, log, acceptors) = proposer();
+        // The leader, proposer 99, went on deciding slots while this one was away.
+        answer_from(acceptors, |id| {
+            let mut state = AcceptorState::new(id);
+            state.accepted.insert(5, accepted(5, 50));
+            state
+        });
+        proposer.pre_vote_enabled = true;
+        let ballot = proposer.ballot;
+
+        let outcome = proposer.propose(10).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Failed {
+                    reason: FailureReason::PreVoteLost,
+                    ..
+                })
+            ),
+            "{outcome:?}"
+        );
+        assert_eq!(proposer.ballot, ballot);
+        assert!(log.recorded().iter().all(|broadcast| matches!(
+            broadcast.message,
+            Message::PreVoteRequest { .. }
+        )));
+    }
+
+    #[tokio::test]
+    async fn pre_vote_is_won_against_idle_acceptors() {
+        let (mut proposer, _log, acceptors) = proposer();
+        answer(acceptors);
+        let ballot = proposer.ballot;
+
+        assert!(proposer.pre_vote().await);
+        assert_eq!(proposer.ballot, ballot);
+    }
+
+    #[tokio::test]
+    async fn single_proposer_elects_itself_leader() {
+        let (mut proposer, log, acceptors) = proposer();
+        answer(acceptors);
+
+        assert!(proposer.elect_leader(Duration::from_secs(1)).await.unwrap());
+
+        assert_eq!(proposer.leader_hint, Some(proposer.id));
+        assert!(accept_requests(&log).is_empty());
+        let outcome = proposer.propose(7).await.unwrap();
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Decided { proposal, .. })
+                    if proposal.value == 7
+            ),
+            "{outcome:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_one_of_two_contending_proposers_wins_the_election() {
+        let states: Vec<_> = (0..ACCEPTORS)
+            .map(|id| Arc::new(std::sync::Mutex::new(AcceptorState::new(id))))
+            .collect();
+        let (mut first, _log, acceptors) = proposer();
+        answer_shared(acceptors, &states);
+        let (mut second, _log, acceptors) = proposer();
+        answer_shared(acceptors, &states);
+        // Same round, so the ballot of the proposer with the higher id wins.
+        second.id = ACCEPTORS + 1;
+
+        let deadline = Duration::from_secs(1);
+        assert!(second.elect_leader(deadline).await.unwrap());
+        assert!(!first.elect_leader(deadline).await.unwrap());
+
+        assert_eq!(second.leader_hint, Some(second.id));
+        assert_ne!(first.leader_hint, Some(first.id));
+    }
+
+    #[tokio::test]
+    async fn late_rejects_of_an_earlier_attempt_do_not_lose_the_round() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.send_prepare_request(1).await.unwrap();
+        let earlier = last_prepare(&log);
+        // Retried for the same slot, with a higher ballot.
+        proposer.next_slot = earlier.slot;
+        proposer.send_prepare_request(1).await.unwrap();
+        let current = last_prepare(&log);
+        assert_eq!(current.slot, earlier.slot);
+
+        for acceptor in 0..2 {
+            proposer
+                .inject_message(Message::PrepareReject {
+                    body: RejectBody {
+                        issuer_id: acceptor,
+                        epoch: earlier.epoch,
+                        slot: earlier.slot,
+                        proposal_id: earlier.proposal_id,
+                        reason: RejectReason::AlreadyPromisedHigher {
+                            promised: accepted(9, 0).id,
+                            ballot: BallotNumber::new(9, 99),
+                        },
+                        abandoned: false,
+                    },
+                })
+                .await
+                .unwrap();
+        }
+
+        assert!(proposer.rejected_nodes.is_empty());
+        assert!(!proposer.is_round_lost());
+        assert_eq!(proposer.ballot, current.ballot);
+        assert_eq!(proposer.leader_hint, None);
+    }
+
+    #[tokio::test]
+    async fn follower_redirects_client_values_to_the_leader_it_learned_of() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+        // Acceptor 0 promised the ballot of proposer 99 already.
+        proposer
+            .inject_message(Message::PrepareReject {
+                body: RejectBody {
+                    issuer_id: 0,
+                    epoch: prepare.epoch,
+                    slot: prepare.slot,
+                    proposal_id: prepare.proposal_id,
+                    reason: RejectReason::AlreadyPromisedHigher {
+                        promised: accepted(9, 0).id,
+                        ballot: BallotNumber::new(9, 99),
+                    },
+                    abandoned: false,
+                },
+            })
+            .await
+            .unwrap();
+        assert_eq!(proposer.leader(), Some(99));
+        let broadcasts = log.recorded().len();
+
+        let outcome = proposer.propose(10).await.unwrap();
+
+        assert!(
+            matches!(outcome, ProposeOutcome::Redirect(99)),
+            "{outcome:?}"
+        );
+        assert_eq!(
+            log.recorded().len(),
+            broadcasts,
+            "a competing round started"
+        );
+    }
+
+    #[tokio::test]
+    async fn sequential_generator_numbers_the_proposals_in_order() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.id_generator = Box::new(SequentialIdGen::default());
+
+        for _ in 0..3 {
+            proposer.send_prepare_request(1).await.unwrap();
+        }
+
+        let ids: Vec<_> = log
+            .recorded()
+            .into_iter()
+            .filter_map(|broadcast| match broadcast.message {
+                Message::PrepareRequest { body } => Some(body.proposal_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            ids,
+            (1..=3)
+                .map(|n| ProposalId(uuid::Uuid::from_u128(n)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn round_outbid_on_every_attempt_fails_as_exhausted() {
+        let (mut proposer, log, acceptors) = proposer();
+        proposer.retry = RetryPolicy {
+            max_attempts: 4,
+            ..RetryPolicy::default()
+        };
+        // Proposer 99 always got a ballot one round higher promised first.
+        for mut channel in acceptors {
+            tokio::spawn(async move {
+                while let Some(envelope) = channel.recv().await {
+                    if let Message::PrepareRequest { body } = envelope.message {
+                        let reject = RejectBody {
+                            issuer_id: channel.id,
+                            epoch: body.epoch,
+                            slot: body.slot,
+                            proposal_id: body.proposal_id,
+                            reason: RejectReason::AlreadyPromisedHigher {
+                                promised: accepted(body.ballot.round + 1, 0).id,
+                                ballot: BallotNumber::new(body.ballot.round + 1, 99),
+                            },
+                            abandoned: false,
+                        };
+                        let _ = channel
+                            .send(
+                                envelope.from,
+                                Message::PrepareReject { body: reject },
+                            )
+                            .await;
+                    }
+                }
+            });
+        }
+
+        let outcome = proposer.propose(10).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Failed {
+                    reason: FailureReason::Exhausted,
+                    ..
+                })
+            ),
+            "{outcome:?}"
+        );
+        let prepares = log
+            .recorded()
+            .iter()
+            .filter(|broadcast| {
+                matches!(broadcast.message, Message::PrepareRequest { .. })
+            })
+            .count();
+        assert_eq!(prepares, 4);
+    }
+
+    /// Acceptor logic refusing every proposal, whatever its ballot.
+    #[derive(Clone)]
+    struct RefuseAll(AcceptorState);
+
+    impl AcceptorLogic for RefuseAll {
+        fn on_prepare(&mut self, request: PreparePhaseBody) -> Option<Message> {
+            let reason = RejectReason::Refused {
+                ballot: request.ballot,
+            };
+            Some(Message::PrepareReject {
+                body: self.0.reject(request.slot, request.proposal_id, reason),
+            })
+        }
+
+        fn on_accept(&mut self, request: AcceptPhaseBody) -> Option<Message> {
+            let reason = RejectReason::Refused {
+                ballot: request.ballot,
+            };
+            Some(Message::AcceptReject {
+                body: self.0.reject(request.slot, request.proposal_id, reason),
+            })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn value_refused_by_every_custom_acceptor_fails_once_retries_are_spent() {
+        let (mut proposer, log, acceptors) = proposer();
+        proposer.retry = RetryPolicy {
+            max_attempts: 3,
+            ..RetryPolicy::default()
+        };
+        let cluster = proposer.cluster.clone();
+        for channel in acceptors {
+            let id = channel.id;
+            let mut acceptor = Acceptor::with_logic(
+                id,
+                cluster.clone(),
+                Box::new(channel),
+                RefuseAll(AcceptorState::new(id)),
+            );
+            tokio::spawn(async move { acceptor.run().await });
+        }
+
+        let outcome = proposer.propose(10).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Failed { .. })
+            ),
+            "{outcome:?}"
+        );
+        let prepares = log
+            .recorded()
+            .iter()
+            .filter(|broadcast| {
+                matches!(broadcast.message, Message::PrepareRequest { .. })
+            })
+            .count();
+        assert_eq!(prepares, 3);
+        assert!(accept_requests(&log).is_empty());
+        assert_eq!(proposer.learner.latest_value(), None);
+    }
+
+    #[tokio::test]
+    async fn conditional_value_is_decided_only_over_the_expected_one() {
+        let (mut proposer, log, acceptors) = proposer();
+        answer(acceptors);
+
+        let outcome = proposer.propose_if(None, 5).await.unwrap();
+        assert!(
+            matches!(outcome, ConsensusResult::Decided { proposal, .. } if proposal.value == 5),
+            "{outcome:?}"
+        );
+        let outcome = proposer.propose_if(Some(5), 6).await.unwrap();
+        assert!(
+            matches!(outcome, ConsensusResult::Decided { proposal, .. } if proposal.value == 6),
+            "{outcome:?}"
+        );
+
+        let broadcasts = log.recorded().len();
+        let outcome = proposer.propose_if(Some(5), 7).await.unwrap();
+        assert!(
+            matches!(
+                outcome,
+                ConsensusResult::Failed {
+                    reason: FailureReason::Conflict { actual: Some(6) },
+                    ..
+                }
+            ),
+            "{outcome:?}"
+        );
+        assert_eq!(log.recorded().len(), broadcasts, "a round was run");
+        assert_eq!(proposer.learner.latest_value(), Some(6));
+    }
+
+    #[tokio::test]
+    async fn conditional_value_conflicts_with_a_value_found_by_the_prepare_phase() {
+        let (mut proposer, _log, acceptors) = proposer();
+        // Another proposer got 50 accepted for slot 0, unknown to this one.
+        answer_from(acceptors, |id| {
+            let mut state = AcceptorState::new(id);
+            state.accepted.insert(0, accepted(1, 50));
+            state
+        });
+
+        let outcome = proposer.propose_if(None, 5).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ConsensusResult::Failed {
+                    reason: FailureReason::Conflict { actual: Some(50) },
+                    ..
+                }
+            ),
+            "{outcome:?}"
+        );
+        assert_eq!(proposer.learner.decision(0).map(|p| p.value), Some(50));
+        assert_eq!(proposer.learner.decision(1), None);
+    }
+
+    #[tokio::test]
+    async fn decision_names_the_acceptors_of_its_quorum() {
+        let (mut proposer, _log, mut acceptors) = proposer();
+        // Acceptor 1 never answers.
+        let _silent = acceptors.remove(1);
+        answer(acceptors);
+        let mut decisions = proposer.learner.subscribe();
+
+        proposer.propose(5).await.unwrap();
+
+        let decision = decisions.recv().await.unwrap();
+        assert_eq!(decision.quorum, vec![0, 2]);
+        let record = proposer.learner.decision_log().pop().unwrap();
+        assert_eq!((record.slot, record.value), (0, 5));
+        assert_eq!(record.acceptors, vec![0, 2]);
+    }
+
+    #[tokio::test]
+    async fn prepare_quorum_reached_one_promise_at_a_time_starts_the_accept_phase() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.ballot = BallotNumber::new(10, ACCEPTORS);
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+
+        proposer
+            .inject_message(promise(&prepare, 0, None))
+            .await
+            .unwrap();
+        assert!(accept_requests(&log).is_empty());
+        // The same promise again does not make a quorum.
+        proposer
+            .inject_message(promise(&prepare, 0, None))
+            .await
+            .unwrap();
+        assert!(accept_requests(&log).is_empty());
+
+        proposer
+            .inject_message(promise(&prepare, 2, None))
+            .await
+            .unwrap();
+        let accepts = accept_requests(&log);
+        assert_eq!(accepts.len(), 1);
+        assert_eq!(accepts[0].ballot, prepare.ballot);
+        assert_eq!(accepts[0].value, 1);
+
+        // A promise past the quorum does not start another accept phase.
+        proposer
+            .inject_message(promise(&prepare, 1, None))
+            .await
+            .unwrap();
+        assert!(accept_requests(&log)
+            .iter()
+            .all(|accept| accept.ballot == prepare.ballot && accept.value == 1));
+    }
+
+    #[tokio::test]
+    async fn value_fails_cleanly_when_no_acceptor_is_connected() {
+        let (mut proposer, _log, acceptors) = proposer();
+        drop(acceptors);
+
+        let outcome = proposer.run_round(7).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ConsensusResult::Failed {
+                    reason: FailureReason::NoAcceptors,
+                    ..
+                }
+            ),
+            "{outcome:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn accept_request_without_a_proposal_is_skipped() {
+        let (mut proposer, log, _acceptors) = proposer();
+        assert!(proposer.latest_proposal.is_none());
+
+        proposer.send_accept_request().await.unwrap();
+
+        assert!(log.recorded().is_empty());
+    }
+
+    #[tokio::test]
+    async fn round_status_counts_the_promises_received_mid_prepare() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.ballot = BallotNumber::new(10, ACCEPTORS);
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+
+        proposer
+            .inject_message(promise(&prepare, 0, None))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            proposer.round_status(),
+            RoundStatus {
+                slot: prepare.slot,
+                ballot: prepare.ballot,
+                prepared: 1,
+                prepare_quorum: 2,
+                accepted: 0,
+                accept_quorum: 2,
+                in_flight: true,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn promises_to_a_request_older_than_the_max_age_are_not_counted() {
+        let (mut proposer, log, _acceptors) = proposer();
+        let clock = Arc::new(ManualClock::new());
+        proposer.clock = clock.clone();
+        proposer.max_message_age = Some(Duration::from_secs(1));
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+        assert!(prepare.sent_at.is_some());
+
+        clock.advance(Duration::from_secs(2));
+        for acceptor in [0, 1] {
+            proposer
+                .inject_message(promise(&prepare, acceptor, None))
+                .await
+                .unwrap();
+        }
+
+        assert!(accept_requests(&log).is_empty());
+        assert_eq!(proposer.round_status().prepared, 0);
+        // The same promises, sent in time, make a quorum.
+        let fresh = PreparePhaseBody {
+            sent_at: Some(clock.now()),
+            ..prepare
+        };
+        for acceptor in [0, 1] {
+            proposer
+                .inject_message(promise(&fresh, acceptor, None))
+                .await
+                .unwrap();
+        }
+        assert_eq!(accept_requests(&log).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn round_fails_right_away_when_too_few_acceptors_are_alive() {
+        let cluster = ClusterConfig::in_process(5);
+        let (transport, _acceptors) = channel::wire(&cluster, false);
+        let transport = RecordingBroadcast::new(Box::new(transport));
+        let log = transport.log();
+        let (_client, client_receiver) = mpsc::channel(1);
+        let mut proposer = Proposer::new(
+            5,
+            cluster,
+            Box::new(transport),
+            client_receiver,
+            Arc::new(ValueRepositoryImpl::in_memory().unwrap()),
+        );
+        let (_detector, suspected) = watch::channel(HashSet::from([2, 3, 4]));
+        proposer.suspected = Some(suspected);
+
+        let outcome = proposer.propose(7).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Failed {
+                    reason: FailureReason::QuorumUnavailable {
+                        live: 2,
+                        required: 3
+                    },
+                    ..
+                })
+            ),
+            "{outcome:?}"
+        );
+        assert!(log.recorded().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn round_in_flight_is_given_up_once_its_quorum_is_lost() {
+        let (mut proposer, log, _acceptors) = proposer();
+        let (detector, suspected) = watch::channel(HashSet::new());
+        proposer.suspected = Some(suspected);
+        let timeout = proposer.prepare_timeout;
+        let started = Instant::now();
+        let round = tokio::spawn(async move { proposer.propose(7).await });
+
+        tokio::time::sleep(timeout / 10).await;
+        assert!(!log.recorded().is_empty());
+        detector.send_replace(HashSet::from([1, 2]));
+        let outcome = round.await.unwrap().unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Failed {
+                    reason: FailureReason::QuorumUnavailable {
+                        live: 1,
+                        required: 2
+                    },
+                    ..
+                })
+            ),
+            "{outcome:?}"
+        );
+        assert!(started.elapsed() < timeout, "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn joint_accept_quorum_needs_a_majority_of_the_old_acceptors_too() {
+        let (mut proposer, log, _acceptors) = proposer();
+        // The cluster is moving to the acceptors 0..3 from the acceptors 1, 2, 4, 5
+        // and 6: the change is not committed yet.
+        let previous = proposer
+            .cluster
+            .with_acceptors(&std::collections::BTreeSet::from([1, 2, 4, 5, 6]));
+        proposer.joint = Some(previous);
+        proposer.ballot = BallotNumber::new(10, ACCEPTORS);
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+        for acceptor in [0, 1, 2, 4] {
+            proposer
+                .inject_message(promise(&prepare, acceptor, None))
+                .await
+                .unwrap();
+        }
+        let accept = accept_requests(&log)
+            .pop()
+            .expect("accept phase not started");
+        let acceptance = |acceptor| Message::AcceptResponse {
+            body: AcceptPhaseBody {
+                issuer_id: acceptor,
+                ..accept.clone()
+            },
+        };
+
+        // Every new acceptor, but only two of the five old ones.
+        for acceptor in [0, 1, 2] {
+            proposer.inject_message(acceptance(acceptor)).await.unwrap();
+        }
+        assert_eq!(proposer.learner.decision(accept.slot), None);
+
+        proposer.inject_message(acceptance(4)).await.unwrap();
+        assert_eq!(
+            proposer
+                .learner
+                .decision(accept.slot)
+                .map(|decided| decided.value),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn responses_replayed_with_the_nonce_of_another_round_are_ignored() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.ballot = BallotNumber::new(10, ACCEPTORS);
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+        let replayed = PreparePhaseBody {
+            nonce: prepare.nonce.wrapping_add(1),
+            ..prepare.clone()
+        };
+
+        for acceptor in [0, 1] {
+            proposer
+                .inject_message(promise(&replayed, acceptor, None))
+                .await
+                .unwrap();
+        }
+        assert!(accept_requests(&log).is_empty());
+        for acceptor in [0, 1] {
+            proposer
+                .inject_message(promise(&prepare, acceptor, None))
+                .await
+                .unwrap();
+        }
+        let accept = accept_requests(&log)
+            .pop()
+            .expect("accept phase not started");
+        let acceptance = |acceptor, nonce| Message::AcceptResponse {
+            body: AcceptPhaseBody {
+                issuer_id: acceptor,
+                nonce,
+                ..accept.clone()
+            },
+        };
+
+        for acceptor in [0, 1] {
+            proposer
+                .inject_message(acceptance(acceptor, accept.nonce.wrapping_add(1)))
+                .await
+                .unwrap();
+        }
+        assert_eq!(proposer.learner.decision(accept.slot), None);
+        for acceptor in [0, 1] {
+            proposer
+                .inject_message(acceptance(acceptor, accept.nonce))
+                .await
+                .unwrap();
+        }
+        assert!(proposer.learner.decision(accept.slot).is_some());
+    }
+
+    #[tokio::test]
+    async fn phases_wait_for_their_own_quorum_size() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.cluster.prepare_quorum = Some(1);
+        proposer.cluster.accept_quorum = Some(3);
+        proposer.ballot = BallotNumber::new(10, ACCEPTORS);
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+
+        proposer
+            .inject_message(promise(&prepare, 0, None))
+            .await
+            .unwrap();
+        let accept = accept_requests(&log)
+            .pop()
+            .expect("accept phase not started");
+        for acceptor in 0..ACCEPTORS {
+            assert_eq!(proposer.learner.decision(accept.slot), None);
+            proposer
+                .inject_message(Message::AcceptResponse {
+                    body: AcceptPhaseBody {
+                        issuer_id: acceptor,
+                        ..accept.clone()
+                    },
+                })
+                .await
+                .unwrap();
+        }
+        assert!(proposer.learner.decision(accept.slot).is_some());
+    }
+
+    #[tokio::test]
+    async fn value_proposed_at_a_later_slot_leaves_the_earlier_ones_as_gaps() {
+        let (mut proposer, log, acceptors) = proposer();
+        answer(acceptors);
+
+        let outcome = proposer.propose_at(5, 50).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ConsensusResult::Decided { slot: 5, proposal, .. } if proposal.value == 50
+            ),
+            "{outcome:?}"
+        );
+        assert_eq!(proposer.learner.gaps(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(proposer.next_slot, 6);
+
+        // The slot is decided: its value is reported without another round.
+        let broadcasts = log.recorded().len();
+        let outcome = proposer.propose_at(5, 60).await.unwrap();
+        assert!(
+            matches!(
+                outcome,
+                ConsensusResult::Decided { slot: 5, proposal, .. } if proposal.value == 50
+            ),
+            "{outcome:?}"
+        );
+        assert_eq!(log.recorded().len(), broadcasts);
+    }
+
+    #[tokio::test]
+    async fn recovery_fills_the_holes_with_what_the_acceptors_accepted() {
+        let (mut proposer, _log, acceptors) = proposer();
+        // A former leader got 20 accepted for slot 2, and nothing for slot 4.
+        answer_from(acceptors, |id| {
+            let mut state = AcceptorState::new(id);
+            state.accepted.insert(2, accepted(5, 20));
+            state
+        });
+        for (slot, value) in [(0, 1), (1, 2), (3, 4), (5, 6)] {
+            proposer.propose_at(slot, value).await.unwrap();
+        }
+        assert_eq!(proposer.learner.gaps(), vec![2, 4]);
+
+        assert_eq!(proposer.recover_log().await.unwrap(), vec![2, 4]);
+
+        assert!(proposer.learner.gaps().is_empty());
+        let decided = |slot| proposer.learner.decision(slot).map(|p| p.value);
+        assert_eq!(decided(2), Some(20));
+        assert_eq!(decided(4), Some(Command::Noop.encode()));
+        assert_eq!(decided(5), Some(6));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn prepare_without_a_quorum_is_retried_with_a_higher_ballot() {
+        let (mut proposer, log, mut acceptors) = proposer();
+        // Acceptors 1 and 2 are down for the first prepare request only.
+        let late = acceptors.split_off(1);
+        answer(acceptors);
+        for mut channel in late {
+            tokio::spawn(async move {
+                let mut state = AcceptorState::new(channel.id);
+                let mut down = true;
+                while let Some(envelope) = channel.recv().await {
+                    if down
+                        && matches!(envelope.message, Message::PrepareRequest { .. })
+                    {
+                        down = false;
+                        continue;
+                    }
+                    if let Some(reply) = state.handle(envelope.message) {
+                        let _ = channel.send(envelope.from, reply).await;
+                    }
+                }
+            });
+        }
+
+        let outcome = proposer.propose(5).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Decided { proposal, .. })
+                    if proposal.value == 5
+            ),
+            "{outcome:?}"
+        );
+        let prepares: Vec<_> = log
+            .recorded()
+            .into_iter()
+            .filter_map(|broadcast| match broadcast.message {
+                Message::PrepareRequest { body } => Some(body),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(prepares.len(), 2);
+        assert!(prepares[1].ballot > prepares[0].ballot);
+        assert_ne!(prepares[1].proposal_id, prepares[0].proposal_id);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn prepare_never_reaching_a_quorum_fails_after_the_last_attempt() {
+        let (mut proposer, log, mut acceptors) = proposer();
+        proposer.retry = RetryPolicy {
+            max_attempts: 3,
+            ..RetryPolicy::default()
+        };
+        let _down = acceptors.split_off(1);
+        answer(acceptors);
+
+        let outcome = proposer.propose(5).await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Failed {
+                    reason: FailureReason::PrepareTimeout,
+                    ..
+                })
+            ),
+            "{outcome:?}"
+        );
+        assert_eq!(
+            log.recorded()
+                .iter()
+                .filter(|broadcast| matches!(
+                    broadcast.message,
+                    Message::PrepareRequest { .. }
+                ))
+                .count(),
+            3
+        );
+        // The timings of the abandoned rounds are not kept around.
+        assert_eq!(proposer.timings, None);
+    }
+
+    #[tokio::test]
+    async fn prepare_times_out_exactly_at_its_deadline_on_a_manual_clock() {
+        let (mut proposer, _log, mut acceptors) = proposer();
+        let clock = Arc::new(ManualClock::new());
+        proposer.clock = clock.clone();
+        proposer.retry = RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        };
+        let timeout = proposer.prepare_timeout;
+        let _down = acceptors.split_off(1);
+        answer(acceptors);
+        let settle = || async {
+            for _ in 0..100 {
+                tokio::task::yield_now().await;
+            }
+        };
+
+        let mut round = tokio::spawn(async move { proposer.propose(5).await });
+        settle().await;
+        clock.advance(timeout - Duration::from_millis(1));
+        settle().await;
+        assert!(!round.is_finished(), "timed out before the deadline");
+
+        clock.advance(Duration::from_millis(1));
+        let outcome = (&mut round).await.unwrap().unwrap();
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Failed {
+                    reason: FailureReason::PrepareTimeout,
+                    ..
+                })
+            ),
+            "{outcome:?}"
+        );
+    }
+
+    /// Everything logged at debug level or above while a proposer decides `value`,
+    /// with the answers of every acceptor.
+    async fn logs_of_a_round(log_values: bool, value: u64) -> String {
+        #[derive(Clone, Default)]
+        struct Capture(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Capture {
+            fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(bytes);
+                Ok(bytes.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let (mut proposer, _log, acceptors) = proposer();
+        proposer.log_values = log_values;
+        answer(acceptors);
+        let outcome = proposer.propose(value).await.unwrap();
+        assert!(
+            matches!(
+                outcome,
+                ProposeOutcome::Decided(ConsensusResult::Decided { .. })
+            ),
+            "{outcome:?}"
+        );
+
+        let logs = capture.0.lock().unwrap().clone();
+        String::from_utf8(logs).unwrap()
+    }
+
+    #[tokio::test]
+    async fn values_are_kept_out_of_the_logs_unless_logged_values_are_enabled() {
+        const VALUE: u64 = 9_876_543_210;
+
+        let redacted = logs_of_a_round(false, VALUE).await;
+        assert!(redacted.contains("<redacted>"), "{redacted}");
+        assert!(!redacted.contains(&VALUE.to_string()), "{redacted}");
+
+        let logged = logs_of_a_round(true, VALUE).await;
+        assert!(logged.contains(&VALUE.to_string()), "{logged}");
+    }
+
+    /// A span opened while a test ran, with the names of the spans it was opened
+    /// in, innermost first, and the fields it recorded.
+    #[derive(Debug, Default)]
+    struct SpanRecord {
+        name: &'static str,
+        ancestors: Vec<&'static str>,
+        fields: HashMap<&'static str, String>,
+    }
+
+    impl tracing::field::Visit for SpanRecord {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.fields.insert(field.name(), value.to_string());
+        }
+
+        fn record_debug(
+            &mut self,
+            field: &tracing::field::Field,
+            value: &dyn fmt::Debug,
+        ) {
+            self.fields.insert(field.name(), format!("{value:?}"));
+        }
+    }
+
+    /// Layer recording every span opened, see `SpanRecord`.
+    #[derive(Clone, Default)]
+    struct SpanLog(Arc<std::sync::Mutex<Vec<SpanRecord>>>);
+
+    impl<S> tracing_subscriber::Layer<S> for SpanLog
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_new_span(
+            &self,
+            attributes: &tracing::span::Attributes<'_>,
+            id: &tracing::span::Id,
+            ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let span = ctx.span(id).expect("span just opened");
+            let mut record = SpanRecord {
+                name: span.name(),
+                ancestors: span.scope().skip(1).map(|parent| parent.name()).collect(),
+                ..SpanRecord::default()
+            };
+            attributes.record(&mut record);
+            let mut spans = self.0.lock().unwrap();
+            span.extensions_mut().insert(spans.len());
+            spans.push(record);
+        }
+
+        fn on_record(
+            &self,
+            id: &tracing::span::Id,
+            values: &tracing::span::Record<'_>,
+            ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let span = ctx.span(id).expect("span recorded into is open");
+            let index = *span.extensions().get::<usize>().expect("span is logged");
+            values.record(&mut self.0.lock().unwrap()[index]);
+        }
+    }
+
+    #[tokio::test]
+    async fn every_handler_of_a_round_runs_within_a_single_round_span() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let spans = SpanLog::default();
+        let subscriber = tracing_subscriber::registry().with(spans.clone());
+        let _default = tracing::subscriber::set_default(subscriber);
+        let (mut proposer, _log, acceptors) = proposer();
+        answer(acceptors);
+
+        let outcome = proposer.propose(5).await.unwrap();
+        let ProposeOutcome::Decided(ConsensusResult::Decided { proposal, .. }) =
+            outcome
+        else {
+            panic!("{outcome:?}");
+        };
+
+        let spans = spans.0.lock().unwrap();
+        let rounds: Vec<&SpanRecord> =
+            spans.iter().filter(|span| span.name == "round").collect();
+        assert_eq!(rounds.len(), 1, "{spans:?}");
+        let round = rounds[0];
+        assert_eq!(round.ancestors, ["propose_command"]);
+        assert_eq!(round.fields["node_id"], ACCEPTORS.to_string());
+        assert_eq!(round.fields["proposal_id"], proposal.id.formatted());
+        assert_eq!(round.fields["outcome"], "chosen");
+        assert!(round.fields.contains_key("round_trace_id"), "{round:?}");
+
+        for handler in [
+            "send_prepare_request",
+            "handle_prepare_response",
+            "send_accept_request",
+            "handle_accept_response",
+        ] {
+            let calls: Vec<&SpanRecord> =
+                spans.iter().filter(|span| span.name == handler).collect();
+            assert!(!calls.is_empty(), "no {handler} span in {spans:?}");
+            for call in calls {
+                assert!(call.ancestors.contains(&"round"), "{call:?}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn vote_log_records_one_promise_and_one_acceptance_per_acceptor() {
+        let (mut proposer, _log, mut acceptors) = proposer();
+        // Acceptor 2 is down: the others are all the quorum of both phases.
+        acceptors.truncate(2);
+        answer(acceptors);
+
+        let outcome = proposer.propose(5).await.unwrap();
+        let ProposeOutcome::Decided(ConsensusResult::Decided { proposal, .. }) =
+            outcome
+        else {
+            panic!("{outcome:?}");
+        };
+
+        let votes = proposer.votes.export_votes();
+        for kind in [VoteKind::Promise, VoteKind::Accept] {
+            let mut voters: Vec<u64> = votes
+                .iter()
+                .filter(|vote| vote.kind == kind)
+                .map(|vote| vote.acceptor)
+                .collect();
+            voters.sort_unstable();
+            assert_eq!(voters, [0, 1], "{kind:?} votes in {votes:?}");
+        }
+        assert!(votes.iter().all(|vote| vote.slot == 0
+            && vote.ballot == proposal.ballot
+            && vote.proposal_id == proposal.id));
+        // Every promise was counted before the first acceptance.
+        assert_eq!(votes[0].kind, VoteKind::Promise);
+        assert_eq!(votes[1].kind, VoteKind::Promise);
+        assert!(votes
+            .windows(2)
+            .all(|pair| pair[0].received_at <= pair[1].received_at));
+    }
+
+    #[tokio::test]
+    async fn rounds_with_sequential_ids_are_reproducible() {
+        /// Proposals a fresh proposer numbering them in order sends for the values
+        /// 5 and 6, and the ones it decides.
+        async fn run() -> (Vec<(u64, ProposalId, BallotNumber, u64)>, Vec<Proposal>) {
+            let (mut proposer, log, acceptors) = proposer();
+            proposer.id_generator = Box::new(SequentialIdGen::default());
+            answer(acceptors);
+            let mut decided = Vec::new();
+            for value in [5, 6] {
+                let outcome = proposer.propose(value).await.unwrap();
+                let ProposeOutcome::Decided(ConsensusResult::Decided {
+                    proposal, ..
+                }) = outcome
+                else {
+                    panic!("{outcome:?}");
+                };
+                decided.push(proposal);
+            }
+            let sent = accept_requests(&log)
+                .into_iter()
+                .map(|body| (body.slot, body.proposal_id, body.ballot, body.value))
+                .collect();
+            (sent, decided)
+        }
+
+        let (sent, decided) = run().await;
+        assert_eq!(run().await, (sent.clone(), decided.clone()));
+        let ids: Vec<ProposalId> = decided.iter().map(|proposal| proposal.id).collect();
+        assert_eq!(ids, [1, 2].map(|n| ProposalId(uuid::Uuid::from_u128(n))));
+        assert!(sent
+            .iter()
+            .all(|&(slot, id, ..)| id == decided[slot as usize].id));
+    }
+
+    #[tokio::test]
+    async fn single_shot_returns_the_first_decision_and_stops_taking_values() {
+        let (mut proposer, _log, acceptors) = proposer();
+        let (client, client_receiver) = mpsc::channel(4);
+        proposer.client_receiver = client_receiver;
+        answer(acceptors);
+        client.send(5).await.unwrap();
+        client.send(6).await.unwrap();
+
+        let result =
+            tokio::time::timeout(Duration::from_secs(5), proposer.run_single_shot())
+                .await
+                .expect("single shot never returned")
+                .unwrap();
+
+        assert!(
+            matches!(result, ConsensusResult::Decided { slot: 0, proposal, .. } if proposal.value == 5),
+            "{result:?}"
+        );
+        // The value queued behind is left undecided, and no more are taken.
+        assert_eq!(proposer.learner.highest_slot(), Some(0));
+        assert!(client.send(7).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn recorder_captures_the_prepare_request_and_its_receivers() {
+        let (mut proposer, log, mut acceptors) = proposer();
+
+        assert_eq!(proposer.send_prepare_request(1).await.unwrap(), 3);
+        acceptors.pop();
+        assert_eq!(proposer.send_prepare_request(1).await.unwrap(), 2);
+
+        let recorded = log.recorded();
+        let receivers: Vec<usize> = recorded
+            .iter()
+            .map(|broadcast| broadcast.receivers)
+            .collect();
+        assert_eq!(receivers, [3, 2]);
+        assert!(recorded.iter().all(|broadcast| matches!(
+            broadcast.message,
+            Message::PrepareRequest { .. }
+        )));
+    }
+
+    #[tokio::test]
+    async fn reads_are_local_while_the_lease_holds_and_prepared_once_it_expired() {
+        let (mut proposer, log, acceptors) = proposer();
+        let clock = Arc::new(ManualClock::new());
+        proposer.clock = clock.clone();
+        answer(acceptors);
+        proposer.propose(5).await.unwrap();
+        let prepares = || {
+            log.recorded()
+                .iter()
+                .filter(|broadcast| {
+                    matches!(broadcast.message, Message::PrepareRequest { .. })
+                })
+                .count()
+        };
+
+        assert!(proposer
+            .acquire_lease(Duration::from_secs(1))
+            .await
+            .unwrap());
+        let before = prepares();
+        clock.advance(Duration::from_millis(999));
+        assert!(proposer.has_lease());
+        assert_eq!(proposer.read(0).await.unwrap(), Some(5));
+        assert_eq!(prepares(), before, "read under a lease ran a prepare");
+
+        clock.advance(Duration::from_millis(1));
+        assert!(!proposer.has_lease());
+        assert_eq!(proposer.read(0).await.unwrap(), Some(5));
+        assert_eq!(prepares(), before + 1);
+    }
+
+    #[tokio::test]
+    async fn read_under_a_lease_learns_a_slot_decided_by_another_proposer_before() {
+        let states: Vec<_> = (0..ACCEPTORS)
+            .map(|id| Arc::new(std::sync::Mutex::new(AcceptorState::new(id))))
+            .collect();
+        let (mut other, _log, acceptors) = proposer();
+        answer_shared(acceptors, &states);
+        let (mut reader, log, acceptors) = proposer();
+        answer_shared(acceptors, &states);
+        // Same round, so the lease of the reader outbids the ballot of the other.
+        reader.id = ACCEPTORS + 1;
+        reader.ballot = BallotNumber::new(0, reader.id);
+        other.propose(7).await.unwrap();
+        assert_eq!(reader.learner.decision(0), None);
+
+        assert!(reader.acquire_lease(Duration::from_secs(10)).await.unwrap());
+        let broadcasts = log.recorded().len();
+        // Above every slot the acceptors know of: nothing was chosen for it.
+        assert_eq!(reader.read(1).await.unwrap(), None);
+        assert_eq!(
+            log.recorded().len(),
+            broadcasts,
+            "read above the log ran a round"
+        );
+
+        assert_eq!(reader.read(0).await.unwrap(), Some(7));
+        assert_eq!(reader.learner.decision(0).unwrap().value, 7);
+    }
+
+    #[tokio::test]
+    async fn stats_snapshot_is_published_after_every_round() {
+        let (mut proposer, _log, acceptors) = proposer();
+        answer(acceptors);
+        let mut snapshots = proposer.metrics_subscribe();
+        // A subscriber that went away never holds the proposer back.
+        drop(proposer.metrics_subscribe());
+
+        proposer.propose(5).await.unwrap();
+        proposer.propose(6).await.unwrap();
+
+        let first = snapshots.try_recv().unwrap();
+        let second = snapshots.try_recv().unwrap();
+        assert!(
+            snapshots.try_recv().is_err(),
+            "more than one snapshot a round"
+        );
+        assert_eq!((first.rounds, first.decided, first.slot), (1, 1, 0));
+        assert_eq!((second.rounds, second.decided, second.slot), (2, 2, 1));
+        assert_eq!(second.failed, 0);
+        assert!(first.ballot < second.ballot);
+        assert_eq!(proposer.stats(), second);
+    }
+
+    #[tokio::test]
+    async fn accept_response_about_a_foreign_proposal_is_not_counted() {
+        let (mut proposer, log, _acceptors) = proposer();
+        proposer.send_prepare_request(1).await.unwrap();
+        let prepare = last_prepare(&log);
+        for acceptor in [0, 1] {
+            proposer
+                .inject_message(promise(&prepare, acceptor, None))
+                .await
+                .unwrap();
+        }
+        let accept = accept_requests(&log)
+            .pop()
+            .expect("accept phase not started");
+        let acceptance = |acceptor, proposal_id| Message::AcceptResponse {
+            body: AcceptPhaseBody {
+                issuer_id: acceptor,
+                proposal_id,
+                ..accept.clone()
+            },
+        };
+
+        // Acceptances of a proposal of another proposer, for the same slot.
+        let foreign = ProposalId(uuid::Uuid::from_u128(u128::MAX));
+        for acceptor in [0, 1] {
+            proposer
+                .inject_message(acceptance(acceptor, foreign))
+                .await
+                .unwrap();
+        }
+        assert!(proposer.accepted_value_nodes.is_empty());
+        assert_eq!(proposer.learner.decision(accept.slot), None);
+
+        for acceptor in [0, 1] {
+            proposer
+                .inject_message(acceptance(acceptor, accept.proposal_id))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            proposer
+                .learner
+                .decision(accept.slot)
+                .map(|proposal| proposal.id),
+            Some(accept.proposal_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn health_reports_the_quorum_lost_once_too_many_acceptors_are_down() {
+        let (mut proposer, _log, acceptors) = proposer();
+        let (detector, suspected) = watch::channel(HashSet::new());
+        proposer.suspected = Some(suspected);
+        answer(acceptors);
+        proposer.propose(5).await.unwrap();
+
+        let health = proposer.health();
+        assert_eq!((health.known_acceptors, health.live_acceptors), (3, 3));
+        assert!(health.quorum_available);
+        assert_eq!(health.last_decided, Some(0));
+
+        detector.send_replace(HashSet::from([0]));
+        let health = proposer.health();
+        assert_eq!(health.live_acceptors, 2);
+        assert!(health.quorum_available);
+
+        detector.send_replace(HashSet::from([0, 1]));
+        let health = proposer.health();
+        assert_eq!(health.live_acceptors, 1);
+        assert!(!health.quorum_available);
+        let summary = health.to_string();
+        assert!(
+            summary.starts_with("1/3 acceptors live, quorum unavailable"),
+            "{summary}"
+        );
+        assert!(summary.ends_with("last decided slot 0"), "{summary}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn aborting_an_abandoned_round_spares_the_competing_proposer_its_backoff() {
+        /// How long proposer 3 takes to decide a value once the acceptors promised
+        /// a higher ballot of proposer 4, which then gave up on its round, aborting
+        /// it if `abort`.
+        async fn time_to_decide(abort: bool) -> Duration {
+            let states: Vec<_> = (0..ACCEPTORS)
+                .map(|id| Arc::new(std::sync::Mutex::new(AcceptorState::new(id))))
+                .collect();
+            let (mut abandoning, log, acceptors) = proposer();
+            answer_shared(acceptors, &states);
+            abandoning.id = ACCEPTORS + 1;
+            abandoning.ballot = BallotNumber::new(5, abandoning.id);
+            abandoning.send_prepare_request(1).await.unwrap();
+            if abort {
+                abandoning.abort_round().await.unwrap();
+            }
+            while states.iter().any(|state| {
+                let state = state.lock().unwrap();
+                state.promised.is_none() || (abort && state.aborted.is_none())
+            }) {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(log.recorded().len(), if abort { 2 } else { 1 });
+
+            let (mut proposer, _log, acceptors) = proposer();
+            answer_shared(acceptors, &states);
+            proposer.retry = RetryPolicy {
+                initial_backoff: Duration::from_millis(500),
+                jitter: 0.0,
+                ..RetryPolicy::default()
+            };
+            let started = Instant::now();
+            let outcome = proposer.propose(7).await.unwrap();
+            assert!(
+                matches!(
+                    outcome,
+                    ProposeOutcome::Decided(ConsensusResult::Decided { proposal, .. })
+                        if proposal.value == 7
+                ),
+                "{outcome:?}"
+            );
+            started.elapsed()
+        }
+
+        let backed_off = time_to_decide(false).await;
+        let aborted = time_to_decide(true).await;
+        assert!(backed_off >= Duration::from_millis(500), "{backed_off:?}");
+        assert!(aborted < Duration::from_millis(500), "{aborted:?}");
     }
 }
@@ -0,0 +1,93 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::domain::{ballot::BallotNumber, id::ProposalId};
+
+/// How many of the latest votes the vote log keeps.
+pub const VOTE_LOG_CAPACITY: usize = 4096;
+
+/// Phase of the protocol a vote was cast in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoteKind {
+    /// The acceptor promised the ballot of a prepare request.
+    Promise,
+    /// The acceptor accepted the proposal of an accept request.
+    Accept,
+}
+
+/// Vote of an acceptor, as counted by a proposer towards a quorum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct VoteRecord {
+    pub kind: VoteKind,
+    pub slot: u64,
+    pub ballot: BallotNumber,
+    pub proposal_id: ProposalId,
+    /// Acceptor that cast the vote.
+    pub acceptor: u64,
+    /// When the proposer counted the vote, on the wall clock of its node.
+    pub received_at: DateTime<Utc>,
+}
+
+/// Trail of the votes counted by a proposer, to reconstruct after the fact how and
+/// when each quorum was reached. Where the decision log only tells what was
+/// decided, this tells who voted for it.
+///
+/// Votes are only appended, in the order they were counted. A vote that was
+/// already counted, e.g. an acceptance of an accept request sent again, is not
+/// recorded twice. Only the latest `VOTE_LOG_CAPACITY` votes are kept. The handle
+/// can be cloned and shared.
+#[derive(Debug, Clone, Default)]
+pub struct VoteLog {
+    records: Arc<Mutex<VecDeque<VoteRecord>>>,
+}
+
+impl VoteLog {
+    pub fn append(&self, record: VoteRecord) {
+        let mut records = self.records.lock().expect("vote log lock poisoned");
+        if records.len() == VOTE_LOG_CAPACITY {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// The votes recorded, oldest first.
+    pub fn export_votes(&self) -> Vec<VoteRecord> {
+        let records = self.records.lock().expect("vote log lock poisoned");
+        records.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn vote(slot: u64) -> VoteRecord {
+        VoteRecord {
+            kind: VoteKind::Accept,
+            slot,
+            ballot: BallotNumber::new(1, 3),
+            proposal_id: ProposalId(Uuid::from_u128(1)),
+            acceptor: 0,
+            received_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn vote_log_keeps_the_latest_votes_in_order() {
+        let log = VoteLog::default();
+        let total = VOTE_LOG_CAPACITY as u64 + 2;
+        for slot in 0..total {
+            log.append(vote(slot));
+        }
+
+        let slots: Vec<u64> = log.export_votes().iter().map(|vote| vote.slot).collect();
+        assert_eq!(slots, (2..total).collect::<Vec<_>>());
+    }
+}
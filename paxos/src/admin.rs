@@ -0,0 +1,310 @@
+//! Endpoint answering operators about the state of a node, over HTTP:
+//!
+//! - `GET /status` describes the node as JSON: its roles, the leader it sees, the
+//!   highest slot it decided, the round its proposer is running and the values waiting
+//!   for it.
+//! - `GET /log?from=&to=` lists the slots decided between `from` and `to`, both
+//!   included, up to `LOG_PAGE_SIZE` of them.
+//! - `POST /propose` submits the value of a JSON body such as `{"value": 42}` to the
+//!   proposer of the node, and waits for its outcome. Meant for manual testing.
+//!
+//! Everything is read through the handles a `Node` hands out, never from the roles
+//! themselves: answering an operator never waits for the proposer.
+
+use std::{future::Future, net::SocketAddr, path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, watch},
+};
+use tracing::{info, warn};
+
+use crate::{
+    actors::learner::Learner,
+    client::Client,
+    config::Role,
+    domain::{
+        ballot::BallotNumber,
+        command::Command,
+        consensus::{ConsensusResult, ProposerStatus, RoundStatus},
+    },
+    node::Node,
+};
+
+/// Most slots listed by a single request to `/log`.
+pub const LOG_PAGE_SIZE: usize = 1024;
+
+/// How long `/propose` waits for the outcome of the value.
+const PROPOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answer of `GET /status`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NodeStatus {
+    pub node_id: u64,
+    pub roles: Vec<Role>,
+    /// Proposer the node sees leading the cluster. Only known to proposers.
+    pub leader: Option<u64>,
+    /// Highest slot decided by the node, the slots below it may not all be.
+    pub commit_index: Option<u64>,
+    /// Round run by the proposer of the node, `None` if it is not a proposer.
+    pub round: Option<RoundStatus>,
+    /// Values submitted to the proposer of the node that it did not take yet.
+    pub queue_depth: usize,
+    /// Acceptors suspected to be down by the failure detector of the proposer,
+    /// `None` without one.
+    pub suspected: Option<Vec<u64>>,
+    /// Directory of the durable state of the node, `None` if it is kept in memory.
+    pub storage_dir: Option<PathBuf>,
+}
+
+/// Slot listed by `GET /log`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LogEntry {
+    pub slot: u64,
+    pub value: u64,
+    pub ballot: BallotNumber,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LogQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ProposeRequest {
+    value: u64,
+}
+
+/// Answer of `POST /propose` for a decided value. The slot may have been decided
+/// with the value of another proposer, which is then the one reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Proposed {
+    pub slot: u64,
+    pub value: u64,
+    pub by_other: bool,
+}
+
+/// Everything the endpoint reads the state of the node from.
+#[derive(Clone)]
+struct NodeView {
+    node_id: u64,
+    roles: Vec<Role>,
+    storage_dir: Option<PathBuf>,
+    learner: Learner,
+    proposer: watch::Receiver<ProposerStatus>,
+    queue: mpsc::Sender<u64>,
+    client: Client,
+}
+
+/// Endpoint of a node, bound and ready to serve.
+pub struct AdminServer {
+    listener: TcpListener,
+    router: Router,
+}
+
+impl AdminServer {
+    /// Listen on `address` for the requests of operators about `node`, port 0
+    /// picking any free port.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn bind(node: &Node, address: SocketAddr) -> Result<Self> {
+        let listener = std::net::TcpListener::bind(address)
+            .with_context(|| format!("could not listen on {address}"))?;
+        listener.set_nonblocking(true)?;
+        let view = NodeView {
+            node_id: node.id,
+            roles: node.roles().to_vec(),
+            storage_dir: node.storage_dir().map(|dir| dir.to_path_buf()),
+            learner: node.learner().clone(),
+            proposer: node.proposer_status(),
+            queue: node.client_handle(),
+            client: node.client(),
+        };
+        let router = Router::new()
+            .route("/status", get(status))
+            .route("/log", get(log))
+            .route("/propose", post(propose))
+            .with_state(view);
+
+        Ok(Self {
+            listener: TcpListener::from_std(listener)?,
+            router,
+        })
+    }
+
+    /// Address the endpoint actually listens on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve the requests until `stop` resolves, then finish answering the ones in
+    /// progress.
+    pub async fn run_until(self, stop: impl Future<Output = ()> + Send + 'static) {
+        let address = self.listener.local_addr().ok();
+        info!(?address, "serving admin endpoint");
+        if let Err(e) = axum::serve(self.listener, self.router)
+            .with_graceful_shutdown(stop)
+            .await
+        {
+            warn!(?address, "admin endpoint stopped: {e}");
+        }
+    }
+}
+
+async fn status(State(node): State<NodeView>) -> Json<NodeStatus> {
+    let proposer = node.roles.contains(&Role::Proposer);
+    let status = node.proposer.borrow().clone();
+    Json(NodeStatus {
+        node_id: node.node_id,
+        roles: node.roles,
+        leader: status.leader,
+        commit_index: node.learner.highest_slot(),
+        round: proposer.then_some(status.round),
+        queue_depth: node.queue.max_capacity() - node.queue.capacity(),
+        suspected: status.suspected,
+        storage_dir: node.storage_dir,
+    })
+}
+
+async fn log(
+    State(node): State<NodeView>,
+    Query(query): Query<LogQuery>,
+) -> Result<Json<Vec<LogEntry>>, Response> {
+    let from = query.from.unwrap_or(0);
+    let to = query.to.unwrap_or(u64::MAX);
+    if from > to {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            format!("from {from} is above to {to}"),
+        ));
+    }
+
+    let entries = node
+        .learner
+        .decided_range(from..=to, LOG_PAGE_SIZE)
+        .into_iter()
+        .map(|(slot, proposal)| LogEntry {
+            slot,
+            value: proposal.value,
+            ballot: proposal.ballot,
+        })
+        .collect();
+    Ok(Json(entries))
+}
+
+async fn propose(
+    State(node): State<NodeView>,
+    Json(request): Json<ProposeRequest>,
+) -> Result<Json<Proposed>, Response> {
+    if Command::is_reserved(request.value) {
+        return Err(error(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "{} is reserved for the commands of the cluster",
+                request.value
+            ),
+        ));
+    }
+
+    match node
+        .client
+        .propose_with_timeout(request.value, PROPOSE_TIMEOUT)
+        .await
+    {
+        Ok(ConsensusResult::Decided {
+            slot,
+            proposal,
+            by_other,
+        }) => Ok(Json(Proposed {
+            slot,
+            value: proposal.value,
+            by_other,
+        })),
+        Ok(ConsensusResult::Failed { reason, .. }) => Err(error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("value not decided: {reason:?}"),
+        )),
+        Err(e) => Err(error(StatusCode::SERVICE_UNAVAILABLE, e.to_string())),
+    }
+}
+
+fn error(status: StatusCode, message: String) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::{config::ClusterConfig, transport::sim::SimNetwork};
+
+    /// Body of the answer of the endpoint at `address` to `GET path`.
+    async fn get(address: SocketAddr, path: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "GET {path} HTTP/1.1\r\nHost: {address}\r\nConnection: close\r\n\r\n"
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200"), "{response}");
+        body.to_string()
+    }
+
+    #[tokio::test]
+    async fn status_reports_the_commit_index_advancing_with_a_decision() {
+        let config = ClusterConfig::symmetric(3);
+        let network = SimNetwork::new(7);
+        let mut nodes: Vec<Node> = config
+            .peers
+            .iter()
+            .map(|peer| {
+                let transport = network.join(peer.node_id, &peer.roles);
+                Node::new(peer.node_id, config.clone(), Box::new(transport)).unwrap()
+            })
+            .collect();
+        let address = nodes[1]
+            .serve_admin(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .unwrap();
+
+        let before: NodeStatus =
+            serde_json::from_str(&get(address, "/status").await).unwrap();
+        assert_eq!(before.node_id, 1);
+        assert_eq!(before.commit_index, None);
+        assert_eq!(before.queue_depth, 0);
+        assert!(before.round.is_some(), "{before:?}");
+
+        nodes[1]
+            .client()
+            .propose_with_timeout(7, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        let after: NodeStatus =
+            serde_json::from_str(&get(address, "/status").await).unwrap();
+        assert_eq!(after.commit_index, Some(0));
+        let log: Vec<LogEntry> =
+            serde_json::from_str(&get(address, "/log?from=0&to=0").await).unwrap();
+        assert_eq!(
+            log.iter()
+                .map(|entry| (entry.slot, entry.value))
+                .collect::<Vec<_>>(),
+            [(0, 7)]
+        );
+        for node in nodes {
+            node.shutdown().await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! Audit trail of the decisions of a node, kept on disk independently of the rest
+//! of its state, for post-mortems.
+//!
+//! The file is a sequence of records, each made of:
+//!
+//! - the length of its payload, as a little-endian `u32`;
+//! - the payload: the slot, the 16 bytes of the proposal id, the value and the time of
+//!   the decision in microseconds since the Unix epoch, each integer being a
+//!   little-endian 64-bit one;
+//! - the CRC-32 of the payload, as a little-endian `u32`.
+//!
+//! A crash can leave the last record half written. Readers stop cleanly before it,
+//! and reopening the log for writing cuts it off.
+
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use crate::domain::id::ProposalId;
+
+/// Size of the payload of a record.
+const PAYLOAD_LEN: usize = 8 + 16 + 8 + 8;
+
+/// Decision as recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub slot: u64,
+    pub proposal_id: ProposalId,
+    pub value: u64,
+    pub decided_at: DateTime<Utc>,
+}
+
+impl AuditRecord {
+    fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(PAYLOAD_LEN);
+        payload.extend_from_slice(&self.slot.to_le_bytes());
+        payload.extend_from_slice(self.proposal_id.0.as_bytes());
+        payload.extend_from_slice(&self.value.to_le_bytes());
+        payload.extend_from_slice(&self.decided_at.timestamp_micros().to_le_bytes());
+
+        let mut record = Vec::with_capacity(payload.len() + 8);
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&payload);
+        record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        record
+    }
+
+    fn decode(payload: &[u8]) -> Option<Self> {
+        if payload.len() != PAYLOAD_LEN {
+            return None;
+        }
+        let u64_at = |at: usize| {
+            u64::from_le_bytes(payload[at..at + 8].try_into().expect("8 bytes"))
+        };
+        let proposal_id = Uuid::from_slice(&payload[8..24]).ok()?;
+        Some(Self {
+            slot: u64_at(0),
+            proposal_id: ProposalId(proposal_id),
+            value: u64_at(24),
+            decided_at: DateTime::from_timestamp_micros(u64_at(32) as i64)?,
+        })
+    }
+}
+
+/// What reading a record from the log gave.
+enum Scan {
+    Record(AuditRecord),
+    /// Nothing is left to read.
+    End,
+    /// The record is cut short, as a crash while writing it would leave it.
+    Torn,
+    /// The record is complete, but does not match its checksum.
+    Corrupt,
+}
+
+fn read_record(reader: &mut impl Read) -> io::Result<Scan> {
+    let mut len = [0; 4];
+    match read_full(reader, &mut len)? {
+        0 => return Ok(Scan::End),
+        4 => (),
+        _ => return Ok(Scan::Torn),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    // A length that is not the one of a record can only be garbage: do not try to
+    // read that much.
+    if len != PAYLOAD_LEN {
+        return Ok(Scan::Corrupt);
+    }
+
+    let mut payload = vec![0; len + 4];
+    if read_full(reader, &mut payload)? < payload.len() {
+        return Ok(Scan::Torn);
+    }
+    let (payload, crc) = payload.split_at(len);
+    let crc = u32::from_le_bytes(crc.try_into().expect("4 bytes"));
+    if crc32fast::hash(payload) != crc {
+        return Ok(Scan::Corrupt);
+    }
+    Ok(AuditRecord::decode(payload).map_or(Scan::Corrupt, Scan::Record))
+}
+
+/// Fill `buffer` as much as possible, returning how many bytes were read: fewer
+/// than its length only at the end of the file.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+/// Append-only file recording every decision of a node, one record each, written
+/// through to the disk before `append` returns.
+#[derive(Debug)]
+pub struct DecisionLog {
+    path: PathBuf,
+    file: File,
+    /// Slots recorded so far, including by the earlier runs of the node.
+    slots: HashSet<u64>,
+}
+
+impl DecisionLog {
+    /// Open the log stored at `path` for appending, creating it if needed. A record
+    /// left half written by a crash, or damaged, is cut off along with everything
+    /// after it, so that the new records can be read back.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("could not open {}", path.display()))?;
+
+        let mut valid = 0;
+        let mut slots = HashSet::new();
+        let mut reader = BufReader::new(&mut file);
+        loop {
+            match read_record(&mut reader)? {
+                Scan::Record(record) => {
+                    valid += (PAYLOAD_LEN + 8) as u64;
+                    slots.insert(record.slot);
+                }
+                Scan::End => break,
+                Scan::Torn | Scan::Corrupt => {
+                    warn!(
+                        path = %path.display(),
+                        offset = valid,
+                        "cutting off the damaged tail of the decision log"
+                    );
+                    break;
+                }
+            }
+        }
+        file.set_len(valid)?;
+        file.seek(SeekFrom::Start(valid))?;
+
+        Ok(Self { path, file, slots })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record a decision, and wait for it to reach the disk. A slot that is already
+    /// recorded is skipped: it was learned again, e.g. by a node resuming the round
+    /// it had in flight when it stopped.
+    pub fn append(&mut self, record: &AuditRecord) -> Result<()> {
+        if !self.slots.insert(record.slot) {
+            debug!(slot = record.slot, "slot already audited");
+            return Ok(());
+        }
+        self.file
+            .write_all(&record.encode())
+            .and_then(|()| self.file.sync_data())
+            .with_context(|| format!("could not write to {}", self.path.display()))
+    }
+
+    /// Read back the records of the log stored at `path`, in the order they were
+    /// written. Iteration stops before the first record that is incomplete or
+    /// damaged, e.g. the last one when the node crashed while writing it.
+    pub fn iter(path: impl AsRef<Path>) -> Result<impl Iterator<Item = AuditRecord>> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("could not open {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+        Ok(std::iter::from_fn(move || match read_record(&mut reader) {
+            Ok(Scan::Record(record)) => Some(record),
+            _ => None,
+        }))
+    }
+}
+
+/// Handle on a `DecisionLog` written by a thread of its own.
+///
+/// Recording a decision then does not wait for the disk. Records are written in the
+/// order they are appended; the thread stops once the handle is dropped and the
+/// records left are written.
+#[derive(Debug)]
+pub struct AuditWriter {
+    records: mpsc::Sender<AuditRecord>,
+}
+
+impl AuditWriter {
+    /// Start the thread writing to `log`.
+    pub fn spawn(mut log: DecisionLog) -> Result<Self> {
+        let (records, received) = mpsc::channel::<AuditRecord>();
+        thread::Builder::new()
+            .name("decision-log".into())
+            .spawn(move || {
+                for record in received {
+                    // The decision stands whether it could be audited or not.
+                    if let Err(e) = log.append(&record) {
+                        error!(slot = record.slot, "could not audit decision: {e:#}");
+                    }
+                }
+            })
+            .context("could not start the decision log writer")?;
+        Ok(Self { records })
+    }
+
+    /// Queue `record` to be written, without waiting for it to reach the disk.
+    pub fn append(&self, record: AuditRecord) {
+        if self.records.send(record).is_err() {
+            error!(slot = record.slot, "decision log writer stopped");
+        }
+    }
+}
+
+/// Check the log stored at `path` from end to end, returning how many records it
+/// holds.
+///
+/// Fails on the first record that is incomplete or does not match its checksum,
+/// and on a slot recorded twice: a slot is only ever decided once. Slots are not
+/// necessarily recorded in increasing order though, since the holes of the log are
+/// filled after the slots above them were decided.
+pub fn verify(path: impl AsRef<Path>) -> Result<usize> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .with_context(|| format!("could not open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut slots = HashSet::new();
+    loop {
+        let index = slots.len();
+        match read_record(&mut reader)? {
+            Scan::Record(record) => {
+                if !slots.insert(record.slot) {
+                    bail!("record {index}: slot {} recorded twice", record.slot);
+                }
+            }
+            Scan::End => return Ok(index),
+            Scan::Torn => bail!("record {index} is incomplete"),
+            Scan::Corrupt => bail!("record {index} does not match its checksum"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path of a log no other test uses.
+    fn scratch_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("paxos-audit-{}.log", Uuid::now_v7().simple()))
+    }
+
+    fn record(slot: u64) -> AuditRecord {
+        AuditRecord {
+            slot,
+            proposal_id: ProposalId(Uuid::from_u128(u128::from(slot))),
+            value: slot * 10,
+            decided_at: DateTime::from_timestamp_micros(1_700_000_000_000_000).unwrap(),
+        }
+    }
+
+    #[test]
+    fn reading_a_torn_log_stops_at_the_last_complete_record() {
+        let path = scratch_path();
+        let mut log = DecisionLog::open(&path).unwrap();
+        for slot in 0..100 {
+            log.append(&record(slot)).unwrap();
+        }
+        drop(log);
+        assert_eq!(verify(&path).unwrap(), 100);
+
+        // The node crashed halfway through writing the last record.
+        let len = std::fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - (PAYLOAD_LEN as u64 + 8) / 2).unwrap();
+        drop(file);
+
+        let records: Vec<AuditRecord> = DecisionLog::iter(&path).unwrap().collect();
+        assert_eq!(records, (0..99).map(record).collect::<Vec<_>>());
+        assert!(verify(&path).is_err());
+
+        // Reopening it cuts the torn record off, so that the next ones can be read.
+        let mut log = DecisionLog::open(&path).unwrap();
+        log.append(&record(99)).unwrap();
+        drop(log);
+        assert_eq!(verify(&path).unwrap(), 100);
+        assert_eq!(DecisionLog::iter(&path).unwrap().last(), Some(record(99)));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn slots_recorded_before_a_restart_are_not_recorded_again() {
+        let path = scratch_path();
+        let mut log = DecisionLog::open(&path).unwrap();
+        for slot in [0, 1] {
+            log.append(&record(slot)).unwrap();
+        }
+        log.append(&record(1)).unwrap();
+        drop(log);
+
+        let mut log = DecisionLog::open(&path).unwrap();
+        for slot in [1, 2] {
+            log.append(&record(slot)).unwrap();
+        }
+        drop(log);
+
+        assert_eq!(verify(&path).unwrap(), 3);
+        let slots: Vec<u64> = DecisionLog::iter(&path)
+            .unwrap()
+            .map(|record| record.slot)
+            .collect();
+        assert_eq!(slots, [0, 1, 2]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn verify_rejects_a_damaged_record_and_a_slot_recorded_twice() {
+        let path = scratch_path();
+        // Written as a log from before slots were checked on append would be.
+        let bytes: Vec<u8> = [0, 1, 1]
+            .into_iter()
+            .flat_map(|slot| record(slot).encode())
+            .collect();
+        std::fs::write(&path, bytes).unwrap();
+        let error = verify(&path).unwrap_err().to_string();
+        assert!(error.contains("slot 1 recorded twice"), "{error}");
+
+        // Flip a bit of the value of the first record.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[4 + 24] ^= 1;
+        std::fs::write(&path, bytes).unwrap();
+        let error = verify(&path).unwrap_err().to_string();
+        assert!(error.contains("record 0 does not match"), "{error}");
+        assert_eq!(DecisionLog::iter(&path).unwrap().count(), 0);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,465 @@
+//! Hammer a cluster running in this process with faults, then check that its nodes
+//! agree on what was decided.
+//!
+//! For the given duration, every proposer is submitted values one after the other,
+//! while acceptors crash and restart, the network is partitioned and healed, and
+//! the links between nodes slow down or lose messages. Every fault is drawn from
+//! the seed printed at startup, along with the conditions of the simulated network.
+//! The timing of the nodes is not, so a seed does not replay a run exactly, but
+//! runs the same kind of faults.
+//!
+//! ```sh
+//! cargo run --bin paxos-chaos -- --duration 30 --acceptors 5
+//! ```
+//!
+//! The acceptors gossip their accepts, so that the decisions each of them learned
+//! are checked along with the logs of the proposers.
+//!
+//! Exits with an error if two nodes learned different values for the same slot, or
+//! a value that was never proposed, so that it can be run in a loop. So does a run
+//! in which fewer than `--min-shared-slots` slots were learned by two nodes or
+//! more, as it checked too little to be trusted.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use paxos::{
+    client::{Client, ProposeError},
+    domain::{command::Command, consensus::ConsensusResult},
+    testkit::{Cluster, ClusterBuilder, ClusterTransport},
+    transport::sim::{Latency, LinkSpec, PartitionPolicy, SimNetwork},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use tokio::{
+    task::JoinSet,
+    time::{sleep, Instant},
+};
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+
+/// Run an in-process cluster under random faults, and check its decisions.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Seed the faults and the network are drawn from. Drawn at random if not set.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// How long to submit values and inject faults for, in seconds.
+    #[arg(long, default_value_t = 10)]
+    duration: u64,
+
+    #[arg(long, default_value_t = 5)]
+    acceptors: usize,
+
+    #[arg(long, default_value_t = 1)]
+    proposers: usize,
+
+    /// Time between two faults, in milliseconds.
+    #[arg(long, default_value_t = 200)]
+    fault_interval: u64,
+
+    /// How long a value is waited for before moving on to the next one, in
+    /// milliseconds.
+    #[arg(long, default_value_t = 2000)]
+    propose_timeout: u64,
+
+    /// Fewest slots that must have been learned by two nodes or more for the run to
+    /// pass.
+    #[arg(long, default_value_t = 10)]
+    min_shared_slots: usize,
+}
+
+/// Highest latency of a link, once degraded.
+const MAX_LATENCY: Duration = Duration::from_millis(50);
+
+/// Highest probability of a degraded link to lose a message.
+const MAX_DROP_PROBABILITY: f64 = 0.2;
+
+/// Conditions of the links that are not degraded.
+const DEFAULT_LINK: LinkSpec = LinkSpec {
+    latency: Latency::Uniform {
+        min: Duration::ZERO,
+        max: Duration::from_millis(2),
+    },
+    drop_probability: 0.0,
+    duplicate_probability: 0.0,
+    reorder_window: Duration::ZERO,
+};
+
+/// What became of the values submitted to a proposer.
+#[derive(Debug, Default)]
+struct ClientStats {
+    /// Latency of every value decided for a slot.
+    latencies: Vec<Duration>,
+    /// Values whose slot was decided with the value of another proposer.
+    displaced: usize,
+    /// Values submitted to the leader after the proposer redirected them to it.
+    redirected: usize,
+    /// Values given up on, by the proposer or by the client.
+    failed: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new("warn,paxos_chaos=info")),
+        )
+        .init();
+
+    let args = Args::parse();
+    let seed = args.seed.unwrap_or_else(rand::random);
+    println!("seed {seed}");
+
+    let network = SimNetwork::with_default_link(seed, DEFAULT_LINK);
+    let mut cluster = ClusterBuilder::new()
+        .acceptors(args.acceptors)
+        .proposers(args.proposers)
+        .transport(ClusterTransport::Sim(network.clone()))
+        .durable_acceptors(true)
+        .gossip(true)
+        .build()?;
+    let acceptors: Vec<u64> = (0..args.acceptors as u64).collect();
+    let proposers: Vec<u64> =
+        (args.acceptors as u64..(args.acceptors + args.proposers) as u64).collect();
+
+    let started_at = Instant::now();
+    let deadline = started_at + Duration::from_secs(args.duration);
+    let propose_timeout = Duration::from_millis(args.propose_timeout);
+    let clients = proposers
+        .iter()
+        .map(|&proposer_id| {
+            let client = cluster
+                .proposer_client(proposer_id)
+                .context("proposer not running")?;
+            Ok((proposer_id, client))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    let mut submitters = JoinSet::new();
+    for (i, &proposer_id) in proposers.iter().enumerate() {
+        let clients = clients.clone();
+        // Every proposer submits values of its own range, so that they can be told
+        // apart once decided.
+        let values = (i as u64 + 1) << 32..;
+        submitters.spawn(async move {
+            let mut stats = ClientStats::default();
+            let mut submitted = Vec::new();
+            for value in values {
+                if Instant::now() >= deadline {
+                    break;
+                }
+                submitted.push(value);
+                let sent_at = Instant::now();
+                let outcome =
+                    propose(&clients, proposer_id, value, propose_timeout, &mut stats)
+                        .await;
+                match outcome {
+                    Ok(ConsensusResult::Decided {
+                        by_other: false, ..
+                    }) => stats.latencies.push(sent_at.elapsed()),
+                    Ok(ConsensusResult::Decided { by_other: true, .. }) => {
+                        stats.displaced += 1
+                    }
+                    Ok(ConsensusResult::Failed { .. }) | Err(_) => stats.failed += 1,
+                }
+            }
+            (stats, submitted)
+        });
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut crashed = BTreeSet::new();
+    let mut faults = 0;
+    while Instant::now() < deadline {
+        sleep(Duration::from_millis(args.fault_interval)).await;
+        inject_fault(&mut rng, &mut cluster, &network, &acceptors, &mut crashed)?;
+        faults += 1;
+    }
+
+    info!("healing the cluster");
+    network.heal();
+    for &a in &acceptors {
+        for &b in acceptors.iter().chain(&proposers) {
+            network.set_link(a, b, DEFAULT_LINK);
+        }
+    }
+    for acceptor in std::mem::take(&mut crashed) {
+        cluster.restart_node(acceptor)?;
+    }
+
+    let mut stats = ClientStats::default();
+    let mut proposed = BTreeSet::new();
+    while let Some(joined) = submitters.join_next().await {
+        let (client, submitted) = joined?;
+        stats.latencies.extend(client.latencies);
+        stats.displaced += client.displaced;
+        stats.redirected += client.redirected;
+        stats.failed += client.failed;
+        proposed.extend(submitted);
+    }
+    let elapsed = started_at.elapsed();
+
+    let logs: BTreeMap<u64, BTreeMap<u64, u64>> = proposers
+        .iter()
+        .filter_map(|&proposer_id| {
+            let learner = cluster.learner(proposer_id)?;
+            let decided = learner
+                .decided_range(0..=u64::MAX, usize::MAX)
+                .into_iter()
+                .map(|(slot, proposal)| (slot, proposal.value))
+                .collect();
+            Some((proposer_id, decided))
+        })
+        .chain(acceptors.iter().filter_map(|&acceptor_id| {
+            Some((acceptor_id, cluster.acceptor_decisions(acceptor_id)?))
+        }))
+        .collect();
+    cluster.shutdown().await?;
+
+    print_stats(&mut stats, faults, elapsed, &logs);
+    check_logs(&logs, &proposed, args.min_shared_slots)
+}
+
+/// Submit `value` to the proposer `proposer_id`, or to the leader it redirects it
+/// to.
+async fn propose(
+    clients: &HashMap<u64, Client>,
+    proposer_id: u64,
+    value: u64,
+    timeout: Duration,
+    stats: &mut ClientStats,
+) -> Result<ConsensusResult, ProposeError> {
+    let outcome = clients[&proposer_id]
+        .propose_with_timeout(value, timeout)
+        .await;
+    match outcome {
+        Err(ProposeError::Redirect(leader)) if clients.contains_key(&leader) => {
+            stats.redirected += 1;
+            clients[&leader].propose_with_timeout(value, timeout).await
+        }
+        outcome => outcome,
+    }
+}
+
+/// Inject one fault, drawn from `rng`: crash or restart an acceptor, partition or
+/// heal the network, or change the conditions of a link.
+fn inject_fault(
+    rng: &mut StdRng,
+    cluster: &mut Cluster,
+    network: &SimNetwork,
+    acceptors: &[u64],
+    crashed: &mut BTreeSet<u64>,
+) -> Result<()> {
+    let nodes: Vec<u64> = cluster
+        .config
+        .peers
+        .iter()
+        .map(|peer| peer.node_id)
+        .collect();
+    match rng.gen_range(0..6) {
+        0 => {
+            let acceptor = *acceptors.choose(rng).expect("at least one acceptor");
+            if crashed.insert(acceptor) {
+                info!(acceptor, "crashing acceptor");
+                cluster.crash_node(acceptor)?;
+            }
+        }
+        1 => {
+            let Some(&acceptor) =
+                crashed.iter().nth(rng.gen_range(0..crashed.len().max(1)))
+            else {
+                return Ok(());
+            };
+            info!(acceptor, "restarting acceptor");
+            crashed.remove(&acceptor);
+            cluster.restart_node(acceptor)?;
+        }
+        2 => {
+            let mut shuffled = nodes;
+            shuffled.shuffle(rng);
+            let split = rng.gen_range(1..shuffled.len());
+            let groups = vec![shuffled[..split].to_vec(), shuffled[split..].to_vec()];
+            let policy = if rng.gen_bool(0.5) {
+                PartitionPolicy::Drop
+            } else {
+                PartitionPolicy::QueueUntilHeal
+            };
+            info!(?groups, ?policy, "partitioning the network");
+            network.set_partition_policy(policy);
+            network.partition(groups);
+        }
+        3 => {
+            info!("healing the network");
+            network.heal();
+        }
+        4 => {
+            let (a, b) = random_link(rng, acceptors, &nodes);
+            let spec = LinkSpec {
+                latency: Latency::Uniform {
+                    min: Duration::ZERO,
+                    max: rng.gen_range(Duration::from_millis(1)..=MAX_LATENCY),
+                },
+                drop_probability: rng.gen_range(0.0..=MAX_DROP_PROBABILITY),
+                duplicate_probability: rng.gen_range(0.0..=0.1),
+                reorder_window: rng.gen_range(Duration::ZERO..=MAX_LATENCY),
+            };
+            info!(a, b, ?spec, "degrading link");
+            network.set_link(a, b, spec);
+        }
+        _ => {
+            let (a, b) = random_link(rng, acceptors, &nodes);
+            info!(a, b, "restoring link");
+            network.set_link(a, b, DEFAULT_LINK);
+        }
+    }
+    Ok(())
+}
+
+/// Link between an acceptor and another node, drawn from `rng`.
+fn random_link(rng: &mut StdRng, acceptors: &[u64], nodes: &[u64]) -> (u64, u64) {
+    let a = *acceptors.choose(rng).expect("at least one acceptor");
+    let b = *nodes.choose(rng).expect("at least one node");
+    (a, b)
+}
+
+fn print_stats(
+    stats: &mut ClientStats,
+    faults: usize,
+    elapsed: Duration,
+    logs: &BTreeMap<u64, BTreeMap<u64, u64>>,
+) {
+    stats.latencies.sort_unstable();
+    let decided = stats.latencies.len();
+    let percentile = |p: usize| {
+        stats
+            .latencies
+            .get((decided * p / 100).min(decided.saturating_sub(1)))
+            .copied()
+            .unwrap_or_default()
+    };
+    println!(
+        "{faults} faults injected in {:.1}s: {decided} values decided ({:.1}/s), {} \
+         displaced by another proposer, {} redirected to the leader, {} failed",
+        elapsed.as_secs_f64(),
+        decided as f64 / elapsed.as_secs_f64(),
+        stats.displaced,
+        stats.redirected,
+        stats.failed
+    );
+    println!(
+        "latency: p50 {:?}, p99 {:?}, max {:?}",
+        percentile(50),
+        percentile(99),
+        stats.latencies.last().copied().unwrap_or_default()
+    );
+    for (node_id, log) in logs {
+        println!("node {node_id} learned {} slots", log.len());
+    }
+}
+
+/// Check that the nodes agree on every slot they learned, that every value of a
+/// client they learned was proposed, and that at least `min_shared_slots` slots
+/// were learned by two nodes or more, so that the logs were actually compared.
+fn check_logs(
+    logs: &BTreeMap<u64, BTreeMap<u64, u64>>,
+    proposed: &BTreeSet<u64>,
+    min_shared_slots: usize,
+) -> Result<()> {
+    let mut chosen = BTreeMap::new();
+    let mut learned_by: BTreeMap<u64, usize> = BTreeMap::new();
+    for (&node_id, log) in logs {
+        for (&slot, &value) in log {
+            if let Command::Value(value) = Command::decode(value) {
+                if !proposed.contains(&value) {
+                    bail!(
+                        "node {node_id} learned {value} for slot {slot}, which was \
+                         never proposed"
+                    );
+                }
+            }
+            *learned_by.entry(slot).or_default() += 1;
+            match chosen.insert(slot, (node_id, value)) {
+                Some((other, chosen)) if chosen != value => bail!(
+                    "slot {slot} decided twice: node {other} learned {chosen}, node \
+                     {node_id} learned {value}"
+                ),
+                _ => (),
+            }
+        }
+    }
+    let shared = learned_by.values().filter(|&&nodes| nodes >= 2).count();
+    if shared < min_shared_slots {
+        bail!(
+            "only {shared} slots learned by two nodes or more, {min_shared_slots} \
+             required to trust the check"
+        );
+    }
+    println!(
+        "decision logs consistent across {} nodes, {shared} slots learned by two \
+         nodes or more",
+        logs.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Logs of the nodes 0 and 1, each the `(slot, value)` pairs it learned.
+    fn logs(
+        first: &[(u64, u64)],
+        second: &[(u64, u64)],
+    ) -> BTreeMap<u64, BTreeMap<u64, u64>> {
+        BTreeMap::from([
+            (0, first.iter().copied().collect()),
+            (1, second.iter().copied().collect()),
+        ])
+    }
+
+    #[test]
+    fn logs_agreeing_on_proposed_values_pass() {
+        let proposed = BTreeSet::from([10, 11, 12]);
+        // Node 1 has not learned slot 2 yet, and learned a no-op for slot 3.
+        let noop = Command::Noop.encode();
+        let logs = logs(&[(0, 10), (1, 11), (2, 12)], &[(0, 10), (1, 11), (3, noop)]);
+
+        check_logs(&logs, &proposed, 2).unwrap();
+    }
+
+    #[test]
+    fn slot_learned_with_two_values_fails() {
+        let proposed = BTreeSet::from([10, 11]);
+        let logs = logs(&[(0, 10)], &[(0, 11)]);
+
+        let error = check_logs(&logs, &proposed, 0).unwrap_err().to_string();
+        assert!(error.contains("slot 0 decided twice"), "{error}");
+    }
+
+    #[test]
+    fn value_never_proposed_fails() {
+        let proposed = BTreeSet::from([10]);
+        let logs = logs(&[(0, 10), (1, 99)], &[(0, 10)]);
+
+        let error = check_logs(&logs, &proposed, 0).unwrap_err().to_string();
+        assert!(
+            error.contains("99 for slot 1, which was never proposed"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn run_comparing_too_few_slots_fails() {
+        let proposed = BTreeSet::from([10, 11]);
+        let logs = logs(&[(0, 10), (1, 11)], &[(0, 10)]);
+
+        assert!(check_logs(&logs, &proposed, 2).is_err());
+        check_logs(&logs, &proposed, 1).unwrap();
+    }
+}
@@ -0,0 +1,573 @@
+use std::{collections::HashSet, net::SocketAddr, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use paxos::{
+    config::{ClusterConfig, NodeConfig, PeerConfig, Role, SECRET_ENV},
+    domain::id::BrandedUuid,
+    node::Node,
+    transport::{
+        tcp::{self, TcpTransport},
+        wire::{Authenticator, Codec},
+    },
+};
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+/// Run a node of the cluster over TCP, or submit a value to a running one.
+///
+/// If the cluster shares a secret to authenticate its messages, it is read from the
+/// `PAXOS_SECRET` environment variable, or from the config file of the node.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Submit a value to a running node, which will propose it.
+    Propose {
+        /// Address the node listens on.
+        #[arg(long)]
+        addr: SocketAddr,
+
+        #[arg(long)]
+        value: u64,
+
+        /// Codec the node was started with.
+        #[arg(long, value_enum, default_value_t = Codec::Bincode)]
+        codec: Codec,
+
+        /// Identifier of the client. It must not be the id of a node.
+        #[arg(long, default_value_t = u64::MAX)]
+        client_id: u64,
+    },
+    /// Run a node that is only an acceptor, keeping its state in memory.
+    Acceptor {
+        #[arg(long)]
+        id: u64,
+
+        /// Address to listen on for the messages of the peers.
+        #[arg(long)]
+        listen: SocketAddr,
+
+        /// The other members of the cluster, as `id@address` separated by commas.
+        /// Peers are assumed to be both proposers and acceptors.
+        #[arg(long, value_delimiter = ',', value_parser = parse_peer)]
+        peers: Vec<(u64, SocketAddr)>,
+
+        /// Format of the messages exchanged with the peers.
+        #[arg(long, value_enum, default_value_t = Codec::Bincode)]
+        codec: Codec,
+
+        /// Minimum level of the logs, or any filter accepted by `RUST_LOG`.
+        #[arg(long, default_value = "info")]
+        log_level: String,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    /// TOML config file of the node. The other flags take precedence over it.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    #[arg(long)]
+    node_id: Option<u64>,
+
+    /// Members of the cluster, this node included, as `id@address` separated by
+    /// commas. Peers are assumed to be both proposers and acceptors.
+    #[arg(long, value_delimiter = ',', value_parser = parse_peer)]
+    peers: Vec<(u64, SocketAddr)>,
+
+    /// Roles of this node.
+    #[arg(long, value_enum)]
+    role: Option<NodeRole>,
+
+    /// Directory where the node keeps its durable state.
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Preference for this node to lead: its ballots win the ties against the
+    /// ballots of proposers with a lower priority.
+    #[arg(long)]
+    priority: Option<u64>,
+
+    /// Address of the endpoint serving the metrics of the node to Prometheus.
+    /// Requires the `prometheus` feature.
+    #[arg(long)]
+    metrics_listen: Option<SocketAddr>,
+
+    /// Address of the endpoint answering operators about the state of the node.
+    /// Requires the `admin` feature.
+    #[arg(long)]
+    admin_listen: Option<SocketAddr>,
+
+    /// File to append every decision of the node to, for audits. Read it back with
+    /// `paxos::audit::DecisionLog::iter`.
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+
+    /// Format of the messages exchanged with the peers.
+    #[arg(long, value_enum, default_value_t = Codec::Bincode)]
+    codec: Codec,
+
+    /// Minimum level of the logs, or any filter accepted by `RUST_LOG`.
+    #[arg(long, default_value = "info")]
+    log_level: String,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum NodeRole {
+    All,
+    Proposer,
+    Acceptor,
+}
+
+impl NodeRole {
+    fn roles(self) -> Vec<Role> {
+        match self {
+            NodeRole::All => vec![Role::Proposer, Role::Acceptor],
+            NodeRole::Proposer => vec![Role::Proposer],
+            NodeRole::Acceptor => vec![Role::Acceptor],
+        }
+    }
+}
+
+fn parse_peer(peer: &str) -> Result<(u64, SocketAddr), String> {
+    let (node_id, address) = peer
+        .split_once('@')
+        .ok_or_else(|| format!("expected `id@address`, got `{peer}`"))?;
+    let node_id = node_id
+        .parse()
+        .map_err(|e| format!("invalid node id `{node_id}`: {e}"))?;
+    let address = address
+        .parse()
+        .map_err(|e| format!("invalid address `{address}`: {e}"))?;
+
+    Ok((node_id, address))
+}
+
+/// Cluster of the acceptor `node_id` listening on `listen`, along with `peers`.
+fn acceptor_cluster(
+    node_id: u64,
+    listen: SocketAddr,
+    peers: &[(u64, SocketAddr)],
+) -> Result<ClusterConfig> {
+    let mut seen = HashSet::from([node_id]);
+    for (node_id, _) in peers {
+        if !seen.insert(*node_id) {
+            bail!("--peers: duplicate node id {node_id}");
+        }
+    }
+
+    let mut cluster = ClusterConfig {
+        peers: vec![PeerConfig {
+            node_id,
+            roles: NodeRole::Acceptor.roles(),
+            address: Some(listen),
+            priority: 0,
+        }],
+        ..ClusterConfig::default()
+    };
+    cluster
+        .peers
+        .extend(peers.iter().map(|&(node_id, address)| PeerConfig {
+            node_id,
+            roles: NodeRole::All.roles(),
+            address: Some(address),
+            priority: 0,
+        }));
+    cluster.validate(node_id)?;
+    Ok(cluster)
+}
+
+impl RunArgs {
+    /// Config of the node: the config file if any, overridden by the flags.
+    fn node_config(&self) -> Result<NodeConfig> {
+        let mut config = match &self.config {
+            Some(path) => NodeConfig::from_path(path)?,
+            None => {
+                let node_id = self
+                    .node_id
+                    .context("--node-id is required without --config")?;
+                let data_dir = self
+                    .data_dir
+                    .clone()
+                    .context("--data-dir is required without --config")?;
+                if self.peers.is_empty() {
+                    bail!("--peers is required without --config");
+                }
+                // The address is filled in from the peers below.
+                let mut config = NodeConfig::new(
+                    node_id,
+                    NodeRole::All.roles(),
+                    SocketAddr::from(([0, 0, 0, 0], 0)),
+                    data_dir,
+                );
+                config.secret = std::env::var(SECRET_ENV).ok();
+                config
+            }
+        };
+
+        if let Some(node_id) = self.node_id {
+            config.node_id = node_id;
+        }
+        if let Some(role) = self.role {
+            config.roles = role.roles();
+        }
+        if let Some(priority) = self.priority {
+            config.priority = priority;
+        }
+        if let Some(address) = self.metrics_listen {
+            config.metrics_listen = Some(address);
+        }
+        if let Some(address) = self.admin_listen {
+            config.admin_listen = Some(address);
+        }
+        if let Some(path) = &self.audit_log {
+            config.audit_log = Some(path.clone());
+        }
+        if let Some(data_dir) = &self.data_dir {
+            config.storage_dir = data_dir.clone();
+        }
+        if !self.peers.is_empty() {
+            config.listen = self
+                .peers
+                .iter()
+                .find(|(node_id, _)| *node_id == config.node_id)
+                .map(|(_, address)| *address)
+                .with_context(|| {
+                    format!("--peers has no address for node {}", config.node_id)
+                })?;
+            config.peers = self
+                .peers
+                .iter()
+                .filter(|(node_id, _)| *node_id != config.node_id)
+                .map(|&(node_id, address)| PeerConfig {
+                    node_id,
+                    roles: NodeRole::All.roles(),
+                    address: Some(address),
+                    priority: 0,
+                })
+                .collect();
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Propose {
+            addr,
+            value,
+            codec,
+            client_id,
+        }) => {
+            let auth = std::env::var(SECRET_ENV)
+                .ok()
+                .map(|secret| Authenticator::new(secret.as_bytes()));
+            tcp::submit(addr, codec, auth.as_ref(), client_id, value).await?;
+            println!("submitted {value} to {addr}");
+            Ok(())
+        }
+        Some(Command::Acceptor {
+            id,
+            listen,
+            peers,
+            codec,
+            log_level,
+        }) => {
+            init_logs(&log_level)?;
+            let cluster = acceptor_cluster(id, listen, &peers)?;
+            run_acceptor(id, cluster, codec).await
+        }
+        None => {
+            init_logs(&cli.run.log_level)?;
+            run(cli.run).await
+        }
+    }
+}
+
+fn init_logs(filter: &str) -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_new(filter)?)
+        .with_target(false)
+        .init();
+    Ok(())
+}
+
+/// Start the acceptor `node_id` of `cluster`, listening over TCP.
+async fn start_acceptor(
+    node_id: u64,
+    cluster: ClusterConfig,
+    codec: Codec,
+) -> Result<Node> {
+    let auth = std::env::var(SECRET_ENV)
+        .ok()
+        .map(|secret| Authenticator::new(secret.as_bytes()));
+    let transport = TcpTransport::listen(node_id, cluster.clone(), codec, auth).await?;
+    Node::new(node_id, cluster, Box::new(transport))
+}
+
+/// Run the acceptor `node_id` of `cluster` until SIGINT.
+async fn run_acceptor(
+    node_id: u64,
+    cluster: ClusterConfig,
+    codec: Codec,
+) -> Result<()> {
+    let node = start_acceptor(node_id, cluster, codec).await?;
+    info!(node_id, "acceptor started");
+
+    tokio::signal::ctrl_c().await?;
+    info!("shutting down");
+    node.shutdown().await?;
+    Ok(())
+}
+
+/// Run the node until SIGINT.
+async fn run(args: RunArgs) -> Result<()> {
+    let config = args.node_config()?;
+    let cluster = config.cluster();
+    let auth = config
+        .secret
+        .as_ref()
+        .map(|secret| Authenticator::new(secret.as_bytes()));
+    let transport =
+        TcpTransport::listen(config.node_id, cluster.clone(), args.codec, auth).await?;
+    #[allow(unused_mut)]
+    let mut node = Node::open(
+        config.node_id,
+        cluster,
+        Box::new(transport),
+        &config.storage_dir,
+        config.audit_log.as_deref(),
+    )?;
+    info!(node_id = config.node_id, listen = %config.listen, "node started");
+    if let Some(address) = config.metrics_listen {
+        #[cfg(feature = "prometheus")]
+        info!(address = %node.serve_metrics(address)?, "serving metrics");
+        #[cfg(not(feature = "prometheus"))]
+        warn!(%address, "not serving metrics, built without the prometheus feature");
+    }
+    if let Some(address) = config.admin_listen {
+        #[cfg(feature = "admin")]
+        info!(address = %node.serve_admin(address)?, "serving admin endpoint");
+        #[cfg(not(feature = "admin"))]
+        warn!(%address, "not serving admin endpoint, built without the admin feature");
+    }
+
+    let mut decisions = node.decisions();
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            Ok(decision) = decisions.recv() => {
+                let value = config.log_values.then_some(decision.proposal.value);
+                info!(
+                    slot = decision.slot,
+                    value,
+                    proposal_id = decision.proposal.id.formatted(),
+                    quorum = ?decision.quorum,
+                    "decided"
+                );
+            },
+        }
+    }
+
+    info!("shutting down");
+    let undecided = node.shutdown().await?;
+    if !undecided.is_empty() && config.log_values {
+        warn!(?undecided, "values left undecided");
+    } else if !undecided.is_empty() {
+        warn!(undecided = undecided.len(), "values left undecided");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use paxos::domain::consensus::ConsensusResult;
+
+    use super::*;
+
+    fn run_args(args: &[&str]) -> RunArgs {
+        let cli = Cli::try_parse_from(["paxos-node"].iter().chain(args)).unwrap();
+        assert!(cli.command.is_none());
+        cli.run
+    }
+
+    fn example_config() -> String {
+        format!("{}/example-config.toml", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn peers_are_parsed_as_id_at_address() {
+        assert_eq!(
+            parse_peer("1@127.0.0.1:7001"),
+            Ok((1, SocketAddr::from(([127, 0, 0, 1], 7001))))
+        );
+        for peer in ["127.0.0.1:7001", "one@127.0.0.1:7001", "1@localhost"] {
+            assert!(parse_peer(peer).is_err(), "{peer}");
+        }
+    }
+
+    #[test]
+    fn propose_subcommand_takes_the_address_and_the_value() {
+        let cli = Cli::try_parse_from([
+            "paxos-node",
+            "propose",
+            "--addr",
+            "127.0.0.1:7001",
+            "--value",
+            "42",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            cli.command,
+            Some(Command::Propose { addr, value: 42, codec: Codec::Bincode, .. })
+                if addr == SocketAddr::from(([127, 0, 0, 1], 7001))
+        ));
+        assert!(
+            Cli::try_parse_from(["paxos-node", "propose", "--value", "42"]).is_err()
+        );
+    }
+
+    #[test]
+    fn flags_alone_make_a_node_config() {
+        let config = run_args(&[
+            "--node-id",
+            "1",
+            "--peers",
+            "0@127.0.0.1:7000,1@127.0.0.1:7001,2@127.0.0.1:7002",
+            "--role",
+            "acceptor",
+            "--data-dir",
+            "data1",
+        ])
+        .node_config()
+        .unwrap();
+
+        assert_eq!(config.node_id, 1);
+        assert_eq!(config.roles, vec![Role::Acceptor]);
+        assert_eq!(config.listen, SocketAddr::from(([127, 0, 0, 1], 7001)));
+        assert_eq!(config.storage_dir, PathBuf::from("data1"));
+        let peers: Vec<u64> = config.peers.iter().map(|peer| peer.node_id).collect();
+        assert_eq!(peers, vec![0, 2]);
+    }
+
+    #[test]
+    fn flags_take_precedence_over_the_config_file() {
+        let config = run_args(&[
+            "--config",
+            &example_config(),
+            "--data-dir",
+            "elsewhere",
+            "--priority",
+            "3",
+        ])
+        .node_config()
+        .unwrap();
+
+        assert_eq!(config.node_id, 0);
+        assert_eq!(config.peers.len(), 2);
+        assert_eq!(config.storage_dir, PathBuf::from("elsewhere"));
+        assert_eq!(config.priority, 3);
+    }
+
+    #[test]
+    fn node_config_without_a_file_needs_the_required_flags() {
+        let error = run_args(&["--data-dir", "data1", "--peers", "1@127.0.0.1:7001"])
+            .node_config()
+            .unwrap_err();
+        assert!(error.to_string().contains("--node-id"), "{error}");
+
+        let error = run_args(&[
+            "--node-id",
+            "1",
+            "--data-dir",
+            "data1",
+            "--peers",
+            "0@127.0.0.1:7000",
+        ])
+        .node_config()
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("no address for node 1"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn acceptor_peers_must_have_distinct_ids() {
+        let listen = SocketAddr::from(([127, 0, 0, 1], 7000));
+        let peer = SocketAddr::from(([127, 0, 0, 1], 7001));
+
+        assert!(acceptor_cluster(0, listen, &[(1, peer), (2, peer)]).is_ok());
+        assert!(acceptor_cluster(0, listen, &[(1, peer), (1, peer)]).is_err());
+        assert!(acceptor_cluster(0, listen, &[(0, peer)]).is_err());
+    }
+
+    #[tokio::test]
+    async fn proposer_decides_through_a_started_acceptor_subcommand() {
+        let free_address = || {
+            std::net::TcpListener::bind(("127.0.0.1", 0))
+                .and_then(|listener| listener.local_addr())
+                .unwrap()
+        };
+        let (listen, peer) = (free_address(), free_address());
+        let cli = Cli::try_parse_from([
+            "paxos-node",
+            "acceptor",
+            "--id",
+            "0",
+            "--listen",
+            &listen.to_string(),
+            "--peers",
+            &format!("1@{peer}"),
+        ])
+        .unwrap();
+        let Some(Command::Acceptor {
+            id,
+            listen,
+            peers,
+            codec,
+            ..
+        }) = cli.command
+        else {
+            panic!("not the acceptor subcommand: {:?}", cli.command);
+        };
+        let cluster = acceptor_cluster(id, listen, &peers).unwrap();
+        let acceptor = start_acceptor(id, cluster.clone(), codec).await.unwrap();
+
+        // Node 1 is the other acceptor of the quorum of 2, and the proposer.
+        let transport = TcpTransport::listen(1, cluster.clone(), codec, None)
+            .await
+            .unwrap();
+        let node = Node::new(1, cluster, Box::new(transport)).unwrap();
+        let mut decisions = node.decisions();
+        let outcome = node
+            .client()
+            .propose_with_timeout(7, Duration::from_secs(10))
+            .await;
+
+        assert!(
+            matches!(outcome, Ok(ConsensusResult::Decided { proposal, .. }) if proposal.value == 7),
+            "{outcome:?}"
+        );
+        assert_eq!(decisions.recv().await.unwrap().quorum, vec![0, 1]);
+        node.shutdown().await.unwrap();
+        acceptor.shutdown().await.unwrap();
+    }
+}
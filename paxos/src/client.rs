@@ -0,0 +1,456 @@
+use std::{fmt, future::Future, sync::Arc, time::Duration};
+
+use tokio::{
+    sync::{broadcast, mpsc, Semaphore},
+    time::{timeout, Instant},
+};
+
+use crate::{
+    actors::proposer::Deadlines,
+    domain::{
+        ballot::BallotNumber,
+        command::Command,
+        consensus::{ClientOutcome, ConsensusResult, FailureReason, ProposeOutcome},
+    },
+};
+
+/// Why a value submitted with `Client::propose_with_timeout` was not decided.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProposeError {
+    /// The proposer did not report any outcome before the deadline, or too few
+    /// acceptors answered its rounds in time. The value may still be decided later.
+    Timeout,
+    /// The round of the value failed, for the given reason.
+    Rejected(String),
+    /// Every round the proposer was allowed to run for the value was rejected.
+    Exhausted { last_ballot: BallotNumber },
+    /// The deadline of the value passed before it was decided. The proposer gave up
+    /// on it.
+    Expired,
+    /// The proposer is not the leader: the value should be submitted to the node
+    /// with this id instead.
+    Redirect(u64),
+    /// Too few acceptors are alive for a quorum: `live` of them, when `required`
+    /// are needed. The proposer did not wait for them.
+    QuorumUnavailable { live: usize, required: usize },
+    /// The proposer could not reach any acceptor.
+    NoAcceptors,
+    /// Another proposer is making progress: the proposer lost its pre-vote, and did
+    /// not run a round for the value so as not to disrupt it.
+    PreVoteLost,
+    /// The value is reserved for the commands of the cluster, see
+    /// `Command::is_reserved`. It was not submitted.
+    Reserved(u64),
+    /// The proposer stopped.
+    Closed,
+}
+
+impl fmt::Display for ProposeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProposeError::Timeout => write!(f, "no outcome before the deadline"),
+            ProposeError::Rejected(reason) => write!(f, "proposal rejected: {reason}"),
+            ProposeError::Exhausted { last_ballot } => {
+                write!(f, "retries exhausted, last ballot {last_ballot}")
+            }
+            ProposeError::Expired => write!(f, "deadline passed before a decision"),
+            ProposeError::Redirect(leader) => {
+                write!(f, "not the leader, try node {leader}")
+            }
+            ProposeError::QuorumUnavailable { live, required } => {
+                write!(
+                    f,
+                    "{live} acceptors alive, {required} required for a quorum"
+                )
+            }
+            ProposeError::NoAcceptors => write!(f, "no acceptor reachable"),
+            ProposeError::PreVoteLost => {
+                write!(f, "pre-vote lost to the proposer making progress")
+            }
+            ProposeError::Reserved(value) => {
+                write!(f, "{value} is reserved for the commands of the cluster")
+            }
+            ProposeError::Closed => write!(f, "proposer stopped"),
+        }
+    }
+}
+
+impl std::error::Error for ProposeError {}
+
+/// Values a proposer may have submitted but not decided yet, by default.
+pub const DEFAULT_MAX_IN_FLIGHT_ROUNDS: usize = 64;
+
+/// How long past the deadline of a value its outcome is waited for: the proposer
+/// only reports it as expired once it gets to it.
+const EXPIRY_GRACE: Duration = Duration::from_millis(100);
+
+/// Submits values to a proposer and waits for their outcome.
+///
+/// Outcomes are told apart by value: if the same value is submitted twice
+/// concurrently, both calls may be given the outcome of the first round.
+///
+/// Every value submitted holds a permit of `admission` until its outcome is
+/// published, so that callers wait once the proposer has `max_in_flight_rounds`
+/// values to decide, instead of piling up more work than it can handle.
+#[derive(Clone)]
+pub struct Client {
+    values: mpsc::Sender<u64>,
+    outcomes: broadcast::Sender<ClientOutcome>,
+    /// Shared by all the clients of the proposer.
+    admission: Arc<Semaphore>,
+    deadlines: Deadlines,
+    /// Commands of the cluster submitted to the proposer, if this client may submit
+    /// any. See `with_commands`.
+    commands: Option<mpsc::Sender<Command>>,
+}
+
+impl Client {
+    /// Client of the proposer reading `values` and `deadlines`, and publishing to
+    /// `outcomes`, admitting values through the permits of `admission`.
+    pub fn new(
+        values: mpsc::Sender<u64>,
+        outcomes: broadcast::Sender<ClientOutcome>,
+        admission: Arc<Semaphore>,
+        deadlines: Deadlines,
+    ) -> Self {
+        Self {
+            values,
+            outcomes,
+            admission,
+            deadlines,
+            commands: None,
+        }
+    }
+
+    /// Let the client submit commands of the cluster to the proposer through
+    /// `commands`, its `Proposer::commands`. Commands reconfigure the cluster, so
+    /// only the operators of the cluster should be handed such a client.
+    pub fn with_commands(mut self, commands: mpsc::Sender<Command>) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
+    /// Submit `value` without waiting for its outcome. Waits for a permit first if
+    /// the proposer already has as many values in flight as it admits. Values
+    /// reserved for the commands of the cluster are refused.
+    pub async fn submit(&self, value: u64) -> Result<(), ProposeError> {
+        if Command::is_reserved(value) {
+            return Err(ProposeError::Reserved(value));
+        }
+        let permit = self
+            .admission
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| ProposeError::Closed)?;
+        // Subscribe first, not to miss an outcome published right after the send.
+        let mut outcomes = self.outcomes.subscribe();
+        self.values
+            .send(value)
+            .await
+            .map_err(|_| ProposeError::Closed)?;
+
+        tokio::spawn(async move {
+            let _permit = permit;
+            loop {
+                match outcomes.recv().await {
+                    Ok(outcome) if outcome.value == value => break,
+                    Ok(_) => continue,
+                    // The outcome may be among the missed ones: rather admit one
+                    // more value than hold the permit forever.
+                    Err(broadcast::error::RecvError::Lagged(_)) => break,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Number of values that can still be submitted without waiting.
+    pub fn available_permits(&self) -> usize {
+        self.admission.available_permits()
+    }
+
+    /// Submit `value`, and wait up to `deadline` for the proposer to decide it. The
+    /// time spent waiting for room in the queue of the proposer counts.
+    pub async fn propose_with_timeout(
+        &self,
+        value: u64,
+        deadline: Duration,
+    ) -> Result<ConsensusResult, ProposeError> {
+        self.decide_with_timeout(value, deadline, self.submit(value))
+            .await
+    }
+
+    /// Submit `command`, and wait up to `deadline` for the proposer to decide it.
+    /// Its outcome is the one of its encoded value. Fails as `Closed` if the client
+    /// can not submit commands.
+    pub async fn propose_command_with_timeout(
+        &self,
+        command: Command,
+        deadline: Duration,
+    ) -> Result<ConsensusResult, ProposeError> {
+        let Some(commands) = &self.commands else {
+            return Err(ProposeError::Closed);
+        };
+        let value = command.encode();
+        let submit = async {
+            commands
+                .send(command)
+                .await
+                .map_err(|_| ProposeError::Closed)
+        };
+        self.decide_with_timeout(value, deadline, submit).await
+    }
+
+    /// Run `submit`, and wait up to `deadline` for the outcome of `value`.
+    async fn decide_with_timeout(
+        &self,
+        value: u64,
+        deadline: Duration,
+        submit: impl Future<Output = Result<(), ProposeError>>,
+    ) -> Result<ConsensusResult, ProposeError> {
+        // Subscribe first, not to miss an outcome published right after the send.
+        let mut outcomes = self.outcomes.subscribe();
+        let outcome = async {
+            submit.await?;
+            loop {
+                match outcomes.recv().await {
+                    Ok(outcome) if outcome.value == value => break Ok(outcome.outcome),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => {
+                        break Err(ProposeError::Closed)
+                    }
+                }
+            }
+        };
+
+        match timeout(deadline, outcome).await {
+            Err(_) => Err(ProposeError::Timeout),
+            Ok(Err(e)) => Err(e),
+            Ok(Ok(Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                reason: FailureReason::Exhausted,
+                last_ballot,
+            })))) => Err(ProposeError::Exhausted { last_ballot }),
+            Ok(Ok(Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                reason: FailureReason::PrepareTimeout | FailureReason::AcceptTimeout,
+                ..
+            })))) => Err(ProposeError::Timeout),
+            Ok(Ok(Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                reason: FailureReason::Expired,
+                ..
+            })))) => Err(ProposeError::Expired),
+            Ok(Ok(Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                reason: FailureReason::QuorumUnavailable { live, required },
+                ..
+            })))) => Err(ProposeError::QuorumUnavailable { live, required }),
+            Ok(Ok(Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                reason: FailureReason::NoAcceptors,
+                ..
+            })))) => Err(ProposeError::NoAcceptors),
+            Ok(Ok(Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                reason: FailureReason::PreVoteLost,
+                ..
+            })))) => Err(ProposeError::PreVoteLost),
+            Ok(Ok(Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                reason: FailureReason::Reserved,
+                ..
+            })))) => Err(ProposeError::Reserved(value)),
+            Ok(Ok(Ok(ProposeOutcome::Decided(result)))) => Ok(result),
+            Ok(Ok(Ok(ProposeOutcome::Redirect(leader)))) => {
+                Err(ProposeError::Redirect(leader))
+            }
+            Ok(Ok(Err(reason))) => Err(ProposeError::Rejected(reason)),
+        }
+    }
+
+    /// Submit `value`, to be decided before `deadline`, and wait for its outcome.
+    /// Past the deadline, the proposer no longer starts or retries rounds for the
+    /// value, and reports it as `Expired`.
+    ///
+    /// Deadlines are told apart by value, like outcomes.
+    pub async fn propose_with_deadline(
+        &self,
+        value: u64,
+        deadline: Instant,
+    ) -> Result<ConsensusResult, ProposeError> {
+        self.deadlines
+            .lock()
+            .expect("deadlines lock poisoned")
+            .insert(value, deadline);
+        let budget = deadline.saturating_duration_since(Instant::now()) + EXPIRY_GRACE;
+        self.propose_with_timeout(value, budget).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testkit::{ClusterBuilder, ClusterTransport},
+        transport::sim::SimNetwork,
+    };
+
+    /// Client of a proposer that never runs, along with the queue of the values it
+    /// submits and where to publish their outcome.
+    fn client() -> (
+        Client,
+        mpsc::Receiver<u64>,
+        broadcast::Sender<ClientOutcome>,
+    ) {
+        let (values, queue) = mpsc::channel(8);
+        let outcomes = broadcast::channel(8).0;
+        let client = Client::new(
+            values,
+            outcomes.clone(),
+            Arc::new(Semaphore::new(DEFAULT_MAX_IN_FLIGHT_ROUNDS)),
+            Deadlines::default(),
+        );
+        (client, queue, outcomes)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn value_without_an_outcome_times_out_at_the_deadline() {
+        let (client, mut queue, _outcomes) = client();
+        let started = Instant::now();
+
+        let outcome = client.propose_with_timeout(7, Duration::from_secs(2)).await;
+
+        assert_eq!(outcome, Err(ProposeError::Timeout));
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+        assert_eq!(queue.try_recv(), Ok(7));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn submissions_past_the_permits_wait_for_an_earlier_outcome() {
+        let (values, mut queue) = mpsc::channel(8);
+        let outcomes = broadcast::channel(8).0;
+        let client = Client::new(
+            values,
+            outcomes.clone(),
+            Arc::new(Semaphore::new(2)),
+            Deadlines::default(),
+        );
+
+        client.submit(1).await.unwrap();
+        client.submit(2).await.unwrap();
+        assert_eq!(client.available_permits(), 0);
+        let blocked = client.clone();
+        let mut third = tokio::spawn(async move { blocked.submit(3).await });
+        assert!(
+            tokio::time::timeout(Duration::from_secs(1), &mut third)
+                .await
+                .is_err(),
+            "a value was admitted past the permits"
+        );
+
+        outcomes
+            .send(ClientOutcome {
+                value: 1,
+                outcome: Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                    reason: FailureReason::AcceptTimeout,
+                    last_ballot: BallotNumber::default(),
+                })),
+            })
+            .unwrap();
+        third.await.unwrap().unwrap();
+
+        let mut submitted = Vec::new();
+        while let Ok(value) = queue.try_recv() {
+            submitted.push(value);
+        }
+        assert_eq!(submitted, [1, 2, 3]);
+        assert_eq!(client.available_permits(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn round_timing_out_is_reported_as_a_timeout() {
+        let (client, mut queue, outcomes) = client();
+        tokio::spawn(async move {
+            let value = queue.recv().await.unwrap();
+            outcomes
+                .send(ClientOutcome {
+                    value,
+                    outcome: Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                        reason: FailureReason::AcceptTimeout,
+                        last_ballot: BallotNumber::default(),
+                    })),
+                })
+                .unwrap();
+        });
+
+        let outcome = client.propose_with_timeout(7, Duration::from_secs(2)).await;
+
+        assert_eq!(outcome, Err(ProposeError::Timeout));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lost_pre_vote_is_reported_as_such() {
+        let (client, mut queue, outcomes) = client();
+        tokio::spawn(async move {
+            let value = queue.recv().await.unwrap();
+            outcomes
+                .send(ClientOutcome {
+                    value,
+                    outcome: Ok(ProposeOutcome::Decided(ConsensusResult::Failed {
+                        reason: FailureReason::PreVoteLost,
+                        last_ballot: BallotNumber::default(),
+                    })),
+                })
+                .unwrap();
+        });
+
+        let outcome = client.propose_with_timeout(7, Duration::from_secs(2)).await;
+
+        assert_eq!(outcome, Err(ProposeError::PreVoteLost));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn value_times_out_when_no_quorum_is_reachable() {
+        let mut cluster = ClusterBuilder::new()
+            .transport(ClusterTransport::Sim(SimNetwork::new(7)))
+            .build()
+            .unwrap();
+        cluster.crash_node(0).unwrap();
+        cluster.crash_node(1).unwrap();
+        let deadline = Duration::from_secs(2);
+        let started = Instant::now();
+
+        let outcome = cluster
+            .proposer_client(3)
+            .unwrap()
+            .propose_with_timeout(7, deadline)
+            .await;
+
+        assert_eq!(outcome, Err(ProposeError::Timeout));
+        assert!(started.elapsed() <= deadline);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn value_past_its_deadline_expires_and_is_no_longer_retried() {
+        let mut cluster = ClusterBuilder::new()
+            .transport(ClusterTransport::Sim(SimNetwork::new(7)))
+            .build()
+            .unwrap();
+        cluster.crash_node(0).unwrap();
+        cluster.crash_node(1).unwrap();
+        let mut events = cluster.events(3).unwrap();
+        let started = Instant::now();
+
+        let outcome = cluster
+            .proposer_client(3)
+            .unwrap()
+            .propose_with_deadline(7, started + Duration::from_millis(50))
+            .await;
+
+        assert_eq!(outcome, Err(ProposeError::Expired));
+        assert!(started.elapsed() <= Duration::from_millis(50) + EXPIRY_GRACE);
+        while events.try_recv().is_ok() {}
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let event = events.try_recv();
+        assert!(
+            event.is_err(),
+            "the proposer went on with the value: {event:?}"
+        );
+    }
+}
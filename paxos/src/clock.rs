@@ -0,0 +1,161 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use tokio::{sync::watch, time::Instant};
+
+/// Source of time of the actors. Every timeout, backoff and lease goes through it,
+/// so that tests can drive time by hand with a `ManualClock`.
+#[async_trait::async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Resolve once `deadline` is reached, immediately if it already is.
+    async fn sleep_until(&self, deadline: Instant);
+
+    async fn sleep(&self, duration: Duration) {
+        self.sleep_until(self.now() + duration).await
+    }
+}
+
+/// Time as kept by tokio, which follows `tokio::time::pause` in tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+#[async_trait::async_trait]
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        tokio::time::sleep_until(deadline).await
+    }
+}
+
+/// Time that only moves when told to, with `advance`. Sleepers wake up as soon as
+/// it reaches their deadline, and not before.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<watch::Sender<Instant>>,
+}
+
+impl ManualClock {
+    /// Clock starting at the current time.
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(watch::Sender::new(Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.send_modify(|now| *now += duration);
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.borrow()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        let mut now = self.now.subscribe();
+        // The sender lives as long as the clock: waiting can not fail.
+        let _ = now.wait_for(|now| *now >= deadline).await;
+    }
+}
+
+/// Ticks every `period` of `clock`. A tick that is late is not made up for: the
+/// next one is due a full period later.
+pub struct Interval {
+    clock: Arc<dyn Clock>,
+    period: Duration,
+    next: Instant,
+}
+
+impl Interval {
+    /// Interval whose first tick is due right away.
+    pub fn new(clock: Arc<dyn Clock>, period: Duration) -> Self {
+        let next = clock.now();
+        Self {
+            clock,
+            period,
+            next,
+        }
+    }
+
+    /// Wait for the next tick, and return when it was due.
+    pub async fn tick(&mut self) -> Instant {
+        self.clock.sleep_until(self.next).await;
+        let due = self.next;
+        self.next = self.clock.now().max(due) + self.period;
+        due
+    }
+}
+
+/// Run `future` until `deadline` of `clock`. Returns `None` if the deadline was
+/// reached first.
+pub async fn timeout_at<F: Future>(
+    clock: &dyn Clock,
+    deadline: Instant,
+    future: F,
+) -> Option<F::Output> {
+    tokio::select! {
+        biased;
+        output = future => Some(output),
+        _ = clock.sleep_until(deadline) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Let the spawned tasks run until they wait on the clock again.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn manual_sleep_wakes_up_at_its_deadline_and_not_before() {
+        let clock = ManualClock::new();
+        let sleeper = clock.clone();
+        let sleep =
+            tokio::spawn(async move { sleeper.sleep(Duration::from_secs(1)).await });
+        settle().await;
+
+        clock.advance(Duration::from_millis(999));
+        settle().await;
+        assert!(!sleep.is_finished());
+
+        clock.advance(Duration::from_millis(1));
+        sleep.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn interval_ticks_once_per_period_without_making_up_late_ticks() {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.now();
+        let mut interval = Interval::new(clock.clone(), Duration::from_secs(1));
+
+        assert_eq!(interval.tick().await, start);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(interval.tick().await, start + Duration::from_secs(1));
+
+        // Three periods late: a single tick, then the next one a period later.
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(interval.tick().await, start + Duration::from_secs(2));
+        let mut next = tokio::spawn(async move { interval.tick().await });
+        settle().await;
+        assert!(!next.is_finished());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!((&mut next).await.unwrap(), start + Duration::from_secs(6));
+    }
+}
@@ -1,4 +1,21 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
 use clap::Parser;
+use tokio::{net::TcpStream, time::timeout};
+use tracing::{info, warn};
+
+use crate::{
+    actors::proposer::{DEFAULT_ACCEPT_TIMEOUT, DEFAULT_PREPARE_TIMEOUT},
+    domain::quorum::{Phase, QuorumTracker},
+    rate_limit::RateLimit,
+    retry::{RetryPolicy, DEFAULT_MAX_ATTEMPTS},
+};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -10,4 +27,921 @@ pub struct Args {
     /// Number of rounds.
     #[arg(short, long, default_value_t = 10)]
     pub rounds: usize,
+
+    /// Sqlite database where the proposer persists its round state. When omitted,
+    /// the state is kept in memory and lost on restart.
+    #[arg(short, long)]
+    pub database: Option<PathBuf>,
+
+    /// Keep the values proposed out of the logs of the proposer, which then only
+    /// show the ids of the proposals.
+    #[arg(long)]
+    pub redact_values: bool,
+
+    /// Carry prepare and accept traffic over separate in-process channels, so that
+    /// the accept phase cannot delay the prepare phase.
+    #[arg(long)]
+    pub split_phases: bool,
+
+    /// Start each round with a pre-vote, so that a proposer that can not win does
+    /// not raise its ballot.
+    #[arg(long)]
+    pub pre_vote: bool,
+
+    /// Tell the acceptors when the proposer gives up on a round, so that the
+    /// proposers it outbid retry without backing off.
+    #[arg(long)]
+    pub abort_rounds: bool,
+
+    /// Rounds the proposer runs for a value before giving up on it, when its
+    /// proposals keep being rejected.
+    #[arg(long, default_value_t = DEFAULT_MAX_ATTEMPTS)]
+    pub max_retries: u32,
+
+    /// How long the prepare phase of a round may wait for a quorum of promises
+    /// before the round is retried, in milliseconds.
+    #[arg(long, default_value_t = DEFAULT_PREPARE_TIMEOUT.as_millis() as u64)]
+    pub prepare_timeout_ms: u64,
+
+    /// How long the accept phase of a round may wait for a quorum of acceptances
+    /// before the round is restarted, in milliseconds.
+    #[arg(long, default_value_t = DEFAULT_ACCEPT_TIMEOUT.as_millis() as u64)]
+    pub accept_timeout_ms: u64,
+
+    /// Age past which the proposer drops the responses to its requests instead of
+    /// counting them, in milliseconds. Unlimited when omitted.
+    #[arg(long)]
+    pub max_message_age_ms: Option<u64>,
+
+    /// Run the nodes over a simulated network instead of in-process channels.
+    #[arg(long)]
+    pub simulate: bool,
+
+    /// Seed of the simulated network.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Latency of every simulated link, in milliseconds.
+    #[arg(long, default_value_t = 0)]
+    pub latency_ms: u64,
+
+    /// Probability, between 0 and 1, that the simulated network drops a message.
+    #[arg(long, default_value_t = 0.0)]
+    pub drop_probability: f64,
+
+    /// Probability, between 0 and 1, that the simulated network duplicates a
+    /// message.
+    #[arg(long, default_value_t = 0.0)]
+    pub duplicate_probability: f64,
+
+    /// Maximum extra delay, in milliseconds, the simulated network may hold a message
+    /// for, letting later messages overtake it.
+    #[arg(long, default_value_t = 0)]
+    pub reorder_window_ms: u64,
+}
+
+/// How long the startup sweep waits for each peer before considering it unreachable,
+/// by default.
+const DIAL_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Proposer,
+    Acceptor,
+}
+
+/// A member of the cluster, as known statically before startup.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PeerConfig {
+    pub node_id: u64,
+    /// Roles played by the node. A node can be both a proposer and an acceptor.
+    pub roles: Vec<Role>,
+    /// Address used by networked transports to dial the peer. Peers living in the
+    /// same process (talking through channels) do not have one.
+    pub address: Option<SocketAddr>,
+    /// Preference for the node to lead, if it is a proposer. Between ballots of the
+    /// same round, the ones of the proposer with the highest priority win.
+    #[serde(default)]
+    pub priority: u64,
+}
+
+impl PeerConfig {
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
+}
+
+/// Static list of the nodes that make up the cluster. This is the source of truth
+/// for the number of acceptors, and therefore for the size of the quorum.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ClusterConfig {
+    pub peers: Vec<PeerConfig>,
+    /// Number of acceptors a phase needs to succeed, instead of a simple majority.
+    /// It must still be a majority of the acceptors, or two quorums could decide
+    /// different values.
+    #[serde(default)]
+    pub quorum: Option<usize>,
+    /// Number of acceptors that must promise a ballot, instead of `quorum`.
+    ///
+    /// Prepare and accept quorums may differ in size as long as any two of them
+    /// intersect, i.e. their sizes add up to more than the number of acceptors.
+    /// They are the Q1 and Q2 of Flexible Paxos, hence the aliases.
+    #[serde(default, alias = "q1_size")]
+    pub prepare_quorum: Option<usize>,
+    /// Number of acceptors that must accept a proposal, instead of `quorum`.
+    #[serde(default, alias = "q2_size")]
+    pub accept_quorum: Option<usize>,
+    /// How the proposers retry the rounds that fail.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Whether the nodes log the values they propose and learn. When not, their
+    /// logs only show the ids of the proposals and how many values are involved.
+    #[serde(default = "default_log_values")]
+    pub log_values: bool,
+    /// Prepare requests each acceptor answers for every proposer. Those beyond it
+    /// are dropped, so that a proposer flooding the acceptors with prepare
+    /// requests can not overwhelm their storage. Unlimited if not set.
+    #[serde(default)]
+    pub prepare_rate_limit: Option<RateLimit>,
+    /// How long the nodes wait for their peers to answer.
+    #[serde(default)]
+    pub timeouts: Timeouts,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            peers: Vec::new(),
+            quorum: None,
+            prepare_quorum: None,
+            accept_quorum: None,
+            retry: RetryPolicy::default(),
+            log_values: default_log_values(),
+            prepare_rate_limit: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+}
+
+fn default_log_values() -> bool {
+    true
+}
+
+impl ClusterConfig {
+    /// Cluster where every node runs in this process: acceptors get the ids
+    /// `0..acceptors` and the proposer the id right after them.
+    pub fn in_process(acceptors: usize) -> Self {
+        let mut peers: Vec<PeerConfig> = (0..acceptors as u64)
+            .map(|node_id| PeerConfig {
+                node_id,
+                roles: vec![Role::Acceptor],
+                address: None,
+                priority: 0,
+            })
+            .collect();
+        peers.push(PeerConfig {
+            node_id: acceptors as u64,
+            roles: vec![Role::Proposer],
+            address: None,
+            priority: 0,
+        });
+
+        Self {
+            peers,
+            quorum: None,
+            prepare_quorum: None,
+            accept_quorum: None,
+            retry: RetryPolicy::default(),
+            log_values: true,
+            prepare_rate_limit: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// Cluster of `nodes` in-process nodes with the ids `0..nodes`, each of them
+    /// being both a proposer and an acceptor.
+    pub fn symmetric(nodes: usize) -> Self {
+        let peers = (0..nodes as u64)
+            .map(|node_id| PeerConfig {
+                node_id,
+                roles: vec![Role::Proposer, Role::Acceptor],
+                address: None,
+                priority: 0,
+            })
+            .collect();
+
+        Self {
+            peers,
+            quorum: None,
+            prepare_quorum: None,
+            accept_quorum: None,
+            retry: RetryPolicy::default(),
+            log_values: true,
+            prepare_rate_limit: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    pub fn peer(&self, node_id: u64) -> Option<&PeerConfig> {
+        self.peers.iter().find(|peer| peer.node_id == node_id)
+    }
+
+    pub fn proposer_id(&self) -> Option<u64> {
+        self.peers
+            .iter()
+            .find(|peer| peer.has_role(Role::Proposer))
+            .map(|peer| peer.node_id)
+    }
+
+    pub fn acceptors(&self) -> impl Iterator<Item = &PeerConfig> {
+        self.peers
+            .iter()
+            .filter(|peer| peer.has_role(Role::Acceptor))
+    }
+
+    pub fn acceptor_count(&self) -> usize {
+        self.acceptors().count()
+    }
+
+    /// The same cluster, with `acceptors` as its only acceptors. Peers that stop
+    /// being acceptors keep their other roles, if any. Acceptors new to the cluster
+    /// are added without an address, so over TCP they must already be among the
+    /// peers.
+    pub fn with_acceptors(&self, acceptors: &BTreeSet<u64>) -> Self {
+        let mut peers: Vec<PeerConfig> = self
+            .peers
+            .iter()
+            .cloned()
+            .filter_map(|mut peer| {
+                peer.roles.retain(|role| *role != Role::Acceptor);
+                if acceptors.contains(&peer.node_id) {
+                    peer.roles.push(Role::Acceptor);
+                }
+                (!peer.roles.is_empty()).then_some(peer)
+            })
+            .collect();
+        for &node_id in acceptors {
+            if self.peer(node_id).is_none() {
+                peers.push(PeerConfig {
+                    node_id,
+                    roles: vec![Role::Acceptor],
+                    address: None,
+                    priority: 0,
+                });
+            }
+        }
+
+        Self {
+            peers,
+            quorum: self.quorum,
+            prepare_quorum: self.prepare_quorum,
+            accept_quorum: self.accept_quorum,
+            retry: self.retry,
+            log_values: self.log_values,
+            prepare_rate_limit: self.prepare_rate_limit,
+            timeouts: self.timeouts,
+        }
+    }
+
+    /// Minimum number of acceptors that must answer for a phase to succeed.
+    pub fn quorum(&self) -> usize {
+        self.quorum.unwrap_or(self.acceptor_count() / 2 + 1)
+    }
+
+    /// Minimum number of acceptors that must promise a ballot for the prepare phase
+    /// to succeed.
+    pub fn prepare_quorum(&self) -> usize {
+        self.prepare_quorum.unwrap_or_else(|| self.quorum())
+    }
+
+    /// Minimum number of acceptors that must accept a proposal for it to be chosen.
+    pub fn accept_quorum(&self) -> usize {
+        self.accept_quorum.unwrap_or_else(|| self.quorum())
+    }
+
+    /// Tracker of the quorums of `phase` in this membership.
+    pub fn quorum_tracker(&self, phase: Phase) -> QuorumTracker {
+        QuorumTracker::new(
+            self.acceptors().map(|peer| peer.node_id),
+            self.phase_quorum(phase),
+        )
+    }
+
+    /// Size of the quorums of `phase`.
+    pub fn phase_quorum(&self, phase: Phase) -> usize {
+        match phase {
+            Phase::Prepare => self.prepare_quorum(),
+            Phase::Accept => self.accept_quorum(),
+        }
+    }
+
+    /// Check that the list of peers is coherent from the point of view of the node
+    /// `node_id`: ids must be unique and the node must be part of the cluster.
+    pub fn validate(&self, node_id: u64) -> Result<()> {
+        let mut seen = HashSet::new();
+        for peer in &self.peers {
+            if !seen.insert(peer.node_id) {
+                bail!("duplicate node id {} in cluster config", peer.node_id);
+            }
+        }
+
+        if !seen.contains(&node_id) {
+            bail!("node {node_id} is not part of the cluster config");
+        }
+
+        if let Some(quorum) = self.quorum {
+            let acceptors = self.acceptor_count();
+            if quorum <= acceptors / 2 || quorum > acceptors {
+                bail!(
+                    "quorum of {quorum} is not a majority of the {acceptors} acceptors"
+                );
+            }
+        }
+
+        let acceptors = self.acceptor_count();
+        for (key, size) in [
+            ("prepare_quorum", self.prepare_quorum),
+            ("accept_quorum", self.accept_quorum),
+        ] {
+            if let Some(size) = size {
+                if size == 0 || size > acceptors {
+                    bail!(
+                        "{key} of {size} is not between 1 and the {acceptors} \
+                         acceptors"
+                    );
+                }
+            }
+        }
+        let (prepare, accept) = (self.prepare_quorum(), self.accept_quorum());
+        if prepare + accept <= acceptors {
+            bail!(
+                "prepare quorum of {prepare} and accept quorum of {accept} may not \
+                 intersect among {acceptors} acceptors"
+            );
+        }
+        self.retry.validate()?;
+        if let Some(limit) = self.prepare_rate_limit {
+            limit.validate().context("invalid prepare_rate_limit")?;
+        }
+        self.timeouts.validate()?;
+
+        Ok(())
+    }
+
+    /// Validate the config and try to reach every other peer once, logging which
+    /// ones answered. Unreachable peers are not an error: they may come up later.
+    pub async fn bootstrap(&self, node_id: u64) -> Result<Vec<u64>> {
+        self.validate(node_id)?;
+
+        let mut reachable = Vec::new();
+        let mut unreachable = Vec::new();
+        for peer in self.peers.iter().filter(|peer| peer.node_id != node_id) {
+            let Some(address) = peer.address else {
+                // In-process peers are always reachable through their channels.
+                reachable.push(peer.node_id);
+                continue;
+            };
+
+            match timeout(self.timeouts.dial(), TcpStream::connect(address)).await {
+                Ok(Ok(_)) => reachable.push(peer.node_id),
+                Ok(Err(e)) => {
+                    warn!(peer = peer.node_id, %address, "could not dial peer: {e}");
+                    unreachable.push(peer.node_id);
+                }
+                Err(_) => {
+                    warn!(peer = peer.node_id, %address, "timed out dialing peer");
+                    unreachable.push(peer.node_id);
+                }
+            }
+        }
+
+        info!(
+            node_id,
+            ?reachable,
+            ?unreachable,
+            "initial connection sweep done"
+        );
+        Ok(reachable)
+    }
+}
+
+/// Environment variables that take precedence over the keys of a node config file,
+/// along with the key each of them overrides.
+const ENV_OVERRIDES: [(&str, &str); 10] = [
+    ("PAXOS_NODE_ID", "node_id"),
+    ("PAXOS_LISTEN", "listen"),
+    ("PAXOS_STORAGE_DIR", "storage_dir"),
+    ("PAXOS_QUORUM", "quorum"),
+    ("PAXOS_PREPARE_QUORUM", "prepare_quorum"),
+    ("PAXOS_ACCEPT_QUORUM", "accept_quorum"),
+    ("PAXOS_METRICS_LISTEN", "metrics_listen"),
+    ("PAXOS_ADMIN_LISTEN", "admin_listen"),
+    ("PAXOS_LOG_VALUES", "log_values"),
+    ("PAXOS_AUDIT_LOG", "audit_log"),
+];
+
+/// Other names of the keys of a node config file, along with the key each of them
+/// stands for.
+const KEY_ALIASES: [(&str, &str); 2] =
+    [("q1_size", "prepare_quorum"), ("q2_size", "accept_quorum")];
+
+/// Environment variable that takes precedence over the `secret` of a node config
+/// file. Unlike the other overrides, its value is never read as a number.
+pub const SECRET_ENV: &str = "PAXOS_SECRET";
+
+/// Settings of a node running in its own process, as loaded from a TOML file by
+/// `NodeConfig::from_path`. See `example-config.toml` for a documented example.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    pub node_id: u64,
+    pub roles: Vec<Role>,
+    /// Address the node listens on for the messages of its peers.
+    pub listen: SocketAddr,
+    /// Directory where the node keeps its durable state.
+    pub storage_dir: PathBuf,
+    /// The other members of the cluster.
+    #[serde(default)]
+    pub peers: Vec<PeerConfig>,
+    /// How long the node waits for its peers, see `ClusterConfig::timeouts`.
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    /// Size of the quorum, if it must not be a simple majority of the acceptors.
+    #[serde(default)]
+    pub quorum: Option<usize>,
+    /// Size of the quorum of the prepare phase, see `ClusterConfig::prepare_quorum`.
+    #[serde(default, alias = "q1_size")]
+    pub prepare_quorum: Option<usize>,
+    /// Size of the quorum of the accept phase, see `ClusterConfig::accept_quorum`.
+    #[serde(default, alias = "q2_size")]
+    pub accept_quorum: Option<usize>,
+    /// Preference for this node to lead, see `PeerConfig::priority`.
+    #[serde(default)]
+    pub priority: u64,
+    /// Secret shared by the cluster, used to authenticate the messages exchanged
+    /// over the network. Messages are not authenticated without one.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Address of the endpoint serving the metrics of the node to Prometheus. Only
+    /// used when built with the `prometheus` feature.
+    #[serde(default)]
+    pub metrics_listen: Option<SocketAddr>,
+    /// Address of the endpoint answering operators about the state of the node.
+    /// Only used when built with the `admin` feature.
+    #[serde(default)]
+    pub admin_listen: Option<SocketAddr>,
+    /// Whether the node logs the values it proposes and learns, see
+    /// `ClusterConfig::log_values`.
+    #[serde(default = "default_log_values")]
+    pub log_values: bool,
+    /// File the node appends every decision it learns to, see `DecisionLog`. No
+    /// decision is written to disk without one.
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+    /// Prepare requests the acceptor of the node answers for every proposer, see
+    /// `ClusterConfig::prepare_rate_limit`.
+    #[serde(default)]
+    pub prepare_rate_limit: Option<RateLimit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Timeouts {
+    /// How long the startup sweep of `ClusterConfig::bootstrap` waits for a peer to
+    /// accept a connection, in milliseconds.
+    pub dial_ms: u64,
+    /// How long a round may last before the proposer gives up on it, in
+    /// milliseconds. It is split evenly between the prepare and the accept phase,
+    /// see `Proposer::prepare_timeout`.
+    pub round_ms: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            dial_ms: DIAL_TIMEOUT.as_millis() as u64,
+            round_ms: (DEFAULT_PREPARE_TIMEOUT + DEFAULT_ACCEPT_TIMEOUT).as_millis()
+                as u64,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn dial(&self) -> Duration {
+        Duration::from_millis(self.dial_ms)
+    }
+
+    /// How long each phase of a round may last.
+    pub fn phase(&self) -> Duration {
+        Duration::from_millis(self.round_ms / 2)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.dial_ms == 0 {
+            bail!("timeouts.dial_ms must be positive");
+        }
+        if self.round_ms < 2 {
+            bail!(
+                "timeouts.round_ms of {} leaves no time to its phases",
+                self.round_ms
+            );
+        }
+        Ok(())
+    }
+}
+
+impl NodeConfig {
+    /// Config of a node without peers, every optional setting left to its default.
+    pub fn new(
+        node_id: u64,
+        roles: Vec<Role>,
+        listen: SocketAddr,
+        storage_dir: PathBuf,
+    ) -> Self {
+        Self {
+            node_id,
+            roles,
+            listen,
+            storage_dir,
+            peers: Vec::new(),
+            timeouts: Timeouts::default(),
+            quorum: None,
+            prepare_quorum: None,
+            accept_quorum: None,
+            priority: 0,
+            secret: None,
+            metrics_listen: None,
+            admin_listen: None,
+            log_values: default_log_values(),
+            audit_log: None,
+            prepare_rate_limit: None,
+        }
+    }
+
+    /// Load the config of a node from a TOML file. The `PAXOS_*` environment
+    /// variables listed in `ENV_OVERRIDES` take precedence over the file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
+
+        Self::from_toml(&contents, |variable| std::env::var(variable).ok())
+            .with_context(|| format!("invalid node config {}", path.display()))
+    }
+
+    /// Parse and validate the config of a node, `env` being used to look up the
+    /// environment variables that override its keys.
+    pub fn from_toml(
+        contents: &str,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self> {
+        let mut table: toml::Table = contents.parse()?;
+        let mut overridden = false;
+        for (variable, key) in ENV_OVERRIDES {
+            if let Some(value) = env(variable) {
+                // Numbers and booleans are passed as such, so that they can fill
+                // integer and boolean keys.
+                let value = value
+                    .parse()
+                    .map(toml::Value::Integer)
+                    .or_else(|_| value.parse().map(toml::Value::Boolean))
+                    .unwrap_or(toml::Value::String(value));
+                table.insert(key.to_string(), value);
+                // The file may set the key under another name, which would then
+                // be a duplicate.
+                for (alias, _) in KEY_ALIASES.iter().filter(|(_, of)| *of == key) {
+                    table.remove(*alias);
+                }
+                overridden = true;
+            }
+        }
+
+        // Errors point at the offending line of the document, which is only the
+        // original one if nothing was overridden.
+        let mut config: Self = if overridden {
+            toml::from_str(&table.to_string())?
+        } else {
+            toml::from_str(contents)?
+        };
+        if let Some(secret) = env(SECRET_ENV) {
+            config.secret = Some(secret);
+        }
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Check that the settings are coherent, naming the offending key otherwise.
+    pub fn validate(&self) -> Result<()> {
+        if self.roles.is_empty() {
+            bail!("roles: a node needs at least one role");
+        }
+
+        let mut seen = HashSet::from([self.node_id]);
+        for (i, peer) in self.peers.iter().enumerate() {
+            if !seen.insert(peer.node_id) {
+                bail!("peers[{i}].node_id: duplicate node id {}", peer.node_id);
+            }
+        }
+
+        if let Some(limit) = self.prepare_rate_limit {
+            limit.validate().context("prepare_rate_limit")?;
+        }
+        self.timeouts.validate()?;
+
+        self.cluster()
+            .validate(self.node_id)
+            .context("invalid quorum override")
+    }
+
+    /// The cluster as seen by this node, itself included.
+    pub fn cluster(&self) -> ClusterConfig {
+        let mut peers = vec![PeerConfig {
+            node_id: self.node_id,
+            roles: self.roles.clone(),
+            address: Some(self.listen),
+            priority: self.priority,
+        }];
+        peers.extend(self.peers.iter().cloned());
+
+        ClusterConfig {
+            peers,
+            quorum: self.quorum,
+            prepare_quorum: self.prepare_quorum,
+            accept_quorum: self.accept_quorum,
+            retry: RetryPolicy::default(),
+            log_values: self.log_values,
+            prepare_rate_limit: self.prepare_rate_limit,
+            timeouts: self.timeouts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{prelude::*, sample::subsequence};
+
+    use super::*;
+    use crate::{
+        domain::consensus::ConsensusResult,
+        node::Node,
+        transport::{tcp::TcpTransport, wire::Codec},
+    };
+
+    const EXAMPLE: &str = include_str!("../example-config.toml");
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    fn free_address() -> SocketAddr {
+        std::net::TcpListener::bind(("127.0.0.1", 0))
+            .and_then(|listener| listener.local_addr())
+            .expect("no free port")
+    }
+
+    /// Cluster of a proposer, node 0, and `acceptors` acceptors, every node with
+    /// an address on the loopback interface.
+    fn loopback_cluster(acceptors: u64) -> ClusterConfig {
+        ClusterConfig {
+            peers: (0..=acceptors)
+                .map(|node_id| PeerConfig {
+                    node_id,
+                    roles: vec![if node_id == 0 {
+                        Role::Proposer
+                    } else {
+                        Role::Acceptor
+                    }],
+                    address: Some(free_address()),
+                    priority: 0,
+                })
+                .collect(),
+            ..ClusterConfig::default()
+        }
+    }
+
+    #[test]
+    fn node_missing_from_the_cluster_is_rejected() {
+        let error = ClusterConfig::in_process(3).validate(7).unwrap_err();
+
+        assert!(
+            error.to_string().contains("node 7 is not part"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn duplicate_peer_is_rejected() {
+        let mut cluster = ClusterConfig::in_process(3);
+        cluster.peers[1].node_id = 0;
+
+        let error = cluster.validate(0).unwrap_err();
+
+        assert!(
+            error.to_string().contains("duplicate node id 0"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn quorum_must_be_a_majority_of_the_acceptors() {
+        let mut cluster = ClusterConfig::in_process(5);
+        cluster.quorum = Some(2);
+        assert!(cluster.validate(0).is_err());
+
+        cluster.quorum = Some(3);
+        cluster.validate(0).unwrap();
+        assert_eq!(cluster.acceptor_count(), 5);
+    }
+
+    #[tokio::test]
+    async fn five_nodes_come_up_over_loopback() {
+        let cluster = loopback_cluster(4);
+        let mut transports = Vec::new();
+        for peer in &cluster.peers {
+            transports.push(
+                TcpTransport::listen(
+                    peer.node_id,
+                    cluster.clone(),
+                    Codec::Bincode,
+                    None,
+                )
+                .await
+                .unwrap(),
+            );
+        }
+        for peer in &cluster.peers {
+            let reachable = cluster.bootstrap(peer.node_id).await.unwrap();
+            assert_eq!(reachable.len(), 4, "node {}", peer.node_id);
+            assert!(!reachable.contains(&peer.node_id));
+        }
+
+        let nodes: Vec<Node> = cluster
+            .peers
+            .iter()
+            .zip(transports)
+            .map(|(peer, transport)| {
+                Node::new(peer.node_id, cluster.clone(), Box::new(transport)).unwrap()
+            })
+            .collect();
+        let outcome = nodes[0]
+            .client()
+            .propose_with_timeout(7, Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            outcome,
+            ConsensusResult::Decided { proposal, .. } if proposal.value == 7
+        ));
+        for node in nodes {
+            node.shutdown().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn peers_that_are_down_are_not_reachable() {
+        // Nothing listens on the addresses of the acceptors.
+        let cluster = loopback_cluster(2);
+
+        assert!(cluster.bootstrap(0).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn example_config_loads_and_validates() {
+        let config = NodeConfig::from_toml(EXAMPLE, no_env).unwrap();
+
+        assert_eq!(config.node_id, 0);
+        assert_eq!(config.peers.len(), 2);
+        assert_eq!(config.timeouts.dial(), Duration::from_millis(500));
+        config.cluster().validate(config.node_id).unwrap();
+    }
+
+    #[test]
+    fn timeouts_of_the_node_apply_to_its_cluster() {
+        let config = NodeConfig::from_toml(
+            &EXAMPLE.replace("round_ms = 2000", "round_ms = 600"),
+            no_env,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.cluster().timeouts.phase(),
+            Duration::from_millis(300)
+        );
+    }
+
+    #[test]
+    fn duplicate_node_id_is_rejected() {
+        let contents = EXAMPLE.replace("node_id = 2", "node_id = 1");
+        let error = NodeConfig::from_toml(&contents, no_env).unwrap_err();
+
+        assert!(
+            error.to_string().contains("duplicate node id 1"),
+            "{error:#}"
+        );
+    }
+
+    #[test]
+    fn missing_key_is_rejected() {
+        let contents = EXAMPLE.replace("listen = \"127.0.0.1:7000\"", "");
+        let error = NodeConfig::from_toml(&contents, no_env).unwrap_err();
+
+        assert!(error.to_string().contains("listen"), "{error:#}");
+    }
+
+    #[test]
+    fn zero_dial_timeout_is_rejected() {
+        let contents = EXAMPLE.replace("dial_ms = 500", "dial_ms = 0");
+
+        assert!(NodeConfig::from_toml(&contents, no_env).is_err());
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let config = NodeConfig::from_toml(EXAMPLE, |variable| {
+            (variable == "PAXOS_NODE_ID").then(|| "3".to_string())
+        })
+        .unwrap();
+
+        assert_eq!(config.node_id, 3);
+    }
+
+    /// The example config, grown to five nodes, with the quorum sizes `q1` and `q2`.
+    fn five_nodes_with_quorums(q1: usize, q2: usize) -> String {
+        let mut contents = EXAMPLE.replacen(
+            "node_id = 0\n",
+            &format!("node_id = 0\nq1_size = {q1}\nq2_size = {q2}\n"),
+            1,
+        );
+        for node_id in [3, 4] {
+            contents.push_str(&format!(
+                "\n[[peers]]\nnode_id = {node_id}\nroles = [\"acceptor\"]\naddress = \
+                 \"127.0.0.1:700{node_id}\"\n"
+            ));
+        }
+        contents
+    }
+
+    #[test]
+    fn flexible_quorum_sizes_are_read_from_the_file() {
+        let config =
+            NodeConfig::from_toml(&five_nodes_with_quorums(4, 2), no_env).unwrap();
+        let cluster = config.cluster();
+
+        assert_eq!(cluster.acceptor_count(), 5);
+        assert_eq!(cluster.prepare_quorum(), 4);
+        assert_eq!(cluster.accept_quorum(), 2);
+        assert_eq!(
+            cluster.quorum_tracker(Phase::Prepare),
+            QuorumTracker::new(0..5, 4)
+        );
+        assert_eq!(
+            cluster.quorum_tracker(Phase::Accept),
+            QuorumTracker::new(0..5, 2)
+        );
+
+        let error =
+            NodeConfig::from_toml(&five_nodes_with_quorums(3, 2), no_env).unwrap_err();
+        assert!(
+            format!("{error:#}").contains("may not intersect"),
+            "{error:#}"
+        );
+    }
+
+    /// Number of acceptors, prepare and accept quorum sizes, and a set of acceptors
+    /// of each size.
+    fn flexible_quorums(
+    ) -> impl Strategy<Value = (usize, usize, usize, Vec<u64>, Vec<u64>)> {
+        (1usize..=9)
+            .prop_flat_map(|acceptors| (Just(acceptors), 1..=acceptors, 1..=acceptors))
+            .prop_flat_map(|(acceptors, prepare, accept)| {
+                let ids: Vec<u64> = (0..acceptors as u64).collect();
+                (
+                    Just(acceptors),
+                    Just(prepare),
+                    Just(accept),
+                    subsequence(ids.clone(), prepare),
+                    subsequence(ids, accept),
+                )
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn valid_flexible_quorums_always_intersect(
+            (acceptors, prepare, accept, promised, accepted) in flexible_quorums()
+        ) {
+            let mut cluster = ClusterConfig::in_process(acceptors);
+            cluster.prepare_quorum = Some(prepare);
+            cluster.accept_quorum = Some(accept);
+
+            let valid = cluster.validate(0).is_ok();
+            prop_assert_eq!(valid, prepare + accept > acceptors);
+            if valid {
+                prop_assert!(promised.iter().any(|id| accepted.contains(id)));
+            }
+        }
+    }
 }
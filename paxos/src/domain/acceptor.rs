@@ -0,0 +1,806 @@
+use std::collections::BTreeMap;
+
+use super::{
+    ballot::BallotNumber,
+    id::ProposalId,
+    message::{
+        AbortBody, AcceptPhaseBody, LeaseBody, Message, PreVoteBody, PreparePhaseBody,
+        RejectBody, RejectReason,
+    },
+    proposal::Proposal,
+};
+
+/// How an acceptor answers the requests of the proposers.
+///
+/// `AcceptorState` is the standard implementation; others can be plugged into the
+/// `Acceptor` actor, e.g. to keep the promises in another store or to check the
+/// requests more strictly. An implementation enforcing a constraint of the
+/// application, such as values that only grow, can wrap an `AcceptorState`: it
+/// answers the requests breaking the constraint itself, with a rejection built
+/// by `AcceptorState::reject` for `RejectReason::Refused`, and hands the others
+/// over. The proposers retry a refused value like any rejected one, until they
+/// give up on it.
+///
+/// Replies are `None` for requests that must be ignored. Implementations must
+/// never promise nor accept a ballot lower than one they already promised or
+/// accepted, or the cluster may decide several values for a slot.
+pub trait AcceptorLogic: Send + Sync {
+    /// Answer a prepare request with a promise or a rejection.
+    fn on_prepare(&mut self, request: PreparePhaseBody) -> Option<Message>;
+
+    /// Answer an accept request with an acceptance or a rejection.
+    fn on_accept(&mut self, request: AcceptPhaseBody) -> Option<Message>;
+
+    /// Answer a pre-vote request. Acceptors that do not answer pre-votes make the
+    /// proposers relying on them give up their rounds.
+    fn on_pre_vote(&mut self, _request: PreVoteBody) -> Option<Message> {
+        None
+    }
+
+    /// Answer a lease request. Granting it promises its ballot: the `Acceptor`
+    /// actor then keeps the other proposers out until the lease expires. Acceptors
+    /// that do not answer lease requests never let a proposer read on its own.
+    fn on_lease(&mut self, _request: LeaseBody) -> Option<Message> {
+        None
+    }
+
+    /// Note that the proposer of a ballot gave up on its round. The ballot must
+    /// still be held to; acceptors that ignore aborts only never tell the proposers
+    /// it outbids that they can retry right away.
+    fn on_abort(&mut self, _request: AbortBody) {}
+
+    /// Note that `proposal` was decided for `slot`, as counted from the gossip of
+    /// the acceptors. Acceptors that ignore decisions only answer the proposers that
+    /// are late as they would any other.
+    fn on_decided(&mut self, _slot: u64, _proposal: Proposal) {}
+
+    /// Highest configuration epoch seen. Acceptors that do not track epochs are
+    /// always in the first one.
+    fn epoch(&self) -> u64 {
+        0
+    }
+}
+
+impl AcceptorLogic for AcceptorState {
+    fn on_prepare(&mut self, request: PreparePhaseBody) -> Option<Message> {
+        self.handle_prepare(request)
+    }
+
+    fn on_accept(&mut self, request: AcceptPhaseBody) -> Option<Message> {
+        self.handle_accept(request)
+    }
+
+    fn on_pre_vote(&mut self, request: PreVoteBody) -> Option<Message> {
+        self.handle_pre_vote(request)
+    }
+
+    fn on_lease(&mut self, request: LeaseBody) -> Option<Message> {
+        self.handle_lease(request)
+    }
+
+    fn on_abort(&mut self, request: AbortBody) {
+        self.handle_abort(request)
+    }
+
+    fn on_decided(&mut self, slot: u64, proposal: Proposal) {
+        self.decided.insert(slot, proposal);
+    }
+
+    fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+/// How an acceptor decided on a prepare request, see `AcceptorState::promise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrepareOutcome {
+    /// The ballot of the request was promised. `last_accepted` is the proposal
+    /// accepted for the slot of the request, if any, which the proposer must adopt.
+    Promised { last_accepted: Option<Proposal> },
+    /// The ballot of the request lost against one already promised or accepted.
+    Rejected { reason: RejectReason },
+}
+
+/// How an acceptor decided on an accept request, see `AcceptorState::accept`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcceptOutcome {
+    /// The proposal of the request was accepted.
+    Accepted,
+    /// The ballot of the request lost against one already promised or accepted.
+    Rejected { reason: RejectReason },
+}
+
+/// What an acceptor remembers of the proposals it was sent, and how it answers
+/// them. It only turns requests into replies: delivering them is up to the caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcceptorState {
+    /// Identifier of the node, stamped on every reply.
+    pub id: u64,
+    /// Highest configuration epoch seen by this node. Requests stamped with an
+    /// older epoch come from a stale configuration and are ignored.
+    pub epoch: u64,
+    /// Ballot and id of the highest proposal promised or accepted by this node. The
+    /// promise holds for every slot: no proposal with a lower ballot is promised
+    /// nor accepted anymore, whatever its slot.
+    pub promised: Option<(BallotNumber, ProposalId)>,
+    /// Proposal accepted by this node for each slot. It is reported to the
+    /// proposers preparing the slot, which have to propose its value.
+    pub accepted: BTreeMap<u64, Proposal>,
+    /// Highest ballot whose proposer told this node it gave up on its round. It is
+    /// still held to: it is only reported as abandoned in the rejections it causes.
+    pub aborted: Option<BallotNumber>,
+    /// Proposal decided for each slot, as learned from gossip. Requests for those
+    /// slots with a ballot no higher than the decided one are rejected right away.
+    /// It is not saved by the repository: it only spares round trips.
+    pub decided: BTreeMap<u64, Proposal>,
+}
+
+impl AcceptorState {
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            epoch: 0,
+            promised: None,
+            accepted: BTreeMap::new(),
+            aborted: None,
+            decided: BTreeMap::new(),
+        }
+    }
+
+    /// Answer a request. Returns `None` for messages that are not requests, and for
+    /// requests from a stale epoch.
+    pub fn handle(&mut self, message: Message) -> Option<Message> {
+        match message {
+            Message::PrepareRequest { body } => self.handle_prepare(body),
+            Message::AcceptRequest { body } => self.handle_accept(body),
+            Message::PreVoteRequest { body } => self.handle_pre_vote(body),
+            Message::LeaseRequest { body } => self.handle_lease(body),
+            Message::AbortRound { body } => {
+                self.handle_abort(body);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Promise the proposal unless a higher ballot was already promised or accepted.
+    pub fn handle_prepare(
+        &mut self,
+        received_proposal: PreparePhaseBody,
+    ) -> Option<Message> {
+        let reply = match self.promise(&received_proposal)? {
+            PrepareOutcome::Promised { last_accepted } => Message::PrepareResponse {
+                body: PreparePhaseBody {
+                    issuer_id: self.id,
+                    accepted: last_accepted,
+                    ..received_proposal
+                },
+            },
+            PrepareOutcome::Rejected { reason } => Message::PrepareReject {
+                body: self.reject(
+                    received_proposal.slot,
+                    received_proposal.proposal_id,
+                    reason,
+                ),
+            },
+        };
+        Some(reply)
+    }
+
+    /// Accept the value unless a higher ballot was already promised or accepted.
+    pub fn handle_accept(
+        &mut self,
+        received_proposal: AcceptPhaseBody,
+    ) -> Option<Message> {
+        let reply = match self.accept(&received_proposal)? {
+            AcceptOutcome::Accepted => Message::AcceptResponse {
+                body: AcceptPhaseBody {
+                    issuer_id: self.id,
+                    ..received_proposal
+                },
+            },
+            AcceptOutcome::Rejected { reason } => Message::AcceptReject {
+                body: self.reject(
+                    received_proposal.slot,
+                    received_proposal.proposal_id,
+                    reason,
+                ),
+            },
+        };
+        Some(reply)
+    }
+
+    /// Decide on a prepare request, updating the promise of this node, without
+    /// building the reply. Returns `None` for a request from a stale epoch, which
+    /// must be ignored.
+    pub fn promise(&mut self, request: &PreparePhaseBody) -> Option<PrepareOutcome> {
+        if self.is_stale_epoch(request.epoch) {
+            return None;
+        }
+
+        // A proposal older than the one promised (or the one accepted for the slot)
+        // can not be promised. Tell the proposer why, so that it can pick a higher
+        // ballot.
+        if let Some(reason) = self.rejection(request.slot, request.ballot) {
+            return Some(PrepareOutcome::Rejected { reason });
+        }
+
+        // The proposal received is the most up-to-date one this node knows about, so
+        // it is promised.
+        // Report the proposal accepted for the slot, if any: the proposer has to
+        // propose its value rather than its own.
+        self.promised = Some((request.ballot, request.proposal_id));
+        let last_accepted = self.accepted.get(&request.slot).copied();
+        Some(PrepareOutcome::Promised { last_accepted })
+    }
+
+    /// Decide on an accept request, updating the proposal accepted by this node,
+    /// without building the reply. Returns `None` for a request from a stale
+    /// epoch, which must be ignored.
+    pub fn accept(&mut self, request: &AcceptPhaseBody) -> Option<AcceptOutcome> {
+        if self.is_stale_epoch(request.epoch) {
+            return None;
+        }
+
+        // Do not accept the value if the one promised is more updated.
+        if let Some(reason) = self.rejection(request.slot, request.ballot) {
+            return Some(AcceptOutcome::Rejected { reason });
+        }
+
+        // The value received is at least as up-to-date as the one promised.
+        // **Accept** the proposal for its slot, which also promises its ballot.
+        self.promised = Some((request.ballot, request.proposal_id));
+        self.accepted.insert(
+            request.slot,
+            Proposal::new(request.value, request.proposal_id, request.ballot),
+        );
+        Some(AcceptOutcome::Accepted)
+    }
+
+    /// Tell whether a prepare request for the slot and ballot of the pre-vote would
+    /// be promised, without promising anything. It would not if a higher ballot was
+    /// promised or accepted, nor if a later slot was already accepted: another
+    /// proposer is then making progress and should not be disrupted.
+    pub fn handle_pre_vote(&mut self, pre_vote: PreVoteBody) -> Option<Message> {
+        if self.is_stale_epoch(pre_vote.epoch) {
+            return None;
+        }
+
+        let is_behind = self
+            .accepted
+            .last_key_value()
+            .is_some_and(|(&slot, _)| slot > pre_vote.slot);
+        let granted =
+            !is_behind && self.rejection(pre_vote.slot, pre_vote.ballot).is_none();
+        Some(Message::PreVoteResponse {
+            body: PreVoteBody {
+                issuer_id: self.id,
+                granted,
+                ..pre_vote
+            },
+        })
+    }
+
+    /// Grant the lease unless a higher ballot was already promised or accepted, in
+    /// which case the requester is not the leader anymore. Granting it promises its
+    /// ballot; how long the lease lasts is up to the caller to enforce.
+    ///
+    /// The response reports the highest slot this acceptor knows a proposal of, so
+    /// that the proposer can catch up on the slots chosen before the lease.
+    pub fn handle_lease(&mut self, request: LeaseBody) -> Option<Message> {
+        if self.is_stale_epoch(request.epoch) {
+            return None;
+        }
+
+        let granted = self
+            .promised
+            .map_or(true, |(promised, _)| promised <= request.ballot);
+        if granted {
+            self.promised = Some((request.ballot, request.proposal_id));
+        }
+        let highest_slot = self
+            .accepted
+            .keys()
+            .next_back()
+            .copied()
+            .max(self.decided.keys().next_back().copied());
+        Some(Message::LeaseResponse {
+            body: LeaseBody {
+                issuer_id: self.id,
+                granted,
+                highest_slot,
+                ..request
+            },
+        })
+    }
+
+    /// Remember that the round of `abort.ballot` was given up on. Only the proposer
+    /// of a ballot can abort it.
+    pub fn handle_abort(&mut self, abort: AbortBody) {
+        if self.is_stale_epoch(abort.epoch) || abort.ballot.node_id != abort.issuer_id {
+            return;
+        }
+        self.aborted = self.aborted.max(Some(abort.ballot));
+    }
+
+    /// Whether a request stamped with `epoch` belongs to an older configuration than
+    /// the latest one seen by this node. Newer epochs are adopted.
+    fn is_stale_epoch(&mut self, epoch: u64) -> bool {
+        if epoch < self.epoch {
+            return true;
+        }
+        self.epoch = epoch;
+        false
+    }
+
+    /// Why a request for `slot` with `ballot` must be rejected, if it must: the slot
+    /// was decided with a ballot at least as high, this node already promised a
+    /// higher ballot, or accepted a proposal with a higher ballot for the slot.
+    fn rejection(&self, slot: u64, ballot: BallotNumber) -> Option<RejectReason> {
+        if let Some(decided) = self.decided.get(&slot) {
+            if decided.ballot >= ballot {
+                return Some(RejectReason::AlreadyDecided {
+                    decided: decided.id,
+                    ballot: decided.ballot,
+                    slot,
+                    value: decided.value,
+                });
+            }
+        }
+        if let Some(accepted) = self.accepted.get(&slot) {
+            if accepted.ballot > ballot {
+                return Some(RejectReason::AlreadyAcceptedHigher {
+                    accepted: accepted.id,
+                    ballot: accepted.ballot,
+                    slot,
+                    value: accepted.value,
+                });
+            }
+        }
+        if let Some((promised_ballot, promised)) = self.promised {
+            if promised_ballot > ballot {
+                return Some(RejectReason::AlreadyPromisedHigher {
+                    promised,
+                    ballot: promised_ballot,
+                });
+            }
+        }
+        None
+    }
+
+    /// Rejection of the proposal `proposal_id` for `slot`, stamped with the id and
+    /// the epoch of this node. It is marked as abandoned if it was caused by a
+    /// ballot that its proposer aborted.
+    pub fn reject(
+        &self,
+        slot: u64,
+        proposal_id: ProposalId,
+        reason: RejectReason,
+    ) -> RejectBody {
+        RejectBody {
+            issuer_id: self.id,
+            epoch: self.epoch,
+            slot,
+            proposal_id,
+            reason,
+            abandoned: !matches!(reason, RejectReason::Refused { .. })
+                && self.aborted == Some(reason.ballot()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::domain::quorum::QuorumTracker;
+
+    fn id(n: u128) -> ProposalId {
+        ProposalId(Uuid::from_u128(n))
+    }
+
+    fn prepare(slot: u64, round: u64, proposer: u64) -> PreparePhaseBody {
+        PreparePhaseBody {
+            issuer_id: proposer,
+            epoch: 0,
+            slot,
+            proposal_id: id(u128::from(round)),
+            ballot: BallotNumber::new(round, proposer),
+            accepted: None,
+            nonce: 0,
+            sent_at: None,
+        }
+    }
+
+    fn accept(slot: u64, round: u64, proposer: u64, value: u64) -> AcceptPhaseBody {
+        AcceptPhaseBody {
+            issuer_id: proposer,
+            epoch: 0,
+            slot,
+            proposal_id: id(u128::from(round)),
+            ballot: BallotNumber::new(round, proposer),
+            value,
+            nonce: 0,
+            sent_at: None,
+        }
+    }
+
+    #[test]
+    fn promise_reports_the_proposal_accepted_for_the_slot_of_the_request() {
+        let mut acceptor = AcceptorState::new(0);
+        assert_eq!(
+            acceptor.accept(&accept(0, 1, 3, 10)),
+            Some(AcceptOutcome::Accepted)
+        );
+        assert_eq!(
+            acceptor.accept(&accept(1, 2, 3, 20)),
+            Some(AcceptOutcome::Accepted)
+        );
+
+        // Accepting slot 1 must not make the acceptor forget the value of slot 0.
+        let outcome = acceptor.promise(&prepare(0, 3, 4));
+        assert_eq!(
+            outcome,
+            Some(PrepareOutcome::Promised {
+                last_accepted: Some(Proposal::new(10, id(1), BallotNumber::new(1, 3)))
+            })
+        );
+        let outcome = acceptor.promise(&prepare(2, 4, 4));
+        assert_eq!(
+            outcome,
+            Some(PrepareOutcome::Promised {
+                last_accepted: None
+            })
+        );
+    }
+
+    #[test]
+    fn promise_holds_for_every_slot() {
+        let mut acceptor = AcceptorState::new(0);
+        acceptor.promise(&prepare(1, 5, 3));
+
+        for outcome in [
+            acceptor.accept(&accept(0, 4, 4, 10)),
+            acceptor.accept(&accept(1, 4, 4, 10)),
+        ] {
+            assert!(
+                matches!(
+                    outcome,
+                    Some(AcceptOutcome::Rejected {
+                        reason: RejectReason::AlreadyPromisedHigher { .. }
+                    })
+                ),
+                "{outcome:?}"
+            );
+        }
+        assert!(acceptor.accepted.is_empty());
+    }
+
+    #[test]
+    fn accepting_a_slot_keeps_the_promise_of_the_others() {
+        let mut acceptor = AcceptorState::new(0);
+        acceptor.promise(&prepare(0, 2, 3));
+        acceptor.promise(&prepare(1, 3, 4));
+        acceptor.accept(&accept(1, 3, 4, 20));
+
+        // The proposer of ballot 2 lost slot 0 to the promise of ballot 3, which an
+        // accept for another slot must not lift.
+        let outcome = acceptor.accept(&accept(0, 2, 3, 10));
+        assert!(
+            matches!(outcome, Some(AcceptOutcome::Rejected { .. })),
+            "{outcome:?}"
+        );
+        assert_eq!(acceptor.accepted.keys().collect::<Vec<_>>(), [&1]);
+    }
+
+    #[test]
+    fn lower_ballot_is_rejected_with_the_value_accepted_for_the_slot() {
+        let mut acceptor = AcceptorState::new(0);
+        acceptor.accept(&accept(0, 2, 3, 10));
+        acceptor.accept(&accept(1, 3, 3, 20));
+
+        let outcome = acceptor.promise(&prepare(0, 1, 4));
+        assert!(
+            matches!(
+                outcome,
+                Some(PrepareOutcome::Rejected {
+                    reason: RejectReason::AlreadyAcceptedHigher {
+                        slot: 0,
+                        value: 10,
+                        ..
+                    }
+                })
+            ),
+            "{outcome:?}"
+        );
+    }
+
+    #[test]
+    fn ballot_no_higher_than_the_decided_one_is_rejected() {
+        let mut acceptor = AcceptorState::new(0);
+        let decided = Proposal::new(10, id(5), BallotNumber::new(5, 3));
+        acceptor.on_decided(0, decided);
+
+        for outcome in [
+            acceptor.promise(&prepare(0, 3, 4)).unwrap(),
+            acceptor.promise(&prepare(0, 5, 3)).unwrap(),
+        ] {
+            assert!(
+                matches!(
+                    outcome,
+                    PrepareOutcome::Rejected {
+                        reason: RejectReason::AlreadyDecided { value: 10, .. }
+                    }
+                ),
+                "{outcome:?}"
+            );
+        }
+        assert!(matches!(
+            acceptor.accept(&accept(0, 4, 4, 40)),
+            Some(AcceptOutcome::Rejected {
+                reason: RejectReason::AlreadyDecided { .. }
+            })
+        ));
+        // Nothing was promised: the other slots are free.
+        assert_eq!(acceptor.promised, None);
+        assert!(matches!(
+            acceptor.promise(&prepare(1, 3, 4)),
+            Some(PrepareOutcome::Promised { .. })
+        ));
+    }
+
+    #[test]
+    fn reject_names_the_proposal_promised_instead() {
+        let mut acceptor = AcceptorState::new(0);
+        acceptor.handle_prepare(prepare(0, 5, 3));
+
+        let reply = acceptor.handle_prepare(prepare(0, 2, 4));
+
+        let Some(Message::PrepareReject { body }) = reply else {
+            panic!("prepare not rejected: {reply:?}");
+        };
+        assert_eq!(body.issuer_id, 0);
+        assert_eq!(body.proposal_id, id(2));
+        assert_eq!(
+            body.reason,
+            RejectReason::AlreadyPromisedHigher {
+                promised: id(5),
+                ballot: BallotNumber::new(5, 3),
+            }
+        );
+    }
+
+    #[test]
+    fn reject_names_the_proposal_accepted_instead() {
+        let mut acceptor = AcceptorState::new(0);
+        acceptor.handle_accept(accept(0, 5, 3, 50));
+
+        let reply = acceptor.handle_accept(accept(0, 2, 4, 20));
+
+        let Some(Message::AcceptReject { body }) = reply else {
+            panic!("accept not rejected: {reply:?}");
+        };
+        assert_eq!(body.proposal_id, id(2));
+        assert_eq!(
+            body.reason,
+            RejectReason::AlreadyAcceptedHigher {
+                accepted: id(5),
+                ballot: BallotNumber::new(5, 3),
+                slot: 0,
+                value: 50,
+            }
+        );
+    }
+
+    /// Plain test on purpose: the state machine must not need an async runtime.
+    #[test]
+    fn round_runs_through_the_state_machine_alone() {
+        let mut acceptors: Vec<_> = (0..3).map(AcceptorState::new).collect();
+        let quorum = QuorumTracker::new(0..3, 2);
+
+        let promised: HashSet<u64> = acceptors
+            .iter_mut()
+            .filter_map(|acceptor| {
+                match acceptor.handle(Message::PrepareRequest {
+                    body: prepare(0, 1, 3),
+                })? {
+                    Message::PrepareResponse { body } => Some(body.issuer_id),
+                    _ => None,
+                }
+            })
+            .collect();
+        assert!(quorum.is_reached(&promised));
+
+        let accepted: HashSet<u64> = acceptors
+            .iter_mut()
+            .filter_map(|acceptor| {
+                match acceptor.handle(Message::AcceptRequest {
+                    body: accept(0, 1, 3, 7),
+                })? {
+                    Message::AcceptResponse { body } => Some(body.issuer_id),
+                    _ => None,
+                }
+            })
+            .collect();
+        assert!(quorum.is_reached(&accepted));
+
+        // A later proposer learns of the value, and must propose it again.
+        let reply = acceptors[0].handle(Message::PrepareRequest {
+            body: prepare(0, 2, 4),
+        });
+        assert!(
+            matches!(
+                &reply,
+                Some(Message::PrepareResponse { body })
+                    if body.accepted.is_some_and(|proposal| proposal.value == 7)
+            ),
+            "{reply:?}"
+        );
+    }
+
+    /// Acceptor logic of an application whose values only grow: on top of the
+    /// rules of Paxos, it refuses to accept a value lower than one it accepted.
+    struct GrowingValues(AcceptorState);
+
+    impl AcceptorLogic for GrowingValues {
+        fn on_prepare(&mut self, request: PreparePhaseBody) -> Option<Message> {
+            self.0.on_prepare(request)
+        }
+
+        fn on_accept(&mut self, request: AcceptPhaseBody) -> Option<Message> {
+            let highest = self
+                .0
+                .accepted
+                .values()
+                .map(|proposal| proposal.value)
+                .max();
+            if highest.is_some_and(|highest| request.value < highest) {
+                let reason = RejectReason::Refused {
+                    ballot: request.ballot,
+                };
+                return Some(Message::AcceptReject {
+                    body: self.0.reject(request.slot, request.proposal_id, reason),
+                });
+            }
+            self.0.on_accept(request)
+        }
+    }
+
+    #[test]
+    fn custom_logic_refuses_the_values_breaking_its_constraint() {
+        let mut acceptor = GrowingValues(AcceptorState::new(0));
+        assert!(matches!(
+            acceptor.on_accept(accept(0, 1, 3, 10)),
+            Some(Message::AcceptResponse { .. })
+        ));
+
+        let reply = acceptor.on_accept(accept(1, 2, 3, 5));
+        let Some(Message::AcceptReject { body }) = reply else {
+            panic!("decreasing value not refused: {reply:?}");
+        };
+        assert_eq!(
+            body.reason,
+            RejectReason::Refused {
+                ballot: BallotNumber::new(2, 3)
+            }
+        );
+        assert!(!body.abandoned);
+        assert_eq!(acceptor.0.accepted.keys().collect::<Vec<_>>(), [&0]);
+
+        assert!(matches!(
+            acceptor.on_accept(accept(1, 3, 3, 12)),
+            Some(Message::AcceptResponse { .. })
+        ));
+        assert_eq!(acceptor.0.accepted[&1].value, 12);
+    }
+
+    /// States an acceptor can be in for slot 0, as left by proposer 3 with ballot
+    /// round 5: untouched, promised, or accepted with the value 50.
+    fn states() -> [(&'static str, AcceptorState); 3] {
+        let fresh = AcceptorState::new(0);
+        let mut promised = fresh.clone();
+        promised.promise(&prepare(0, 5, 3));
+        let mut accepted = fresh.clone();
+        accepted.accept(&accept(0, 5, 3, 50));
+        [
+            ("fresh", fresh),
+            ("promised", promised),
+            ("accepted", accepted),
+        ]
+    }
+
+    #[test]
+    fn prepare_is_promised_only_from_the_ballot_held_to_up() {
+        let held = Proposal::new(50, id(5), BallotNumber::new(5, 3));
+        for (name, state) in states() {
+            for round in [4, 5, 6] {
+                let mut acceptor = state.clone();
+                let outcome = acceptor.promise(&prepare(0, round, 3));
+
+                let expected = match (name, round) {
+                    ("promised", 4) => PrepareOutcome::Rejected {
+                        reason: RejectReason::AlreadyPromisedHigher {
+                            promised: id(5),
+                            ballot: held.ballot,
+                        },
+                    },
+                    ("accepted", 4) => PrepareOutcome::Rejected {
+                        reason: RejectReason::AlreadyAcceptedHigher {
+                            accepted: id(5),
+                            ballot: held.ballot,
+                            slot: 0,
+                            value: 50,
+                        },
+                    },
+                    ("accepted", _) => PrepareOutcome::Promised {
+                        last_accepted: Some(held),
+                    },
+                    _ => PrepareOutcome::Promised {
+                        last_accepted: None,
+                    },
+                };
+                assert_eq!(outcome, Some(expected), "{name} state, round {round}");
+                match expected {
+                    PrepareOutcome::Rejected { .. } => assert_eq!(acceptor, state),
+                    PrepareOutcome::Promised { .. } => assert_eq!(
+                        acceptor.promised,
+                        Some((BallotNumber::new(round, 3), id(u128::from(round))))
+                    ),
+                }
+                assert_eq!(acceptor.accepted, state.accepted);
+            }
+        }
+    }
+
+    #[test]
+    fn accept_is_accepted_only_from_the_ballot_held_to_up() {
+        for (name, state) in states() {
+            for round in [4, 5, 6] {
+                let mut acceptor = state.clone();
+                let outcome = acceptor.accept(&accept(0, round, 3, 60));
+
+                let expected = match (name, round) {
+                    ("promised", 4) => AcceptOutcome::Rejected {
+                        reason: RejectReason::AlreadyPromisedHigher {
+                            promised: id(5),
+                            ballot: BallotNumber::new(5, 3),
+                        },
+                    },
+                    ("accepted", 4) => AcceptOutcome::Rejected {
+                        reason: RejectReason::AlreadyAcceptedHigher {
+                            accepted: id(5),
+                            ballot: BallotNumber::new(5, 3),
+                            slot: 0,
+                            value: 50,
+                        },
+                    },
+                    _ => AcceptOutcome::Accepted,
+                };
+                assert_eq!(outcome, Some(expected), "{name} state, round {round}");
+                if expected == AcceptOutcome::Accepted {
+                    let ballot = BallotNumber::new(round, 3);
+                    let proposal = Proposal::new(60, id(u128::from(round)), ballot);
+                    assert_eq!(acceptor.accepted.get(&0), Some(&proposal));
+                    assert_eq!(acceptor.promised, Some((ballot, proposal.id)));
+                } else {
+                    assert_eq!(acceptor, state);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn requests_of_a_stale_epoch_change_nothing() {
+        for (name, mut state) in states() {
+            state.epoch = 2;
+            let mut acceptor = state.clone();
+
+            assert_eq!(acceptor.promise(&prepare(0, 6, 3)), None, "{name}");
+            assert_eq!(acceptor.accept(&accept(0, 6, 3, 60)), None, "{name}");
+            assert_eq!(acceptor, state);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::fmt;
+
+/// Orderable priority of a proposal. Acceptors only compare ballots, never proposal
+/// ids, so that ordering does not depend on how ids are generated.
+///
+/// Ballots are ordered by `round` first, then by the `priority` of the proposer and
+/// finally by `node_id`, which makes the ordering total: two proposers can never
+/// issue the same ballot. A preferred proposer therefore wins the ties between
+/// ballots of the same round, but still yields to a higher round.
+#[derive(
+    PartialEq,
+    PartialOrd,
+    Eq,
+    Ord,
+    Hash,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct BallotNumber {
+    pub round: u64,
+    #[serde(default)]
+    pub priority: u64,
+    pub node_id: u64,
+}
+
+impl BallotNumber {
+    pub fn new(round: u64, node_id: u64) -> Self {
+        Self {
+            round,
+            priority: 0,
+            node_id,
+        }
+    }
+
+    pub fn with_priority(self, priority: u64) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// The smallest ballot of the next round, issued by `node_id` with `priority`.
+    pub fn next(&self, node_id: u64, priority: u64) -> Self {
+        Self {
+            round: self.round + 1,
+            priority,
+            node_id,
+        }
+    }
+}
+
+impl fmt::Display for BallotNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.round, self.node_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::domain::{
+        acceptor::{AcceptorState, PrepareOutcome},
+        id::ProposalId,
+        message::PreparePhaseBody,
+    };
+
+    /// Proposer preferred to lead, despite its lower id.
+    const PREFERRED: u64 = 1;
+    const OTHER: u64 = 2;
+
+    fn prepare(ballot: BallotNumber) -> PreparePhaseBody {
+        PreparePhaseBody {
+            issuer_id: ballot.node_id,
+            epoch: 0,
+            slot: 0,
+            proposal_id: ProposalId(Uuid::from_u128(u128::from(ballot.node_id))),
+            ballot,
+            accepted: None,
+            nonce: 0,
+            sent_at: None,
+        }
+    }
+
+    #[test]
+    fn ballots_are_totally_ordered() {
+        let ballots = [
+            BallotNumber::new(2, 1),
+            BallotNumber::new(1, 2),
+            BallotNumber::new(1, 1).with_priority(1),
+            BallotNumber::new(1, 1),
+            BallotNumber::new(0, 3),
+        ];
+
+        for a in ballots {
+            for b in ballots {
+                let relations = [a < b, a == b, a > b];
+                assert_eq!(
+                    relations.iter().filter(|&&holds| holds).count(),
+                    1,
+                    "{a:?} and {b:?}"
+                );
+            }
+        }
+        let mut sorted = ballots;
+        sorted.sort();
+        assert_eq!(
+            sorted,
+            [ballots[4], ballots[3], ballots[1], ballots[2], ballots[0]]
+        );
+    }
+
+    #[test]
+    fn proposals_of_the_same_ballot_do_not_outrank_each_other() {
+        let ballot = BallotNumber::new(3, OTHER);
+        let first = PreparePhaseBody {
+            proposal_id: ProposalId(Uuid::from_u128(1)),
+            ..prepare(ballot)
+        };
+        let second = PreparePhaseBody {
+            proposal_id: ProposalId(Uuid::from_u128(2)),
+            ..prepare(ballot)
+        };
+
+        for order in [[&first, &second], [&second, &first]] {
+            let mut acceptor = AcceptorState::new(0);
+            for request in order {
+                assert!(
+                    matches!(
+                        acceptor.promise(request),
+                        Some(PrepareOutcome::Promised { .. })
+                    ),
+                    "{:?} rejected",
+                    request.proposal_id
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn preferred_proposer_wins_the_ties_of_a_round() {
+        let current = BallotNumber::new(4, OTHER);
+        let preferred = current.next(PREFERRED, 1);
+        let other = current.next(OTHER, 0);
+
+        assert!(preferred > other);
+        // It still yields to a higher round.
+        assert!(other.next(OTHER, 0) > preferred);
+    }
+
+    #[test]
+    fn preferred_proposer_wins_a_duel_whatever_the_order_of_its_prepare() {
+        let preferred = BallotNumber::new(1, PREFERRED).with_priority(1);
+        let other = BallotNumber::new(1, OTHER);
+
+        for order in [[preferred, other], [other, preferred]] {
+            let mut acceptor = AcceptorState::new(0);
+            for ballot in order {
+                acceptor.promise(&prepare(ballot));
+            }
+            assert_eq!(acceptor.promised.map(|(ballot, _)| ballot), Some(preferred));
+            assert!(!matches!(
+                acceptor.promise(&prepare(other)),
+                Some(PrepareOutcome::Promised { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeSet;
+
+/// Values with this bit set are not values of clients, but commands of the cluster
+/// itself, mostly changes of its membership.
+///
+/// The other bits are the set of acceptors, one bit per node id. With no other bit
+/// set, the value commits a joint config change.
+pub const CONFIG_CHANGE_FLAG: u64 = 1 << 63;
+
+/// Set along with `CONFIG_CHANGE_FLAG`, marks a no-op rather than a config change.
+pub const NOOP_FLAG: u64 = 1 << 62;
+
+/// Highest node id an acceptor can have to be part of a config change.
+pub const MAX_ACCEPTOR_ID: u64 = 61;
+
+/// Acceptors of the cluster after a config change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Membership {
+    acceptors: BTreeSet<u64>,
+}
+
+impl Membership {
+    /// Membership made of `acceptors`. `None` if there are none, or if one of them
+    /// has an id above `MAX_ACCEPTOR_ID`.
+    pub fn new(acceptors: impl IntoIterator<Item = u64>) -> Option<Self> {
+        let acceptors: BTreeSet<u64> = acceptors.into_iter().collect();
+        if acceptors.is_empty() || acceptors.iter().any(|&id| id > MAX_ACCEPTOR_ID) {
+            return None;
+        }
+        Some(Self { acceptors })
+    }
+
+    pub fn acceptors(&self) -> &BTreeSet<u64> {
+        &self.acceptors
+    }
+}
+
+/// What a decided value stands for. Config changes go through the same rounds as
+/// the values of clients, so that every node switches to the new membership at
+/// the same slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Value submitted by a client. It must not have `CONFIG_CHANGE_FLAG` set.
+    Value(u64),
+    ConfigChange(Membership),
+    /// End of the joint consensus started by the last config change: from then on,
+    /// quorums are only counted among the new acceptors.
+    ConfigCommit,
+    /// Value without any effect, used to fill the slots for which nothing was
+    /// proposed.
+    Noop,
+}
+
+impl Command {
+    /// Whether `value` is reserved for the commands of the cluster. Clients can not
+    /// propose such values: decoded, they would reconfigure the cluster.
+    pub fn is_reserved(value: u64) -> bool {
+        value & CONFIG_CHANGE_FLAG != 0
+    }
+
+    pub fn decode(value: u64) -> Self {
+        if !Self::is_reserved(value) {
+            return Self::Value(value);
+        }
+        if value & NOOP_FLAG != 0 {
+            return Self::Noop;
+        }
+        if value == CONFIG_CHANGE_FLAG {
+            return Self::ConfigCommit;
+        }
+        let acceptors = (0..=MAX_ACCEPTOR_ID)
+            .filter(|id| value & (1 << id) != 0)
+            .collect();
+        Self::ConfigChange(Membership { acceptors })
+    }
+
+    pub fn encode(&self) -> u64 {
+        match self {
+            Self::Value(value) => *value,
+            Self::ConfigChange(membership) => membership
+                .acceptors
+                .iter()
+                .fold(CONFIG_CHANGE_FLAG, |value, id| value | (1 << id)),
+            Self::ConfigCommit => CONFIG_CHANGE_FLAG,
+            Self::Noop => CONFIG_CHANGE_FLAG | NOOP_FLAG,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_survive_their_encoding() {
+        let membership = Membership::new([0, 2, MAX_ACCEPTOR_ID]).unwrap();
+        for command in [
+            Command::Value(42),
+            Command::ConfigChange(membership),
+            Command::ConfigCommit,
+            Command::Noop,
+        ] {
+            assert_eq!(Command::decode(command.encode()), command);
+        }
+    }
+
+    #[test]
+    fn only_client_values_are_not_reserved() {
+        assert!(!Command::is_reserved(
+            Command::Value(u64::MAX >> 1).encode()
+        ));
+        assert!(Command::is_reserved(Command::ConfigCommit.encode()));
+        assert!(Command::is_reserved(Command::Noop.encode()));
+        assert!(Command::is_reserved(CONFIG_CHANGE_FLAG | 1));
+    }
+}
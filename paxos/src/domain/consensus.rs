@@ -0,0 +1,219 @@
+use std::{collections::BTreeMap, fmt};
+
+use super::{ballot::BallotNumber, proposal::Proposal};
+
+/// Outcome of a round, as reported to whoever submitted the value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsensusResult {
+    /// A value was chosen for the slot. With several proposers, the slot may have
+    /// been decided by the round of another proposer (`by_other`), in which case
+    /// `proposal` holds its value rather than the one that was submitted.
+    Decided {
+        slot: u64,
+        proposal: Proposal,
+        by_other: bool,
+    },
+    /// The proposer gave up on the value, which was not chosen for any slot.
+    Failed {
+        reason: FailureReason,
+        /// Ballot of the last round run for the value.
+        last_ballot: BallotNumber,
+    },
+}
+
+/// Why a proposer gave up on a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureReason {
+    /// Every round allowed by the retry budget was rejected by the acceptors.
+    Exhausted,
+    /// The last round allowed by the retry budget did not get a quorum of promises
+    /// in time: too few acceptors answered.
+    PrepareTimeout,
+    /// The last round allowed by the retry budget was promised, but did not get a
+    /// quorum of acceptances in time.
+    AcceptTimeout,
+    /// The deadline the client set for the value passed.
+    Expired,
+    /// Too few acceptors are alive for a quorum, according to the failure detector
+    /// of the proposer: `live` of them, when `required` are needed.
+    QuorumUnavailable { live: usize, required: usize },
+    /// The prepare requests of the round could not be sent to any acceptor: every
+    /// one of them disconnected from the transport.
+    NoAcceptors,
+    /// The value was proposed on the condition that the latest value decided was
+    /// another one than `actual`, which is `None` if no value was decided yet.
+    Conflict { actual: Option<u64> },
+    /// The value is reserved for the commands of the cluster, see
+    /// `Command::is_reserved`. No round was run for it.
+    Reserved,
+    /// The pre-vote of the round was lost: another proposer is making progress, and
+    /// no round was run for the value, so as not to disrupt it.
+    PreVoteLost,
+}
+
+/// A slot learned as decided with another value than the one it was already
+/// decided with.
+///
+/// Two quorums chose different values for it, which safety forbids. Either the protocol
+/// has a bug, or an acceptor lied about what it accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsensusViolation {
+    pub slot: u64,
+    /// Value the slot was decided with first, and keeps.
+    pub existing: u64,
+    /// Value it was then learned as decided with.
+    pub attempted: u64,
+}
+
+impl fmt::Display for ConsensusViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "consensus violated: slot {} decided {}, then {}",
+            self.slot, self.existing, self.attempted
+        )
+    }
+}
+
+impl std::error::Error for ConsensusViolation {}
+
+/// Record `proposal` as decided for `slot` in `decided`, a log of decisions.
+///
+/// A decided slot can only be confirmed with the same value, and is never
+/// overwritten. Returns whether the slot was newly decided.
+pub fn record_decision(
+    decided: &mut BTreeMap<u64, Proposal>,
+    slot: u64,
+    proposal: Proposal,
+) -> Result<bool, ConsensusViolation> {
+    match decided.get(&slot) {
+        None => {
+            decided.insert(slot, proposal);
+            Ok(true)
+        }
+        Some(existing) if existing.value == proposal.value => Ok(false),
+        Some(existing) => Err(ConsensusViolation {
+            slot,
+            existing: existing.value,
+            attempted: proposal.value,
+        }),
+    }
+}
+
+/// What a proposer did with a value submitted by a client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProposeOutcome {
+    /// The proposer ran a round for the value.
+    Decided(ConsensusResult),
+    /// Another proposer is leading. Rather than competing with it for the slot, the
+    /// client should submit the value to the node with this id.
+    Redirect(u64),
+}
+
+/// What became of a value submitted by a client, as published by the proposer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientOutcome {
+    pub value: u64,
+    /// What the proposer did with the value, or why its round failed.
+    pub outcome: Result<ProposeOutcome, String>,
+}
+
+/// Snapshot of the round a proposer is running, for operators to check on its
+/// progress.
+///
+/// Quorums are the ones of the current membership: while a config change is not
+/// committed, the round must also reach a quorum of the previous acceptors.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub struct RoundStatus {
+    /// Slot of the round in flight, or of the last round if none is.
+    pub slot: u64,
+    pub ballot: BallotNumber,
+    /// Acceptors that promised the ballot of the round.
+    pub prepared: usize,
+    pub prepare_quorum: usize,
+    /// Acceptors that accepted the proposal of the round.
+    pub accepted: usize,
+    pub accept_quorum: usize,
+    pub in_flight: bool,
+}
+
+/// Counters of a proposer, as of the end of its latest round. Small and `Copy`, so
+/// that a snapshot can be published after every round for next to nothing.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub struct ProposerStats {
+    /// Values a round was run for, whatever became of them.
+    pub rounds: u64,
+    /// Values chosen, for their slot or for a slot decided by another proposer.
+    pub decided: u64,
+    /// Values given up on.
+    pub failed: u64,
+    /// Slot of the latest round.
+    pub slot: u64,
+    /// Ballot of the latest round.
+    pub ballot: BallotNumber,
+}
+
+/// What a proposer knows of the cluster, as last published by it for introspection.
+/// Reading it never waits for the proposer.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+pub struct ProposerStatus {
+    /// Proposer seen leading the cluster, which may be this one.
+    pub leader: Option<u64>,
+    pub round: RoundStatus,
+    /// Acceptors suspected to be down by the failure detector, sorted by id. `None`
+    /// without a failure detector.
+    pub suspected: Option<Vec<u64>>,
+}
+
+/// At-a-glance view of the cluster, as seen by a proposer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterHealth {
+    /// Acceptors of the current membership.
+    pub known_acceptors: usize,
+    /// Acceptors not suspected to be down by the failure detector of the proposer.
+    /// All of them without a failure detector.
+    pub live_acceptors: usize,
+    /// Proposal of the round in flight, or of the last round if none is, unless
+    /// the proposer gave up on its value.
+    pub current_round_proposal: Option<Proposal>,
+    /// Highest slot decided so far.
+    pub last_decided: Option<u64>,
+    /// Whether enough acceptors are live for the quorums of both phases.
+    pub quorum_available: bool,
+}
+
+/// One line, e.g. `2/3 acceptors live, quorum available, round proposal 42 at
+/// ballot 1.3, last decided slot 7`.
+impl fmt::Display for ClusterHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} acceptors live, quorum {}",
+            self.live_acceptors,
+            self.known_acceptors,
+            if self.quorum_available {
+                "available"
+            } else {
+                "unavailable"
+            }
+        )?;
+        match self.current_round_proposal {
+            Some(proposal) => write!(
+                f,
+                ", round proposal {} at ballot {}",
+                proposal.value, proposal.ballot
+            )?,
+            None => write!(f, ", no round yet")?,
+        }
+        match self.last_decided {
+            Some(slot) => write!(f, ", last decided slot {slot}"),
+            None => write!(f, ", nothing decided"),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use super::{ballot::BallotNumber, consensus::FailureReason};
+
+/// Step of the protocol taken by a proposer, published for external observers such
+/// as dashboards and tests.
+///
+/// Events are published on a lossy broadcast channel: a subscriber that lags too far
+/// behind misses some of them rather than slowing the proposer down.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaxosEvent {
+    /// Prepare requests were sent for the slot.
+    RoundStarted { slot: u64, ballot: BallotNumber },
+    /// An acceptor promised the ballot of the round.
+    PromiseReceived {
+        slot: u64,
+        ballot: BallotNumber,
+        acceptor: u64,
+    },
+    /// A quorum of acceptors promised the ballot of the round, sorted by id.
+    QuorumPrepared {
+        slot: u64,
+        ballot: BallotNumber,
+        promised: Vec<u64>,
+    },
+    /// Accept requests were sent for `value`, which may have been adopted from an
+    /// earlier round rather than submitted by the client.
+    AcceptSent {
+        slot: u64,
+        ballot: BallotNumber,
+        value: u64,
+    },
+    /// A value was chosen for the slot, by this proposer or by another one.
+    Chosen {
+        slot: u64,
+        ballot: BallotNumber,
+        value: u64,
+    },
+    /// The round ended without a decision. It may be retried with a higher ballot.
+    RoundAbandoned {
+        slot: u64,
+        ballot: BallotNumber,
+        reason: FailureReason,
+    },
+    /// Another proposer is now seen leading the cluster, or this one.
+    LeaderChanged { leader: u64 },
+}
@@ -1,7 +1,12 @@
-use super::id::ProposalId;
+use std::{fmt, time::Duration};
+
+use tokio::time::Instant;
+
+use super::{ballot::BallotNumber, id::ProposalId, proposal::Proposal};
 
 // TODO: separate acceptor and proposer messages.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Message {
     /// Message sent by the proposer to all the acceptors. It is the first message of
     /// the protocol.
@@ -21,17 +26,586 @@ pub enum Message {
     AcceptResponse {
         body: AcceptPhaseBody,
     },
+    /// Message sent by an acceptor that refuses to promise a proposal.
+    PrepareReject {
+        body: RejectBody,
+    },
+    /// Message sent by an acceptor that refuses to accept a proposal.
+    AcceptReject {
+        body: RejectBody,
+    },
+    /// Message sent by a proposer to find out whether its next prepare request
+    /// would be promised. Acceptors answer it without promising anything.
+    PreVoteRequest {
+        body: PreVoteBody,
+    },
+    /// Answer of an acceptor to a pre-vote request.
+    PreVoteResponse {
+        body: PreVoteBody,
+    },
+    /// Accept response of an acceptor, copied to the other acceptors so that they
+    /// can tell on their own when a value is decided.
+    AcceptGossip {
+        body: AcceptPhaseBody,
+    },
+    /// Value submitted by a client from outside of the cluster, to be proposed by
+    /// the node receiving it.
+    ClientRequest {
+        body: ClientRequestBody,
+    },
+    /// Message sent by a proposer asking the acceptors not to promise any other
+    /// proposer for a while, so that it can serve reads on its own.
+    LeaseRequest {
+        body: LeaseBody,
+    },
+    /// Answer of an acceptor to a lease request.
+    LeaseResponse {
+        body: LeaseBody,
+    },
+    /// Message sent by a proposer to all the acceptors once it gave up on a round,
+    /// telling them that its ballot will not be used anymore. Not answered.
+    AbortRound {
+        body: AbortBody,
+    },
+}
+
+impl Message {
+    /// Node that claims to have sent the message.
+    pub fn issuer_id(&self) -> u64 {
+        match self {
+            Self::PrepareRequest { body } | Self::PrepareResponse { body } => {
+                body.issuer_id
+            }
+            Self::AcceptRequest { body }
+            | Self::AcceptResponse { body }
+            | Self::AcceptGossip { body } => body.issuer_id,
+            Self::PrepareReject { body } | Self::AcceptReject { body } => {
+                body.issuer_id
+            }
+            Self::PreVoteRequest { body } | Self::PreVoteResponse { body } => {
+                body.issuer_id
+            }
+            Self::ClientRequest { body } => body.issuer_id,
+            Self::LeaseRequest { body } | Self::LeaseResponse { body } => {
+                body.issuer_id
+            }
+            Self::AbortRound { body } => body.issuer_id,
+        }
+    }
+}
+
+/// One line per message, for logs and tools, e.g.
+/// `ACCEPT issuer=2 epoch=0 slot=3 id=4f1c2a ballot=5.2 value=42`.
+///
+/// The first word is the kind of message, followed by its fields as `key=value`
+/// pairs. Proposals are written `id@ballot:value`.
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PrepareRequest { body } => write!(f, "PREP {body}"),
+            Self::PrepareResponse { body } => write!(f, "PROMISE {body}"),
+            Self::AcceptRequest { body } => write!(f, "ACCEPT {body}"),
+            Self::AcceptResponse { body } => write!(f, "ACCEPTED {body}"),
+            Self::PrepareReject { body } => write!(f, "PREP-REJECT {body}"),
+            Self::AcceptReject { body } => write!(f, "ACCEPT-REJECT {body}"),
+            Self::PreVoteRequest { body } => write!(f, "PREVOTE {body}"),
+            Self::PreVoteResponse { body } => write!(f, "PREVOTE-REPLY {body}"),
+            Self::AcceptGossip { body } => write!(f, "GOSSIP {body}"),
+            Self::ClientRequest { body } => {
+                write!(f, "CLIENT issuer={} value={}", body.issuer_id, body.value)
+            }
+            Self::LeaseRequest { body } => write!(f, "LEASE {body}"),
+            Self::LeaseResponse { body } => write!(f, "LEASE-REPLY {body}"),
+            Self::AbortRound { body } => write!(f, "ABORT {body}"),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct PreparePhaseBody {
     pub issuer_id: u64,
+    /// Epoch of the cluster configuration the message was sent in.
+    pub epoch: u64,
+    /// Instance of the protocol the message belongs to.
+    pub slot: u64,
     pub proposal_id: ProposalId,
+    pub ballot: BallotNumber,
+    /// In a prepare response, the proposal the acceptor already accepted for the
+    /// slot, if any. Always `None` in a prepare request.
+    pub accepted: Option<Proposal>,
+    /// Random number drawn by the proposer for each round, and copied by the
+    /// acceptors into their responses. A response whose nonce is not the one of
+    /// the round in flight replays an earlier round, and is ignored.
+    pub nonce: u64,
+    /// When the proposer sent the request, on its own clock. Acceptors copy it into
+    /// their responses, so that the proposer can tell how old they are.
+    ///
+    /// Instants of different processes can not be compared, so it is not
+    /// serialized: messages that went over the network arrive without one.
+    #[serde(skip)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    pub sent_at: Option<Instant>,
 }
 
-#[derive(Debug, Clone)]
+impl fmt::Display for PreparePhaseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "issuer={} epoch={} slot={} id={} ballot={}",
+            self.issuer_id,
+            self.epoch,
+            self.slot,
+            self.proposal_id.short(),
+            self.ballot
+        )?;
+        match &self.accepted {
+            Some(accepted) => write!(
+                f,
+                " accepted={}@{}:{}",
+                accepted.id.short(),
+                accepted.ballot,
+                accepted.value
+            ),
+            None => write!(f, " accepted=none"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct AcceptPhaseBody {
     pub issuer_id: u64,
+    /// Epoch of the cluster configuration the message was sent in.
+    pub epoch: u64,
+    /// Instance of the protocol the message belongs to.
+    pub slot: u64,
+    pub proposal_id: ProposalId,
+    pub ballot: BallotNumber,
+    pub value: u64,
+    /// Nonce of the round, see `PreparePhaseBody::nonce`.
+    pub nonce: u64,
+    /// When the proposer sent the request, on its own clock.
+    /// See `PreparePhaseBody::sent_at`.
+    #[serde(skip)]
+    #[cfg_attr(feature = "arbitrary", arbitrary(default))]
+    pub sent_at: Option<Instant>,
+}
+
+impl fmt::Display for AcceptPhaseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "issuer={} epoch={} slot={} id={} ballot={} value={}",
+            self.issuer_id,
+            self.epoch,
+            self.slot,
+            self.proposal_id.short(),
+            self.ballot,
+            self.value
+        )
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct PreVoteBody {
+    pub issuer_id: u64,
+    /// Epoch of the cluster configuration the message was sent in.
+    pub epoch: u64,
+    /// Slot the proposer would propose a value for.
+    pub slot: u64,
+    /// Ballot the proposer would use.
+    pub ballot: BallotNumber,
+    /// In a response, whether the acceptor would promise the proposal. Always
+    /// `false` in a request.
+    pub granted: bool,
+}
+
+impl fmt::Display for PreVoteBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "issuer={} epoch={} slot={} ballot={} granted={}",
+            self.issuer_id, self.epoch, self.slot, self.ballot, self.granted
+        )
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LeaseBody {
+    pub issuer_id: u64,
+    /// Epoch of the cluster configuration the message was sent in.
+    pub epoch: u64,
+    /// Proposal the lease is promised under.
     pub proposal_id: ProposalId,
+    pub ballot: BallotNumber,
+    /// How long the acceptor must not promise any other proposer, from the moment
+    /// it grants the lease.
+    pub duration: Duration,
+    /// In a response, whether the acceptor granted the lease. Always `false` in a
+    /// request.
+    pub granted: bool,
+    /// In a response, the highest slot the acceptor accepted a proposal for or
+    /// learned decided, if any. Always `None` in a request.
+    pub highest_slot: Option<u64>,
+}
+
+impl fmt::Display for LeaseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "issuer={} epoch={} id={} ballot={} duration={}ms granted={}",
+            self.issuer_id,
+            self.epoch,
+            self.proposal_id.short(),
+            self.ballot,
+            self.duration.as_millis(),
+            self.granted
+        )?;
+        match self.highest_slot {
+            Some(slot) => write!(f, " highest={slot}"),
+            None => write!(f, " highest=none"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct AbortBody {
+    pub issuer_id: u64,
+    /// Epoch of the cluster configuration the message was sent in.
+    pub epoch: u64,
+    /// Instance of the protocol the abandoned round was run for.
+    pub slot: u64,
+    /// Ballot of the abandoned round, which must be one of the issuer.
+    pub ballot: BallotNumber,
+}
+
+impl fmt::Display for AbortBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "issuer={} epoch={} slot={} ballot={}",
+            self.issuer_id, self.epoch, self.slot, self.ballot
+        )
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct ClientRequestBody {
+    /// Identifier the client picked for itself. It is not part of the cluster.
+    pub issuer_id: u64,
     pub value: u64,
 }
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct RejectBody {
+    pub issuer_id: u64,
+    /// Epoch of the cluster configuration the message was sent in.
+    pub epoch: u64,
+    /// Instance of the protocol the message belongs to.
+    pub slot: u64,
+    /// Proposal that was rejected.
+    pub proposal_id: ProposalId,
+    pub reason: RejectReason,
+    /// Whether the proposer of the ballot of `reason` gave up on its round. The
+    /// acceptor still holds to it, but no one is competing for the slot with it
+    /// anymore: the rejected proposer can retry with a higher ballot right away.
+    pub abandoned: bool,
+}
+
+impl fmt::Display for RejectBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "issuer={} epoch={} slot={} id={} {} abandoned={}",
+            self.issuer_id,
+            self.epoch,
+            self.slot,
+            self.proposal_id.short(),
+            self.reason,
+            self.abandoned
+        )
+    }
+}
+
+/// Why an acceptor rejected a proposal. Every variant carries the ballot that caused
+/// the rejection, so the proposer knows which ballot it has to outbid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum RejectReason {
+    /// The acceptor promised not to accept proposals older than `promised`.
+    AlreadyPromisedHigher {
+        promised: ProposalId,
+        ballot: BallotNumber,
+    },
+    /// The acceptor already accepted `accepted`, which is newer than the proposal.
+    /// It was accepted with `value` for `slot`, so that a proposer can tell when a
+    /// quorum of acceptors accepted the same proposal.
+    AlreadyAcceptedHigher {
+        accepted: ProposalId,
+        ballot: BallotNumber,
+        slot: u64,
+        value: u64,
+    },
+    /// The acceptor refused the proposal itself, with `ballot`, for reasons of its
+    /// own: a custom `AcceptorLogic` enforcing a constraint of the application on
+    /// the values. A higher ballot would not change its mind.
+    Refused { ballot: BallotNumber },
+    /// The slot is already decided, for `value` with `ballot`, as the acceptor
+    /// learned from the gossip of the others. A proposal with a ballot no higher
+    /// could not change the decision: it is rejected without being considered.
+    AlreadyDecided {
+        decided: ProposalId,
+        ballot: BallotNumber,
+        slot: u64,
+        value: u64,
+    },
+}
+
+impl RejectReason {
+    /// Ballot that the rejected proposal lost against.
+    pub fn ballot(&self) -> BallotNumber {
+        match self {
+            Self::AlreadyPromisedHigher { ballot, .. }
+            | Self::AlreadyAcceptedHigher { ballot, .. }
+            | Self::Refused { ballot }
+            | Self::AlreadyDecided { ballot, .. } => *ballot,
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyPromisedHigher { promised, ballot } => {
+                write!(f, "promised={}@{}", promised.short(), ballot)
+            }
+            Self::AlreadyAcceptedHigher {
+                accepted,
+                ballot,
+                slot,
+                value,
+            } => write!(
+                f,
+                "accepted={}@{}:{} accepted_slot={}",
+                accepted.short(),
+                ballot,
+                value,
+                slot
+            ),
+            Self::Refused { ballot } => write!(f, "refused@{}", ballot),
+            Self::AlreadyDecided {
+                decided,
+                ballot,
+                slot,
+                value,
+            } => write!(
+                f,
+                "decided={}@{}:{} decided_slot={}",
+                decided.short(),
+                ballot,
+                value,
+                slot
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn id(n: u128) -> ProposalId {
+        ProposalId(Uuid::from_u128(n))
+    }
+
+    fn prepare(issuer_id: u64, accepted: Option<Proposal>) -> PreparePhaseBody {
+        PreparePhaseBody {
+            issuer_id,
+            epoch: 0,
+            slot: 2,
+            proposal_id: id(0xabcdef),
+            ballot: BallotNumber::new(3, 1),
+            accepted,
+            nonce: 0,
+            sent_at: None,
+        }
+    }
+
+    fn accept(issuer_id: u64) -> AcceptPhaseBody {
+        AcceptPhaseBody {
+            issuer_id,
+            epoch: 0,
+            slot: 2,
+            proposal_id: id(0xabcdef),
+            ballot: BallotNumber::new(3, 1),
+            value: 42,
+            nonce: 0,
+            sent_at: None,
+        }
+    }
+
+    fn reject(reason: RejectReason) -> RejectBody {
+        RejectBody {
+            issuer_id: 2,
+            epoch: 0,
+            slot: 2,
+            proposal_id: id(0xabcdef),
+            reason,
+            abandoned: false,
+        }
+    }
+
+    fn pre_vote(issuer_id: u64, granted: bool) -> PreVoteBody {
+        PreVoteBody {
+            issuer_id,
+            epoch: 0,
+            slot: 2,
+            ballot: BallotNumber::new(3, 1),
+            granted,
+        }
+    }
+
+    fn lease(issuer_id: u64, granted: bool) -> LeaseBody {
+        LeaseBody {
+            issuer_id,
+            epoch: 0,
+            proposal_id: id(0xabcdef),
+            ballot: BallotNumber::new(3, 1),
+            duration: Duration::from_millis(500),
+            granted,
+            highest_slot: granted.then_some(4),
+        }
+    }
+
+    #[test]
+    fn every_message_has_a_stable_display() {
+        let higher = BallotNumber::new(5, 4);
+        let accepted = Proposal::new(42, id(0x123456), BallotNumber::new(2, 4));
+        let messages = [
+            (
+                Message::PrepareRequest {
+                    body: prepare(1, None),
+                },
+                "PREP issuer=1 epoch=0 slot=2 id=abcdef ballot=3.1 accepted=none",
+            ),
+            (
+                Message::PrepareResponse {
+                    body: prepare(2, Some(accepted)),
+                },
+                "PROMISE issuer=2 epoch=0 slot=2 id=abcdef ballot=3.1 \
+                 accepted=123456@2.4:42",
+            ),
+            (
+                Message::AcceptRequest { body: accept(1) },
+                "ACCEPT issuer=1 epoch=0 slot=2 id=abcdef ballot=3.1 value=42",
+            ),
+            (
+                Message::AcceptResponse { body: accept(2) },
+                "ACCEPTED issuer=2 epoch=0 slot=2 id=abcdef ballot=3.1 value=42",
+            ),
+            (
+                Message::PrepareReject {
+                    body: reject(RejectReason::AlreadyPromisedHigher {
+                        promised: id(0x123456),
+                        ballot: higher,
+                    }),
+                },
+                "PREP-REJECT issuer=2 epoch=0 slot=2 id=abcdef promised=123456@5.4 \
+                 abandoned=false",
+            ),
+            (
+                Message::AcceptReject {
+                    body: reject(RejectReason::AlreadyAcceptedHigher {
+                        accepted: id(0x123456),
+                        ballot: higher,
+                        slot: 2,
+                        value: 50,
+                    }),
+                },
+                "ACCEPT-REJECT issuer=2 epoch=0 slot=2 id=abcdef \
+                 accepted=123456@5.4:50 accepted_slot=2 abandoned=false",
+            ),
+            (
+                Message::AcceptReject {
+                    body: reject(RejectReason::Refused { ballot: higher }),
+                },
+                "ACCEPT-REJECT issuer=2 epoch=0 slot=2 id=abcdef refused@5.4 \
+                 abandoned=false",
+            ),
+            (
+                Message::PrepareReject {
+                    body: reject(RejectReason::AlreadyDecided {
+                        decided: id(0x123456),
+                        ballot: higher,
+                        slot: 2,
+                        value: 50,
+                    }),
+                },
+                "PREP-REJECT issuer=2 epoch=0 slot=2 id=abcdef decided=123456@5.4:50 \
+                 decided_slot=2 abandoned=false",
+            ),
+            (
+                Message::PreVoteRequest {
+                    body: pre_vote(1, false),
+                },
+                "PREVOTE issuer=1 epoch=0 slot=2 ballot=3.1 granted=false",
+            ),
+            (
+                Message::PreVoteResponse {
+                    body: pre_vote(2, true),
+                },
+                "PREVOTE-REPLY issuer=2 epoch=0 slot=2 ballot=3.1 granted=true",
+            ),
+            (
+                Message::AcceptGossip { body: accept(2) },
+                "GOSSIP issuer=2 epoch=0 slot=2 id=abcdef ballot=3.1 value=42",
+            ),
+            (
+                Message::ClientRequest {
+                    body: ClientRequestBody {
+                        issuer_id: 9,
+                        value: 42,
+                    },
+                },
+                "CLIENT issuer=9 value=42",
+            ),
+            (
+                Message::LeaseRequest {
+                    body: lease(1, false),
+                },
+                "LEASE issuer=1 epoch=0 id=abcdef ballot=3.1 duration=500ms \
+                 granted=false highest=none",
+            ),
+            (
+                Message::LeaseResponse {
+                    body: lease(2, true),
+                },
+                "LEASE-REPLY issuer=2 epoch=0 id=abcdef ballot=3.1 duration=500ms \
+                 granted=true highest=4",
+            ),
+            (
+                Message::AbortRound {
+                    body: AbortBody {
+                        issuer_id: 1,
+                        epoch: 0,
+                        slot: 2,
+                        ballot: BallotNumber::new(3, 1),
+                    },
+                },
+                "ABORT issuer=1 epoch=0 slot=2 ballot=3.1",
+            ),
+        ];
+
+        for (message, expected) in messages {
+            assert_eq!(message.to_string(), expected);
+        }
+    }
+}
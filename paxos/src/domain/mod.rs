@@ -1,6 +1,15 @@
+//! Core of the protocol: the messages and the state machines that answer them.
+//! Nothing in here depends on an async runtime, on `anyhow` or on `tracing`, so
+//! that it can be reused outside of the actors.
+
+pub mod acceptor;
+pub mod ballot;
+pub mod command;
+pub mod consensus;
+pub mod event;
 pub mod message;
-pub mod node;
 pub mod proposal;
+pub mod quorum;
 
 pub mod id {
     use std::ops::Deref;
@@ -18,6 +27,7 @@ pub mod id {
         serde::Serialize,
         serde::Deserialize,
     )]
+    #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
     pub struct ProposalId(pub Uuid);
 
     #[derive(
@@ -39,14 +49,58 @@ pub mod id {
         fn formatted(&self) -> String;
     }
 
-    impl BrandedUuid for ProposalId {
-        //Get only the last 6 characters of the string representation of the uuid.
-        fn formatted(&self) -> String {
+    impl ProposalId {
+        /// Last 6 characters of the id: enough to tell proposals apart in logs.
+        pub fn short(&self) -> String {
             let uuid_str = self.0.to_string();
             uuid_str[uuid_str.len() - 6..].to_string()
         }
     }
 
+    impl BrandedUuid for ProposalId {
+        fn formatted(&self) -> String {
+            self.short()
+        }
+    }
+
+    /// Source of the ids of the proposals issued by a proposer. Ids only have to be
+    /// unique among the proposals of a proposer: ballots, not ids, order them.
+    pub trait ProposalIdGen: Send + Sync {
+        /// Id of the next proposal issued by the node `node_id`.
+        fn next(&mut self, node_id: u64) -> ProposalId;
+    }
+
+    /// Time-ordered random ids (UUIDv7), unique across nodes and restarts.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct TimeBasedIdGen;
+
+    impl ProposalIdGen for TimeBasedIdGen {
+        fn next(&mut self, _node_id: u64) -> ProposalId {
+            ProposalId(Uuid::now_v7())
+        }
+    }
+
+    /// Ids 1, 2, 3... in the order the proposals are issued, so that runs are
+    /// reproducible. They start over when the proposer restarts.
+    #[derive(Debug, Clone, Copy)]
+    pub struct SequentialIdGen {
+        next: u128,
+    }
+
+    impl Default for SequentialIdGen {
+        fn default() -> Self {
+            Self { next: 1 }
+        }
+    }
+
+    impl ProposalIdGen for SequentialIdGen {
+        fn next(&mut self, _node_id: u64) -> ProposalId {
+            let id = ProposalId(Uuid::from_u128(self.next));
+            self.next += 1;
+            id
+        }
+    }
+
     impl Deref for ProposalId {
         type Target = Uuid;
 
@@ -1,9 +1,14 @@
-use super::id::ProposalId;
+use super::{ballot::BallotNumber, id::ProposalId};
 
 /// A proposal is a message sent by a **proposer** to the **acceptors**,
-/// containing the id of the proposal and a value.
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Copy)]
+/// containing the id of the proposal, its ballot and a value.
+///
+/// The `id` identifies the proposal, while the `ballot` is what acceptors use to
+/// decide which proposal takes priority.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, Copy)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Proposal {
     pub id: ProposalId,
+    pub ballot: BallotNumber,
     pub value: u64,
 }
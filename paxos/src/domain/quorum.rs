@@ -0,0 +1,125 @@
+use std::collections::{BTreeSet, HashSet};
+
+/// Phase of a round, each of which may require quorums of a different size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Prepare,
+    Accept,
+}
+
+/// Tells whether the acceptors that voted for a proposal form a quorum.
+///
+/// Usually, a quorum is a majority (or the configured number) of the acceptors of
+/// the cluster. While the membership changes through joint consensus, it must be a
+/// quorum of both the old and the new acceptors, so that neither of them can decide
+/// a value on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuorumTracker {
+    sets: Vec<AcceptorSet>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AcceptorSet {
+    acceptors: BTreeSet<u64>,
+    /// Number of acceptors of the set that must vote for a proposal.
+    quorum: usize,
+}
+
+impl AcceptorSet {
+    fn count(&self, voters: &HashSet<u64>) -> usize {
+        voters
+            .iter()
+            .filter(|id| self.acceptors.contains(id))
+            .count()
+    }
+}
+
+impl QuorumTracker {
+    /// Quorums of `quorum` among `acceptors`.
+    pub fn new(acceptors: impl IntoIterator<Item = u64>, quorum: usize) -> Self {
+        Self {
+            sets: vec![AcceptorSet {
+                acceptors: acceptors.into_iter().collect(),
+                quorum,
+            }],
+        }
+    }
+
+    /// Also require `quorum` of `acceptors` to vote.
+    pub fn joint(
+        mut self,
+        acceptors: impl IntoIterator<Item = u64>,
+        quorum: usize,
+    ) -> Self {
+        self.sets.push(AcceptorSet {
+            acceptors: acceptors.into_iter().collect(),
+            quorum,
+        });
+        self
+    }
+
+    /// Whether quorums of more than one set of acceptors are required.
+    pub fn is_joint(&self) -> bool {
+        self.sets.len() > 1
+    }
+
+    /// Whether `node_id` is an acceptor of any of the sets, i.e. whether its vote
+    /// counts.
+    pub fn contains(&self, node_id: u64) -> bool {
+        self.sets.iter().any(|set| set.acceptors.contains(&node_id))
+    }
+
+    /// Whether `voters` hold a quorum of every set. Voters that are not acceptors
+    /// do not count.
+    pub fn is_reached(&self, voters: &HashSet<u64>) -> bool {
+        self.sets.iter().all(|set| set.count(voters) >= set.quorum)
+    }
+
+    /// Whether so many acceptors of a set refused a proposal that the others can
+    /// no longer form a quorum of it.
+    pub fn is_lost(&self, refusing: &HashSet<u64>) -> bool {
+        self.sets.iter().any(|set| {
+            set.count(refusing) > set.acceptors.len().saturating_sub(set.quorum)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voters<const N: usize>(ids: [u64; N]) -> HashSet<u64> {
+        HashSet::from(ids)
+    }
+
+    #[test]
+    fn joint_quorum_needs_a_quorum_of_both_sets() {
+        let quorum = QuorumTracker::new(0..3, 2).joint([2, 3, 4], 2);
+        assert!(quorum.is_joint());
+
+        // A majority of the first set only, then of the second set only.
+        assert!(!quorum.is_reached(&voters([0, 1])));
+        assert!(!quorum.is_reached(&voters([3, 4])));
+        assert!(!quorum.is_reached(&voters([0, 1, 3])));
+        assert!(quorum.is_reached(&voters([1, 2, 3])));
+        assert!(quorum.is_reached(&voters([0, 1, 3, 4])));
+    }
+
+    #[test]
+    fn joint_quorum_is_lost_once_either_set_can_not_reach_it() {
+        let quorum = QuorumTracker::new(0..3, 2).joint([2, 3, 4], 2);
+
+        assert!(!quorum.is_lost(&voters([0])));
+        assert!(quorum.is_lost(&voters([3, 4])));
+        assert!(quorum.is_lost(&voters([0, 1])));
+    }
+
+    #[test]
+    fn votes_of_other_nodes_do_not_count() {
+        let quorum = QuorumTracker::new(0..3, 2);
+
+        assert!(!quorum.contains(5));
+        assert!(!quorum.is_reached(&voters([0, 5, 6])));
+        assert!(quorum.is_reached(&voters([0, 2, 5])));
+    }
+}
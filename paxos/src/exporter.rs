@@ -0,0 +1,142 @@
+//! Endpoint serving the metrics of a node to Prometheus, in its text format.
+//!
+//! The metrics of every node of the process are recorded together, by a single
+//! recorder installed along with the first endpoint. Each endpoint only serves the
+//! samples of its own node, labeled with its `node_id` and `role`.
+
+use std::{future::Future, net::SocketAddr, sync::OnceLock, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tracing::{debug, info};
+
+use crate::config::Role;
+
+/// How long a scraper may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Recorder of the metrics of the process, or why it could not be installed.
+static RECORDER: OnceLock<Result<PrometheusHandle, String>> = OnceLock::new();
+
+fn recorder() -> Result<&'static PrometheusHandle> {
+    RECORDER
+        .get_or_init(|| {
+            PrometheusBuilder::new()
+                .install_recorder()
+                .map_err(|e| e.to_string())
+        })
+        .as_ref()
+        .map_err(|e| anyhow!("could not install the Prometheus recorder: {e}"))
+}
+
+/// Endpoint of a node, answering any request with the metrics of the node.
+pub struct Exporter {
+    node_id: u64,
+    /// Roles of the node, as the value of the `role` label.
+    role: String,
+    listener: TcpListener,
+    recorder: &'static PrometheusHandle,
+}
+
+impl Exporter {
+    /// Listen on `address` for the scrapers of the metrics of the node `node_id`,
+    /// port 0 picking any free port. Installs the recorder of the process if no
+    /// endpoint did yet.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn bind(node_id: u64, roles: &[Role], address: SocketAddr) -> Result<Self> {
+        let recorder = recorder()?;
+        let listener = std::net::TcpListener::bind(address)
+            .with_context(|| format!("could not listen on {address}"))?;
+        listener.set_nonblocking(true)?;
+        let role = roles
+            .iter()
+            .map(|role| match role {
+                Role::Proposer => "proposer",
+                Role::Acceptor => "acceptor",
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        Ok(Self {
+            node_id,
+            role,
+            listener: TcpListener::from_std(listener)?,
+            recorder,
+        })
+    }
+
+    /// Address the endpoint actually listens on.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Serve the scrapers one at a time until `stop` resolves.
+    pub async fn run_until(self, stop: impl Future<Output = ()>) {
+        let address = self.listener.local_addr().ok();
+        let node_id = self.node_id;
+        info!(node_id, ?address, "serving metrics");
+        tokio::pin!(stop);
+        loop {
+            tokio::select! {
+                _ = &mut stop => return,
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, scraper)) => {
+                        if let Err(e) = self.serve(stream).await {
+                            debug!(node_id, %scraper, "could not serve metrics: {e}");
+                        }
+                    }
+                    Err(e) => debug!(node_id, "could not accept scraper: {e}"),
+                },
+            }
+        }
+    }
+
+    async fn serve(&self, mut stream: TcpStream) -> Result<()> {
+        // The request itself does not matter, only that it was fully sent.
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read = timeout(REQUEST_TIMEOUT, stream.read(&mut buffer))
+                .await
+                .context("request timed out")??;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+
+        let body = self.render();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; \
+             version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+
+    /// Samples of the node, along with the descriptions of every metric.
+    fn render(&self) -> String {
+        let node_label = format!("node_id=\"{}\"", self.node_id);
+        let role_label = format!("{{role=\"{}\",", self.role);
+        let mut rendered = String::new();
+        for line in self.recorder.render().lines() {
+            if line.starts_with('#') || line.is_empty() {
+                rendered.push_str(line);
+            } else if line.contains(&node_label) {
+                rendered.push_str(&line.replacen('{', &role_label, 1));
+            } else {
+                continue;
+            }
+            rendered.push('\n');
+        }
+        rendered
+    }
+}
@@ -0,0 +1,161 @@
+//! Random sequences of messages fed to a fresh proposer and a fresh acceptor, to
+//! check that their handlers cope with whatever the network may deliver.
+//!
+//! Shared by the `message_handlers` fuzz target and the `fuzz_handlers` example, which
+//! runs without `cargo-fuzz`.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::{runtime, sync::mpsc};
+
+use crate::{
+    actors::proposer::Proposer,
+    config::{ClusterConfig, PeerConfig, Role},
+    domain::{acceptor::AcceptorState, ballot::BallotNumber, message::Message},
+    repository::ValueRepositoryImpl,
+    transport::channel,
+};
+
+/// Acceptors of the cluster the messages are fed to. The proposer comes right
+/// after them.
+pub const ACCEPTORS: u64 = 3;
+
+/// Value the proposer runs a round for, and the messages it is then delivered.
+#[derive(Debug, arbitrary::Arbitrary)]
+pub struct HandlerInput {
+    pub value: u64,
+    /// Every message along with its sender, taken modulo `ACCEPTORS + 2`: most of
+    /// them come from an acceptor, some from the proposer itself or from a node
+    /// outside of the cluster. The issuer id of the message is set to the sender.
+    pub messages: Vec<(u8, Message)>,
+}
+
+/// Feed `input` to a fresh `Proposer` and a fresh `AcceptorState`.
+///
+/// Panics if one of their handlers does, if the proposer counts more votes than
+/// there are acceptors, or if the acceptor ever lowers the highest ballot it
+/// promised or accepted. Errors returned by the handlers are fine: they are how
+/// unexpected messages must be turned down.
+pub fn handle_messages(input: HandlerInput) -> Result<()> {
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let cluster = ClusterConfig {
+            peers: (0..=ACCEPTORS)
+                .map(|node_id| PeerConfig {
+                    node_id,
+                    roles: vec![if node_id == ACCEPTORS {
+                        Role::Proposer
+                    } else {
+                        Role::Acceptor
+                    }],
+                    address: None,
+                    priority: 0,
+                })
+                .collect(),
+            ..ClusterConfig::default()
+        };
+        // The acceptor channels are only kept for the broadcasts of the proposer to
+        // have receivers.
+        let (transport, _acceptors) = channel::wire(&cluster, false);
+        let (_client, client_receiver) = mpsc::channel(1);
+        let mut proposer = Proposer::new(
+            ACCEPTORS,
+            cluster,
+            Box::new(transport),
+            client_receiver,
+            Arc::new(ValueRepositoryImpl::in_memory()?),
+        );
+        let _ = proposer.send_prepare_request(input.value).await;
+
+        let mut acceptor = AcceptorState::new(0);
+        let mut highest = highest_ballot(&acceptor);
+        for (sender, mut message) in input.messages {
+            set_issuer(&mut message, u64::from(sender) % (ACCEPTORS + 2));
+
+            acceptor.handle(message.clone());
+            let now = highest_ballot(&acceptor);
+            assert!(
+                now >= highest,
+                "acceptor went from ballot {highest:?} to {now:?}"
+            );
+            highest = now;
+
+            let _ = proposer.inject_message(message).await;
+            for (name, voters) in [
+                ("prepared", proposer.prepared_nodes.len()),
+                ("accepted", proposer.accepted_value_nodes.len()),
+                ("rejected", proposer.rejected_nodes.len()),
+            ] {
+                assert!(
+                    voters <= ACCEPTORS as usize,
+                    "{voters} acceptors counted as {name}"
+                );
+            }
+            for (ballot, voters) in &proposer.accepted_elsewhere {
+                assert!(
+                    voters.len() <= ACCEPTORS as usize,
+                    "{} acceptors counted as accepting {ballot}",
+                    voters.len()
+                );
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Highest ballot the acceptor promised or accepted, which must never go down.
+fn highest_ballot(acceptor: &AcceptorState) -> Option<BallotNumber> {
+    let promised = acceptor.promised.map(|(ballot, _)| ballot);
+    let accepted = acceptor
+        .accepted
+        .values()
+        .map(|proposal| proposal.ballot)
+        .max();
+    promised.max(accepted)
+}
+
+fn set_issuer(message: &mut Message, issuer_id: u64) {
+    match message {
+        Message::PrepareRequest { body } | Message::PrepareResponse { body } => {
+            body.issuer_id = issuer_id
+        }
+        Message::AcceptRequest { body }
+        | Message::AcceptResponse { body }
+        | Message::AcceptGossip { body } => body.issuer_id = issuer_id,
+        Message::PrepareReject { body } | Message::AcceptReject { body } => {
+            body.issuer_id = issuer_id
+        }
+        Message::PreVoteRequest { body } | Message::PreVoteResponse { body } => {
+            body.issuer_id = issuer_id
+        }
+        Message::ClientRequest { body } => body.issuer_id = issuer_id,
+        Message::LeaseRequest { body } | Message::LeaseResponse { body } => {
+            body.issuer_id = issuer_id
+        }
+        Message::AbortRound { body } => body.issuer_id = issuer_id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::{Arbitrary, Unstructured};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        /// Fallback of the fuzz target, run by `cargo test`: the inputs are drawn
+        /// from the random bytes proptest generates, and shrunk along with them.
+        #[test]
+        fn handlers_cope_with_any_sequence_of_messages(
+            data in proptest::collection::vec(any::<u8>(), 0..4096),
+        ) {
+            if let Ok(input) = HandlerInput::arbitrary(&mut Unstructured::new(&data)) {
+                handle_messages(input).expect("could not feed the messages");
+            }
+        }
+    }
+}
@@ -0,0 +1,23 @@
+pub mod actors;
+#[cfg(feature = "admin")]
+pub mod admin;
+pub mod audit;
+pub mod client;
+pub mod clock;
+pub mod config;
+pub mod domain;
+#[cfg(feature = "prometheus")]
+pub mod exporter;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+pub mod node;
+pub mod rate_limit;
+pub mod repository;
+pub mod retry;
+#[cfg(any(test, feature = "simulation"))]
+pub mod simulation;
+pub mod supervisor;
+pub mod telemetry;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+pub mod transport;
@@ -1,26 +1,32 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use actors::proposer::Proposer;
 use clap::Parser;
-use config::Args;
-use domain::{message::Message, node::Node};
-use tokio::{
-    sync::{broadcast, mpsc},
-    time::sleep,
+use paxos::{
+    actors::{acceptor::Acceptor, proposer::Proposer},
+    config::{Args, ClusterConfig, Role},
+    domain::id::SequentialIdGen,
+    repository::ValueRepositoryImpl,
+    transport::{
+        channel,
+        sim::{Latency, LinkSpec, SimNetwork},
+        AcceptorTransport, Transport,
+    },
 };
+use tokio::{sync::mpsc, time::sleep};
 use tracing_subscriber::EnvFilter;
 
-pub mod actors;
-mod config;
-pub mod domain;
-pub mod repository;
-
 /// General rules:
 /// Only a value that has been proposed may be chosen.
 /// A process never learns that a value has been chosen unless it actually has been.
 #[tokio::main]
 async fn main() {
-    let Args { nodes, rounds } = Args::parse();
+    let args = Args::parse();
+    let Args {
+        nodes,
+        rounds,
+        ref database,
+        ..
+    } = args;
 
     let filter_layer =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug"));
@@ -30,21 +36,43 @@ async fn main() {
         .with_target(false)
         .init();
 
-    // FIXME: this number should (probably?) be the same as the number of nodes.
-    // Decrease this and handle `Lagged` error.
-    let (broadcast_tx, _) = broadcast::channel::<Message>(1000);
-    let (proposer_tx, proposer_rx) = mpsc::channel::<Message>(nodes);
     let (client_tx, client_rx) = mpsc::channel::<u64>(nodes);
 
-    let mut proposer = Proposer::new(broadcast_tx.clone(), proposer_rx, client_rx);
+    let repository = match database.clone() {
+        Some(path) => ValueRepositoryImpl::open(path),
+        None => ValueRepositoryImpl::in_memory(),
+    }
+    .expect("could not open repository");
+
+    let cluster = ClusterConfig::in_process(nodes);
+    let proposer_id = cluster.proposer_id().expect("cluster has no proposer");
+    let (proposer_transport, acceptor_transports) = transports(&args, &cluster);
+    let mut proposer = Proposer::new(
+        proposer_id,
+        cluster.clone(),
+        proposer_transport,
+        client_rx,
+        Arc::new(repository),
+    );
+    proposer.pre_vote_enabled = args.pre_vote;
+    proposer.abort_abandoned_rounds = args.abort_rounds;
+    proposer.log_values = !args.redact_values;
+    proposer.retry.max_attempts = args.max_retries;
+    proposer.prepare_timeout = Duration::from_millis(args.prepare_timeout_ms);
+    proposer.accept_timeout = Duration::from_millis(args.accept_timeout_ms);
+    proposer.max_message_age = args.max_message_age_ms.map(Duration::from_millis);
+    // Ids taken from a counter keep the runs over the simulated network reproducible.
+    // They start over on restart, so they can not be mixed with a persisted round.
+    if args.simulate && database.is_none() {
+        proposer.id_generator = Box::new(SequentialIdGen::default());
+    }
 
     tokio::spawn(async move {
         proposer.run().await.expect("could not run proposer");
     });
 
-    for i in 0..nodes {
-        let node_subscriber = broadcast_tx.subscribe();
-        let mut acceptor = Node::new(i as u64, proposer_tx.clone(), node_subscriber);
+    for (i, node_transport) in acceptor_transports.into_iter().enumerate() {
+        let mut acceptor = Acceptor::new(i as u64, cluster.clone(), node_transport);
 
         tokio::spawn(async move {
             acceptor.run().await.expect("could not run acceptor {i}");
@@ -60,14 +88,45 @@ async fn main() {
     }
 }
 
-impl Drop for Proposer {
-    fn drop(&mut self) {
-        println!("Proposer dropped");
-    }
-}
+/// Build the transport of the proposer and of each acceptor, in the order of their
+/// ids.
+fn transports(
+    args: &Args,
+    cluster: &ClusterConfig,
+) -> (Box<dyn Transport>, Vec<Box<dyn AcceptorTransport>>) {
+    if args.simulate {
+        let network = SimNetwork::with_default_link(
+            args.seed,
+            LinkSpec {
+                latency: Latency::Fixed(Duration::from_millis(args.latency_ms)),
+                drop_probability: args.drop_probability,
+                duplicate_probability: args.duplicate_probability,
+                reorder_window: Duration::from_millis(args.reorder_window_ms),
+            },
+        );
+        let transports = cluster
+            .peers
+            .iter()
+            .map(|peer| (peer, network.join(peer.node_id, &peer.roles)));
+
+        let mut proposer = None;
+        let mut acceptors: Vec<Box<dyn AcceptorTransport>> = Vec::new();
+        for (peer, transport) in transports {
+            if peer.has_role(Role::Acceptor) {
+                acceptors.push(Box::new(transport));
+            } else {
+                proposer = Some(Box::new(transport) as _);
+            }
+        }
 
-impl Drop for Node {
-    fn drop(&mut self) {
-        println!("Acceptor dropped");
+        return (proposer.expect("cluster has no proposer"), acceptors);
     }
+
+    let (proposer, acceptors) = channel::wire(cluster, args.split_phases);
+    let acceptors = acceptors
+        .into_iter()
+        .map(|acceptor| Box::new(acceptor) as Box<dyn AcceptorTransport>)
+        .collect();
+
+    (Box::new(proposer), acceptors)
 }
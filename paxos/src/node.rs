@@ -0,0 +1,676 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use tokio::{
+    sync::{broadcast, mpsc, watch, Semaphore},
+    task::JoinHandle,
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+use crate::{
+    actors::{
+        follower::Follower,
+        learner::{Decision, DecisionRecord, Learner},
+        proposer::{
+            Deadlines, Proposer, EVENTS_CAPACITY, METRICS_CAPACITY, OUTCOMES_CAPACITY,
+        },
+        vote_log::{VoteLog, VoteRecord},
+    },
+    audit::DecisionLog,
+    client::{Client, DEFAULT_MAX_IN_FLIGHT_ROUNDS},
+    config::{ClusterConfig, Role},
+    domain::{
+        acceptor::AcceptorState,
+        consensus::{ClientOutcome, ConsensusResult, ProposerStats, ProposerStatus},
+        event::PaxosEvent,
+    },
+    repository::{AcceptorRepository, AcceptorRepositoryImpl, ValueRepositoryImpl},
+    supervisor::{self, RestartPolicy, Supervisor, SupervisorStatus},
+    transport::{local, Transport},
+};
+
+/// How long the proposer of a node may spend deciding the values queued by its
+/// client once the node is asked to shut down. The other roles get as long to
+/// stop.
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+
+/// Member of the cluster running every role its peer config enables.
+///
+/// All the roles share a single transport. When a node is both a proposer and an
+/// acceptor, its proposer counts the vote of its own acceptor like any other.
+///
+/// The acceptor is supervised, and restarted if it crashes. The proposer is not:
+/// restarting it would lose the values queued by its client.
+pub struct Node {
+    pub id: u64,
+    roles: Vec<Role>,
+    client: mpsc::Sender<u64>,
+    /// Outcome of the values submitted to the proposer of the node.
+    outcomes: broadcast::Sender<ClientOutcome>,
+    /// Steps of the protocol taken by the proposer of the node.
+    events: broadcast::Sender<PaxosEvent>,
+    /// Stats of the proposer of the node, published after every round.
+    metrics: broadcast::Sender<ProposerStats>,
+    /// Permits of the values submitted through `client` and not decided yet.
+    admission: Arc<Semaphore>,
+    deadlines: Deadlines,
+    learner: Learner,
+    votes: VoteLog,
+    latest_decided: watch::Receiver<Option<ConsensusResult>>,
+    proposer_status: watch::Receiver<ProposerStatus>,
+    status: watch::Receiver<SupervisorStatus>,
+    /// Directory of the durable state of the node, if it has one.
+    storage_dir: Option<PathBuf>,
+    /// Cancelled first on shutdown, to stop the proposer.
+    stop_proposer: CancellationToken,
+    /// Cancelled once the proposer is done, to stop the other roles: the proposer
+    /// still needs them to decide the values it drains.
+    stop: CancellationToken,
+    /// Task of the proposer, returning the values it could not decide.
+    proposer: Option<JoinHandle<Vec<u64>>>,
+    /// Tasks of the other roles, along with their name.
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl Node {
+    /// Spawn the roles of the node `node_id` of `config`, talking to the rest of the
+    /// cluster through `transport`. The proposer keeps its state in memory.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn new(
+        node_id: u64,
+        config: ClusterConfig,
+        transport: Box<dyn Transport>,
+    ) -> Result<Self> {
+        Self::spawn(node_id, config, transport, None, None)
+    }
+
+    /// Like `Node::new`, but the proposer and the acceptor keep their state in
+    /// databases stored in `storage_dir`, so that it survives restarts. The acceptor
+    /// starts from the state it last saved there.
+    ///
+    /// Every decision the node learns, from the round it resumes on, is appended to
+    /// the audit log at `audit_log` if any, created if needed. See `DecisionLog`.
+    pub fn open(
+        node_id: u64,
+        config: ClusterConfig,
+        transport: Box<dyn Transport>,
+        storage_dir: &Path,
+        audit_log: Option<&Path>,
+    ) -> Result<Self> {
+        Self::spawn(node_id, config, transport, Some(storage_dir), audit_log)
+    }
+
+    fn spawn(
+        node_id: u64,
+        config: ClusterConfig,
+        transport: Box<dyn Transport>,
+        storage_dir: Option<&Path>,
+        audit_log: Option<&Path>,
+    ) -> Result<Self> {
+        config.validate(node_id)?;
+        let peer = config
+            .peer(node_id)
+            .context("node is not part of the cluster config")?
+            .clone();
+
+        let (client, client_receiver) = mpsc::channel(config.acceptor_count().max(1));
+        let (router, proposer_transport, acceptor_transport) =
+            local::split(node_id, config.clone(), transport, client.clone());
+        let stop_proposer = CancellationToken::new();
+        let stop = CancellationToken::new();
+        let mut tasks = vec![(
+            "router",
+            tokio::spawn(router.run_until(stop.clone().cancelled_owned())),
+        )];
+
+        // Nodes that do not propose never learn of any decision.
+        let mut learner = Learner::default();
+        let mut votes = VoteLog::default();
+        let mut outcomes = broadcast::channel(OUTCOMES_CAPACITY).0;
+        let mut events = broadcast::channel(EVENTS_CAPACITY).0;
+        let mut metrics = broadcast::channel(METRICS_CAPACITY).0;
+        let mut deadlines = Deadlines::default();
+        let mut latest_decided = watch::channel(None).1;
+        let mut proposer_status = watch::channel(ProposerStatus::default()).1;
+        let mut proposer_task = None;
+        if peer.has_role(Role::Proposer) {
+            let repository = match storage_dir {
+                Some(storage_dir) => {
+                    std::fs::create_dir_all(storage_dir).with_context(|| {
+                        format!("could not create {}", storage_dir.display())
+                    })?;
+                    ValueRepositoryImpl::open(storage_dir.join("proposer.sqlite"))?
+                }
+                None => ValueRepositoryImpl::in_memory()?,
+            };
+            let mut proposer = Proposer::new(
+                node_id,
+                config.clone(),
+                Box::new(proposer_transport),
+                client_receiver,
+                Arc::new(repository),
+            );
+            // Before the proposer runs, so that no decision is left out.
+            if let Some(path) = audit_log {
+                proposer.learner.set_audit_log(DecisionLog::open(path)?)?;
+            }
+            learner = proposer.learner.clone();
+            votes = proposer.votes.clone();
+            outcomes = proposer.outcomes.clone();
+            events = proposer.events.clone();
+            metrics = proposer.metrics.clone();
+            deadlines = proposer.deadlines.clone();
+            latest_decided = proposer.latest_decided.subscribe();
+            proposer_status = proposer.status.subscribe();
+
+            let stopped = stop_proposer.clone().cancelled_owned();
+            proposer_task = Some(tokio::spawn(async move {
+                proposer
+                    .run_until(stopped, SHUTDOWN_GRACE)
+                    .await
+                    .expect("could not run proposer")
+            }));
+        }
+
+        let mut supervisor = Supervisor::new(node_id, RestartPolicy::default());
+        if peer.has_role(Role::Acceptor) {
+            let (state, repository) = match storage_dir {
+                Some(storage_dir) => {
+                    let (state, repository) = open_acceptor(node_id, storage_dir)?;
+                    (state, Some(repository))
+                }
+                None => (AcceptorState::new(node_id), None),
+            };
+            supervisor.add(
+                "acceptor",
+                supervisor::acceptor_factory(
+                    node_id,
+                    config,
+                    Box::new(acceptor_transport),
+                    Arc::new(Mutex::new(state)),
+                    repository,
+                    false,
+                    stop.clone(),
+                ),
+            );
+        }
+        let status = supervisor.status();
+        tasks.push(("supervisor", tokio::spawn(supervisor.run())));
+
+        Ok(Self {
+            id: node_id,
+            roles: peer.roles,
+            client,
+            outcomes,
+            events,
+            metrics,
+            admission: Arc::new(Semaphore::new(DEFAULT_MAX_IN_FLIGHT_ROUNDS)),
+            deadlines,
+            learner,
+            votes,
+            latest_decided,
+            proposer_status,
+            status,
+            storage_dir: storage_dir.map(Path::to_path_buf),
+            stop_proposer,
+            stop,
+            proposer: proposer_task,
+            tasks,
+        })
+    }
+
+    /// Submit values to the proposer of the node, bypassing the admission control
+    /// of `client`. Sending fails if the node is not a proposer.
+    pub fn client_handle(&self) -> mpsc::Sender<u64> {
+        self.client.clone()
+    }
+
+    /// Client of the proposer of the node, which can wait for the outcome of the
+    /// values it submits. Submitting fails if the node is not a proposer.
+    ///
+    /// The clients of a node share up to `DEFAULT_MAX_IN_FLIGHT_ROUNDS` values in
+    /// flight.
+    pub fn client(&self) -> Client {
+        Client::new(
+            self.client.clone(),
+            self.outcomes.clone(),
+            self.admission.clone(),
+            self.deadlines.clone(),
+        )
+    }
+
+    /// Read-only replica of the decisions learned by the node from now on.
+    pub fn follower(&self) -> Follower {
+        Follower::spawn(self.decisions())
+    }
+
+    /// Decisions learned by the node from now on.
+    pub fn decisions(&self) -> broadcast::Receiver<Decision> {
+        self.learner.subscribe()
+    }
+
+    /// Steps of the protocol taken by the proposer of the node from now on, e.g. to
+    /// check the sequence of a round in tests. A subscriber that lags behind by more
+    /// than `EVENTS_CAPACITY` events misses the oldest ones: the proposer never
+    /// waits for it. Nothing is published if the node is not a proposer.
+    pub fn events(&self) -> broadcast::Receiver<PaxosEvent> {
+        self.events.subscribe()
+    }
+
+    /// Stats of the proposer of the node, published at the end of every round from
+    /// now on. See `Proposer::metrics_subscribe`. Nothing is published if the node
+    /// is not a proposer.
+    pub fn metrics_subscribe(&self) -> broadcast::Receiver<ProposerStats> {
+        self.metrics.subscribe()
+    }
+
+    /// The latest decisions learned by the node, oldest first. See
+    /// `Learner::decision_log`.
+    pub fn decision_log(&self) -> Vec<DecisionRecord> {
+        self.learner.decision_log()
+    }
+
+    /// The latest votes of the acceptors counted by the proposer of the node, oldest
+    /// first. See `VoteLog`. Empty if the node is not a proposer.
+    pub fn export_votes(&self) -> Vec<VoteRecord> {
+        self.votes.export_votes()
+    }
+
+    /// Latest value decided by the node, always up to date: `borrow` it rather
+    /// than following `decisions`. Stays `None` if the node is not a proposer.
+    pub fn latest_decided(&self) -> watch::Receiver<Option<ConsensusResult>> {
+        self.latest_decided.clone()
+    }
+
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
+    }
+
+    /// Handle on the decisions learned by the node, to look them up.
+    pub fn learner(&self) -> &Learner {
+        &self.learner
+    }
+
+    /// Status of the proposer of the node, as of its latest step. Stays at its
+    /// default if the node is not a proposer.
+    pub fn proposer_status(&self) -> watch::Receiver<ProposerStatus> {
+        self.proposer_status.clone()
+    }
+
+    /// Values submitted to the proposer of the node that it did not take yet.
+    pub fn queue_depth(&self) -> usize {
+        self.client.max_capacity() - self.client.capacity()
+    }
+
+    /// Directory where the node keeps its durable state, `None` if it keeps it in
+    /// memory.
+    pub fn storage_dir(&self) -> Option<&Path> {
+        self.storage_dir.as_deref()
+    }
+
+    /// Serve the metrics of the node to Prometheus on `address` until it shuts
+    /// down. Returns the address actually listened on, `address` may have port 0.
+    #[cfg(feature = "prometheus")]
+    pub fn serve_metrics(
+        &mut self,
+        address: std::net::SocketAddr,
+    ) -> Result<std::net::SocketAddr> {
+        let exporter = crate::exporter::Exporter::bind(self.id, &self.roles, address)?;
+        let address = exporter.local_addr()?;
+        self.tasks.push((
+            "metrics",
+            tokio::spawn(exporter.run_until(self.stop.clone().cancelled_owned())),
+        ));
+        Ok(address)
+    }
+
+    /// Answer operators about the state of the node on `address` until it shuts
+    /// down. Returns the address actually listened on, `address` may have port 0.
+    #[cfg(feature = "admin")]
+    pub fn serve_admin(
+        &mut self,
+        address: std::net::SocketAddr,
+    ) -> Result<std::net::SocketAddr> {
+        let admin = crate::admin::AdminServer::bind(self, address)?;
+        let address = admin.local_addr()?;
+        self.tasks.push((
+            "admin",
+            tokio::spawn(admin.run_until(self.stop.clone().cancelled_owned())),
+        ));
+        Ok(address)
+    }
+
+    /// Health of the supervised roles of the node, and how many times they were
+    /// restarted.
+    pub fn status(&self) -> SupervisorStatus {
+        self.status.borrow().clone()
+    }
+
+    /// Stop every role of the node. The proposer first gets a chance to decide the
+    /// values already submitted; the ones it could not decide are returned. Roles
+    /// that do not stop in time are aborted, and reported in the error.
+    pub async fn shutdown(mut self) -> Result<Vec<u64>> {
+        let mut stuck = Vec::new();
+
+        self.stop_proposer.cancel();
+        let mut undecided = Vec::new();
+        if let Some(proposer) = self.proposer.take() {
+            // A round in flight is not interrupted by the cancellation: leave it
+            // some time on top of the grace period.
+            let abort = proposer.abort_handle();
+            match timeout(SHUTDOWN_GRACE * 2, proposer).await {
+                Ok(Ok(values)) => undecided = values,
+                Ok(Err(e)) => {
+                    warn!(node_id = self.id, "proposer stopped abnormally: {e}")
+                }
+                Err(_) => {
+                    abort.abort();
+                    stuck.push("proposer");
+                }
+            }
+        }
+
+        self.stop.cancel();
+        for (role, task) in self.tasks.drain(..) {
+            let abort = task.abort_handle();
+            match timeout(SHUTDOWN_GRACE, task).await {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => {
+                    warn!(node_id = self.id, "{role} stopped abnormally: {e}")
+                }
+                Err(_) => {
+                    abort.abort();
+                    stuck.push(role);
+                }
+            }
+        }
+
+        if !stuck.is_empty() {
+            bail!(
+                "node {}: {} did not stop in time",
+                self.id,
+                stuck.join(", ")
+            );
+        }
+        Ok(undecided)
+    }
+}
+
+/// State of the acceptor `node_id` as last saved in `storage_dir`, along with the
+/// repository to keep saving it to.
+fn open_acceptor(
+    node_id: u64,
+    storage_dir: &Path,
+) -> Result<(AcceptorState, Arc<dyn AcceptorRepository<AcceptorState>>)> {
+    std::fs::create_dir_all(storage_dir)
+        .with_context(|| format!("could not create {}", storage_dir.display()))?;
+    let repository = AcceptorRepositoryImpl::open(storage_dir.join("acceptor.sqlite"))?;
+    let state = match repository.load()? {
+        Some(state) if state.id != node_id => bail!(
+            "{} holds the state of acceptor {}, not {node_id}",
+            storage_dir.display(),
+            state.id
+        ),
+        Some(state) => state,
+        None => AcceptorState::new(node_id),
+    };
+    Ok((state, Arc::new(repository)))
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        if let Some(proposer) = &self.proposer {
+            proposer.abort();
+        }
+        for (_, task) in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::sim::SimNetwork;
+
+    /// Nodes `0..3`, each of them a proposer and an acceptor, over `network`.
+    fn nodes(network: &SimNetwork) -> Vec<Node> {
+        let config = ClusterConfig::symmetric(3);
+        (0..3)
+            .map(|node_id| {
+                let roles = &config.peer(node_id).unwrap().roles;
+                let transport = network.join(node_id, roles);
+                Node::new(node_id, config.clone(), Box::new(transport)).unwrap()
+            })
+            .collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn three_nodes_decide_a_value() {
+        let network = SimNetwork::new(7);
+        let nodes = nodes(&network);
+        let mut decisions = nodes[1].decisions();
+
+        nodes[1].client_handle().send(7).await.unwrap();
+
+        let decision = decisions.recv().await.unwrap();
+        assert_eq!(decision.proposal.value, 7);
+        for node in nodes {
+            assert_eq!(node.shutdown().await.unwrap(), Vec::<u64>::new());
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn events_of_a_round_are_published_in_order() {
+        let network = SimNetwork::new(7);
+        let nodes = nodes(&network);
+        let mut events = nodes[1].events();
+
+        nodes[1].client_handle().send(7).await.unwrap();
+
+        let mut published = Vec::new();
+        loop {
+            let event = events.recv().await.unwrap();
+            let chosen = matches!(event, PaxosEvent::Chosen { .. });
+            published.push(event);
+            if chosen {
+                break;
+            }
+        }
+
+        let PaxosEvent::RoundStarted { slot: 0, ballot } = published[0] else {
+            panic!("round did not start first: {published:?}");
+        };
+        let position = |kind: fn(&PaxosEvent) -> bool| {
+            published
+                .iter()
+                .position(kind)
+                .unwrap_or_else(|| panic!("missing event: {published:?}"))
+        };
+        let prepared =
+            position(|event| matches!(event, PaxosEvent::QuorumPrepared { .. }));
+        let PaxosEvent::QuorumPrepared { promised, .. } = &published[prepared] else {
+            unreachable!()
+        };
+        let mut promised_before: Vec<u64> = published[..prepared]
+            .iter()
+            .filter_map(|event| match event {
+                PaxosEvent::PromiseReceived { acceptor, .. } => Some(*acceptor),
+                _ => None,
+            })
+            .collect();
+        promised_before.sort_unstable();
+        assert_eq!(&promised_before, promised);
+        assert!(
+            position(|event| matches!(event, PaxosEvent::AcceptSent { .. })) > prepared
+        );
+        assert_eq!(
+            published.last(),
+            Some(&PaxosEvent::Chosen {
+                slot: 0,
+                ballot,
+                value: 7
+            })
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn proposer_counts_the_acceptor_of_its_own_node() {
+        let network = SimNetwork::new(7);
+        // Node 0 can only reach node 1: the quorum it gets must include itself.
+        network.partition(vec![vec![0, 1], vec![2]]);
+        let nodes = nodes(&network);
+        let mut decisions = nodes[0].decisions();
+
+        nodes[0].client_handle().send(7).await.unwrap();
+
+        let decision = decisions.recv().await.unwrap();
+        assert_eq!(decision.proposal.value, 7);
+        assert_eq!(decision.quorum, vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn audit_log_records_each_slot_once_across_restarts() {
+        let storage_dir = std::env::temp_dir()
+            .join(format!("paxos-node-{}", uuid::Uuid::now_v7().simple()));
+        let audit_log = storage_dir.join("decisions.log");
+        let config = ClusterConfig::symmetric(3);
+        let network = SimNetwork::new(7);
+        let open = || {
+            let transport = network.join(0, &config.peer(0).unwrap().roles);
+            Node::open(
+                0,
+                config.clone(),
+                Box::new(transport),
+                &storage_dir,
+                Some(&audit_log),
+            )
+            .unwrap()
+        };
+        let _others: Vec<Node> = (1..3)
+            .map(|node_id| {
+                let transport =
+                    network.join(node_id, &config.peer(node_id).unwrap().roles);
+                Node::new(node_id, config.clone(), Box::new(transport)).unwrap()
+            })
+            .collect();
+        // Decided right as the node starts, before anything else could attach a log.
+        let node = open();
+        node.client_handle().send(7).await.unwrap();
+        node.learner().wait_for_decision(0).await;
+        node.shutdown().await.unwrap();
+        // As if it crashed before the proposal of slot 0 was marked decided: the
+        // restarted node resumes its round, and learns the slot again.
+        rusqlite::Connection::open(storage_dir.join("proposer.sqlite"))
+            .unwrap()
+            .execute("UPDATE latest_proposal SET decided = 0", ())
+            .unwrap();
+
+        let node = open();
+        node.learner().wait_for_decision(0).await;
+        node.client_handle().send(8).await.unwrap();
+        node.learner().wait_for_decision(1).await;
+        node.shutdown().await.unwrap();
+
+        // Written by the thread of the log, in the background.
+        let audited = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let slots: Vec<u64> = DecisionLog::iter(&audit_log)
+                    .unwrap()
+                    .map(|record| record.slot)
+                    .collect();
+                if slots.last() == Some(&1) {
+                    break slots;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("decisions not audited");
+        assert_eq!(audited, [0, 1]);
+        assert_eq!(crate::audit::verify(&audit_log).unwrap(), 2);
+        std::fs::remove_dir_all(storage_dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_node_shuts_down_right_away() {
+        let network = SimNetwork::new(7);
+        let mut nodes = nodes(&network);
+        let node = nodes.remove(0);
+        let started = tokio::time::Instant::now();
+
+        assert_eq!(node.shutdown().await.unwrap(), Vec::<u64>::new());
+        assert!(started.elapsed() < SHUTDOWN_GRACE);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn node_mid_round_shuts_down_within_the_grace_period() {
+        let network = SimNetwork::new(7);
+        // Node 0 can not reach a quorum: its round never ends on its own.
+        network.partition(vec![vec![0], vec![1, 2]]);
+        let mut nodes = nodes(&network);
+        let node = nodes.remove(0);
+        let mut events = node.events();
+        node.client_handle().send(7).await.unwrap();
+        while !matches!(events.recv().await, Ok(PaxosEvent::RoundStarted { .. })) {}
+        let started = tokio::time::Instant::now();
+
+        assert_eq!(node.shutdown().await.unwrap(), vec![7]);
+        assert!(started.elapsed() <= SHUTDOWN_GRACE * 3);
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[tokio::test]
+    async fn metrics_endpoint_reports_the_decided_round() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Ids no other test uses: the recorder is shared by the whole process.
+        let mut config = ClusterConfig::symmetric(3);
+        for peer in &mut config.peers {
+            peer.node_id += 40;
+        }
+        let network = SimNetwork::new(7);
+        let mut nodes: Vec<Node> = config
+            .peers
+            .iter()
+            .map(|peer| {
+                let transport = network.join(peer.node_id, &peer.roles);
+                Node::new(peer.node_id, config.clone(), Box::new(transport)).unwrap()
+            })
+            .collect();
+        // Every node of the process gets its own port.
+        let any_port = std::net::SocketAddr::from(([127, 0, 0, 1], 0));
+        let address = nodes[1].serve_metrics(any_port).unwrap();
+        assert_ne!(nodes[2].serve_metrics(any_port).unwrap(), address);
+
+        nodes[1]
+            .client()
+            .propose_with_timeout(7, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let mut scrape = tokio::net::TcpStream::connect(address).await.unwrap();
+        scrape
+            .write_all(b"GET /metrics HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        scrape.read_to_string(&mut response).await.unwrap();
+
+        let decided = response
+            .lines()
+            .find(|line| line.starts_with(crate::telemetry::ROUNDS_DECIDED))
+            .unwrap_or_else(|| panic!("no decided rounds in {response}"));
+        assert!(decided.contains("node_id=\"41\""), "{decided}");
+        assert!(decided.ends_with(" 1"), "{decided}");
+        for node in nodes {
+            node.shutdown().await.unwrap();
+        }
+    }
+}
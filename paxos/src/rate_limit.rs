@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use tokio::time::Instant;
+
+/// How many requests a node answers for each of its peers: `per_second` on
+/// average, and up to `burst` at once after a quiet period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn validate(&self) -> Result<()> {
+        if self.per_second == 0 {
+            bail!("per_second must be at least 1");
+        }
+        if self.burst == 0 {
+            bail!("burst must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// Token bucket of each peer, enforcing a `RateLimit`. Every peer starts with a
+/// full bucket of `burst` tokens, refilled at `per_second`; a request is let
+/// through if it can take a token.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: HashMap<u64, Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    /// Requests refused since the last one let through.
+    throttled: u64,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Take a token for a request of `peer` received at `now`. Returns whether the
+    /// request is let through.
+    pub fn try_acquire(&mut self, peer: u64, now: Instant) -> bool {
+        let burst = f64::from(self.limit.burst.max(1));
+        let bucket = self.buckets.entry(peer).or_insert(Bucket {
+            tokens: burst,
+            refilled_at: now,
+            throttled: 0,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens
+            + elapsed.as_secs_f64() * f64::from(self.limit.per_second))
+        .min(burst);
+        bucket.refilled_at = now;
+
+        if bucket.tokens < 1.0 {
+            bucket.throttled += 1;
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        bucket.throttled = 0;
+        true
+    }
+
+    /// Requests of `peer` refused since the last one let through.
+    pub fn throttled(&self, peer: u64) -> u64 {
+        self.buckets.get(&peer).map_or(0, |bucket| bucket.throttled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn burst_is_let_through_then_requests_wait_for_the_refill() {
+        let mut limiter = RateLimiter::new(RateLimit {
+            per_second: 2,
+            burst: 3,
+        });
+        let start = Instant::now();
+
+        let through: Vec<bool> =
+            (0..5).map(|_| limiter.try_acquire(7, start)).collect();
+        assert_eq!(through, [true, true, true, false, false]);
+        assert_eq!(limiter.throttled(7), 2);
+        // Every peer has a bucket of its own.
+        assert!(limiter.try_acquire(8, start));
+
+        // Half a second refills a single token.
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.try_acquire(7, later));
+        assert_eq!(limiter.throttled(7), 0);
+        assert!(!limiter.try_acquire(7, later));
+
+        // A long quiet period refills no more than a burst.
+        let much_later = later + Duration::from_secs(60);
+        let through = (0..4)
+            .filter(|_| limiter.try_acquire(7, much_later))
+            .count();
+        assert_eq!(through, 3);
+    }
+
+    #[test]
+    fn limit_without_rate_or_burst_is_refused() {
+        for (per_second, burst) in [(0, 1), (1, 0)] {
+            assert!(RateLimit { per_second, burst }.validate().is_err());
+        }
+        assert!(RateLimit {
+            per_second: 1,
+            burst: 1
+        }
+        .validate()
+        .is_ok());
+    }
+}
@@ -1,27 +1,520 @@
+use std::{collections::BTreeMap, path::Path, str::FromStr, sync::Mutex};
+
 use anyhow::Result;
+use rusqlite::{Connection, OptionalExtension};
+use uuid::Uuid;
+
+use crate::domain::{
+    acceptor::AcceptorState, ballot::BallotNumber, id::ProposalId, proposal::Proposal,
+};
 
-use crate::domain::{id::ProposalId, proposal::Proposal};
-pub struct ValueRepositoryImpl;
+/// Sqlite-backed storage for the proposer's round state.
+pub struct ValueRepositoryImpl {
+    // `Connection` is not `Sync`, so it is guarded to be shared between tasks.
+    connection: Mutex<Connection>,
+}
 
 impl Proposal {
-    pub fn new(value: u64, id: ProposalId) -> Self {
-        Self { value, id }
+    pub fn new(value: u64, id: ProposalId, ballot: BallotNumber) -> Self {
+        Self { value, id, ballot }
     }
 }
 
+/// Latest proposal written by a proposer, as saved in its `ValueRepository`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatestProposal {
+    /// Slot it was proposed for.
+    pub slot: u64,
+    pub proposal: Proposal,
+    /// Whether the slot is known to be decided: there is no round left to resume,
+    /// but the ballot of the proposal must still be outbid by the next ones.
+    pub decided: bool,
+}
+
 #[async_trait::async_trait]
 pub trait ValueRepository {
-    async fn get_latest_value(&self) -> Result<Option<Proposal>>;
-    async fn write_latest_value(&self, value: Proposal) -> Result<()>;
+    /// Latest proposal written, if any.
+    async fn get_latest_value(&self) -> Result<Option<LatestProposal>>;
+    async fn write_latest_value(&self, slot: u64, value: Proposal) -> Result<()>;
+    /// Mark the latest proposal as decided if it was proposed for `slot`, so that it
+    /// is not resumed anymore.
+    async fn mark_decided(&self, slot: u64) -> Result<()>;
+}
+
+/// Durable copy of the state of an acceptor, saved before every reply so that the
+/// acceptor keeps its promises across restarts of its process.
+pub trait AcceptorRepository<L>: Send + Sync {
+    /// State last saved, if any.
+    fn load(&self) -> Result<Option<L>>;
+    fn save(&self, state: &L) -> Result<()>;
+}
+
+impl ValueRepositoryImpl {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Repository that lives only as long as the process. Useful for simulations,
+    /// where there is no restart to survive.
+    pub fn in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self> {
+        // There is a single row, holding the latest proposal sent by the proposer.
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS latest_proposal (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                slot INTEGER NOT NULL,
+                proposal_id TEXT NOT NULL,
+                ballot_round INTEGER NOT NULL,
+                ballot_priority INTEGER NOT NULL,
+                ballot_node_id INTEGER NOT NULL,
+                value INTEGER NOT NULL,
+                decided INTEGER NOT NULL DEFAULT 0
+            )",
+            (),
+        )?;
+        // Databases created before proposals were marked decided lack the column.
+        let has_decided: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('latest_proposal')
+            WHERE name = 'decided'",
+            (),
+            |row| row.get(0),
+        )?;
+        if !has_decided {
+            connection.execute(
+                "ALTER TABLE latest_proposal ADD COLUMN decided INTEGER NOT NULL \
+                 DEFAULT 0",
+                (),
+            )?;
+        }
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
 }
 
 #[async_trait::async_trait]
 impl ValueRepository for ValueRepositoryImpl {
-    async fn get_latest_value(&self) -> Result<Option<Proposal>> {
-        todo!()
+    async fn get_latest_value(&self) -> Result<Option<LatestProposal>> {
+        let connection = self
+            .connection
+            .lock()
+            .map_err(|_| anyhow::anyhow!("repository lock poisoned"))?;
+
+        let row = connection
+            .query_row(
+                "SELECT slot, proposal_id, ballot_round, ballot_priority, \
+                 ballot_node_id,
+                    value, decided
+                FROM latest_proposal WHERE id = 0",
+                (),
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, i64>(4)?,
+                        row.get::<_, i64>(5)?,
+                        row.get::<_, bool>(6)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        row.map(
+            |(slot, proposal_id, round, priority, node_id, value, decided)| {
+                let proposal = Proposal::new(
+                    value as u64,
+                    ProposalId(Uuid::from_str(&proposal_id)?),
+                    BallotNumber::new(round as u64, node_id as u64)
+                        .with_priority(priority as u64),
+                );
+                Ok(LatestProposal {
+                    slot: slot as u64,
+                    proposal,
+                    decided,
+                })
+            },
+        )
+        .transpose()
+    }
+
+    async fn write_latest_value(&self, slot: u64, value: Proposal) -> Result<()> {
+        let connection = self
+            .connection
+            .lock()
+            .map_err(|_| anyhow::anyhow!("repository lock poisoned"))?;
+
+        // Sqlite has no unsigned integers; values are stored bit-for-bit as i64.
+        connection.execute(
+            "INSERT INTO latest_proposal
+                (id, slot, proposal_id, ballot_round, ballot_priority, ballot_node_id,
+                    value, decided)
+            VALUES (0, ?1, ?2, ?3, ?4, ?5, ?6, 0)
+            ON CONFLICT (id) DO UPDATE SET
+                slot = excluded.slot,
+                proposal_id = excluded.proposal_id,
+                ballot_round = excluded.ballot_round,
+                ballot_priority = excluded.ballot_priority,
+                ballot_node_id = excluded.ballot_node_id,
+                value = excluded.value,
+                decided = excluded.decided",
+            (
+                slot as i64,
+                value.id.to_string(),
+                value.ballot.round as i64,
+                value.ballot.priority as i64,
+                value.ballot.node_id as i64,
+                value.value as i64,
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    async fn mark_decided(&self, slot: u64) -> Result<()> {
+        let connection = self
+            .connection
+            .lock()
+            .map_err(|_| anyhow::anyhow!("repository lock poisoned"))?;
+
+        connection.execute(
+            "UPDATE latest_proposal SET decided = 1 WHERE id = 0 AND slot = ?1",
+            (slot as i64,),
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Sqlite-backed storage for the state of an acceptor.
+pub struct AcceptorRepositoryImpl {
+    connection: Mutex<Connection>,
+    /// Proposals accepted as of the last save. Only the slots that changed since
+    /// are written.
+    saved: Mutex<BTreeMap<u64, Proposal>>,
+}
+
+impl AcceptorRepositoryImpl {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Repository that lives only as long as the process.
+    pub fn in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self> {
+        // A single row holds the promises of the acceptor, and another table the
+        // proposal it accepted for each slot. Ballots that are not set are NULL.
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS acceptor (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                node_id INTEGER NOT NULL,
+                epoch INTEGER NOT NULL,
+                promised_round INTEGER,
+                promised_priority INTEGER,
+                promised_node_id INTEGER,
+                promised_proposal_id TEXT,
+                aborted_round INTEGER,
+                aborted_priority INTEGER,
+                aborted_node_id INTEGER
+            );
+            CREATE TABLE IF NOT EXISTS accepted_proposal (
+                slot INTEGER PRIMARY KEY,
+                proposal_id TEXT NOT NULL,
+                ballot_round INTEGER NOT NULL,
+                ballot_priority INTEGER NOT NULL,
+                ballot_node_id INTEGER NOT NULL,
+                value INTEGER NOT NULL
+            );",
+        )?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+            saved: Mutex::new(BTreeMap::new()),
+        })
     }
+}
+
+/// Ballot stored in three nullable columns, as round, priority and node id.
+type StoredBallot = (Option<i64>, Option<i64>, Option<i64>);
+
+fn stored_ballot(ballot: Option<BallotNumber>) -> StoredBallot {
+    match ballot {
+        Some(ballot) => (
+            Some(ballot.round as i64),
+            Some(ballot.priority as i64),
+            Some(ballot.node_id as i64),
+        ),
+        None => (None, None, None),
+    }
+}
+
+fn ballot_of((round, priority, node_id): StoredBallot) -> Option<BallotNumber> {
+    Some(
+        BallotNumber::new(round? as u64, node_id? as u64)
+            .with_priority(priority? as u64),
+    )
+}
+
+impl AcceptorRepository<AcceptorState> for AcceptorRepositoryImpl {
+    fn load(&self) -> Result<Option<AcceptorState>> {
+        let connection = self
+            .connection
+            .lock()
+            .map_err(|_| anyhow::anyhow!("repository lock poisoned"))?;
+
+        let row = connection
+            .query_row(
+                "SELECT node_id, epoch,
+                    promised_round, promised_priority, promised_node_id,
+                    promised_proposal_id,
+                    aborted_round, aborted_priority, aborted_node_id
+                FROM acceptor WHERE id = 0",
+                (),
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, i64>(1)?,
+                        (row.get(2)?, row.get(3)?, row.get(4)?),
+                        row.get::<_, Option<String>>(5)?,
+                        (row.get(6)?, row.get(7)?, row.get(8)?),
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((node_id, epoch, promised, promised_id, aborted)) = row else {
+            return Ok(None);
+        };
+
+        let mut state = AcceptorState::new(node_id as u64);
+        state.epoch = epoch as u64;
+        state.promised = match (ballot_of(promised), promised_id) {
+            (Some(ballot), Some(id)) => {
+                Some((ballot, ProposalId(Uuid::from_str(&id)?)))
+            }
+            _ => None,
+        };
+        state.aborted = ballot_of(aborted);
+
+        let mut statement = connection.prepare(
+            "SELECT slot, proposal_id, ballot_round, ballot_priority, ballot_node_id,
+                value
+            FROM accepted_proposal",
+        )?;
+        let rows = statement.query_map((), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, i64>(5)?,
+            ))
+        })?;
+        for row in rows {
+            let (slot, proposal_id, round, priority, node_id, value) = row?;
+            let proposal = Proposal::new(
+                value as u64,
+                ProposalId(Uuid::from_str(&proposal_id)?),
+                BallotNumber::new(round as u64, node_id as u64)
+                    .with_priority(priority as u64),
+            );
+            state.accepted.insert(slot as u64, proposal);
+        }
+
+        *self
+            .saved
+            .lock()
+            .map_err(|_| anyhow::anyhow!("repository lock poisoned"))? =
+            state.accepted.clone();
+        Ok(Some(state))
+    }
+
+    fn save(&self, state: &AcceptorState) -> Result<()> {
+        let mut connection = self
+            .connection
+            .lock()
+            .map_err(|_| anyhow::anyhow!("repository lock poisoned"))?;
+        let mut saved = self
+            .saved
+            .lock()
+            .map_err(|_| anyhow::anyhow!("repository lock poisoned"))?;
+
+        // Sqlite has no unsigned integers; values are stored bit-for-bit as i64.
+        let transaction = connection.transaction()?;
+        let (promised_round, promised_priority, promised_node_id) =
+            stored_ballot(state.promised.map(|(ballot, _)| ballot));
+        let (aborted_round, aborted_priority, aborted_node_id) =
+            stored_ballot(state.aborted);
+        transaction.execute(
+            "INSERT INTO acceptor
+                (id, node_id, epoch, promised_round, promised_priority,
+                    promised_node_id, promised_proposal_id, aborted_round,
+                    aborted_priority, aborted_node_id)
+            VALUES (0, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT (id) DO UPDATE SET
+                node_id = excluded.node_id,
+                epoch = excluded.epoch,
+                promised_round = excluded.promised_round,
+                promised_priority = excluded.promised_priority,
+                promised_node_id = excluded.promised_node_id,
+                promised_proposal_id = excluded.promised_proposal_id,
+                aborted_round = excluded.aborted_round,
+                aborted_priority = excluded.aborted_priority,
+                aborted_node_id = excluded.aborted_node_id",
+            (
+                state.id as i64,
+                state.epoch as i64,
+                promised_round,
+                promised_priority,
+                promised_node_id,
+                state.promised.map(|(_, id)| id.to_string()),
+                aborted_round,
+                aborted_priority,
+                aborted_node_id,
+            ),
+        )?;
+        for (&slot, proposal) in &state.accepted {
+            if saved.get(&slot) == Some(proposal) {
+                continue;
+            }
+            transaction.execute(
+                "INSERT INTO accepted_proposal
+                    (slot, proposal_id, ballot_round, ballot_priority, ballot_node_id,
+                        value)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ON CONFLICT (slot) DO UPDATE SET
+                    proposal_id = excluded.proposal_id,
+                    ballot_round = excluded.ballot_round,
+                    ballot_priority = excluded.ballot_priority,
+                    ballot_node_id = excluded.ballot_node_id,
+                    value = excluded.value",
+                (
+                    slot as i64,
+                    proposal.id.to_string(),
+                    proposal.ballot.round as i64,
+                    proposal.ballot.priority as i64,
+                    proposal.ballot.node_id as i64,
+                    proposal.value as i64,
+                ),
+            )?;
+        }
+        transaction.commit()?;
+
+        saved.clone_from(&state.accepted);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proposal(round: u64, value: u64) -> Proposal {
+        Proposal::new(
+            value,
+            ProposalId(Uuid::from_u128(u128::from(round))),
+            BallotNumber::new(round, 3).with_priority(1),
+        )
+    }
+
+    #[tokio::test]
+    async fn latest_proposal_keeps_the_priority_of_its_ballot() {
+        let repository = ValueRepositoryImpl::in_memory().unwrap();
+        repository
+            .write_latest_value(3, proposal(2, 20))
+            .await
+            .unwrap();
+
+        let latest = repository.get_latest_value().await.unwrap().unwrap();
+        assert_eq!(latest.slot, 3);
+        assert_eq!(latest.proposal, proposal(2, 20));
+        assert_eq!(latest.proposal.ballot.priority, 1);
+    }
+
+    #[tokio::test]
+    async fn latest_proposal_is_only_marked_decided_for_its_own_slot() {
+        let repository = ValueRepositoryImpl::in_memory().unwrap();
+        repository
+            .write_latest_value(3, proposal(2, 20))
+            .await
+            .unwrap();
+        let decided = || async {
+            let latest = repository.get_latest_value().await.unwrap().unwrap();
+            assert_eq!(latest.proposal, proposal(2, 20));
+            latest.decided
+        };
+
+        repository.mark_decided(2).await.unwrap();
+        assert!(!decided().await);
+        repository.mark_decided(3).await.unwrap();
+        assert!(decided().await);
+
+        // A new proposal is not decided yet.
+        repository
+            .write_latest_value(4, proposal(3, 20))
+            .await
+            .unwrap();
+        assert!(
+            !repository
+                .get_latest_value()
+                .await
+                .unwrap()
+                .unwrap()
+                .decided
+        );
+    }
+
+    #[tokio::test]
+    async fn latest_proposal_saved_before_it_could_be_marked_decided_is_loaded() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE latest_proposal (
+                    id INTEGER PRIMARY KEY CHECK (id = 0),
+                    slot INTEGER NOT NULL,
+                    proposal_id TEXT NOT NULL,
+                    ballot_round INTEGER NOT NULL,
+                    ballot_priority INTEGER NOT NULL,
+                    ballot_node_id INTEGER NOT NULL,
+                    value INTEGER NOT NULL
+                );
+                INSERT INTO latest_proposal VALUES
+                    (0, 3, '00000000-0000-0000-0000-000000000002', 2, 1, 3, 20);",
+            )
+            .unwrap();
+
+        let repository = ValueRepositoryImpl::from_connection(connection).unwrap();
+
+        let latest = repository.get_latest_value().await.unwrap().unwrap();
+        assert_eq!(latest.proposal, proposal(2, 20));
+        assert!(!latest.decided);
+    }
+
+    #[test]
+    fn acceptor_state_is_loaded_as_saved() {
+        let repository = AcceptorRepositoryImpl::in_memory().unwrap();
+        assert_eq!(repository.load().unwrap(), None);
+
+        let mut state = AcceptorState::new(2);
+        state.epoch = 4;
+        state.accepted.insert(0, proposal(1, 10));
+        state.accepted.insert(1, proposal(2, 20));
+        repository.save(&state).unwrap();
+        // Only slot 1 changes, and the promise.
+        state.accepted.insert(1, proposal(5, 50));
+        state.promised =
+            Some((BallotNumber::new(6, 3), ProposalId(Uuid::from_u128(6))));
+        state.aborted = Some(BallotNumber::new(5, 3));
+        repository.save(&state).unwrap();
 
-    async fn write_latest_value(&self, _value: Proposal) -> Result<()> {
-        todo!()
+        assert_eq!(repository.load().unwrap(), Some(state));
     }
 }
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use rand::Rng;
+
+/// Attempts made by default for an operation, the first one included.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// How an operation that failed is retried: how many times, and how long to wait
+/// before each attempt.
+///
+/// The delays grow exponentially from `initial_backoff`, up to `max_backoff`. A part
+/// of each of them is randomized, so that nodes that failed at the same time do not
+/// retry in lockstep. The randomness comes from an RNG of the caller, which is
+/// seeded to keep runs reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RetryPolicy {
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Factor applied to the delay after every retry. At least 1.
+    pub multiplier: f64,
+    /// Part of each delay, between 0 and 1, that is randomly cut off.
+    pub jitter: f64,
+    /// Attempts made in total, the first one included. At least one attempt is
+    /// always made.
+    pub max_attempts: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.25,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay to wait before the attempt number `attempt`, counting from 1. The first
+    /// attempt is made right away. Returns `None` once every attempt was made.
+    pub fn next_delay(&self, attempt: u32, rng: &mut impl Rng) -> Option<Duration> {
+        if attempt > self.max_attempts.max(1) {
+            return None;
+        }
+        if attempt <= 1 {
+            return Some(Duration::ZERO);
+        }
+
+        let retries = (attempt - 2).min(i32::MAX as u32) as i32;
+        let backoff = Duration::from_secs_f64(
+            (self.initial_backoff.as_secs_f64() * self.multiplier.powi(retries))
+                .min(self.max_backoff.as_secs_f64()),
+        );
+        if self.jitter <= 0.0 {
+            return Some(backoff);
+        }
+        Some(backoff.mul_f64(1.0 - self.jitter * rng.gen::<f64>()))
+    }
+
+    /// Check that the delays it gives are well defined.
+    pub fn validate(&self) -> Result<()> {
+        if !(self.multiplier >= 1.0 && self.multiplier.is_finite()) {
+            bail!(
+                "retry multiplier of {} is not a number of at least 1",
+                self.multiplier
+            );
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            bail!("retry jitter of {} is not between 0 and 1", self.jitter);
+        }
+        if self.initial_backoff > self.max_backoff {
+            bail!(
+                "initial retry backoff of {:?} is above the maximum of {:?}",
+                self.initial_backoff,
+                self.max_backoff
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// Delays before each attempt of `policy`, until it gives up.
+    fn delays(policy: &RetryPolicy, seed: u64) -> Vec<Duration> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (1..)
+            .map_while(|attempt| policy.next_delay(attempt, &mut rng))
+            .collect()
+    }
+
+    #[test]
+    fn delays_grow_exponentially_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            multiplier: 2.0,
+            jitter: 0.0,
+            max_attempts: 6,
+        };
+
+        assert_eq!(
+            delays(&policy, 7),
+            [0, 10, 20, 40, 50, 50].map(Duration::from_millis)
+        );
+    }
+
+    #[test]
+    fn jittered_delays_are_reproducible_for_a_seed() {
+        let policy = RetryPolicy::default();
+
+        let jittered = delays(&policy, 7);
+
+        assert_eq!(jittered, delays(&policy, 7));
+        assert_eq!(jittered.len(), policy.max_attempts as usize);
+        let unjittered = delays(
+            &RetryPolicy {
+                jitter: 0.0,
+                ..policy
+            },
+            7,
+        );
+        for (delay, full) in jittered.iter().zip(&unjittered) {
+            assert!(
+                *delay <= *full && *delay >= full.mul_f64(1.0 - policy.jitter),
+                "{delay:?} out of the jitter of {full:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn at_least_one_attempt_is_made() {
+        let policy = RetryPolicy {
+            max_attempts: 0,
+            ..RetryPolicy::default()
+        };
+
+        assert_eq!(delays(&policy, 7), [Duration::ZERO]);
+    }
+
+    #[test]
+    fn ill_defined_policies_are_refused() {
+        assert!(RetryPolicy::default().validate().is_ok());
+        for policy in [
+            RetryPolicy {
+                multiplier: 0.5,
+                ..RetryPolicy::default()
+            },
+            RetryPolicy {
+                multiplier: f64::NAN,
+                ..RetryPolicy::default()
+            },
+            RetryPolicy {
+                jitter: 1.5,
+                ..RetryPolicy::default()
+            },
+            RetryPolicy {
+                initial_backoff: Duration::from_secs(2),
+                ..RetryPolicy::default()
+            },
+        ] {
+            assert!(policy.validate().is_err(), "{policy:?}");
+        }
+    }
+}
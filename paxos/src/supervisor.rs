@@ -0,0 +1,383 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use tokio::{
+    sync::{watch, Mutex as AsyncMutex},
+    task::{Id, JoinSet},
+    time::Instant,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::{
+    actors::acceptor::Acceptor,
+    clock::{Clock, TokioClock},
+    config::ClusterConfig,
+    domain::{acceptor::AcceptorState, message::Message},
+    repository::AcceptorRepository,
+    transport::{AcceptorTransport, Envelope},
+};
+
+/// Builds a fresh instance of an actor, and returns the future running it. Called
+/// once when the actor is started, then again on every restart.
+pub type ActorFactory =
+    Box<dyn FnMut() -> Pin<Box<dyn Future<Output = Result<()>> + Send>> + Send>;
+
+/// How eagerly a supervisor restarts the actors that crash.
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Delay before the first restart of an actor. It doubles with every restart
+    /// within the last minute.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Restarts of a single actor allowed within a minute. Past that, the actor is
+    /// left stopped and the node is reported unhealthy, instead of restart-looping.
+    pub max_restarts_per_minute: usize,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            max_restarts_per_minute: 5,
+        }
+    }
+}
+
+/// What a supervisor reports about the actors of its node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupervisorStatus {
+    /// Whether every actor is running or stopped on its own. False once an actor
+    /// crashed too often to be restarted.
+    pub healthy: bool,
+    /// Number of times each actor was restarted.
+    pub restarts: HashMap<&'static str, usize>,
+}
+
+impl Default for SupervisorStatus {
+    fn default() -> Self {
+        Self {
+            healthy: true,
+            restarts: HashMap::new(),
+        }
+    }
+}
+
+/// Runs the actors of a node, restarting the ones that return an error or panic.
+pub struct Supervisor {
+    node_id: u64,
+    policy: RestartPolicy,
+    factories: HashMap<&'static str, ActorFactory>,
+    status: watch::Sender<SupervisorStatus>,
+    /// Time the restart window and backoffs are measured in.
+    clock: Arc<dyn Clock>,
+}
+
+impl Supervisor {
+    pub fn new(node_id: u64, policy: RestartPolicy) -> Self {
+        Self {
+            node_id,
+            policy,
+            factories: HashMap::new(),
+            status: watch::Sender::new(SupervisorStatus::default()),
+            clock: Arc::new(TokioClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Supervise the actor `name`, started by `run` when the supervisor is.
+    pub fn add(&mut self, name: &'static str, factory: ActorFactory) {
+        self.factories.insert(name, factory);
+    }
+
+    /// Status of the actors, kept up to date while the supervisor runs.
+    pub fn status(&self) -> watch::Receiver<SupervisorStatus> {
+        self.status.subscribe()
+    }
+
+    /// Start every actor, and restart them as they crash. Returns once they all
+    /// stopped on their own, or gave up on restarting.
+    #[tracing::instrument(skip_all, fields(node_id = self.node_id))]
+    pub async fn run(mut self) {
+        let mut tasks = JoinSet::new();
+        let mut names: HashMap<Id, &'static str> = HashMap::new();
+        let mut recent_restarts: HashMap<&'static str, VecDeque<Instant>> =
+            HashMap::new();
+
+        for (&name, factory) in &mut self.factories {
+            names.insert(tasks.spawn(factory()).id(), name);
+        }
+
+        while let Some(joined) = tasks.join_next_with_id().await {
+            let (id, failure) = match joined {
+                Ok((id, Ok(()))) => (id, None),
+                Ok((id, Err(e))) => (id, Some(format!("{e:#}"))),
+                Err(e) if e.is_panic() => (e.id(), Some("panicked".to_string())),
+                // Only a task aborted from the outside can be cancelled, which means
+                // the whole node is being torn down.
+                Err(e) => (e.id(), None),
+            };
+            let Some(name) = names.remove(&id) else {
+                continue;
+            };
+            let Some(failure) = failure else {
+                info!(actor = name, "actor stopped");
+                continue;
+            };
+
+            let restarts = recent_restarts.entry(name).or_default();
+            let now = self.clock.now();
+            while restarts
+                .front()
+                .is_some_and(|&at| now - at > Duration::from_secs(60))
+            {
+                restarts.pop_front();
+            }
+            if restarts.len() >= self.policy.max_restarts_per_minute {
+                error!(
+                    actor = name,
+                    "actor crashed: {failure}; restarted too often, giving up"
+                );
+                self.status.send_modify(|status| status.healthy = false);
+                continue;
+            }
+
+            let backoff = self
+                .policy
+                .initial_backoff
+                .saturating_mul(1 << restarts.len().min(16))
+                .min(self.policy.max_backoff);
+            restarts.push_back(now);
+            warn!(
+                actor = name,
+                ?backoff,
+                "actor crashed: {failure}; restarting"
+            );
+            self.status.send_modify(|status| {
+                *status.restarts.entry(name).or_default() += 1;
+            });
+
+            let run = self
+                .factories
+                .get_mut(name)
+                .expect("crashed actor has a factory")();
+            let clock = self.clock.clone();
+            let id = tasks
+                .spawn(async move {
+                    clock.sleep(backoff).await;
+                    run.await
+                })
+                .id();
+            names.insert(id, name);
+        }
+    }
+}
+
+/// Factory of an acceptor that survives restarts: every instance it builds answers
+/// through the same `transport`, and starts from the state the previous one left.
+///
+/// The state is checkpointed in `checkpoint` before every reply, so it survives a
+/// crash of the acceptor task. It is saved to `repository` as well, if any, to
+/// survive a crash of the process. The acceptors gossip their accepts if `gossip`
+/// is set, see `Acceptor::gossip`.
+pub fn acceptor_factory(
+    id: u64,
+    cluster: ClusterConfig,
+    transport: Box<dyn AcceptorTransport>,
+    checkpoint: Arc<Mutex<AcceptorState>>,
+    repository: Option<Arc<dyn AcceptorRepository<AcceptorState>>>,
+    gossip: bool,
+    shutdown: CancellationToken,
+) -> ActorFactory {
+    let transport = Arc::new(AsyncMutex::new(transport));
+
+    Box::new(move || {
+        let mut acceptor = Acceptor::new(
+            id,
+            cluster.clone(),
+            Box::new(SharedTransport(transport.clone())),
+        );
+        acceptor.state = checkpoint
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        acceptor.checkpoint = Some(checkpoint.clone());
+        acceptor.repository = repository.clone();
+        acceptor.gossip = gossip;
+
+        let stopped = shutdown.clone().cancelled_owned();
+        Box::pin(async move { acceptor.run_until(stopped).await })
+    })
+}
+
+/// Transport outliving the actors that use it, one at a time.
+struct SharedTransport(Arc<AsyncMutex<Box<dyn AcceptorTransport>>>);
+
+#[async_trait::async_trait]
+impl AcceptorTransport for SharedTransport {
+    async fn send(&self, to: u64, message: Message) -> Result<()> {
+        self.0.lock().await.send(to, message).await
+    }
+
+    async fn recv(&mut self) -> Option<Envelope> {
+        self.0.lock().await.recv().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        actors::proposer::Proposer,
+        domain::consensus::{ConsensusResult, ProposeOutcome},
+        repository::ValueRepositoryImpl,
+        transport::channel,
+    };
+
+    /// Policy restarting the actors without waiting.
+    const EAGER: RestartPolicy = RestartPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+        max_restarts_per_minute: 3,
+    };
+
+    /// Transport of an acceptor that panics on the first message it receives if
+    /// `armed`, as a bug in the acceptor would.
+    struct PanickingTransport {
+        inner: Box<dyn AcceptorTransport>,
+        armed: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl AcceptorTransport for PanickingTransport {
+        async fn send(&self, to: u64, message: Message) -> Result<()> {
+            self.inner.send(to, message).await
+        }
+
+        async fn recv(&mut self) -> Option<Envelope> {
+            let envelope = self.inner.recv().await;
+            if std::mem::take(&mut self.armed) {
+                panic!("injected acceptor panic");
+            }
+            envelope
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn crashing_actor_is_restarted_and_counted() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let mut supervisor = Supervisor::new(0, EAGER);
+        let counted = runs.clone();
+        supervisor.add(
+            "flaky",
+            Box::new(move || {
+                let run = counted.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async move {
+                    match run {
+                        0 => panic!("first run panics"),
+                        1 => anyhow::bail!("second run fails"),
+                        _ => Ok(()),
+                    }
+                })
+            }),
+        );
+        let status = supervisor.status();
+
+        supervisor.run().await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        let status = status.borrow().clone();
+        assert!(status.healthy);
+        assert_eq!(status.restarts.get("flaky"), Some(&2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn actor_crashing_too_often_makes_the_node_unhealthy() {
+        let mut supervisor = Supervisor::new(0, EAGER);
+        supervisor.add(
+            "broken",
+            Box::new(|| Box::pin(async { anyhow::bail!("always fails") })),
+        );
+        let status = supervisor.status();
+
+        supervisor.run().await;
+
+        let status = status.borrow().clone();
+        assert!(!status.healthy);
+        assert_eq!(
+            status.restarts.get("broken"),
+            Some(&EAGER.max_restarts_per_minute)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cluster_decides_after_an_acceptor_panicked() {
+        let cluster = ClusterConfig::in_process(3);
+        let (transport, mut acceptors) = channel::wire(&cluster, false);
+        // Acceptor 1 is down: every quorum needs acceptor 0 from now on.
+        acceptors.remove(1);
+        let shutdown = CancellationToken::new();
+        let mut statuses = Vec::new();
+        for channel in acceptors {
+            let id = channel.id;
+            let transport: Box<dyn AcceptorTransport> = Box::new(PanickingTransport {
+                inner: Box::new(channel),
+                armed: id == 0,
+            });
+            let mut supervisor = Supervisor::new(id, EAGER);
+            supervisor.add(
+                "acceptor",
+                acceptor_factory(
+                    id,
+                    cluster.clone(),
+                    transport,
+                    Arc::new(Mutex::new(AcceptorState::new(id))),
+                    None,
+                    false,
+                    shutdown.clone(),
+                ),
+            );
+            statuses.push(supervisor.status());
+            tokio::spawn(supervisor.run());
+        }
+        let (_client, client_receiver) = mpsc::channel(1);
+        let mut proposer = Proposer::new(
+            3,
+            cluster,
+            Box::new(transport),
+            client_receiver,
+            Arc::new(ValueRepositoryImpl::in_memory().unwrap()),
+        );
+
+        for value in [5, 6] {
+            let outcome = proposer.propose(value).await.unwrap();
+            assert!(
+                matches!(
+                    outcome,
+                    ProposeOutcome::Decided(ConsensusResult::Decided { proposal, .. })
+                        if proposal.value == value
+                ),
+                "{outcome:?}"
+            );
+        }
+        let status = statuses[0].borrow().clone();
+        assert!(status.healthy);
+        assert_eq!(status.restarts.get("acceptor"), Some(&1));
+        shutdown.cancel();
+    }
+}
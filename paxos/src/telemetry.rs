@@ -0,0 +1,280 @@
+//! Counters and gauges of the protocol, emitted through the `metrics` facade when
+//! the `metrics` feature is enabled. Without it, recording them does nothing.
+//!
+//! Every metric is labeled with the `node_id` of the node recording it. Counters
+//! are named the way Prometheus expects them, with a `_total` suffix.
+
+/// Prepare requests sent by a proposer, one per acceptor.
+pub const PREPARES_SENT: &str = "paxos_prepares_sent_total";
+/// Promises received by a proposer.
+pub const PROMISES_RECEIVED: &str = "paxos_promises_received_total";
+/// Rejections of prepare and accept requests received by a proposer.
+pub const NACKS_RECEIVED: &str = "paxos_nacks_received_total";
+/// Accept requests sent by a proposer, one per acceptor.
+pub const ACCEPTS_SENT: &str = "paxos_accepts_sent_total";
+/// Acceptances received by a proposer.
+pub const ACCEPT_RESPONSES_RECEIVED: &str = "paxos_accept_responses_received_total";
+/// Responses of any kind received by a proposer, also labeled with the `acceptor`
+/// that sent them.
+pub const ACCEPTOR_RESPONSES: &str = "paxos_acceptor_responses_total";
+/// Rounds started by a proposer, retries included.
+pub const ROUNDS_STARTED: &str = "paxos_rounds_started_total";
+/// Rounds that ended with their slot decided.
+pub const ROUNDS_DECIDED: &str = "paxos_rounds_decided_total";
+/// Rounds that ended without a decision, to be retried or given up on.
+pub const ROUNDS_ABANDONED: &str = "paxos_rounds_abandoned_total";
+/// Values of clients taken off the queue of a proposer.
+pub const VALUES_QUEUED: &str = "paxos_values_queued_total";
+/// Gauge of the values of clients waiting in the queue of a proposer.
+pub const QUEUE_DEPTH: &str = "paxos_queue_depth";
+/// Histogram of the time, in seconds, from the broadcast of the prepare requests of
+/// a round to a quorum of promises.
+pub const PREPARE_LATENCY: &str = "paxos_prepare_latency_seconds";
+/// Histogram of the time, in seconds, from the broadcast of the accept requests of
+/// a round to its decision.
+pub const ACCEPT_LATENCY: &str = "paxos_accept_latency_seconds";
+/// Histogram of the time, in seconds, from the moment a proposer takes a value to
+/// the decision of its slot, retries included.
+pub const ROUND_LATENCY: &str = "paxos_round_latency_seconds";
+/// Histogram of the requests broadcast again for the value of each decided slot:
+/// prepare requests of retried rounds, and accept requests sent more than once.
+pub const ROUND_RETRANSMISSIONS: &str = "paxos_round_retransmissions";
+/// Promises made by an acceptor.
+pub const PROMISES_SENT: &str = "paxos_promises_sent_total";
+/// Proposals accepted by an acceptor.
+pub const PROPOSALS_ACCEPTED: &str = "paxos_proposals_accepted_total";
+/// Prepare and accept requests rejected by an acceptor.
+pub const REQUESTS_REJECTED: &str = "paxos_requests_rejected_total";
+/// Prepare requests dropped by an acceptor for exceeding its rate limit.
+pub const PREPARES_THROTTLED: &str = "paxos_prepares_throttled_total";
+/// Slots learned as decided with another value than the one they were decided
+/// with first.
+pub const CONSENSUS_VIOLATIONS: &str = "paxos_consensus_violations_total";
+
+pub(crate) fn count(name: &'static str, node_id: u64, count: u64) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(name, "node_id" => node_id.to_string()).increment(count);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (name, node_id, count);
+}
+
+/// Count a response of `acceptor` received by the node `node_id`.
+pub(crate) fn count_response(node_id: u64, acceptor: u64) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(
+        ACCEPTOR_RESPONSES,
+        "node_id" => node_id.to_string(),
+        "acceptor" => acceptor.to_string()
+    )
+    .increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (node_id, acceptor);
+}
+
+pub(crate) fn histogram(name: &'static str, node_id: u64, value: f64) {
+    #[cfg(feature = "metrics")]
+    metrics::histogram!(name, "node_id" => node_id.to_string()).record(value);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (name, node_id, value);
+}
+
+pub(crate) fn gauge(name: &'static str, node_id: u64, value: f64) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!(name, "node_id" => node_id.to_string()).set(value);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (name, node_id, value);
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use metrics_util::{
+        debugging::{DebugValue, DebuggingRecorder, Snapshotter},
+        CompositeKey,
+    };
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        actors::{acceptor::Acceptor, proposer::Proposer},
+        clock::ManualClock,
+        config::ClusterConfig,
+        domain::{acceptor::AcceptorState, message::Message},
+        repository::ValueRepositoryImpl,
+        transport::{channel, AcceptorTransport},
+    };
+
+    /// Value of the counter `name` with exactly `labels`, 0 if it was never
+    /// incremented.
+    fn counter(snapshotter: &Snapshotter, name: &str, labels: &[(&str, &str)]) -> u64 {
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .find_map(|(key, _, _, value)| {
+                let key = key.key();
+                let matches = key.name() == name
+                    && key.labels().count() == labels.len()
+                    && labels.iter().all(|&(label, expected)| {
+                        key.labels()
+                            .any(|l| l.key() == label && l.value() == expected)
+                    });
+                match value {
+                    DebugValue::Counter(count) if matches => Some(count),
+                    _ => None,
+                }
+            })
+            .unwrap_or(0)
+    }
+
+    /// Metric of a snapshot, as listed by `Snapshot::into_vec`.
+    type Sample = (
+        CompositeKey,
+        Option<metrics::Unit>,
+        Option<metrics::SharedString>,
+        DebugValue,
+    );
+
+    /// Values of the histogram `name` of the node `node_id` in `snapshot`. Taking
+    /// a snapshot drains the histograms: they have to be read from a single one.
+    fn histogram(snapshot: &[Sample], name: &str, node_id: &str) -> Vec<f64> {
+        snapshot
+            .iter()
+            .find_map(|(key, _, _, value)| {
+                let key = key.key();
+                let matches = key.name() == name
+                    && key
+                        .labels()
+                        .any(|l| l.key() == "node_id" && l.value() == node_id);
+                match value {
+                    DebugValue::Histogram(values) if matches => {
+                        Some(values.iter().map(|v| v.into_inner()).collect())
+                    }
+                    _ => None,
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn one_round_counts_every_message_exactly() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        metrics::with_local_recorder(&recorder, || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    let cluster = ClusterConfig::in_process(3);
+                    let (transport, mut acceptors) = channel::wire(&cluster, false);
+                    // Acceptor 2 never answers: the round needs the other two.
+                    let _silent = acceptors.pop();
+                    for channel in acceptors {
+                        let mut acceptor = Acceptor::new(
+                            channel.id,
+                            cluster.clone(),
+                            Box::new(channel),
+                        );
+                        tokio::spawn(async move { acceptor.run().await });
+                    }
+                    let (_client, client_receiver) = mpsc::channel(1);
+                    let mut proposer = Proposer::new(
+                        3,
+                        cluster,
+                        Box::new(transport),
+                        client_receiver,
+                        Arc::new(ValueRepositoryImpl::in_memory().unwrap()),
+                    );
+                    proposer.propose(7).await.unwrap();
+                })
+        });
+
+        let proposer = [("node_id", "3")];
+        for (name, expected) in [
+            (ROUNDS_STARTED, 1),
+            (PREPARES_SENT, 3),
+            (PROMISES_RECEIVED, 2),
+            (ACCEPTS_SENT, 3),
+            (ACCEPT_RESPONSES_RECEIVED, 2),
+            (NACKS_RECEIVED, 0),
+            (ROUNDS_DECIDED, 1),
+            (ROUNDS_ABANDONED, 0),
+        ] {
+            assert_eq!(counter(&snapshotter, name, &proposer), expected, "{name}");
+        }
+        for acceptor in ["0", "1"] {
+            let labels = [("node_id", "3"), ("acceptor", acceptor)];
+            assert_eq!(counter(&snapshotter, ACCEPTOR_RESPONSES, &labels), 2);
+            let labels = [("node_id", acceptor)];
+            assert_eq!(counter(&snapshotter, PROMISES_SENT, &labels), 1);
+            assert_eq!(counter(&snapshotter, PROPOSALS_ACCEPTED, &labels), 1);
+            assert_eq!(counter(&snapshotter, REQUESTS_REJECTED, &labels), 0);
+        }
+    }
+
+    #[test]
+    fn latencies_of_a_round_are_recorded_on_the_clock_of_the_proposer() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        metrics::with_local_recorder(&recorder, || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    let clock = Arc::new(ManualClock::new());
+                    let cluster = ClusterConfig::in_process(3);
+                    let (transport, mut acceptors) = channel::wire(&cluster, false);
+                    // Acceptor 2 never answers: the round needs acceptor 0, which
+                    // takes 30ms to promise and 20ms to accept.
+                    let _silent = acceptors.pop();
+                    for mut channel in acceptors {
+                        let clock = clock.clone();
+                        tokio::spawn(async move {
+                            let mut state = AcceptorState::new(channel.id);
+                            while let Some(envelope) = channel.recv().await {
+                                let delay = match envelope.message {
+                                    Message::PrepareRequest { .. } => 30,
+                                    Message::AcceptRequest { .. } => 20,
+                                    _ => 0,
+                                };
+                                if channel.id == 0 {
+                                    clock.advance(Duration::from_millis(delay));
+                                }
+                                if let Some(reply) = state.handle(envelope.message) {
+                                    let _ = channel.send(envelope.from, reply).await;
+                                }
+                            }
+                        });
+                    }
+                    let (_client, client_receiver) = mpsc::channel(1);
+                    let mut proposer = Proposer::new(
+                        3,
+                        cluster,
+                        Box::new(transport),
+                        client_receiver,
+                        Arc::new(ValueRepositoryImpl::in_memory().unwrap()),
+                    );
+                    proposer.clock = clock;
+                    proposer.run_round(7).await.unwrap();
+                    assert_eq!(proposer.timings, None);
+                })
+        });
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        for (name, expected) in [
+            (PREPARE_LATENCY, 0.03),
+            (ACCEPT_LATENCY, 0.02),
+            (ROUND_LATENCY, 0.05),
+            (ROUND_RETRANSMISSIONS, 0.0),
+        ] {
+            let values = histogram(&snapshot, name, "3");
+            assert_eq!(values.len(), 1, "{name}: {values:?}");
+            assert!((values[0] - expected).abs() < 1e-9, "{name}: {values:?}");
+        }
+    }
+}
@@ -0,0 +1,1388 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::{
+    sync::{broadcast, mpsc, watch, Semaphore},
+    task::JoinHandle,
+    time::{timeout_at, Instant},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    actors::{
+        learner::{Decision, Learner},
+        proposer::{Deadlines, Proposer},
+    },
+    client::{Client, ProposeError, DEFAULT_MAX_IN_FLIGHT_ROUNDS},
+    clock::{Clock, TokioClock},
+    config::{ClusterConfig, PeerConfig, Role, Timeouts},
+    domain::{
+        acceptor::AcceptorState,
+        command::{Command, Membership, MAX_ACCEPTOR_ID},
+        consensus::{ClientOutcome, ConsensusResult},
+        event::PaxosEvent,
+        message::Message,
+    },
+    node::SHUTDOWN_GRACE,
+    rate_limit::RateLimit,
+    repository::ValueRepositoryImpl,
+    retry::RetryPolicy,
+    supervisor::{self, RestartPolicy, Supervisor, SupervisorStatus},
+    transport::{channel, sim::SimNetwork, AcceptorTransport, Envelope, Transport},
+};
+
+/// How the nodes of a test cluster talk to each other.
+#[derive(Clone)]
+pub enum ClusterTransport {
+    /// In-process channels, as wired by `channel::wire`. Only supports a single
+    /// proposer, and nodes can not be restarted.
+    Channels { split_phases: bool },
+    /// Simulated network, whose links can be degraded or partitioned by the test.
+    Sim(SimNetwork),
+}
+
+/// Where the proposers of a test cluster keep their state.
+#[derive(Debug, Clone, Default)]
+pub enum Storage {
+    #[default]
+    InMemory,
+    /// One sqlite database per proposer, in the given directory.
+    Directory(PathBuf),
+}
+
+/// Describe a cluster running in the current process, then spawn it with `build`.
+/// Acceptors get the ids `0..acceptors` and proposers the ids right after them.
+pub struct ClusterBuilder {
+    acceptors: usize,
+    proposers: usize,
+    quorum: Option<usize>,
+    prepare_quorum: Option<usize>,
+    accept_quorum: Option<usize>,
+    transport: ClusterTransport,
+    storage: Storage,
+    joint_consensus: bool,
+    max_in_flight_rounds: usize,
+    clock: Arc<dyn Clock>,
+    retry: RetryPolicy,
+    prepare_rate_limit: Option<RateLimit>,
+    durable_acceptors: bool,
+    gossip: bool,
+}
+
+impl Default for ClusterBuilder {
+    fn default() -> Self {
+        Self {
+            acceptors: 3,
+            proposers: 1,
+            quorum: None,
+            prepare_quorum: None,
+            accept_quorum: None,
+            transport: ClusterTransport::Channels {
+                split_phases: false,
+            },
+            storage: Storage::InMemory,
+            joint_consensus: false,
+            max_in_flight_rounds: DEFAULT_MAX_IN_FLIGHT_ROUNDS,
+            clock: Arc::new(TokioClock),
+            retry: RetryPolicy::default(),
+            prepare_rate_limit: None,
+            durable_acceptors: false,
+            gossip: false,
+        }
+    }
+}
+
+impl ClusterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn acceptors(mut self, acceptors: usize) -> Self {
+        self.acceptors = acceptors;
+        self
+    }
+
+    pub fn proposers(mut self, proposers: usize) -> Self {
+        self.proposers = proposers;
+        self
+    }
+
+    /// Override the size of the quorum, which must still be a majority.
+    pub fn quorum(mut self, quorum: usize) -> Self {
+        self.quorum = Some(quorum);
+        self
+    }
+
+    /// Use quorums of `prepare` acceptors to promise a ballot and of `accept` to
+    /// choose a value. Their sizes must add up to more than the number of acceptors.
+    pub fn flexible_quorums(mut self, prepare: usize, accept: usize) -> Self {
+        self.prepare_quorum = Some(prepare);
+        self.accept_quorum = Some(accept);
+        self
+    }
+
+    pub fn transport(mut self, transport: ClusterTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Make the proposers go through joint consensus when the membership changes,
+    /// see `Proposer::joint_consensus`.
+    pub fn joint_consensus(mut self, joint_consensus: bool) -> Self {
+        self.joint_consensus = joint_consensus;
+        self
+    }
+
+    /// Number of values the clients of a proposer may have in flight before
+    /// `Client::submit` waits for earlier ones to be decided.
+    pub fn max_in_flight_rounds(mut self, max_in_flight_rounds: usize) -> Self {
+        self.max_in_flight_rounds = max_in_flight_rounds;
+        self
+    }
+
+    /// Measure the timeouts of the proposers and the restart backoffs of the
+    /// acceptors in `clock`, e.g. a `ManualClock` to step through them.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Retry the failed rounds of the proposers under `retry`.
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Have the acceptors drop the prepare requests of a proposer beyond `limit`,
+    /// see `ClusterConfig::prepare_rate_limit`.
+    pub fn prepare_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.prepare_rate_limit = Some(limit);
+        self
+    }
+
+    /// Have restarted acceptors resume from the state they had when they stopped,
+    /// as if it was kept on disk, rather than start from scratch.
+    pub fn durable_acceptors(mut self, durable: bool) -> Self {
+        self.durable_acceptors = durable;
+        self
+    }
+
+    /// Have the acceptors gossip their accepts to each other, so that each of them
+    /// learns the decisions on its own, see `Acceptor::gossip`.
+    pub fn gossip(mut self, gossip: bool) -> Self {
+        self.gossip = gossip;
+        self
+    }
+
+    /// Check the description of the cluster and spawn its nodes. Must be called
+    /// from within a tokio runtime.
+    pub fn build(self) -> Result<Cluster> {
+        if self.acceptors == 0 {
+            bail!("a cluster needs at least one acceptor");
+        }
+        if self.proposers == 0 {
+            bail!("a cluster needs at least one proposer");
+        }
+
+        let acceptors =
+            (0..self.acceptors as u64).map(|node_id| (node_id, Role::Acceptor));
+        let proposers = (self.acceptors as u64
+            ..(self.acceptors + self.proposers) as u64)
+            .map(|node_id| (node_id, Role::Proposer));
+        let config = ClusterConfig {
+            peers: acceptors
+                .chain(proposers)
+                .map(|(node_id, role)| PeerConfig {
+                    node_id,
+                    roles: vec![role],
+                    address: None,
+                    priority: 0,
+                })
+                .collect(),
+            quorum: self.quorum,
+            prepare_quorum: self.prepare_quorum,
+            accept_quorum: self.accept_quorum,
+            retry: self.retry,
+            log_values: true,
+            prepare_rate_limit: self.prepare_rate_limit,
+            timeouts: Timeouts::default(),
+        };
+        config.validate(0)?;
+
+        let mut cluster = Cluster {
+            config: config.clone(),
+            network: None,
+            storage: self.storage,
+            joint_consensus: self.joint_consensus,
+            max_in_flight_rounds: self.max_in_flight_rounds,
+            durable_acceptors: self.durable_acceptors,
+            gossip: self.gossip,
+            clock: self.clock,
+            repositories: HashMap::new(),
+            clients: HashMap::new(),
+            commands: HashMap::new(),
+            outcomes: HashMap::new(),
+            events: HashMap::new(),
+            admissions: HashMap::new(),
+            deadlines: HashMap::new(),
+            learners: HashMap::new(),
+            statuses: HashMap::new(),
+            checkpoints: HashMap::new(),
+            membership: watch::Sender::new(config.clone()),
+            epoch: 0,
+            tasks: HashMap::new(),
+            stop_proposers: CancellationToken::new(),
+            stop_acceptors: CancellationToken::new(),
+        };
+
+        match self.transport {
+            ClusterTransport::Channels { split_phases } => {
+                if self.proposers > 1 {
+                    bail!("in-process channels only support a single proposer");
+                }
+
+                let (proposer, acceptors) = channel::wire(&config, split_phases);
+                cluster.spawn_proposer(proposer.id, Box::new(proposer))?;
+                for acceptor in acceptors {
+                    cluster.spawn_acceptor(acceptor.id, Box::new(acceptor));
+                }
+            }
+            ClusterTransport::Sim(network) => {
+                cluster.network = Some(network);
+                for peer in &config.peers {
+                    cluster.restart_node(peer.node_id)?;
+                }
+            }
+        }
+
+        Ok(cluster)
+    }
+}
+
+/// Nodes of a cluster running in the current process. Dropping it stops every node.
+pub struct Cluster {
+    pub config: ClusterConfig,
+    network: Option<SimNetwork>,
+    storage: Storage,
+    joint_consensus: bool,
+    max_in_flight_rounds: usize,
+    /// Whether restarted acceptors resume from their latest checkpoint.
+    durable_acceptors: bool,
+    /// Whether the acceptors gossip their accepts.
+    gossip: bool,
+    clock: Arc<dyn Clock>,
+    /// State of each proposer, kept across restarts.
+    repositories: HashMap<u64, Arc<ValueRepositoryImpl>>,
+    clients: HashMap<u64, mpsc::Sender<u64>>,
+    /// Submit commands of the cluster to each proposer.
+    commands: HashMap<u64, mpsc::Sender<Command>>,
+    outcomes: HashMap<u64, broadcast::Sender<ClientOutcome>>,
+    events: HashMap<u64, broadcast::Sender<PaxosEvent>>,
+    /// Permits shared by the clients of each proposer.
+    admissions: HashMap<u64, Arc<Semaphore>>,
+    deadlines: HashMap<u64, Deadlines>,
+    learners: HashMap<u64, Learner>,
+    /// Status of the supervisor of each acceptor.
+    statuses: HashMap<u64, watch::Receiver<SupervisorStatus>>,
+    /// Latest state of each acceptor.
+    checkpoints: HashMap<u64, Arc<Mutex<AcceptorState>>>,
+    /// Publishes the config to the proposers every time acceptors are added or
+    /// removed.
+    membership: watch::Sender<ClusterConfig>,
+    /// Number of membership changes so far. Proposers started afterwards begin in
+    /// this epoch, so that acceptors do not take their messages for stale ones.
+    epoch: u64,
+    tasks: HashMap<u64, JoinHandle<()>>,
+    /// Cancelled first on shutdown, to stop the proposers.
+    stop_proposers: CancellationToken,
+    /// Cancelled once the proposers are done deciding the values they drained.
+    stop_acceptors: CancellationToken,
+}
+
+impl Cluster {
+    /// Submit values to the proposer `proposer_id`.
+    pub fn client(&self, proposer_id: u64) -> Option<mpsc::Sender<u64>> {
+        self.clients.get(&proposer_id).cloned()
+    }
+
+    /// Client of the proposer `proposer_id`, which can wait for the outcome of the
+    /// values it submits.
+    pub fn proposer_client(&self, proposer_id: u64) -> Option<Client> {
+        let values = self.clients.get(&proposer_id)?.clone();
+        let outcomes = self.outcomes.get(&proposer_id)?.clone();
+        let admission = self.admissions.get(&proposer_id)?.clone();
+        let deadlines = self.deadlines.get(&proposer_id)?.clone();
+        Some(Client::new(values, outcomes, admission, deadlines))
+    }
+
+    /// Steps of the protocol taken by the proposer `proposer_id` from now on. A
+    /// restarted proposer publishes to a new stream.
+    pub fn events(&self, proposer_id: u64) -> Option<broadcast::Receiver<PaxosEvent>> {
+        self.events
+            .get(&proposer_id)
+            .map(|events| events.subscribe())
+    }
+
+    /// Learner of the proposer `proposer_id`. A restarted proposer starts with an
+    /// empty learner.
+    pub fn learner(&self, proposer_id: u64) -> Option<&Learner> {
+        self.learners.get(&proposer_id)
+    }
+
+    /// Values the acceptor `acceptor_id` learned were decided for each slot, as of
+    /// its latest checkpoint. Only learned when the acceptors gossip, see
+    /// `ClusterBuilder::gossip`.
+    pub fn acceptor_decisions(&self, acceptor_id: u64) -> Option<BTreeMap<u64, u64>> {
+        let checkpoint = self.checkpoints.get(&acceptor_id)?;
+        let state = checkpoint
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        Some(
+            state
+                .decided
+                .iter()
+                .map(|(&slot, proposal)| (slot, proposal.value))
+                .collect(),
+        )
+    }
+
+    /// Health and restart counts of the acceptor `acceptor_id`. A restarted node
+    /// starts with a fresh status.
+    pub fn status(&self, acceptor_id: u64) -> Option<SupervisorStatus> {
+        self.statuses
+            .get(&acceptor_id)
+            .map(|status| status.borrow().clone())
+    }
+
+    /// Stop the node `node_id`, as if its process died.
+    pub fn crash_node(&mut self, node_id: u64) -> Result<()> {
+        let task = self
+            .tasks
+            .remove(&node_id)
+            .with_context(|| format!("node {node_id} is not running"))?;
+        task.abort();
+        Ok(())
+    }
+
+    /// Freeze the node `node_id` without losing its state, as if its process was
+    /// stopped: the messages it exchanges are held until it is resumed. Only for
+    /// nodes of a simulated network.
+    pub fn pause_node(&mut self, node_id: u64) -> Result<()> {
+        let Some(network) = &self.network else {
+            bail!("only nodes of a simulated network can be paused");
+        };
+        if !self.tasks.contains_key(&node_id) {
+            bail!("node {node_id} is not running");
+        }
+        network.pause(node_id);
+        Ok(())
+    }
+
+    /// Let the node `node_id` run again after `pause_node`, delivering the messages
+    /// it exchanged in the meantime.
+    pub fn resume_node(&mut self, node_id: u64) -> Result<()> {
+        let Some(network) = &self.network else {
+            bail!("only nodes of a simulated network can be paused");
+        };
+        network.resume(node_id);
+        Ok(())
+    }
+
+    /// Start the node `node_id` again, stopping it first if it is running. A
+    /// proposer resumes from its storage; an acceptor starts from scratch, unless
+    /// the acceptors are durable.
+    pub fn restart_node(&mut self, node_id: u64) -> Result<()> {
+        let Some(network) = &self.network else {
+            bail!("only nodes of a simulated network can be restarted");
+        };
+        let peer = self
+            .config
+            .peer(node_id)
+            .with_context(|| format!("node {node_id} is not part of the cluster"))?;
+        let roles = peer.roles.clone();
+
+        if let Some(task) = self.tasks.remove(&node_id) {
+            task.abort();
+        }
+
+        let transport = network.join(node_id, &roles);
+        if roles.contains(&Role::Proposer) {
+            self.spawn_proposer(node_id, Box::new(transport))?;
+        } else {
+            self.spawn_acceptor(node_id, Box::new(transport));
+        }
+        Ok(())
+    }
+
+    /// Add the acceptor `node_id` to a cluster on a simulated network. The acceptor
+    /// joins the network first, then the proposers switch to the new membership:
+    /// quorums grow with it, and rounds in flight start over.
+    pub fn add_acceptor(&mut self, node_id: u64) -> Result<()> {
+        if self.network.is_none() {
+            bail!("only clusters on a simulated network can change membership");
+        }
+        if self.config.peer(node_id).is_some() {
+            bail!("node {node_id} is already part of the cluster");
+        }
+
+        let mut config = self.config.clone();
+        config.peers.push(PeerConfig {
+            node_id,
+            roles: vec![Role::Acceptor],
+            address: None,
+            priority: 0,
+        });
+        config.validate(node_id)?;
+
+        self.config = config;
+        self.restart_node(node_id)?;
+        self.publish_membership();
+        Ok(())
+    }
+
+    /// Remove the acceptor `node_id` from a cluster on a simulated network. The
+    /// proposers switch to the new membership first, then the acceptor is stopped
+    /// and leaves the network.
+    ///
+    /// Removal is refused while the acceptor holds a value accepted for a slot no
+    /// proposer has learned the decision of: it may be the only copy of a value
+    /// that was chosen, which would then be lost.
+    pub fn remove_acceptor(&mut self, node_id: u64) -> Result<()> {
+        let Some(network) = self.network.clone() else {
+            bail!("only clusters on a simulated network can change membership");
+        };
+        let peer = self
+            .config
+            .peer(node_id)
+            .with_context(|| format!("node {node_id} is not part of the cluster"))?;
+        if !peer.has_role(Role::Acceptor) || peer.has_role(Role::Proposer) {
+            bail!("node {node_id} is not a plain acceptor");
+        }
+        if self.config.acceptor_count() == 1 {
+            bail!("can not remove the last acceptor of the cluster");
+        }
+
+        let accepted = self
+            .checkpoints
+            .get(&node_id)
+            .map(|state| {
+                state
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .accepted
+                    .clone()
+            })
+            .unwrap_or_default();
+        for (slot, proposal) in accepted {
+            let decided = self
+                .learners
+                .values()
+                .any(|learner| learner.decision(slot).is_some());
+            if !decided {
+                bail!(
+                    "acceptor {node_id} accepted value {} for slot {slot}, which is \
+                     not decided yet",
+                    proposal.value
+                );
+            }
+        }
+
+        let mut config = self.config.clone();
+        config.peers.retain(|peer| peer.node_id != node_id);
+        if let Some(proposer_id) = config.proposer_id() {
+            config.validate(proposer_id)?;
+        }
+
+        self.config = config;
+        self.publish_membership();
+        self.unplug(&network, node_id);
+        Ok(())
+    }
+
+    /// Change the acceptors of a cluster on a simulated network through consensus:
+    /// the change is proposed by `proposer_id` like any value, and the proposers
+    /// that learn its decision switch to the new membership from the next slot on.
+    /// Returns the slot the change was decided for.
+    ///
+    /// New acceptors are started before the change is proposed, so that the
+    /// quorums of the next slots can be reached. Acceptors that are left out are
+    /// only stopped once it is decided, and committed with joint consensus.
+    pub async fn change_membership(
+        &mut self,
+        proposer_id: u64,
+        acceptors: impl IntoIterator<Item = u64>,
+        deadline: Duration,
+    ) -> Result<u64> {
+        let Some(network) = self.network.clone() else {
+            bail!("only clusters on a simulated network can change membership");
+        };
+        let membership = Membership::new(acceptors).with_context(|| {
+            format!("a membership needs acceptors, with ids up to {MAX_ACCEPTOR_ID}")
+        })?;
+        let client = self
+            .proposer_client(proposer_id)
+            .zip(self.commands.get(&proposer_id).cloned())
+            .map(|(client, commands)| client.with_commands(commands))
+            .with_context(|| format!("node {proposer_id} is not a proposer"))?;
+
+        let joining: Vec<u64> = membership
+            .acceptors()
+            .iter()
+            .copied()
+            .filter(|&node_id| self.config.peer(node_id).is_none())
+            .collect();
+        let leaving: Vec<u64> = self
+            .config
+            .acceptors()
+            .map(|peer| peer.node_id)
+            .filter(|node_id| !membership.acceptors().contains(node_id))
+            .collect();
+        let config = self.config.with_acceptors(membership.acceptors());
+        config.validate(proposer_id)?;
+
+        let previous = std::mem::replace(&mut self.config, config);
+        for &node_id in &joining {
+            self.restart_node(node_id)?;
+        }
+
+        let command = Command::ConfigChange(membership);
+        let value = command.encode();
+        let decided = match client.propose_command_with_timeout(command, deadline).await
+        {
+            Ok(ConsensusResult::Decided { slot, proposal, .. })
+                if proposal.value == value =>
+            {
+                Ok(slot)
+            }
+            Ok(result) => Err(anyhow!("config change not decided: {result:?}")),
+            Err(e) => Err(anyhow!("config change not decided: {e}")),
+        };
+        // Once the change is decided, the acceptors left out are stopped. If it is
+        // not, the ones started for it are.
+        let stopping = match decided {
+            Ok(_) => leaving,
+            Err(_) => {
+                self.config = previous;
+                joining
+            }
+        };
+        for node_id in stopping {
+            self.unplug(&network, node_id);
+        }
+
+        let slot = decided?;
+        info!(slot, "membership changed through consensus");
+        Ok(slot)
+    }
+
+    /// Stop the node `node_id` and remove it from `network`.
+    fn unplug(&mut self, network: &SimNetwork, node_id: u64) {
+        if let Some(task) = self.tasks.remove(&node_id) {
+            task.abort();
+        }
+        network.leave(node_id);
+        self.statuses.remove(&node_id);
+        self.checkpoints.remove(&node_id);
+    }
+
+    fn publish_membership(&mut self) {
+        self.epoch += 1;
+        info!(
+            epoch = self.epoch,
+            acceptors = self.config.acceptor_count(),
+            "cluster membership changed"
+        );
+        self.membership.send_replace(self.config.clone());
+    }
+
+    /// Stop every node of the cluster: the proposers first, giving them a chance to
+    /// decide the values already submitted, then the acceptors. Nodes that do not
+    /// stop in time are aborted, and reported in the error.
+    pub async fn shutdown(mut self) -> Result<()> {
+        let (proposers, acceptors): (Vec<_>, Vec<_>) = std::mem::take(&mut self.tasks)
+            .into_iter()
+            .partition(|(node_id, _)| {
+                self.config
+                    .peer(*node_id)
+                    .is_some_and(|peer| peer.has_role(Role::Proposer))
+            });
+
+        let mut stuck = Vec::new();
+        self.stop_proposers.cancel();
+        // A round in flight is not interrupted by the cancellation: leave it some
+        // time on top of the grace period.
+        stuck.extend(join_all(proposers, SHUTDOWN_GRACE * 2).await);
+        self.stop_acceptors.cancel();
+        stuck.extend(join_all(acceptors, SHUTDOWN_GRACE).await);
+
+        if !stuck.is_empty() {
+            stuck.sort();
+            bail!("nodes {stuck:?} did not stop in time");
+        }
+        Ok(())
+    }
+
+    fn spawn_proposer(
+        &mut self,
+        node_id: u64,
+        transport: Box<dyn Transport>,
+    ) -> Result<()> {
+        let repository = match self.repositories.get(&node_id) {
+            Some(repository) => repository.clone(),
+            None => {
+                let repository = Arc::new(match &self.storage {
+                    Storage::InMemory => ValueRepositoryImpl::in_memory()?,
+                    Storage::Directory(directory) => ValueRepositoryImpl::open(
+                        directory.join(format!("proposer-{node_id}.sqlite")),
+                    )?,
+                });
+                self.repositories.insert(node_id, repository.clone());
+                repository
+            }
+        };
+
+        let (client, client_receiver) = mpsc::channel(self.config.acceptor_count());
+        let mut proposer = Proposer::new(
+            node_id,
+            self.config.clone(),
+            transport,
+            client_receiver,
+            repository,
+        );
+        proposer.epoch = self.epoch;
+        proposer.clock = self.clock.clone();
+        proposer.joint_consensus = self.joint_consensus;
+        proposer.membership = Some(self.membership.subscribe());
+        self.clients.insert(node_id, client);
+        self.commands.insert(node_id, proposer.commands.clone());
+        self.outcomes.insert(node_id, proposer.outcomes.clone());
+        self.events.insert(node_id, proposer.events.clone());
+        self.deadlines.insert(node_id, proposer.deadlines.clone());
+        self.admissions
+            .insert(node_id, Arc::new(Semaphore::new(self.max_in_flight_rounds)));
+        self.learners.insert(node_id, proposer.learner.clone());
+        let stopped = self.stop_proposers.clone().cancelled_owned();
+        self.tasks.insert(
+            node_id,
+            tokio::spawn(async move {
+                proposer
+                    .run_until(stopped, SHUTDOWN_GRACE)
+                    .await
+                    .expect("could not run proposer");
+            }),
+        );
+        Ok(())
+    }
+
+    /// Spawn a supervised acceptor. Crashing the node aborts its supervisor along
+    /// with it.
+    fn spawn_acceptor(&mut self, node_id: u64, transport: Box<dyn AcceptorTransport>) {
+        let checkpoint = match self.checkpoints.get(&node_id) {
+            Some(checkpoint) if self.durable_acceptors => checkpoint.clone(),
+            _ => Arc::new(Mutex::new(AcceptorState::new(node_id))),
+        };
+        let mut supervisor = Supervisor::new(node_id, RestartPolicy::default())
+            .with_clock(self.clock.clone());
+        supervisor.add(
+            "acceptor",
+            supervisor::acceptor_factory(
+                node_id,
+                self.config.clone(),
+                transport,
+                checkpoint.clone(),
+                None,
+                self.gossip,
+                self.stop_acceptors.clone(),
+            ),
+        );
+        self.statuses.insert(node_id, supervisor.status());
+        self.checkpoints.insert(node_id, checkpoint);
+        self.tasks.insert(node_id, tokio::spawn(supervisor.run()));
+    }
+}
+
+/// Wait for the tasks of the nodes to finish, all of them within `grace`. Returns
+/// the nodes whose task had to be aborted.
+async fn join_all(tasks: Vec<(u64, JoinHandle<()>)>, grace: Duration) -> Vec<u64> {
+    let deadline = Instant::now() + grace;
+    let mut stuck = Vec::new();
+    for (node_id, task) in tasks {
+        let abort = task.abort_handle();
+        match timeout_at(deadline, task).await {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => warn!(node_id, "node stopped abnormally: {e}"),
+            Err(_) => {
+                abort.abort();
+                stuck.push(node_id);
+            }
+        }
+    }
+    stuck
+}
+
+impl Drop for Cluster {
+    fn drop(&mut self) {
+        for task in self.tasks.values() {
+            task.abort();
+        }
+    }
+}
+
+/// Seed of the simulated network of the clusters spawned by `spawn_cluster`.
+pub const SPAWN_CLUSTER_SEED: u64 = 42;
+
+/// How long the helpers of a `ClusterHandle` wait for a decision.
+const DECISION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Handles to a cluster of a single proposer running in the current process.
+/// Dropping it stops every node of the cluster.
+pub struct ClusterHandle {
+    /// Submit values to the proposer.
+    pub client: mpsc::Sender<u64>,
+    /// Decisions made by the cluster, from the moment it was spawned.
+    pub decisions: broadcast::Receiver<Decision>,
+    /// Learner of the proposer, to look up or wait for the decision of a slot.
+    pub learner: Learner,
+    pub cluster: ClusterConfig,
+    proposer_id: u64,
+    nodes: Cluster,
+}
+
+impl ClusterHandle {
+    /// Submit `value` to the proposer, and wait for its outcome.
+    pub async fn propose(&self, value: u64) -> Result<ConsensusResult, ProposeError> {
+        self.nodes
+            .proposer_client(self.proposer_id)
+            .expect("proposer has no client")
+            .propose_with_timeout(value, DECISION_TIMEOUT)
+            .await
+    }
+
+    /// Value of the next decision of the cluster not read yet from `decisions`,
+    /// waiting for it if needed. Decisions are read in the order they were made,
+    /// starting from the first one.
+    pub async fn decided_value(&mut self) -> Result<u64> {
+        let decision = tokio::time::timeout(DECISION_TIMEOUT, self.decisions.recv())
+            .await
+            .context("no decision in time")??;
+        Ok(decision.proposal.value)
+    }
+
+    /// Stop the acceptor `acceptor_id`, as if its process died.
+    pub fn crash_acceptor(&mut self, acceptor_id: u64) -> Result<()> {
+        self.nodes.crash_node(acceptor_id)
+    }
+
+    /// Freeze the acceptor `acceptor_id` until `resume_acceptor`, see
+    /// `Cluster::pause_node`.
+    pub fn pause_acceptor(&mut self, acceptor_id: u64) -> Result<()> {
+        self.nodes.pause_node(acceptor_id)
+    }
+
+    pub fn resume_acceptor(&mut self, acceptor_id: u64) -> Result<()> {
+        self.nodes.resume_node(acceptor_id)
+    }
+
+    /// Steps of the protocol taken by the proposer from now on.
+    pub fn events(&self) -> broadcast::Receiver<PaxosEvent> {
+        self.nodes
+            .events(self.proposer_id)
+            .expect("proposer has no events")
+    }
+
+    /// The nodes of the cluster, for what the handle does not cover.
+    pub fn nodes(&mut self) -> &mut Cluster {
+        &mut self.nodes
+    }
+}
+
+/// Spawn a proposer and `num_acceptors` acceptors over a simulated network seeded
+/// with `SPAWN_CLUSTER_SEED`, with the proposer state kept in memory. Acceptors get
+/// the ids `0..num_acceptors`.
+///
+/// Must be called from within a tokio runtime.
+pub fn spawn_cluster(num_acceptors: usize) -> ClusterHandle {
+    let nodes = ClusterBuilder::new()
+        .acceptors(num_acceptors)
+        .transport(ClusterTransport::Sim(SimNetwork::new(SPAWN_CLUSTER_SEED)))
+        .build()
+        .expect("could not spawn cluster");
+    let proposer_id = nodes.config.proposer_id().expect("cluster has no proposer");
+    let learner = nodes
+        .learner(proposer_id)
+        .expect("proposer has no learner")
+        .clone();
+
+    ClusterHandle {
+        client: nodes.client(proposer_id).expect("proposer has no client"),
+        decisions: learner.subscribe(),
+        learner,
+        cluster: nodes.config.clone(),
+        proposer_id,
+        nodes,
+    }
+}
+
+/// Acceptor driven by hand, without any transport.
+///
+/// Requests are fed to `handle`, which returns the reply to deliver. It can be told
+/// to `crash`, losing the state it did not persist, to check what the durability
+/// of acceptors protects.
+pub struct InMemoryAcceptor {
+    pub state: AcceptorState,
+    /// Copy of `state` saved before every reply, if the acceptor is durable.
+    saved: Option<AcceptorState>,
+}
+
+impl InMemoryAcceptor {
+    /// Acceptor `id` that forgets its promises and accepted proposal when it
+    /// crashes. Relying on it is unsafe: it may promise or accept again what it
+    /// already refused.
+    pub fn volatile(id: u64) -> Self {
+        Self {
+            state: AcceptorState::new(id),
+            saved: None,
+        }
+    }
+
+    /// Acceptor `id` that saves its state before every reply, and recovers it when
+    /// it crashes.
+    pub fn durable(id: u64) -> Self {
+        Self {
+            state: AcceptorState::new(id),
+            saved: Some(AcceptorState::new(id)),
+        }
+    }
+
+    /// Answer a request, see `AcceptorState::handle`.
+    pub fn handle(&mut self, message: Message) -> Option<Message> {
+        let reply = self.state.handle(message);
+        if let Some(saved) = &mut self.saved {
+            *saved = self.state.clone();
+        }
+        reply
+    }
+
+    /// Lose the volatile state, as if the acceptor restarted.
+    pub fn crash(&mut self) {
+        self.state = match &self.saved {
+            Some(saved) => saved.clone(),
+            None => AcceptorState::new(self.state.id),
+        };
+    }
+}
+
+/// Message broadcast by a `RecordingBroadcast`, with the number of receivers the
+/// transport reported when it was sent.
+#[derive(Debug, Clone)]
+pub struct RecordedBroadcast {
+    pub message: Message,
+    /// `receiver_count` of the broadcast channel at send time, 0 if the broadcast
+    /// failed.
+    pub receivers: usize,
+}
+
+/// Messages recorded by a `RecordingBroadcast`, readable while the transport it
+/// wraps is owned by a node.
+#[derive(Debug, Clone, Default)]
+pub struct BroadcastLog {
+    recorded: Arc<Mutex<Vec<RecordedBroadcast>>>,
+}
+
+impl BroadcastLog {
+    /// Every message broadcast so far, in the order they were sent.
+    pub fn recorded(&self) -> Vec<RecordedBroadcast> {
+        self.recorded
+            .lock()
+            .expect("broadcast log poisoned")
+            .clone()
+    }
+
+    /// Broadcast messages matching `predicate`, in the order they were sent.
+    pub fn matching(
+        &self,
+        predicate: impl Fn(&Message) -> bool,
+    ) -> Vec<RecordedBroadcast> {
+        self.recorded
+            .lock()
+            .expect("broadcast log poisoned")
+            .iter()
+            .filter(|broadcast| predicate(&broadcast.message))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.recorded
+            .lock()
+            .expect("broadcast log poisoned")
+            .clear();
+    }
+
+    fn push(&self, message: Message, receivers: usize) {
+        self.recorded
+            .lock()
+            .expect("broadcast log poisoned")
+            .push(RecordedBroadcast { message, receivers });
+    }
+}
+
+/// Transport recording every message broadcast through it, along with how many
+/// receivers it reached, before handing it over to the transport it wraps.
+///
+/// Wrapping the transport of a proposer tells exactly what it broadcast to the
+/// acceptors, and to how many of them, without scraping its logs.
+pub struct RecordingBroadcast {
+    inner: Box<dyn Transport>,
+    log: BroadcastLog,
+}
+
+impl RecordingBroadcast {
+    pub fn new(inner: Box<dyn Transport>) -> Self {
+        Self {
+            inner,
+            log: BroadcastLog::default(),
+        }
+    }
+
+    /// Handle to the messages recorded, still valid once the transport is moved.
+    pub fn log(&self) -> BroadcastLog {
+        self.log.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for RecordingBroadcast {
+    async fn send(&self, to: u64, message: Message) -> Result<()> {
+        self.inner.send(to, message).await
+    }
+
+    async fn broadcast(&self, message: Message) -> Result<usize> {
+        let result = self.inner.broadcast(message.clone()).await;
+        self.log.push(message, *result.as_ref().unwrap_or(&0));
+        result
+    }
+
+    async fn recv(&mut self) -> Option<Envelope> {
+        self.inner.recv().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            ballot::BallotNumber,
+            command::CONFIG_CHANGE_FLAG,
+            id::ProposalId,
+            message::{AcceptPhaseBody, PreparePhaseBody},
+        },
+        transport::sim::{Latency, LinkSpec},
+    };
+
+    /// Deadline of the changes of membership of the tests.
+    const CHANGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn spawned_cluster_decides_a_submitted_value() {
+        let mut handle = spawn_cluster(3);
+
+        handle.client.send(7).await.unwrap();
+
+        assert_eq!(handle.decided_value().await.unwrap(), 7);
+        assert_eq!(handle.cluster.acceptor_count(), 3);
+        assert_eq!(handle.learner.latest_value(), Some(7));
+    }
+
+    #[tokio::test]
+    async fn client_value_with_the_config_change_flag_is_refused() {
+        let mut handle = spawn_cluster(3);
+        let forged = CONFIG_CHANGE_FLAG | 1;
+        assert_eq!(
+            handle.propose(forged).await,
+            Err(ProposeError::Reserved(forged))
+        );
+        // Straight into the queue of the proposer, past the checks of the client.
+        handle.client.send(forged).await.unwrap();
+
+        assert!(matches!(
+            handle.propose(7).await,
+            Ok(ConsensusResult::Decided { proposal, .. }) if proposal.value == 7
+        ));
+        let decision = tokio::time::timeout(DECISION_TIMEOUT, handle.decisions.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(decision.proposal.value, 7);
+        assert_eq!(decision.quorum.len(), 2, "membership was changed");
+    }
+
+    #[tokio::test]
+    async fn added_acceptor_counts_from_the_slot_after_the_change() {
+        let mut handle = spawn_cluster(3);
+        handle.propose(1).await.unwrap();
+        let proposer_id = handle.proposer_id;
+        let slot = handle
+            .nodes()
+            .change_membership(proposer_id, [0, 1, 2, 3], CHANGE_TIMEOUT)
+            .await
+            .unwrap();
+        handle.propose(2).await.unwrap();
+
+        let mut quorums = HashMap::new();
+        while let Ok(decision) = handle.decisions.try_recv() {
+            quorums.insert(decision.slot, decision.quorum.len());
+        }
+        assert_eq!(quorums.get(&slot), Some(&2));
+        assert_eq!(quorums.get(&(slot + 1)), Some(&3));
+    }
+
+    #[tokio::test]
+    async fn gossiping_acceptors_learn_the_decisions() {
+        let cluster = ClusterBuilder::new()
+            .transport(ClusterTransport::Sim(SimNetwork::new(3)))
+            .gossip(true)
+            .build()
+            .unwrap();
+        let client = cluster.proposer_client(3).unwrap();
+        client
+            .propose_with_timeout(7, DECISION_TIMEOUT)
+            .await
+            .unwrap();
+
+        // The accepts are gossiped after they are answered: wait for them.
+        let learned = async {
+            loop {
+                let decisions: Vec<_> = (0..3)
+                    .map(|acceptor_id| cluster.acceptor_decisions(acceptor_id).unwrap())
+                    .collect();
+                if decisions
+                    .iter()
+                    .all(|decided| decided.values().any(|&v| v == 7))
+                {
+                    break decisions;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        let decisions = tokio::time::timeout(DECISION_TIMEOUT, learned)
+            .await
+            .expect("acceptors did not learn the decision");
+        assert!(decisions.iter().all(|decided| decided.len() == 1));
+        cluster.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acceptor_cut_off_from_the_proposer_learns_the_decision_by_gossip() {
+        let network = SimNetwork::new(3);
+        let cluster = ClusterBuilder::new()
+            .acceptors(5)
+            .transport(ClusterTransport::Sim(network.clone()))
+            .gossip(true)
+            .build()
+            .unwrap();
+        // Acceptor 4 neither hears from the proposer nor answers it.
+        network.set_link(
+            5,
+            4,
+            LinkSpec {
+                drop_probability: 1.0,
+                ..LinkSpec::default()
+            },
+        );
+        let client = cluster.proposer_client(5).unwrap();
+        client
+            .propose_with_timeout(7, DECISION_TIMEOUT)
+            .await
+            .unwrap();
+
+        let learned = async {
+            loop {
+                let decided = cluster.acceptor_decisions(4).unwrap();
+                if !decided.is_empty() {
+                    break decided;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        let decided = tokio::time::timeout(DECISION_TIMEOUT, learned)
+            .await
+            .expect("acceptor 4 did not learn the decision");
+        assert_eq!(decided.into_values().collect::<Vec<_>>(), [7]);
+    }
+
+    #[tokio::test]
+    async fn nonsensical_clusters_are_refused() {
+        let clusters = [
+            ClusterBuilder::new().acceptors(0),
+            ClusterBuilder::new().proposers(0),
+            ClusterBuilder::new().acceptors(3).quorum(4),
+            ClusterBuilder::new().acceptors(4).quorum(2),
+        ];
+        for builder in clusters {
+            assert!(builder.build().is_err());
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cluster_decides_while_a_crashed_acceptor_restarts() {
+        let mut cluster = ClusterBuilder::new()
+            .transport(ClusterTransport::Sim(SimNetwork::new(3)))
+            .build()
+            .unwrap();
+        let client = cluster.proposer_client(3).unwrap();
+
+        cluster.crash_node(0).unwrap();
+        assert!(cluster.crash_node(0).is_err());
+        let first = client.propose_with_timeout(7, DECISION_TIMEOUT).await;
+        cluster.restart_node(0).unwrap();
+        cluster.crash_node(1).unwrap();
+        let second = client.propose_with_timeout(8, DECISION_TIMEOUT).await;
+
+        for (outcome, value) in [(first, 7), (second, 8)] {
+            assert!(
+                matches!(
+                    outcome,
+                    Ok(ConsensusResult::Decided { proposal, .. }) if proposal.value == value
+                ),
+                "{outcome:?}"
+            );
+        }
+        cluster.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cluster_grows_to_five_acceptors_and_back_while_proposing() {
+        let mut cluster = ClusterBuilder::new()
+            .transport(ClusterTransport::Sim(SimNetwork::new(3)))
+            .build()
+            .unwrap();
+        let client = cluster.proposer_client(3).unwrap();
+        let proposing = tokio::spawn(async move {
+            let mut outcomes = Vec::new();
+            for value in 1..=20 {
+                outcomes
+                    .push(client.propose_with_timeout(value, DECISION_TIMEOUT).await);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            outcomes
+        });
+
+        for acceptor in [4, 5] {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            cluster.add_acceptor(acceptor).unwrap();
+        }
+        assert_eq!(cluster.config.acceptor_count(), 5);
+        for acceptor in [5, 4] {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            cluster.remove_acceptor(acceptor).unwrap();
+        }
+
+        for (value, outcome) in (1..).zip(proposing.await.unwrap()) {
+            assert!(
+                matches!(
+                    outcome,
+                    Ok(ConsensusResult::Decided { proposal, .. }) if proposal.value == value
+                ),
+                "{value}: {outcome:?}"
+            );
+        }
+        assert_eq!(cluster.config.acceptor_count(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acceptor_holding_an_undecided_value_is_not_removed() {
+        let link = LinkSpec {
+            latency: Latency::Fixed(Duration::from_millis(10)),
+            ..LinkSpec::default()
+        };
+        let network = SimNetwork::with_default_link(7, link);
+        let mut cluster = ClusterBuilder::new()
+            .transport(ClusterTransport::Sim(network.clone()))
+            .retry_policy(RetryPolicy {
+                max_attempts: 50,
+                ..RetryPolicy::default()
+            })
+            .build()
+            .unwrap();
+        let client = cluster.proposer_client(3).unwrap();
+        let round = tokio::spawn(async move {
+            client.propose_with_timeout(7, DECISION_TIMEOUT).await
+        });
+
+        // Only acceptor 0 gets the accept request: it holds the only copy of 7.
+        tokio::time::sleep(Duration::from_millis(15)).await;
+        network.partition(vec![vec![3, 0], vec![1, 2]]);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(cluster.remove_acceptor(0).is_err());
+
+        network.heal();
+        round.await.unwrap().unwrap();
+        cluster.remove_acceptor(0).unwrap();
+        assert_eq!(cluster.config.acceptor_count(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn phases_of_flexible_quorums_wait_for_their_own_size() {
+        let mut cluster = ClusterBuilder::new()
+            .acceptors(5)
+            .flexible_quorums(4, 2)
+            .transport(ClusterTransport::Sim(SimNetwork::new(3)))
+            .build()
+            .unwrap();
+        let client = cluster.proposer_client(5).unwrap();
+        let mut decisions = cluster.learner(5).unwrap().subscribe();
+
+        cluster.crash_node(4).unwrap();
+        let outcome = client.propose_with_timeout(7, DECISION_TIMEOUT).await;
+        assert!(
+            matches!(outcome, Ok(ConsensusResult::Decided { proposal, .. }) if proposal.value == 7),
+            "{outcome:?}"
+        );
+        assert_eq!(decisions.recv().await.unwrap().quorum.len(), 2);
+
+        // Three acceptors are still a quorum of the accept phase, not of the prepare
+        // phase.
+        cluster.crash_node(3).unwrap();
+        let outcome = client.propose_with_timeout(8, DECISION_TIMEOUT).await;
+        assert!(outcome.is_err(), "{outcome:?}");
+    }
+
+    /// Prepare request of proposer `round` for slot 0.
+    fn prepare(round: u64) -> Message {
+        Message::PrepareRequest {
+            body: PreparePhaseBody {
+                issuer_id: round,
+                epoch: 0,
+                slot: 0,
+                proposal_id: ProposalId(uuid::Uuid::from_u128(u128::from(round))),
+                ballot: BallotNumber::new(round, round),
+                accepted: None,
+                nonce: 0,
+                sent_at: None,
+            },
+        }
+    }
+
+    /// Accept request of proposer `round` for `value` in slot 0.
+    fn accept(round: u64, value: u64) -> Message {
+        Message::AcceptRequest {
+            body: AcceptPhaseBody {
+                issuer_id: round,
+                epoch: 0,
+                slot: 0,
+                proposal_id: ProposalId(uuid::Uuid::from_u128(u128::from(round))),
+                ballot: BallotNumber::new(round, round),
+                value,
+                nonce: 0,
+                sent_at: None,
+            },
+        }
+    }
+
+    /// Values the acceptors `0..3` built by `acceptor` let a second proposer choose
+    /// for slot 0, once 5 was chosen by acceptors 0 and 1 and acceptor 1 crashed.
+    /// The second proposer only hears from acceptors 1 and 2.
+    fn value_chosen_after_a_crash(acceptor: fn(u64) -> InMemoryAcceptor) -> u64 {
+        let mut acceptors: Vec<_> = (0..3).map(acceptor).collect();
+        for acceptor in &mut acceptors[..2] {
+            acceptor.handle(prepare(1)).unwrap();
+            acceptor.handle(accept(1, 5)).unwrap();
+        }
+
+        acceptors[1].crash();
+
+        let reported = acceptors[1..]
+            .iter_mut()
+            .filter_map(|acceptor| match acceptor.handle(prepare(2)) {
+                Some(Message::PrepareResponse { body }) => body.accepted,
+                reply => panic!("no promise: {reply:?}"),
+            })
+            .max_by_key(|proposal| proposal.ballot);
+        let value = reported.map_or(6, |proposal| proposal.value);
+        for acceptor in &mut acceptors[1..] {
+            assert!(matches!(
+                acceptor.handle(accept(2, value)),
+                Some(Message::AcceptResponse { .. })
+            ));
+        }
+        value
+    }
+
+    #[test]
+    fn only_durable_acceptors_keep_a_chosen_value_across_a_crash() {
+        // Forgetting the accepted value lets another value be chosen for the slot.
+        assert_eq!(value_chosen_after_a_crash(InMemoryAcceptor::volatile), 6);
+        assert_eq!(value_chosen_after_a_crash(InMemoryAcceptor::durable), 5);
+    }
+
+    #[tokio::test]
+    async fn spawned_cluster_decides_with_a_crashed_acceptor() {
+        let mut handle = spawn_cluster(3);
+        handle.crash_acceptor(0).unwrap();
+        assert!(handle.crash_acceptor(0).is_err());
+
+        handle.client.send(7).await.unwrap();
+
+        let decision = tokio::time::timeout(DECISION_TIMEOUT, handle.decisions.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(decision.proposal.value, 7);
+        assert_eq!(decision.quorum, vec![1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spawned_cluster_decides_once_a_paused_acceptor_resumes() {
+        let mut handle = spawn_cluster(3);
+        handle.pause_acceptor(1).unwrap();
+        handle.pause_acceptor(2).unwrap();
+
+        handle.client.send(7).await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_secs(1), handle.decisions.recv())
+                .await
+                .is_err(),
+            "decided without a quorum"
+        );
+
+        handle.resume_acceptor(1).unwrap();
+        assert_eq!(handle.decided_value().await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn spawned_cluster_publishes_the_steps_of_a_round() {
+        let handle = spawn_cluster(3);
+        let mut events = handle.events();
+
+        handle.propose(7).await.unwrap();
+
+        let first = events.recv().await.unwrap();
+        assert!(
+            matches!(first, PaxosEvent::RoundStarted { slot: 0, .. }),
+            "{first:?}"
+        );
+        loop {
+            match events.recv().await.unwrap() {
+                PaxosEvent::Chosen { slot, value, .. } => {
+                    assert_eq!((slot, value), (0, 7));
+                    break;
+                }
+                PaxosEvent::RoundAbandoned { .. } => panic!("round abandoned"),
+                _ => (),
+            }
+        }
+    }
+}
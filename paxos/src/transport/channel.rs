@@ -0,0 +1,251 @@
+use anyhow::Result;
+use tokio::sync::{broadcast, mpsc};
+use tracing::error;
+
+use super::{AcceptorTransport, Envelope, Transport};
+use crate::{config::ClusterConfig, domain::message::Message};
+
+/// Wire the proposer and the acceptors of `cluster` together through in-process
+/// channels. The acceptor transports are returned in the order of the peer list.
+///
+/// With `split_phases`, accept-phase traffic gets its own channels, so that it
+/// neither competes with prepare-phase traffic for buffer space nor delays it.
+pub fn wire(
+    cluster: &ClusterConfig,
+    split_phases: bool,
+) -> (ProposerChannel, Vec<AcceptorChannel>) {
+    let proposer_id = cluster.proposer_id().expect("cluster has no proposer");
+    let acceptors = cluster.acceptor_count().max(1);
+
+    // FIXME: this number should (probably?) be the same as the number of nodes.
+    // Decrease this and handle `Lagged` error.
+    let (broadcast_tx, _) = broadcast::channel::<Envelope>(1000);
+    let (proposer_tx, proposer_rx) = mpsc::channel::<Envelope>(acceptors);
+    let (accept_broadcast_tx, accept_proposer_tx, accept_proposer_rx) = if split_phases
+    {
+        let (accept_broadcast_tx, _) = broadcast::channel::<Envelope>(1000);
+        let (accept_proposer_tx, accept_proposer_rx) =
+            mpsc::channel::<Envelope>(acceptors);
+        (
+            Some(accept_broadcast_tx),
+            Some(accept_proposer_tx),
+            Some(accept_proposer_rx),
+        )
+    } else {
+        (None, None, None)
+    };
+
+    let acceptor_channels = cluster
+        .acceptors()
+        .map(|acceptor| AcceptorChannel {
+            id: acceptor.node_id,
+            proposer_sender: proposer_tx.clone(),
+            proposer_receiver: broadcast_tx.subscribe(),
+            accept_sender: accept_proposer_tx.clone(),
+            accept_receiver: accept_broadcast_tx.as_ref().map(|tx| tx.subscribe()),
+        })
+        .collect();
+    let proposer_channel = ProposerChannel {
+        id: proposer_id,
+        acceptor_sender: broadcast_tx,
+        acceptor_receiver: proposer_rx,
+        accept_sender: accept_broadcast_tx,
+        accept_receiver: accept_proposer_rx,
+    };
+
+    (proposer_channel, acceptor_channels)
+}
+
+/// Whether a message goes through the accept-phase channels, when they are split.
+fn is_accept_phase(message: &Message) -> bool {
+    matches!(
+        message,
+        Message::AcceptRequest { .. }
+            | Message::AcceptResponse { .. }
+            | Message::AcceptReject { .. }
+    )
+}
+
+/// Proposer side of the in-process wiring: it broadcasts to every acceptor and
+/// receives all their responses in a single queue.
+pub struct ProposerChannel {
+    /// Id of the proposer, stamped on every message it sends.
+    pub id: u64,
+    /// Interface to broadcast messages to the acceptors.
+    pub acceptor_sender: broadcast::Sender<Envelope>,
+    /// Interface to receive messages **from** the acceptors.
+    pub acceptor_receiver: mpsc::Receiver<Envelope>,
+    /// Broadcast channel dedicated to the accept phase, if the phases are split.
+    pub accept_sender: Option<broadcast::Sender<Envelope>>,
+    /// Queue dedicated to accept responses, if the phases are split.
+    pub accept_receiver: Option<mpsc::Receiver<Envelope>>,
+}
+
+/// Acceptor side of the in-process wiring.
+pub struct AcceptorChannel {
+    /// Id of the acceptor, stamped on every message it sends.
+    pub id: u64,
+    /// Interface to send messages **to** the proposer. This is mpsc (multiple senders
+    /// send to a single consumer, which in this case is the proposer).
+    pub proposer_sender: mpsc::Sender<Envelope>,
+    /// Interface to receive messages **from** the proposer. Remember, the proposer
+    /// broadcasts proposals.
+    pub proposer_receiver: broadcast::Receiver<Envelope>,
+    /// Queue dedicated to accept responses, if the phases are split.
+    pub accept_sender: Option<mpsc::Sender<Envelope>>,
+    /// Broadcast channel dedicated to the accept phase, if the phases are split.
+    pub accept_receiver: Option<broadcast::Receiver<Envelope>>,
+}
+
+#[async_trait::async_trait]
+impl Transport for ProposerChannel {
+    /// Acceptors can only be reached through the broadcast channel.
+    async fn send(&self, _to: u64, message: Message) -> Result<()> {
+        self.broadcast(message).await.map(|_| ())
+    }
+
+    async fn broadcast(&self, message: Message) -> Result<usize> {
+        let sender = match &self.accept_sender {
+            Some(accept_sender) if is_accept_phase(&message) => accept_sender,
+            _ => &self.acceptor_sender,
+        };
+        sender
+            .send(Envelope {
+                from: self.id,
+                message,
+            })
+            .map_err(anyhow::Error::from)
+    }
+
+    /// Prepare responses are given priority over accept responses.
+    async fn recv(&mut self) -> Option<Envelope> {
+        let Some(accept_receiver) = &mut self.accept_receiver else {
+            return self.acceptor_receiver.recv().await;
+        };
+
+        tokio::select! {
+            biased;
+            Some(envelope) = self.acceptor_receiver.recv() => Some(envelope),
+            Some(envelope) = accept_receiver.recv() => Some(envelope),
+            else => None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AcceptorTransport for AcceptorChannel {
+    /// There is a single proposer, so every message goes to it.
+    async fn send(&self, _to: u64, message: Message) -> Result<()> {
+        let sender = match &self.accept_sender {
+            Some(accept_sender) if is_accept_phase(&message) => accept_sender,
+            _ => &self.proposer_sender,
+        };
+        sender
+            .send(Envelope {
+                from: self.id,
+                message,
+            })
+            .await
+            .map_err(anyhow::Error::from)
+    }
+
+    async fn recv(&mut self) -> Option<Envelope> {
+        // FIXME: a lagged receiver could skip the missed messages and keep going.
+        let received = match &mut self.accept_receiver {
+            None => self.proposer_receiver.recv().await,
+            Some(accept_receiver) => tokio::select! {
+                biased;
+                received = self.proposer_receiver.recv() => received,
+                received = accept_receiver.recv() => received,
+            },
+        };
+
+        received.inspect_err(|e| error!(?e)).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::domain::{
+        ballot::BallotNumber,
+        id::ProposalId,
+        message::{AcceptPhaseBody, PreparePhaseBody},
+    };
+
+    /// How long a send may wait for room in the queue of the proposer.
+    const SEND_TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn promise(acceptor: u64) -> Message {
+        Message::PrepareResponse {
+            body: PreparePhaseBody {
+                issuer_id: acceptor,
+                epoch: 0,
+                slot: 0,
+                proposal_id: ProposalId(Uuid::from_u128(1)),
+                ballot: BallotNumber::new(1, 3),
+                accepted: None,
+                nonce: 0,
+                sent_at: None,
+            },
+        }
+    }
+
+    fn accepted(acceptor: u64, slot: u64) -> Message {
+        Message::AcceptResponse {
+            body: AcceptPhaseBody {
+                issuer_id: acceptor,
+                epoch: 0,
+                slot,
+                proposal_id: ProposalId(Uuid::from_u128(1)),
+                ballot: BallotNumber::new(1, 3),
+                value: 7,
+                nonce: 0,
+                sent_at: None,
+            },
+        }
+    }
+
+    /// Fill the queue of the proposer with accept responses of `acceptor`.
+    async fn flood(acceptor: &AcceptorChannel, cluster: &ClusterConfig) {
+        for slot in 0..cluster.acceptor_count() as u64 {
+            acceptor.send(3, accepted(acceptor.id, slot)).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn flood_of_accept_responses_does_not_hold_up_the_prepare_phase() {
+        let cluster = ClusterConfig::in_process(3);
+        let (mut proposer, acceptors) = wire(&cluster, true);
+        flood(&acceptors[0], &cluster).await;
+
+        timeout(SEND_TIMEOUT, acceptors[1].send(3, promise(1)))
+            .await
+            .expect("promise held up by the accept responses")
+            .unwrap();
+
+        let first = Transport::recv(&mut proposer).await.unwrap();
+        assert_eq!(first.from, 1);
+        assert!(matches!(first.message, Message::PrepareResponse { .. }));
+        assert!(matches!(
+            Transport::recv(&mut proposer).await.unwrap().message,
+            Message::AcceptResponse { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn single_channel_shares_its_buffer_between_the_phases() {
+        let cluster = ClusterConfig::in_process(3);
+        let (_proposer, acceptors) = wire(&cluster, false);
+        flood(&acceptors[0], &cluster).await;
+
+        assert!(timeout(SEND_TIMEOUT, acceptors[1].send(3, promise(1)))
+            .await
+            .is_err());
+    }
+}
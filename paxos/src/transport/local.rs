@@ -0,0 +1,188 @@
+use std::future::Future;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{trace, warn};
+
+use super::{Envelope, Transport};
+use crate::{
+    config::ClusterConfig,
+    domain::{command::Command, message::Message},
+};
+
+/// Split the transport of a node between its proposer and its acceptor.
+///
+/// The returned `Router` owns the network transport and must be running for the
+/// roles to communicate. Values submitted by clients over the network are passed to
+/// `client`.
+///
+/// Messages a node sends to itself, including its own share of a broadcast, are
+/// handed over directly to the other role and never reach the network.
+pub fn split(
+    id: u64,
+    cluster: ClusterConfig,
+    network: Box<dyn Transport>,
+    client: mpsc::Sender<u64>,
+) -> (Router, LocalTransport, LocalTransport) {
+    let (outgoing_sender, outgoing) = mpsc::unbounded_channel();
+    let (proposer_sender, proposer_inbox) = mpsc::unbounded_channel();
+    let (acceptor_sender, acceptor_inbox) = mpsc::unbounded_channel();
+
+    let router = Router {
+        id,
+        cluster,
+        network,
+        outgoing,
+        proposer: proposer_sender,
+        acceptor: acceptor_sender,
+        client,
+    };
+    let proposer = LocalTransport {
+        outgoing: outgoing_sender.clone(),
+        inbox: proposer_inbox,
+    };
+    let acceptor = LocalTransport {
+        outgoing: outgoing_sender,
+        inbox: acceptor_inbox,
+    };
+
+    (router, proposer, acceptor)
+}
+
+/// Message a role asks the router to send. Broadcasts have no destination.
+struct Outgoing {
+    to: Option<u64>,
+    message: Message,
+    /// Outcome of the send: how many nodes the message was sent to.
+    reply: oneshot::Sender<Result<usize>>,
+}
+
+/// Dispatches the traffic of a node between the network and its roles.
+pub struct Router {
+    id: u64,
+    cluster: ClusterConfig,
+    network: Box<dyn Transport>,
+    outgoing: mpsc::UnboundedReceiver<Outgoing>,
+    proposer: mpsc::UnboundedSender<Envelope>,
+    acceptor: mpsc::UnboundedSender<Envelope>,
+    client: mpsc::Sender<u64>,
+}
+
+impl Router {
+    pub async fn run(self) {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Route messages until `shutdown` resolves. The roles can not send anything
+    /// afterwards.
+    #[tracing::instrument(skip_all, fields(node_id = self.id))]
+    pub async fn run_until(mut self, shutdown: impl Future<Output = ()>) {
+        tokio::pin!(shutdown);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                Some(envelope) = self.network.recv() => self.deliver(envelope),
+                Some(Outgoing { to, message, reply }) = self.outgoing.recv() => {
+                    let sent = match to {
+                        Some(to) => self.send(to, message).await.map(|_| 1),
+                        None => self.broadcast(message).await,
+                    };
+                    // The role may have given up waiting.
+                    let _ = reply.send(sent);
+                },
+                else => break,
+            }
+        }
+    }
+
+    /// Hand over a message to the role it is meant for: requests and gossip go to
+    /// the acceptor, values of clients to the client queue of the proposer, everything
+    /// else to the proposer. Messages for a role this node does not play are
+    /// dropped.
+    fn deliver(&self, envelope: Envelope) {
+        if let Message::ClientRequest { body } = envelope.message {
+            if Command::is_reserved(body.value) {
+                warn!(
+                    client = envelope.from,
+                    value = body.value,
+                    "dropping client value reserved for the commands of the cluster"
+                );
+                return;
+            }
+            if let Err(e) = self.client.try_send(body.value) {
+                warn!(client = envelope.from, "dropping client value: {e}");
+            }
+            return;
+        }
+
+        let inbox = match envelope.message {
+            Message::PrepareRequest { .. }
+            | Message::AcceptRequest { .. }
+            | Message::PreVoteRequest { .. }
+            | Message::LeaseRequest { .. }
+            | Message::AbortRound { .. }
+            | Message::AcceptGossip { .. } => &self.acceptor,
+            _ => &self.proposer,
+        };
+
+        if let Err(e) = inbox.send(envelope) {
+            trace!(message = %e.0.message, "no role to deliver the message to");
+        }
+    }
+
+    async fn send(&self, to: u64, message: Message) -> Result<()> {
+        if to == self.id {
+            self.deliver(Envelope {
+                from: self.id,
+                message,
+            });
+            return Ok(());
+        }
+
+        self.network.send(to, message).await
+    }
+
+    /// Send a message to every acceptor of the cluster, one by one, so that this
+    /// node gets its own copy without going through the network.
+    async fn broadcast(&self, message: Message) -> Result<usize> {
+        let acceptors: Vec<u64> =
+            self.cluster.acceptors().map(|peer| peer.node_id).collect();
+        for &to in &acceptors {
+            self.send(to, message.clone()).await?;
+        }
+
+        Ok(acceptors.len())
+    }
+}
+
+/// Transport of a single role of a node, going through the `Router` of the node.
+pub struct LocalTransport {
+    outgoing: mpsc::UnboundedSender<Outgoing>,
+    inbox: mpsc::UnboundedReceiver<Envelope>,
+}
+
+impl LocalTransport {
+    async fn submit(&self, to: Option<u64>, message: Message) -> Result<usize> {
+        let (reply, sent) = oneshot::channel();
+        self.outgoing
+            .send(Outgoing { to, message, reply })
+            .map_err(|_| anyhow!("router stopped"))?;
+
+        sent.await.map_err(|_| anyhow!("router stopped"))?
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for LocalTransport {
+    async fn send(&self, to: u64, message: Message) -> Result<()> {
+        self.submit(Some(to), message).await.map(|_| ())
+    }
+
+    async fn broadcast(&self, message: Message) -> Result<usize> {
+        self.submit(None, message).await
+    }
+
+    async fn recv(&mut self) -> Option<Envelope> {
+        self.inbox.recv().await
+    }
+}
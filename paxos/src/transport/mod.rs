@@ -0,0 +1,57 @@
+use anyhow::Result;
+
+use crate::domain::message::Message;
+
+pub mod channel;
+pub mod local;
+pub mod sim;
+pub mod tcp;
+pub mod wire;
+
+/// Message handed over by a transport, along with the node it physically came
+/// from. Unlike the issuer id written in the message body, `from` is set by the
+/// transport and can not be forged by the sender.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub from: u64,
+    pub message: Message,
+}
+
+/// Interface used by the actors to exchange messages, regardless of how they are
+/// physically delivered (in-process channels, a simulated network, ...).
+#[async_trait::async_trait]
+pub trait Transport: Send + Sync {
+    /// Send a message to a single node.
+    async fn send(&self, to: u64, message: Message) -> Result<()>;
+
+    /// Send a message to every acceptor, returning how many of them it was sent to.
+    async fn broadcast(&self, message: Message) -> Result<usize>;
+
+    /// Wait for the next message addressed to this node. Returns `None` once the
+    /// transport is closed and no more messages will arrive.
+    async fn recv(&mut self) -> Option<Envelope>;
+}
+
+/// Part of `Transport` that acceptors rely on. Acceptors never broadcast: they pull
+/// requests and answer the node each one came from, so they can be driven by
+/// transports that have no notion of broadcast.
+#[async_trait::async_trait]
+pub trait AcceptorTransport: Send + Sync {
+    /// Send a message to a single node.
+    async fn send(&self, to: u64, message: Message) -> Result<()>;
+
+    /// Wait for the next message addressed to this node. Returns `None` once the
+    /// transport is closed and no more messages will arrive.
+    async fn recv(&mut self) -> Option<Envelope>;
+}
+
+#[async_trait::async_trait]
+impl<T: Transport> AcceptorTransport for T {
+    async fn send(&self, to: u64, message: Message) -> Result<()> {
+        Transport::send(self, to, message).await
+    }
+
+    async fn recv(&mut self) -> Option<Envelope> {
+        Transport::recv(self).await
+    }
+}
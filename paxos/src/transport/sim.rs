@@ -0,0 +1,665 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::{
+    sync::{mpsc, Notify},
+    time::sleep,
+};
+use tracing::{debug, trace};
+
+use super::{Envelope, Transport};
+use crate::{config::Role, domain::message::Message};
+
+/// Delay applied to every message crossing a link.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Latency {
+    Fixed(Duration),
+    /// Latency drawn uniformly from `min..=max` for every message.
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+}
+
+/// Conditions of the link between two nodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkSpec {
+    pub latency: Latency,
+    /// Probability, between 0 and 1, that a message sent over the link is lost.
+    pub drop_probability: f64,
+    /// Probability, between 0 and 1, that a message is delivered twice.
+    pub duplicate_probability: f64,
+    /// Maximum extra delay a message can be held for, drawn for every message on
+    /// top of the latency. Messages sent later may overtake a held one, so a
+    /// non-zero window reorders the traffic of the link.
+    pub reorder_window: Duration,
+}
+
+impl Default for LinkSpec {
+    fn default() -> Self {
+        Self {
+            latency: Latency::Fixed(Duration::ZERO),
+            drop_probability: 0.0,
+            duplicate_probability: 0.0,
+            reorder_window: Duration::ZERO,
+        }
+    }
+}
+
+/// What happens to messages sent across a partition boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PartitionPolicy {
+    /// Messages are lost, as if the link was down.
+    #[default]
+    Drop,
+    /// Messages are held and delivered once the partition heals.
+    QueueUntilHeal,
+}
+
+/// In-memory network that every `SimTransport` sends through. Latency and losses
+/// are drawn from a seeded RNG, so a simulation is reproducible given its seed.
+///
+/// Delays are implemented with `tokio::time`, which means tests can use
+/// `tokio::time::pause` to advance virtual time instead of actually sleeping.
+#[derive(Clone)]
+pub struct SimNetwork {
+    state: Arc<Mutex<SimState>>,
+}
+
+struct SimState {
+    rng: StdRng,
+    default_link: LinkSpec,
+    /// Overrides of `default_link`, keyed by `(from, to)`.
+    links: HashMap<(u64, u64), LinkSpec>,
+    inboxes: HashMap<u64, mpsc::UnboundedSender<Envelope>>,
+    /// Nodes that receive the messages broadcast to acceptors.
+    acceptors: BTreeSet<u64>,
+    /// Group of each node while the network is partitioned. Nodes can only talk to
+    /// nodes of the same group; nodes left out of every group are isolated.
+    partition: Option<HashMap<u64, usize>>,
+    partition_policy: PartitionPolicy,
+    /// Messages held at a partition boundary, waiting for the network to heal.
+    held: Vec<(u64, u64, Message)>,
+    /// Nodes frozen by `pause`, and the messages they sent or were sent since.
+    paused: BTreeSet<u64>,
+    parked: Vec<(u64, u64, Message)>,
+    /// Messages waiting to be delivered by `deliver_next`, if the network is
+    /// stepped rather than delivering them on its own.
+    stepped: Option<Stepped>,
+}
+
+/// Messages of a stepped network that were sent but not delivered yet.
+#[derive(Default)]
+struct Stepped {
+    /// Keyed by link and by order of sending over the link, so that the order they
+    /// are drawn in does not depend on the order the links were used in.
+    pending: BTreeMap<(u64, u64, u64), Message>,
+    /// Number of messages sent so far over each link.
+    sent: HashMap<(u64, u64), u64>,
+    /// Signaled every time a message is sent.
+    notify: Arc<Notify>,
+}
+
+impl SimState {
+    fn is_partitioned(&self, from: u64, to: u64) -> bool {
+        let Some(groups) = &self.partition else {
+            return false;
+        };
+        match (groups.get(&from), groups.get(&to)) {
+            (Some(from_group), Some(to_group)) => from_group != to_group,
+            _ => true,
+        }
+    }
+}
+
+impl SimNetwork {
+    pub fn new(seed: u64) -> Self {
+        Self::with_default_link(seed, LinkSpec::default())
+    }
+
+    /// Network where every link not configured through `set_link` behaves like
+    /// `default_link`.
+    pub fn with_default_link(seed: u64, default_link: LinkSpec) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SimState {
+                rng: StdRng::seed_from_u64(seed),
+                default_link,
+                links: HashMap::new(),
+                inboxes: HashMap::new(),
+                acceptors: BTreeSet::new(),
+                partition: None,
+                partition_policy: PartitionPolicy::default(),
+                held: Vec::new(),
+                paused: BTreeSet::new(),
+                parked: Vec::new(),
+                stepped: None,
+            })),
+        }
+    }
+
+    /// Network that delivers nothing on its own: messages wait until `deliver_next`
+    /// hands over one of them, drawn with the RNG seeded with `seed`. Whoever steps
+    /// the network thus decides of the order every message is received in, and the
+    /// same seed gives the same order. Latencies do not apply, losses and
+    /// duplicates still do.
+    pub fn stepped(seed: u64) -> Self {
+        let network = Self::new(seed);
+        network
+            .state
+            .lock()
+            .expect("sim network lock poisoned")
+            .stepped = Some(Stepped::default());
+        network
+    }
+
+    /// Deliver one of the messages waiting in a stepped network, drawn at random,
+    /// and return it along with its sender and destination. `None` if no message
+    /// is waiting, or if the network is not stepped.
+    pub fn deliver_next(&self) -> Option<(u64, u64, Message)> {
+        let mut state = self.state.lock().expect("sim network lock poisoned");
+        let (from, to, message) = Self::draw_next(&mut state)?;
+        // The destination may have left the network in the meantime.
+        if let Some(inbox) = state.inboxes.get(&to) {
+            let _ = inbox.send(Envelope {
+                from,
+                message: message.clone(),
+            });
+        }
+        Some((from, to, message))
+    }
+
+    /// Draw the message `deliver_next` would deliver, but hand it over to the
+    /// caller rather than to its destination: it is lost unless sent again with
+    /// `resend`.
+    pub fn intercept_next(&self) -> Option<(u64, u64, Message)> {
+        let mut state = self.state.lock().expect("sim network lock poisoned");
+        Self::draw_next(&mut state)
+    }
+
+    /// Send again a message intercepted with `intercept_next`, as if `from` had just
+    /// sent it to `to`.
+    pub fn resend(&self, from: u64, to: u64, message: Message) {
+        self.deliver(from, to, message);
+    }
+
+    fn draw_next(state: &mut SimState) -> Option<(u64, u64, Message)> {
+        let stepped = state.stepped.as_mut()?;
+        if stepped.pending.is_empty() {
+            return None;
+        }
+
+        let index = state.rng.gen_range(0..stepped.pending.len());
+        let key = *stepped.pending.keys().nth(index).expect("index in range");
+        let message = stepped.pending.remove(&key).expect("key is pending");
+        let (from, to, _) = key;
+        Some((from, to, message))
+    }
+
+    /// Number of messages waiting in a stepped network.
+    pub fn pending(&self) -> usize {
+        let state = self.state.lock().expect("sim network lock poisoned");
+        state
+            .stepped
+            .as_ref()
+            .map_or(0, |stepped| stepped.pending.len())
+    }
+
+    /// Resolve once a message is sent over a stepped network, or right away if one
+    /// was sent since the last call. Never resolves if the network is not stepped.
+    pub async fn message_sent(&self) {
+        let notify = {
+            let state = self.state.lock().expect("sim network lock poisoned");
+            state.stepped.as_ref().map(|stepped| stepped.notify.clone())
+        };
+        match notify {
+            Some(notify) => notify.notified().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Plug a node into the network, returning the transport it should use.
+    pub fn join(&self, node_id: u64, roles: &[Role]) -> SimTransport {
+        let (inbox_sender, inbox) = mpsc::unbounded_channel();
+        let mut state = self.state.lock().expect("sim network lock poisoned");
+        state.inboxes.insert(node_id, inbox_sender);
+        if roles.contains(&Role::Acceptor) {
+            state.acceptors.insert(node_id);
+        }
+
+        SimTransport {
+            node_id,
+            network: self.clone(),
+            inbox,
+        }
+    }
+
+    /// Unplug a node from the network. Messages sent to it from now on, including
+    /// those already in flight, are lost.
+    pub fn leave(&self, node_id: u64) {
+        let mut state = self.state.lock().expect("sim network lock poisoned");
+        state.inboxes.remove(&node_id);
+        state.acceptors.remove(&node_id);
+    }
+
+    /// Change the conditions of the link between `a` and `b`, in both directions.
+    /// Messages already in flight are not affected.
+    pub fn set_link(&self, a: u64, b: u64, spec: LinkSpec) {
+        let mut state = self.state.lock().expect("sim network lock poisoned");
+        state.links.insert((a, b), spec);
+        state.links.insert((b, a), spec);
+    }
+
+    /// Choose what happens to messages crossing a partition boundary. Only affects
+    /// messages sent after the call.
+    pub fn set_partition_policy(&self, policy: PartitionPolicy) {
+        self.state
+            .lock()
+            .expect("sim network lock poisoned")
+            .partition_policy = policy;
+    }
+
+    /// Split the network so that nodes can only reach the nodes in their own group.
+    /// Replaces any previous partition.
+    pub fn partition(&self, groups: Vec<Vec<u64>>) {
+        let membership = groups
+            .into_iter()
+            .enumerate()
+            .flat_map(|(group, nodes)| nodes.into_iter().map(move |node| (node, group)))
+            .collect();
+
+        let mut state = self.state.lock().expect("sim network lock poisoned");
+        debug!(groups = ?membership, "partitioning network");
+        state.partition = Some(membership);
+    }
+
+    /// Remove the partition, delivering the messages held at its boundaries. Held
+    /// messages go through the link conditions again, so they can still be delayed
+    /// or lost.
+    pub fn heal(&self) {
+        let held = {
+            let mut state = self.state.lock().expect("sim network lock poisoned");
+            state.partition = None;
+            std::mem::take(&mut state.held)
+        };
+
+        debug!(held = held.len(), "healing network");
+        for (from, to, message) in held {
+            self.deliver(from, to, message);
+        }
+    }
+
+    /// Freeze `node_id`, as if its process was stopped: the messages it sends and
+    /// the ones sent to it are held until it is `resume`d, then delivered as if
+    /// they were sent at that time.
+    pub fn pause(&self, node_id: u64) {
+        let mut state = self.state.lock().expect("sim network lock poisoned");
+        debug!(node_id, "pausing node");
+        state.paused.insert(node_id);
+    }
+
+    /// Unfreeze `node_id`, delivering the messages held while it was paused. Those
+    /// exchanged with another paused node stay held.
+    pub fn resume(&self, node_id: u64) {
+        let parked = {
+            let mut state = self.state.lock().expect("sim network lock poisoned");
+            state.paused.remove(&node_id);
+            let (parked, kept) = std::mem::take(&mut state.parked)
+                .into_iter()
+                .partition(|(from, to, _)| *from == node_id || *to == node_id);
+            state.parked = kept;
+            parked
+        };
+
+        debug!(node_id, parked = parked.len(), "resuming node");
+        for (from, to, message) in parked {
+            self.deliver(from, to, message);
+        }
+    }
+
+    /// Decide the fate of a message and, unless it is dropped, schedule its
+    /// delivery once the link latency has elapsed.
+    fn deliver(&self, from: u64, to: u64, message: Message) {
+        let mut state = self.state.lock().expect("sim network lock poisoned");
+        let Some(inbox) = state.inboxes.get(&to).cloned() else {
+            trace!(from, to, "destination is not part of the network");
+            return;
+        };
+        let link = state
+            .links
+            .get(&(from, to))
+            .copied()
+            .unwrap_or(state.default_link);
+
+        if state.paused.contains(&from) || state.paused.contains(&to) {
+            trace!(from, to, %message, "message held by a paused node");
+            state.parked.push((from, to, message));
+            return;
+        }
+
+        if state.is_partitioned(from, to) {
+            trace!(from, to, %message, "message crosses a partition");
+            if state.partition_policy == PartitionPolicy::QueueUntilHeal {
+                state.held.push((from, to, message));
+            }
+            return;
+        }
+
+        if link.drop_probability > 0.0 && state.rng.gen_bool(link.drop_probability) {
+            trace!(from, to, %message, "message dropped");
+            return;
+        }
+
+        let copies = if link.duplicate_probability > 0.0
+            && state.rng.gen_bool(link.duplicate_probability)
+        {
+            trace!(from, to, %message, "message duplicated");
+            2
+        } else {
+            1
+        };
+
+        if let Some(stepped) = &mut state.stepped {
+            for _ in 0..copies {
+                let sent = stepped.sent.entry((from, to)).or_default();
+                *sent += 1;
+                stepped.pending.insert((from, to, *sent), message.clone());
+            }
+            stepped.notify.notify_one();
+            return;
+        }
+
+        for _ in 0..copies {
+            let mut latency = match link.latency {
+                Latency::Fixed(latency) => latency,
+                Latency::Uniform { min, max } => state.rng.gen_range(min..=max),
+            };
+            if !link.reorder_window.is_zero() {
+                latency += state.rng.gen_range(Duration::ZERO..=link.reorder_window);
+            }
+
+            let inbox = inbox.clone();
+            let message = message.clone();
+            tokio::spawn(async move {
+                sleep(latency).await;
+                // The destination may have left the network in the meantime.
+                let _ = inbox.send(Envelope { from, message });
+            });
+        }
+    }
+}
+
+/// Transport of a single node plugged into a `SimNetwork`.
+pub struct SimTransport {
+    node_id: u64,
+    network: SimNetwork,
+    inbox: mpsc::UnboundedReceiver<Envelope>,
+}
+
+#[async_trait::async_trait]
+impl Transport for SimTransport {
+    async fn send(&self, to: u64, message: Message) -> Result<()> {
+        self.network.deliver(self.node_id, to, message);
+        Ok(())
+    }
+
+    async fn broadcast(&self, message: Message) -> Result<usize> {
+        let acceptors = self
+            .network
+            .state
+            .lock()
+            .expect("sim network lock poisoned")
+            .acceptors
+            .clone();
+
+        for &to in &acceptors {
+            self.network.deliver(self.node_id, to, message.clone());
+        }
+
+        Ok(acceptors.len())
+    }
+
+    async fn recv(&mut self) -> Option<Envelope> {
+        self.inbox.recv().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::task::JoinSet;
+
+    use super::*;
+    use crate::{
+        client::ProposeError,
+        domain::{consensus::ConsensusResult, event::PaxosEvent},
+        retry::RetryPolicy,
+        testkit::{Cluster, ClusterBuilder, ClusterTransport},
+    };
+
+    /// Deadline of the values proposed, in virtual time.
+    const PROPOSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Cluster of 3 acceptors and a proposer, whose links lose `drop_probability`
+    /// of the messages.
+    fn lossy_cluster(drop_probability: f64) -> Cluster {
+        let link = LinkSpec {
+            latency: Latency::Fixed(Duration::from_millis(5)),
+            drop_probability,
+            ..LinkSpec::default()
+        };
+        ClusterBuilder::new()
+            .transport(ClusterTransport::Sim(SimNetwork::with_default_link(
+                7, link,
+            )))
+            .retry_policy(RetryPolicy {
+                max_attempts: 50,
+                ..RetryPolicy::default()
+            })
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn round_decides_despite_lossy_links() {
+        let cluster = lossy_cluster(0.3);
+
+        let outcome = cluster
+            .proposer_client(3)
+            .unwrap()
+            .propose_with_timeout(7, PROPOSE_TIMEOUT)
+            .await;
+
+        assert!(
+            matches!(
+                outcome,
+                Ok(ConsensusResult::Decided { proposal, .. }) if proposal.value == 7
+            ),
+            "{outcome:?}"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn round_times_out_when_every_message_is_lost() {
+        let cluster = lossy_cluster(1.0);
+
+        let outcome = cluster
+            .proposer_client(3)
+            .unwrap()
+            .propose_with_timeout(7, PROPOSE_TIMEOUT)
+            .await;
+
+        assert_eq!(outcome, Err(ProposeError::Timeout));
+        assert_eq!(cluster.learner(3).unwrap().highest_slot(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn value_accepted_by_a_minority_is_decided_once_the_partition_heals() {
+        let link = LinkSpec {
+            latency: Latency::Fixed(Duration::from_millis(10)),
+            ..LinkSpec::default()
+        };
+        let network = SimNetwork::with_default_link(7, link);
+        let cluster = ClusterBuilder::new()
+            .transport(ClusterTransport::Sim(network.clone()))
+            .retry_policy(RetryPolicy {
+                max_attempts: 50,
+                ..RetryPolicy::default()
+            })
+            .build()
+            .unwrap();
+        let mut events = cluster.events(3).unwrap();
+        let client = cluster.proposer_client(3).unwrap();
+        let round = tokio::spawn(async move {
+            client.propose_with_timeout(7, PROPOSE_TIMEOUT).await
+        });
+
+        // The promises are on their way back, sent at 10ms: the accept requests will
+        // only reach acceptor 0.
+        sleep(Duration::from_millis(15)).await;
+        network.partition(vec![vec![3, 0], vec![1, 2]]);
+        sleep(Duration::from_secs(5)).await;
+        assert_eq!(cluster.learner(3).unwrap().highest_slot(), None);
+
+        network.heal();
+        let outcome = round.await.unwrap();
+
+        assert!(
+            matches!(
+                outcome,
+                Ok(ConsensusResult::Decided { proposal, by_other: false, .. })
+                    if proposal.value == 7
+            ),
+            "{outcome:?}"
+        );
+        let mut accepted_ballots = BTreeSet::new();
+        while let Ok(event) = events.try_recv() {
+            if let PaxosEvent::AcceptSent { ballot, value, .. } = event {
+                assert_eq!(value, 7, "another value was sent to be accepted");
+                accepted_ballots.insert(ballot);
+            }
+        }
+        assert!(accepted_ballots.len() > 1, "the round was not retried");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn late_accept_responses_of_an_abandoned_round_are_ignored() {
+        let link = LinkSpec {
+            latency: Latency::Fixed(Duration::from_millis(10)),
+            ..LinkSpec::default()
+        };
+        let network = SimNetwork::with_default_link(7, link);
+        network.set_partition_policy(PartitionPolicy::QueueUntilHeal);
+        let cluster = ClusterBuilder::new()
+            .transport(ClusterTransport::Sim(network.clone()))
+            .retry_policy(RetryPolicy {
+                max_attempts: 50,
+                ..RetryPolicy::default()
+            })
+            .build()
+            .unwrap();
+        let mut events = cluster.events(3).unwrap();
+        let client = cluster.proposer_client(3).unwrap();
+        let round = tokio::spawn(async move {
+            client.propose_with_timeout(7, PROPOSE_TIMEOUT).await
+        });
+
+        // The accept requests are sent at 20ms: every acceptor accepts 7, but their
+        // responses are held until the round gave up on them.
+        sleep(Duration::from_millis(25)).await;
+        network.partition(vec![vec![3], vec![0, 1, 2]]);
+        sleep(Duration::from_secs(5)).await;
+        assert_eq!(cluster.learner(3).unwrap().highest_slot(), None);
+
+        network.heal();
+        let outcome = round.await.unwrap();
+
+        assert!(
+            matches!(outcome, Ok(ConsensusResult::Decided { proposal, .. }) if proposal.value == 7),
+            "{outcome:?}"
+        );
+        let mut accepted_ballots = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let PaxosEvent::AcceptSent { ballot, value, .. } = event {
+                assert_eq!(value, 7, "the restarted round lost the client value");
+                accepted_ballots.push(ballot);
+            }
+        }
+        assert!(accepted_ballots.len() > 1, "the round was not restarted");
+        let decisions = cluster.learner(3).unwrap().decision_log();
+        assert_eq!(decisions.len(), 1, "{decisions:?}");
+        assert_eq!(decisions[0].value, 7);
+        assert_ne!(
+            decisions[0].ballot, accepted_ballots[0],
+            "a late response of the abandoned round was counted"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn duplicated_and_reordered_messages_keep_a_single_value_per_slot() {
+        for (duplicate_probability, reorder_window) in [
+            (0.0, Duration::from_millis(20)),
+            (0.5, Duration::ZERO),
+            (0.5, Duration::from_millis(20)),
+            (1.0, Duration::from_millis(50)),
+        ] {
+            let link = LinkSpec {
+                latency: Latency::Fixed(Duration::from_millis(5)),
+                duplicate_probability,
+                reorder_window,
+                ..LinkSpec::default()
+            };
+            let cluster = ClusterBuilder::new()
+                .proposers(2)
+                .transport(ClusterTransport::Sim(SimNetwork::with_default_link(
+                    7, link,
+                )))
+                .retry_policy(RetryPolicy {
+                    max_attempts: 50,
+                    ..RetryPolicy::default()
+                })
+                .build()
+                .unwrap();
+
+            let mut rounds = JoinSet::new();
+            for proposer_id in [3, 4] {
+                let client = cluster.proposer_client(proposer_id).unwrap();
+                rounds.spawn(async move {
+                    for value in 0..5 {
+                        // Redirects and lost slots are fine: only safety is checked.
+                        let _ = client
+                            .propose_with_timeout(
+                                proposer_id * 100 + value,
+                                PROPOSE_TIMEOUT,
+                            )
+                            .await;
+                    }
+                });
+            }
+            while let Some(round) = rounds.join_next().await {
+                round.unwrap();
+            }
+
+            let mut chosen = BTreeMap::new();
+            for proposer_id in [3, 4] {
+                for record in cluster.learner(proposer_id).unwrap().decision_log() {
+                    let acceptors: BTreeSet<_> = record.acceptors.iter().collect();
+                    assert_eq!(acceptors.len(), record.acceptors.len(), "{record:?}");
+                    assert!(record.acceptors.len() <= 3, "{record:?}");
+                    let value = *chosen.entry(record.slot).or_insert(record.value);
+                    assert_eq!(
+                        value, record.value,
+                        "slot {} decided twice with {duplicate_probability} \
+                         duplicates and a window of {reorder_window:?}",
+                        record.slot
+                    );
+                }
+            }
+            assert!(!chosen.is_empty());
+            cluster.shutdown().await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,508 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{bail, Context, Result};
+use futures_util::future::join_all;
+use tokio::{
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{tcp::OwnedWriteHalf, TcpListener, TcpStream},
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+    time::timeout,
+};
+use tracing::{debug, warn};
+
+use super::{
+    wire::{Authenticator, Codec, WireMessage, MAX_MESSAGE_SIZE, TAG_SIZE},
+    Envelope, Transport,
+};
+use crate::{
+    config::ClusterConfig,
+    domain::{
+        command::Command,
+        message::{ClientRequestBody, Message},
+    },
+};
+
+/// Transport over TCP, for nodes living in different processes.
+///
+/// Every message is sent as a frame made of its length, as a big endian `u32`,
+/// followed by the message encoded with the codec of the transport.
+///
+/// Connections to peers are opened on the first message sent to them, and reused
+/// afterwards. Dialing a peer gives up after the `dial_ms` of the cluster, and only
+/// holds up the messages to that peer.
+///
+/// If the cluster shares a secret, every frame ends with the HMAC of the message,
+/// and frames whose HMAC does not match are dropped before being decoded.
+pub struct TcpTransport {
+    id: u64,
+    cluster: ClusterConfig,
+    codec: Codec,
+    auth: Option<Authenticator>,
+    /// Number of frames dropped because of an invalid HMAC.
+    rejected: Arc<AtomicU64>,
+    /// Outgoing connection to each peer, by node id. The map is only locked to look
+    /// up the connection of a peer, which has a lock of its own.
+    connections: std::sync::Mutex<HashMap<u64, Connection>>,
+    /// Messages read from the incoming connections.
+    inbox: mpsc::UnboundedReceiver<Envelope>,
+    listener: JoinHandle<()>,
+}
+
+impl TcpTransport {
+    /// Listen on the address of the node `id` in `cluster`. Every peer must use the
+    /// same codec, and the same authenticator if any.
+    pub async fn listen(
+        id: u64,
+        cluster: ClusterConfig,
+        codec: Codec,
+        auth: Option<Authenticator>,
+    ) -> Result<Self> {
+        let address = peer_address(&cluster, id)?;
+        let listener = TcpListener::bind(address)
+            .await
+            .with_context(|| format!("could not listen on {address}"))?;
+        debug!(node_id = id, %address, ?codec, authenticated = auth.is_some(), "listening");
+
+        let (inbox_sender, inbox) = mpsc::unbounded_channel();
+        let rejected = Arc::new(AtomicU64::new(0));
+        let reader = FrameReader {
+            codec,
+            auth: auth.clone(),
+            rejected: rejected.clone(),
+        };
+        let listener = tokio::spawn(accept(listener, reader, inbox_sender));
+
+        Ok(Self {
+            id,
+            cluster,
+            codec,
+            auth,
+            rejected,
+            connections: std::sync::Mutex::new(HashMap::new()),
+            inbox,
+            listener,
+        })
+    }
+
+    /// Connection to the peer `to`, opened or not.
+    fn connection(&self, to: u64) -> Connection {
+        self.connections
+            .lock()
+            .expect("connections lock poisoned")
+            .entry(to)
+            .or_default()
+            .clone()
+    }
+
+    async fn connect(&self, to: u64) -> Result<OwnedWriteHalf> {
+        let address = peer_address(&self.cluster, to)?;
+        let stream = timeout(self.cluster.timeouts.dial(), TcpStream::connect(address))
+            .await
+            .with_context(|| format!("timed out connecting to node {to} at {address}"))?
+            .with_context(|| format!("could not connect to node {to} at {address}"))?;
+
+        // Nothing is ever sent back on an outgoing connection.
+        let (_, writer) = stream.into_split();
+        Ok(writer)
+    }
+
+    /// Number of frames dropped so far because their HMAC was invalid.
+    pub fn rejected_frames(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+/// Outgoing connection to a peer, `None` until it is opened. Its lock is held while
+/// dialing the peer, so that messages to it are still sent in order.
+type Connection = Arc<Mutex<Option<OwnedWriteHalf>>>;
+
+impl Drop for TcpTransport {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+/// Accept the connections of the peers, reading the messages of each of them in
+/// its own task.
+async fn accept(
+    listener: TcpListener,
+    reader: FrameReader,
+    inbox: mpsc::UnboundedSender<Envelope>,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, address)) => {
+                let inbox = inbox.clone();
+                let reader = reader.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = reader.read_connection(stream, inbox).await {
+                        warn!(%address, "closing connection: {e:#}");
+                    }
+                });
+            }
+            Err(e) => warn!("could not accept connection: {e}"),
+        }
+    }
+}
+
+/// Turns the frames of the incoming connections back into messages.
+#[derive(Debug, Clone)]
+struct FrameReader {
+    codec: Codec,
+    auth: Option<Authenticator>,
+    rejected: Arc<AtomicU64>,
+}
+
+impl FrameReader {
+    /// Forward the messages of a connection to the inbox. The node on the other end
+    /// is identified by its first message: later messages claiming to come from
+    /// another node are dropped.
+    async fn read_connection(
+        &self,
+        mut stream: TcpStream,
+        inbox: mpsc::UnboundedSender<Envelope>,
+    ) -> Result<()> {
+        let mut peer = None;
+        while let Some(frame) = read_frame(&mut stream).await? {
+            let payload = match &self.auth {
+                Some(auth) => match auth.verify(&frame) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        self.rejected.fetch_add(1, Ordering::Relaxed);
+                        warn!("dropping frame: {e}");
+                        continue;
+                    }
+                },
+                None => &frame,
+            };
+
+            let WireMessage { from, message } = self.codec.decode(payload)?;
+            match peer {
+                None => peer = Some(from),
+                Some(peer) if peer != from => {
+                    warn!(peer, from, "dropping message from another node");
+                    continue;
+                }
+                Some(_) => (),
+            }
+
+            if inbox.send(Envelope { from, message }).is_err() {
+                // The transport was dropped.
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the next frame of a connection. Returns `None` if the connection was closed
+/// in between two frames.
+async fn read_frame(stream: &mut TcpStream) -> Result<Option<Vec<u8>>> {
+    let length = match stream.read_u32().await {
+        Ok(length) => length,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if u64::from(length) > MAX_MESSAGE_SIZE + TAG_SIZE as u64 {
+        bail!("frame of {length} bytes is larger than the maximum message size");
+    }
+
+    let mut frame = vec![0; length as usize];
+    stream.read_exact(&mut frame).await?;
+    Ok(Some(frame))
+}
+
+async fn write_frame(
+    writer: &mut (impl AsyncWrite + Unpin),
+    frame: &[u8],
+) -> Result<()> {
+    writer.write_u32(frame.len() as u32).await?;
+    writer.write_all(frame).await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl Transport for TcpTransport {
+    async fn send(&self, to: u64, message: Message) -> Result<()> {
+        let frame = encode_frame(
+            self.codec,
+            self.auth.as_ref(),
+            &WireMessage {
+                from: self.id,
+                message,
+            },
+        )?;
+
+        let connection = self.connection(to);
+        let mut connection = connection.lock().await;
+        if let Some(writer) = connection.as_mut() {
+            if write_frame(writer, &frame).await.is_ok() {
+                return Ok(());
+            }
+            // The peer may have restarted since: connect again.
+            *connection = None;
+        }
+
+        let mut writer = self.connect(to).await?;
+        write_frame(&mut writer, &frame).await?;
+        *connection = Some(writer);
+        Ok(())
+    }
+
+    async fn broadcast(&self, message: Message) -> Result<usize> {
+        let acceptors: Vec<u64> =
+            self.cluster.acceptors().map(|peer| peer.node_id).collect();
+
+        // Sent to every acceptor at once, so that a peer slow to dial does not
+        // delay the others.
+        let message = &message;
+        let results = join_all(
+            acceptors
+                .iter()
+                .map(|&to| async move { (to, self.send(to, message.clone()).await) }),
+        )
+        .await;
+        let mut sent = 0;
+        for (to, result) in results {
+            match result {
+                Ok(()) => sent += 1,
+                Err(e) => warn!(to, "could not send message: {e:#}"),
+            }
+        }
+
+        Ok(sent)
+    }
+
+    async fn recv(&mut self) -> Option<Envelope> {
+        self.inbox.recv().await
+    }
+}
+
+/// Submit `value` to the node listening on `address`, which will propose it.
+///
+/// The client is not part of the cluster, and identifies itself with `client_id`.
+/// It must know the secret of the cluster, if there is one. No answer is sent back.
+/// Values reserved for the commands of the cluster are refused before connecting.
+pub async fn submit(
+    address: SocketAddr,
+    codec: Codec,
+    auth: Option<&Authenticator>,
+    client_id: u64,
+    value: u64,
+) -> Result<()> {
+    if Command::is_reserved(value) {
+        bail!("{value} is reserved for the commands of the cluster");
+    }
+    let mut stream = TcpStream::connect(address)
+        .await
+        .with_context(|| format!("could not connect to {address}"))?;
+    let frame = encode_frame(
+        codec,
+        auth,
+        &WireMessage {
+            from: client_id,
+            message: Message::ClientRequest {
+                body: ClientRequestBody {
+                    issuer_id: client_id,
+                    value,
+                },
+            },
+        },
+    )?;
+
+    write_frame(&mut stream, &frame).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+fn encode_frame(
+    codec: Codec,
+    auth: Option<&Authenticator>,
+    message: &WireMessage,
+) -> Result<Vec<u8>> {
+    let mut frame = codec.encode(message)?;
+    if let Some(auth) = auth {
+        auth.sign(&mut frame);
+    }
+    Ok(frame)
+}
+
+/// Address the node `node_id` listens on.
+fn peer_address(cluster: &ClusterConfig, node_id: u64) -> Result<SocketAddr> {
+    cluster
+        .peer(node_id)
+        .and_then(|peer| peer.address)
+        .with_context(|| format!("node {node_id} has no address in the cluster config"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::{PeerConfig, Role};
+
+    /// How long a message that was sent is waited for.
+    const DELIVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn free_address() -> SocketAddr {
+        std::net::TcpListener::bind(("127.0.0.1", 0))
+            .and_then(|listener| listener.local_addr())
+            .expect("no free port")
+    }
+
+    /// Cluster of a proposer, node 0, and an acceptor at each of `acceptors`.
+    fn cluster(acceptors: &[SocketAddr]) -> ClusterConfig {
+        let proposer = (0, Role::Proposer, free_address());
+        let acceptors = (1..)
+            .zip(acceptors)
+            .map(|(node_id, &address)| (node_id, Role::Acceptor, address));
+        ClusterConfig {
+            peers: std::iter::once(proposer)
+                .chain(acceptors)
+                .map(|(node_id, role, address)| PeerConfig {
+                    node_id,
+                    roles: vec![role],
+                    address: Some(address),
+                    priority: 0,
+                })
+                .collect(),
+            ..ClusterConfig::default()
+        }
+    }
+
+    fn request(value: u64) -> Message {
+        Message::ClientRequest {
+            body: ClientRequestBody {
+                issuer_id: 0,
+                value,
+            },
+        }
+    }
+
+    fn is_request(message: &Message, value: u64) -> bool {
+        matches!(message, Message::ClientRequest { body } if body.value == value)
+    }
+
+    #[tokio::test]
+    async fn messages_cross_with_either_codec() {
+        for codec in [Codec::Bincode, Codec::Json] {
+            let cluster = cluster(&[free_address()]);
+            let sender = TcpTransport::listen(0, cluster.clone(), codec, None)
+                .await
+                .unwrap();
+            let mut receiver =
+                TcpTransport::listen(1, cluster, codec, None).await.unwrap();
+
+            sender.send(1, request(7)).await.unwrap();
+            let envelope = timeout(DELIVERY_TIMEOUT, receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(envelope.from, 0, "{codec:?}");
+            assert!(is_request(&envelope.message, 7), "{codec:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn peers_with_different_codecs_drop_the_messages() {
+        let cluster = cluster(&[free_address()]);
+        let sender = TcpTransport::listen(0, cluster.clone(), Codec::Json, None)
+            .await
+            .unwrap();
+        let mut receiver = TcpTransport::listen(1, cluster, Codec::Bincode, None)
+            .await
+            .unwrap();
+
+        sender.send(1, request(7)).await.unwrap();
+
+        assert!(timeout(Duration::from_millis(200), receiver.recv())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn frames_signed_with_another_secret_are_rejected() {
+        let cluster = cluster(&[free_address(), free_address()]);
+        let auth = Authenticator::new(b"cluster secret");
+        let sender = TcpTransport::listen(
+            0,
+            cluster.clone(),
+            Codec::Bincode,
+            Some(auth.clone()),
+        )
+        .await
+        .unwrap();
+        let mut trusting =
+            TcpTransport::listen(1, cluster.clone(), Codec::Bincode, Some(auth))
+                .await
+                .unwrap();
+        let mut other = TcpTransport::listen(
+            2,
+            cluster,
+            Codec::Bincode,
+            Some(Authenticator::new(b"other secret")),
+        )
+        .await
+        .unwrap();
+
+        sender.send(1, request(7)).await.unwrap();
+        sender.send(2, request(7)).await.unwrap();
+
+        let envelope = timeout(DELIVERY_TIMEOUT, trusting.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(is_request(&envelope.message, 7));
+        assert_eq!(trusting.rejected_frames(), 0);
+        assert!(timeout(Duration::from_millis(200), other.recv())
+            .await
+            .is_err());
+        assert_eq!(other.rejected_frames(), 1);
+    }
+
+    #[tokio::test]
+    async fn broadcast_reaches_the_acceptors_that_are_up() {
+        // Nothing listens on the address of the second acceptor.
+        let cluster = cluster(&[free_address(), free_address()]);
+        let sender = TcpTransport::listen(0, cluster.clone(), Codec::Bincode, None)
+            .await
+            .unwrap();
+        let mut receiver = TcpTransport::listen(1, cluster, Codec::Bincode, None)
+            .await
+            .unwrap();
+
+        assert_eq!(sender.broadcast(request(7)).await.unwrap(), 1);
+        let envelope = timeout(DELIVERY_TIMEOUT, receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(is_request(&envelope.message, 7));
+    }
+
+    #[tokio::test]
+    async fn dialing_gives_up_after_the_dial_timeout() {
+        // Reserved for documentation: connecting to it never completes, or fails
+        // right away without a route.
+        let unroutable = SocketAddr::from(([192, 0, 2, 1], 7000));
+        let mut cluster = cluster(&[unroutable]);
+        cluster.timeouts.dial_ms = 100;
+        let sender = TcpTransport::listen(0, cluster, Codec::Bincode, None)
+            .await
+            .unwrap();
+
+        let sent = timeout(DELIVERY_TIMEOUT, sender.send(1, request(7))).await;
+
+        assert!(matches!(sent, Ok(Err(_))), "{sent:?}");
+    }
+}
@@ -0,0 +1,281 @@
+use anyhow::{Context, Result};
+use bincode::Options;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::domain::message::Message;
+
+/// Largest encoded message accepted by `WireMessage::decode`. Bounding the input
+/// keeps a corrupt or malicious length field from triggering a huge allocation.
+pub const MAX_MESSAGE_SIZE: u64 = 64 * 1024;
+
+/// Message as it travels between processes, along with the node that sent it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct WireMessage {
+    pub from: u64,
+    pub message: Message,
+}
+
+/// Format of the messages on the wire. Both ends of a connection must use the same
+/// one: a message encoded with one codec fails to decode with the other.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    /// Compact binary format, for normal operation.
+    #[default]
+    Bincode,
+    /// Human readable format, for debugging.
+    Json,
+}
+
+fn options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_MESSAGE_SIZE)
+}
+
+impl Codec {
+    pub fn encode(self, message: &WireMessage) -> Result<Vec<u8>> {
+        match self {
+            Codec::Bincode => options().serialize(message).map_err(anyhow::Error::from),
+            Codec::Json => serde_json::to_vec(message).map_err(anyhow::Error::from),
+        }
+    }
+
+    /// Decode a message received from a peer. Arbitrary bytes must never make this
+    /// panic: anything that is not a valid message is reported as an error.
+    pub fn decode(self, bytes: &[u8]) -> Result<WireMessage> {
+        if bytes.len() as u64 > MAX_MESSAGE_SIZE {
+            anyhow::bail!("message of {} bytes is too large", bytes.len());
+        }
+
+        match self {
+            Codec::Bincode => options().deserialize(bytes).map_err(anyhow::Error::from),
+            Codec::Json => serde_json::from_slice(bytes).map_err(anyhow::Error::from),
+        }
+        .with_context(|| format!("could not decode message as {self:?}"))
+    }
+}
+
+impl WireMessage {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Codec::Bincode.encode(self)
+    }
+
+    /// Decode a message received from a peer, encoded with the default codec.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Codec::Bincode.decode(bytes)
+    }
+}
+
+/// Size of the tag `Authenticator::sign` appends to a frame.
+pub const TAG_SIZE: usize = 32;
+
+/// Authenticates frames with an HMAC-SHA256 keyed by a secret shared by the whole
+/// cluster, so that a node can not be impersonated by anyone who does not know it.
+#[derive(Clone)]
+pub struct Authenticator {
+    mac: Hmac<Sha256>,
+}
+
+impl Authenticator {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            mac: Hmac::new_from_slice(secret).expect("HMAC accepts keys of any size"),
+        }
+    }
+
+    /// Append the tag of `frame` to it.
+    pub fn sign(&self, frame: &mut Vec<u8>) {
+        let mut mac = self.mac.clone();
+        mac.update(frame);
+        frame.extend_from_slice(&mac.finalize().into_bytes());
+    }
+
+    /// Check the tag at the end of a signed frame, and return the frame without it.
+    pub fn verify<'a>(&self, frame: &'a [u8]) -> Result<&'a [u8]> {
+        let Some(split) = frame.len().checked_sub(TAG_SIZE) else {
+            anyhow::bail!("frame of {} bytes is too short to be signed", frame.len());
+        };
+        let (payload, tag) = frame.split_at(split);
+
+        let mut mac = self.mac.clone();
+        mac.update(payload);
+        mac.verify_slice(tag)
+            .context("invalid message authentication code")?;
+        Ok(payload)
+    }
+}
+
+impl std::fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The key must never end up in the logs.
+        f.debug_struct("Authenticator").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::domain::{
+        ballot::BallotNumber,
+        id::ProposalId,
+        message::{AcceptPhaseBody, PreparePhaseBody},
+        proposal::Proposal,
+    };
+
+    /// Seed corpus of the `wire_message` fuzz target, along with the message each
+    /// file holds. The files must be written again whenever the encoding of the
+    /// messages changes, or the fuzzer starts from invalid inputs.
+    fn corpus() -> [(&'static [u8], WireMessage); 4] {
+        let prepare = PreparePhaseBody {
+            issuer_id: 3,
+            epoch: 1,
+            slot: 2,
+            proposal_id: ProposalId(Uuid::from_u128(7)),
+            ballot: BallotNumber::new(4, 3),
+            accepted: None,
+            nonce: 5,
+            sent_at: None,
+        };
+        let accept = AcceptPhaseBody {
+            issuer_id: 3,
+            epoch: 1,
+            slot: 2,
+            proposal_id: ProposalId(Uuid::from_u128(7)),
+            ballot: BallotNumber::new(4, 3),
+            value: 42,
+            nonce: 5,
+            sent_at: None,
+        };
+        let accepted =
+            Proposal::new(41, ProposalId(Uuid::from_u128(6)), BallotNumber::new(2, 4));
+        [
+            (
+                include_bytes!("../../fuzz/corpus/wire_message/prepare_request"),
+                WireMessage {
+                    from: 3,
+                    message: Message::PrepareRequest {
+                        body: prepare.clone(),
+                    },
+                },
+            ),
+            (
+                include_bytes!("../../fuzz/corpus/wire_message/prepare_response"),
+                WireMessage {
+                    from: 0,
+                    message: Message::PrepareResponse {
+                        body: PreparePhaseBody {
+                            issuer_id: 0,
+                            accepted: Some(accepted),
+                            ..prepare
+                        },
+                    },
+                },
+            ),
+            (
+                include_bytes!("../../fuzz/corpus/wire_message/accept_request"),
+                WireMessage {
+                    from: 3,
+                    message: Message::AcceptRequest {
+                        body: accept.clone(),
+                    },
+                },
+            ),
+            (
+                include_bytes!("../../fuzz/corpus/wire_message/accept_response"),
+                WireMessage {
+                    from: 0,
+                    message: Message::AcceptResponse {
+                        body: AcceptPhaseBody {
+                            issuer_id: 0,
+                            ..accept
+                        },
+                    },
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn seed_corpus_holds_valid_messages() {
+        for (bytes, message) in corpus() {
+            assert_eq!(message.encode().unwrap(), bytes, "{}", message.message);
+            assert_eq!(WireMessage::decode(bytes).unwrap().encode().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn random_bytes_never_make_decoding_panic() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10_000 {
+            let mut bytes = vec![0; rng.gen_range(0..128)];
+            rng.fill(&mut bytes[..]);
+            for codec in [Codec::Bincode, Codec::Json] {
+                if let Ok(message) = codec.decode(&bytes) {
+                    codec.decode(&codec.encode(&message).unwrap()).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_messages_never_make_decoding_panic() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for (bytes, _) in corpus() {
+            for _ in 0..2_000 {
+                let mut corrupted = bytes.to_vec();
+                let at = rng.gen_range(0..corrupted.len());
+                corrupted[at] = rng.gen();
+                corrupted.truncate(rng.gen_range(at..=corrupted.len()));
+                let _ = WireMessage::decode(&corrupted);
+            }
+            // Every byte is needed: a truncated message never decodes.
+            assert!(WireMessage::decode(&bytes[..bytes.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn oversized_input_is_rejected_before_decoding() {
+        let bytes = vec![0; MAX_MESSAGE_SIZE as usize + 1];
+
+        let error = WireMessage::decode(&bytes).unwrap_err();
+
+        assert!(error.to_string().contains("too large"), "{error:#}");
+    }
+
+    #[test]
+    fn signed_frame_passes_verification() {
+        let auth = Authenticator::new(b"cluster secret");
+        let payload = Codec::Bincode.encode(&corpus()[0].1).unwrap();
+        let mut frame = payload.clone();
+        auth.sign(&mut frame);
+
+        assert_eq!(frame.len(), payload.len() + TAG_SIZE);
+        assert_eq!(auth.verify(&frame).unwrap(), payload.as_slice());
+    }
+
+    #[test]
+    fn tampered_frame_is_rejected() {
+        let auth = Authenticator::new(b"cluster secret");
+        let mut frame = Codec::Bincode.encode(&corpus()[0].1).unwrap();
+        auth.sign(&mut frame);
+
+        let mut tampered = frame.clone();
+        tampered[0] ^= 1;
+        assert!(auth.verify(&tampered).is_err());
+        assert!(Authenticator::new(b"other secret").verify(&frame).is_err());
+        assert!(auth.verify(&frame[..TAG_SIZE - 1]).is_err());
+    }
+}